@@ -0,0 +1,61 @@
+//! Byte order swaps and element type casts over 16 MB of data, against a
+//! plain element-by-element swap for comparison.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rawarray::{elements_from_bytes_with_endianness, DType, Endianness, RawArray, WriteOptions};
+use std::hint::black_box;
+use std::mem;
+
+const BYTES: usize = 16 << 20;
+
+/// Swap by reversing each element's bytes in place, one at a time
+fn scalar_swap(bytes: &mut [u8], width: usize) {
+    for c in bytes.chunks_exact_mut(width) {
+        c.reverse();
+    }
+}
+
+fn swap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("swap");
+    group.throughput(Throughput::Bytes(BYTES as u64));
+    let bytes: Vec<u8> = (0..BYTES).map(|i| i as u8).collect();
+    for &width in &[2, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("scalar", width), &width, |b, &w| {
+            let mut buf = bytes.clone();
+            b.iter(|| scalar_swap(black_box(&mut buf), w))
+        });
+    }
+    group.bench_function("i16", |b| {
+        b.iter(|| elements_from_bytes_with_endianness::<i16>(black_box(&bytes), Endianness::Big))
+    });
+    group.bench_function("f32", |b| {
+        b.iter(|| elements_from_bytes_with_endianness::<f32>(black_box(&bytes), Endianness::Big))
+    });
+    group.bench_function("f64", |b| {
+        b.iter(|| elements_from_bytes_with_endianness::<f64>(black_box(&bytes), Endianness::Big))
+    });
+    group.finish();
+}
+
+fn cast(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cast");
+    let n = BYTES / mem::size_of::<f64>();
+    group.throughput(Throughput::Bytes(BYTES as u64));
+    let ra: RawArray<f64> = (0..n).map(|i| i as f64 * 0.25).collect::<Vec<f64>>().into();
+    let path = std::env::temp_dir().join("rawarray_bench_cast.ra");
+    for to in &["f32", "i16", "u8"] {
+        let opts = WriteOptions::new().dtype(DType::parse(to).unwrap());
+        group.bench_function(format!("f64 to {}", to), |b| {
+            b.iter(|| ra.write_with(&path, &opts).unwrap())
+        });
+    }
+    let opts = WriteOptions::new().endianness(Endianness::Big);
+    group.bench_function("f64 to big endian", |b| {
+        b.iter(|| ra.write_with(&path, &opts).unwrap())
+    });
+    let _ = std::fs::remove_file(&path);
+    group.finish();
+}
+
+criterion_group!(benches, swap, cast);
+criterion_main!(benches);
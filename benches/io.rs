@@ -0,0 +1,157 @@
+//! Reading, writing, casting, and encoding whole files, from kilobytes up
+//! to a gigabyte. The gigabyte size only runs with `RAWARRAY_BENCH_GB=1`
+//! set, as it needs several gigabytes of memory and disk.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rawarray::codec::{Delta, Rle};
+use rawarray::{DType, RawArray, ReadOptions, WriteOptions};
+use std::mem;
+use std::path::PathBuf;
+
+const KB: usize = 1 << 10;
+const MB: usize = 1 << 20;
+const GB: usize = 1 << 30;
+
+/// Data sizes in bytes to run each benchmark at
+fn sizes() -> Vec<usize> {
+    let mut sizes = vec![4 * KB, MB, 64 * MB];
+    if std::env::var_os("RAWARRAY_BENCH_GB").is_some() {
+        sizes.push(GB);
+    }
+    sizes
+}
+
+fn label(bytes: usize) -> String {
+    match bytes {
+        b if b >= GB => format!("{}GB", b / GB),
+        b if b >= MB => format!("{}MB", b / MB),
+        b => format!("{}KB", b / KB),
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rawarray_bench_{}.ra", name))
+}
+
+/// Smooth, slowly varying data, like an image or a signal
+fn array(bytes: usize) -> RawArray<f32> {
+    let n = bytes / mem::size_of::<f32>();
+    (0..n)
+        .map(|i| (i as f32 * 1e-3).sin())
+        .collect::<Vec<f32>>()
+        .into()
+}
+
+/// Integer data with long runs, like a label map
+fn labels(bytes: usize) -> RawArray<u16> {
+    let n = bytes / mem::size_of::<u16>();
+    (0..n)
+        .map(|i| (i / 1000) as u16)
+        .collect::<Vec<u16>>()
+        .into()
+}
+
+fn write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write");
+    group.sample_size(10);
+    let path = temp_path("write");
+    for bytes in sizes() {
+        let ra = array(bytes);
+        group.throughput(Throughput::Bytes(bytes as u64));
+        group.bench_function(BenchmarkId::new("plain", label(bytes)), |b| {
+            b.iter(|| ra.write(&path).unwrap())
+        });
+        let opts = WriteOptions::new().checksum(true);
+        group.bench_function(BenchmarkId::new("checksum", label(bytes)), |b| {
+            b.iter(|| ra.write_with(&path, &opts).unwrap())
+        });
+    }
+    let _ = std::fs::remove_file(&path);
+    group.finish();
+}
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+    group.sample_size(10);
+    let path = temp_path("read");
+    let checked = temp_path("read_checksum");
+    for bytes in sizes() {
+        let ra = array(bytes);
+        ra.write(&path).unwrap();
+        ra.write_with(&checked, &WriteOptions::new().checksum(true))
+            .unwrap();
+        drop(ra);
+        group.throughput(Throughput::Bytes(bytes as u64));
+        group.bench_function(BenchmarkId::new("plain", label(bytes)), |b| {
+            b.iter(|| RawArray::<f32>::read(&path).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("checksum", label(bytes)), |b| {
+            b.iter(|| RawArray::<f32>::read(&checked).unwrap())
+        });
+    }
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&checked);
+    group.finish();
+}
+
+fn cast(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cast");
+    group.sample_size(10);
+    let path = temp_path("cast");
+    let narrow = WriteOptions::new().dtype(DType::parse("i16").unwrap());
+    let widen = ReadOptions::new().allow_width_promotion(true);
+    for bytes in sizes() {
+        let ra = array(bytes);
+        group.throughput(Throughput::Bytes(bytes as u64));
+        group.bench_function(BenchmarkId::new("write f32 as i16", label(bytes)), |b| {
+            b.iter(|| ra.write_with(&path, &narrow).unwrap())
+        });
+        ra.write(&path).unwrap();
+        drop(ra);
+        group.bench_function(BenchmarkId::new("read f32 as f64", label(bytes)), |b| {
+            b.iter(|| RawArray::<f64>::read_with(&path, &widen).unwrap())
+        });
+    }
+    let _ = std::fs::remove_file(&path);
+    group.finish();
+}
+
+/// Every codec built in, with its name
+fn codecs() -> Vec<(&'static str, WriteOptions)> {
+    #[allow(unused_mut)]
+    let mut codecs = vec![
+        ("rle", WriteOptions::new().codec(Rle)),
+        ("delta", WriteOptions::new().codec(Delta)),
+    ];
+    #[cfg(feature = "lz4")]
+    codecs.push(("lz4", WriteOptions::new().codec(rawarray::codec::Lz4)));
+    #[cfg(feature = "zstd")]
+    codecs.push(("zstd", WriteOptions::new().codec(rawarray::codec::Zstd(3))));
+    codecs
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    group.sample_size(10);
+    let path = temp_path("encode");
+    for bytes in sizes() {
+        let ra = labels(bytes);
+        group.throughput(Throughput::Bytes(bytes as u64));
+        for (name, opts) in codecs() {
+            group.bench_function(
+                BenchmarkId::new(format!("write {}", name), label(bytes)),
+                |b| b.iter(|| ra.write_with(&path, &opts).unwrap()),
+            );
+            ra.write_with(&path, &opts).unwrap();
+            group.bench_function(
+                BenchmarkId::new(format!("read {}", name), label(bytes)),
+                |b| b.iter(|| RawArray::<u16>::read(&path).unwrap()),
+            );
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+    group.finish();
+}
+
+criterion_group!(benches, write, read, cast, encode);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+fn main() {
+    // the C header for the `ffi` module, written to OUT_DIR rather than the
+    // source tree; a test checks that include/rawarray.h matches it
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        let config = cbindgen::Config {
+            language: cbindgen::Language::C,
+            include_guard: Some("RAWARRAY_H".to_string()),
+            cpp_compat: true,
+            ..Default::default()
+        };
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/ffi.rs")
+            .generate()
+            .expect("unable to generate the C header")
+            .write_to_file(
+                std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("rawarray.h"),
+            );
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,21 @@
+use rawarray::{net, RawArray};
+use std::env;
+use std::io;
+use std::net::TcpStream;
+
+/// Send a few frames to `array_server`, as a scanner would.
+///
+/// Run `cargo run --example array_client [ADDR]` once the server is
+/// listening.
+fn main() -> io::Result<()> {
+    let addr = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:7878".to_string());
+    let mut socket = TcpStream::connect(&addr)?;
+    for frame in 0..5 {
+        let pixels = (0..64 * 64).map(|i| (i + frame) as f32);
+        let ra = RawArray::from_iter_with_dims(pixels, &[64, 64])?;
+        net::send_array(&mut socket, &ra)?;
+    }
+    Ok(())
+}
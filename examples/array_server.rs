@@ -0,0 +1,36 @@
+use rawarray::net;
+use std::env;
+use std::io;
+use std::net::TcpListener;
+
+/// Receive frames from `array_client` and report on each.
+///
+/// Run `cargo run --example array_server [ADDR]`, then the client, with the
+/// same address, from another terminal. The address defaults to
+/// `127.0.0.1:7878`. With `std::os::unix::net::UnixListener` in place of
+/// `TcpListener`, the same code serves a Unix socket.
+fn main() -> io::Result<()> {
+    let addr = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:7878".to_string());
+    let listener = TcpListener::bind(&addr)?;
+    println!("listening on {}", addr);
+    for socket in listener.incoming() {
+        let mut socket = socket?;
+        let peer = socket.peer_addr()?;
+        let mut frames = 0;
+        while let Some(frame) = net::recv_array::<f32, _>(&mut socket)? {
+            let sum: f32 = frame.data().iter().sum();
+            println!(
+                "{}: frame {} of {:?}, sum {}",
+                peer,
+                frames,
+                frame.dims(),
+                sum
+            );
+            frames += 1;
+        }
+        println!("{} closed after {} frames", peer, frames);
+    }
+    Ok(())
+}
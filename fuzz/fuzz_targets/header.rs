@@ -0,0 +1,12 @@
+//! Header parsing must fail cleanly, never panic, on any input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rawarray::RawArrayHeader;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(h) = RawArrayHeader::read_from(&mut &data[..]) {
+        let _ = (h.len(), h.nelem());
+    }
+});
@@ -0,0 +1,23 @@
+//! Reading whole arrays from untrusted bytes must fail cleanly, and must
+//! not allocate more than the byte limit allows, on any input.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rawarray::{RawArray, ReadOptions, SizePolicy};
+
+/// Well under libFuzzer's default 2 GB memory limit
+const MAX_BYTES: u64 = 1 << 24;
+
+fuzz_target!(|data: &[u8]| {
+    for policy in &[
+        SizePolicy::Strict,
+        SizePolicy::TrustDims,
+        SizePolicy::TrustSize,
+    ] {
+        let opts = ReadOptions::new().size_policy(*policy).max_bytes(MAX_BYTES);
+        let _ = RawArray::<u8>::read_from_with(&mut &data[..], &opts);
+        let _ = RawArray::<f32>::read_from_with(&mut &data[..], &opts);
+        let _ = RawArray::<i64>::read_from_with(&mut &data[..], &opts);
+    }
+});
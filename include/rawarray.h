@@ -0,0 +1,134 @@
+#ifndef RAWARRAY_H
+#define RAWARRAY_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * An array read from a file, with its elements in this machine's byte
+ * order
+ */
+typedef struct RaArray RaArray;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Read the RawArray file at `path`, returning `NULL` on failure. Free the
+ * result with `ra_free`.
+ *
+ * # Safety
+ *
+ * `path` must be `NULL` or a NUL-terminated string.
+ */
+struct RaArray *ra_read(const char *path);
+
+/**
+ * Read the RawArray file at `path` as `ra_read` does, but fail if its data
+ * is more than `max_bytes`, rather than 64 GiB, so that a hostile or
+ * corrupt file can't exhaust memory.
+ *
+ * # Safety
+ *
+ * `path` must be `NULL` or a NUL-terminated string.
+ */
+struct RaArray *ra_read_limited(const char *path, uint64_t max_bytes);
+
+/**
+ * Free an array from `ra_read`. `NULL` is ignored.
+ *
+ * # Safety
+ *
+ * `ra` must be `NULL` or from `ra_read`, and not already freed.
+ */
+void ra_free(struct RaArray *ra);
+
+/**
+ * Element type code of the array
+ *
+ * # Safety
+ *
+ * `ra` must be a live array from `ra_read`, as for all the accessors.
+ */
+uint64_t ra_eltype(const struct RaArray *ra);
+
+/**
+ * Width of one element in bytes
+ *
+ * # Safety
+ *
+ * See `ra_eltype`.
+ */
+uint64_t ra_elbyte(const struct RaArray *ra);
+
+/**
+ * Length of the data in bytes
+ *
+ * # Safety
+ *
+ * See `ra_eltype`.
+ */
+uint64_t ra_size(const struct RaArray *ra);
+
+/**
+ * Number of dimensions
+ *
+ * # Safety
+ *
+ * See `ra_eltype`.
+ */
+uint64_t ra_ndims(const struct RaArray *ra);
+
+/**
+ * The `ra_ndims` dimensions, first one fastest varying, valid until the
+ * array is freed
+ *
+ * # Safety
+ *
+ * See `ra_eltype`.
+ */
+const uint64_t *ra_dims(const struct RaArray *ra);
+
+/**
+ * The elements, in this machine's byte order, valid until the array is
+ * freed. They are aligned for any element type.
+ *
+ * # Safety
+ *
+ * See `ra_eltype`.
+ */
+const void *ra_data(const struct RaArray *ra);
+
+/**
+ * Write an array of `ndims` dimensions `dims`, whose elements of type
+ * `eltype` and `elbyte` bytes each are at `data` in this machine's byte
+ * order, to a RawArray file at `path`. Returns 0 on success and -1 on
+ * failure.
+ *
+ * # Safety
+ *
+ * `path` must be `NULL` or a NUL-terminated string, `dims` must point to
+ * `ndims` values, and `data` must point to as many bytes as the
+ * dimensions and `elbyte` call for.
+ */
+int ra_write(const char *path,
+             uint64_t eltype,
+             uint64_t elbyte,
+             const uint64_t *dims,
+             uint64_t ndims,
+             const void *data);
+
+/**
+ * Why the last failed call on this thread failed, or `NULL` if none has.
+ * The message is valid until the next failure on this thread.
+ */
+const char *ra_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RAWARRAY_H */
@@ -0,0 +1,360 @@
+//! The RawArray format on byte slices, needing only `core` and `alloc`, so
+//! embedded acquisition hardware can emit and parse RawArray buffers. The
+//! `rawarray` crate parses headers with this and adds files, streams,
+//! codecs, and typed arrays on top.
+//!
+//! Only plain data sections are handled here. Arrays whose data is
+//! compressed or encoded by a codec can be recognized, but need `rawarray`
+//! to be decoded. A checksum or attributes after the data section are left
+//! alone.
+//!
+//! The `std` feature, off by default, implements `std::error::Error` for
+//! `Error` and converts it to `std::io::Error`.
+//! ```
+//! use rawarray_core::{emit, parse, Header};
+//! let h = Header {
+//!     flags: 0,
+//!     eltype: 2,
+//!     elbyte: 2,
+//!     size: 6,
+//!     dims: vec![3],
+//! };
+//! let data = [1u8, 0, 2, 0, 3, 0];
+//! let mut buf = Vec::new();
+//! emit(&h, &data, &mut buf).unwrap();
+//! assert_eq!(parse(&buf).unwrap(), (h, &data[..]));
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+/// The first eight bytes of every RawArray, `rawarray` in ASCII
+pub const MAGIC_NUMBER: u64 = 0x79_61_72_72_61_77_61_72u64;
+/// Headers with more dimensions than this are rejected as corrupt
+pub const MAX_NDIMS: u64 = 64;
+
+/// Data is big endian
+pub const FLAG_BIG_ENDIAN: u64 = 1;
+/// Data is encoded by a codec, identified after the header
+pub const FLAG_ENCODED: u64 = 2;
+/// Elements are single bits
+pub const FLAG_BITS: u64 = 4;
+/// A CRC32 of the data section follows it
+pub const FLAG_CRC: u64 = 8;
+/// Data is compressed with zstd
+pub const FLAG_ZSTD: u64 = 16;
+/// Data is compressed with LZ4
+pub const FLAG_LZ4: u64 = 32;
+/// Named attributes follow the data
+pub const FLAG_ATTRS: u64 = 64;
+
+/// Flags whose data sections aren't the plain elements
+const CODED_FLAGS: u64 = FLAG_ENCODED | FLAG_ZSTD | FLAG_LZ4;
+
+/// Why a buffer couldn't be parsed or written
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The buffer holds `len` bytes, but `needed` are required
+    Truncated { needed: usize, len: usize },
+    /// The buffer doesn't start with the magic number
+    BadMagic,
+    /// The header claims more than `MAX_NDIMS` dimensions
+    TooManyDims(u64),
+    /// The flags call for a codec, checksum, or attributes, which this
+    /// crate doesn't handle
+    Unsupported(u64),
+    /// The header's data size disagrees with the data given
+    SizeMismatch { header: u64, data: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Truncated { needed, len } => {
+                write!(f, "needed {} bytes, but the buffer has {}", needed, len)
+            }
+            Error::BadMagic => write!(f, "Invalid magic, likely not a RawArray file."),
+            Error::TooManyDims(n) => write!(
+                f,
+                "header claims {} dimensions, more than the limit of {}",
+                n, MAX_NDIMS
+            ),
+            Error::Unsupported(flags) => {
+                write!(f, "flags {:#x} need the rawarray crate", flags)
+            }
+            Error::SizeMismatch { header, data } => write!(
+                f,
+                "header says the data is {} bytes, but it is {}",
+                header, data
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> std::io::Error {
+        let kind = match e {
+            Error::Truncated { .. } => std::io::ErrorKind::UnexpectedEof,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
+
+/// Length in bytes of the header of an array with `ndims` dimensions,
+/// which is also the offset of its data section
+pub fn header_len(ndims: usize) -> usize {
+    48 + 8 * ndims
+}
+
+/// Write a header with the given fields to the start of `buf` and return
+/// its length.
+pub fn write_header(
+    buf: &mut [u8],
+    flags: u64,
+    eltype: u64,
+    elbyte: u64,
+    size: u64,
+    dims: &[u64],
+) -> Result<usize, Error> {
+    let n = header_len(dims.len());
+    if buf.len() < n {
+        return Err(Error::Truncated {
+            needed: n,
+            len: buf.len(),
+        });
+    }
+    let fields = [MAGIC_NUMBER, flags, eltype, elbyte, size, dims.len() as u64];
+    let words = fields.iter().chain(dims.iter());
+    for (chunk, word) in buf[..n].chunks_exact_mut(8).zip(words) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+    Ok(n)
+}
+
+/// Header fields of a RawArray, without the data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Format flags, the `FLAG_` constants
+    pub flags: u64,
+    /// Element type code
+    pub eltype: u64,
+    /// Width of one element in bytes
+    pub elbyte: u64,
+    /// Length of the decoded data section in bytes
+    pub size: u64,
+    /// Array dimensions, first one fastest varying
+    pub dims: Vec<u64>,
+}
+
+fn word(buf: &[u8], i: usize) -> Result<u64, Error> {
+    let end = 8 * (i + 1);
+    let bytes = buf.get(end - 8..end).ok_or(Error::Truncated {
+        needed: end,
+        len: buf.len(),
+    })?;
+    let mut w = [0u8; 8];
+    w.copy_from_slice(bytes);
+    Ok(u64::from_le_bytes(w))
+}
+
+impl Header {
+    /// Parse a header from the start of `buf`, ignoring what follows it.
+    pub fn parse(buf: &[u8]) -> Result<Header, Error> {
+        if word(buf, 0)? != MAGIC_NUMBER {
+            return Err(Error::BadMagic);
+        }
+        let ndims = word(buf, 5)?;
+        if ndims > MAX_NDIMS {
+            return Err(Error::TooManyDims(ndims));
+        }
+        let dims = (0..ndims as usize)
+            .map(|i| word(buf, 6 + i))
+            .collect::<Result<Vec<u64>, Error>>()?;
+        Ok(Header {
+            flags: word(buf, 1)?,
+            eltype: word(buf, 2)?,
+            elbyte: word(buf, 3)?,
+            size: word(buf, 4)?,
+            dims,
+        })
+    }
+
+    /// Write the header to the start of `buf` and return its length.
+    pub fn write_to_buf(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        write_header(
+            buf,
+            self.flags,
+            self.eltype,
+            self.elbyte,
+            self.size,
+            &self.dims,
+        )
+    }
+
+    /// Length of the encoded header in bytes
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        header_len(self.dims.len())
+    }
+
+    /// Number of elements, or `None` if the dimensions are too large for
+    /// their product to fit in a `u64`, as they can be in a corrupt buffer.
+    pub fn nelem(&self) -> Option<u64> {
+        self.dims.iter().try_fold(1u64, |n, &d| n.checked_mul(d))
+    }
+}
+
+/// Split a buffer holding a whole array into its header and its data
+/// section, the elements as they are stored.
+/// ```
+/// use rawarray_core::{parse, Error};
+/// assert_eq!(parse(b"not an array, but long enough"), Err(Error::BadMagic));
+/// ```
+pub fn parse(buf: &[u8]) -> Result<(Header, &[u8]), Error> {
+    let header = Header::parse(buf)?;
+    if header.flags & CODED_FLAGS != 0 {
+        return Err(Error::Unsupported(header.flags & CODED_FLAGS));
+    }
+    let start = header.len();
+    let end = usize::try_from(header.size)
+        .ok()
+        .and_then(|size| start.checked_add(size))
+        .unwrap_or(usize::MAX);
+    match buf.get(start..end) {
+        Some(data) => Ok((header, data)),
+        None => Err(Error::Truncated {
+            needed: end,
+            len: buf.len(),
+        }),
+    }
+}
+
+/// Append a whole array to `out`: `header`, then `data`, the elements in
+/// the byte order its flags give.
+pub fn emit(header: &Header, data: &[u8], out: &mut Vec<u8>) -> Result<(), Error> {
+    let extra = header.flags & !(FLAG_BIG_ENDIAN | FLAG_BITS);
+    if extra != 0 {
+        return Err(Error::Unsupported(extra));
+    }
+    if header.size != data.len() as u64 {
+        return Err(Error::SizeMismatch {
+            header: header.size,
+            data: data.len(),
+        });
+    }
+    let at = out.len();
+    out.resize(at + header.len(), 0);
+    header.write_to_buf(&mut out[at..])?;
+    out.extend_from_slice(data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn header(flags: u64, size: u64, dims: Vec<u64>) -> Header {
+        Header {
+            flags,
+            eltype: 2,
+            elbyte: 1,
+            size,
+            dims,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let h = header(FLAG_BIG_ENDIAN, 4, vec![2, 2]);
+        let mut buf = Vec::new();
+        emit(&h, &[1, 2, 3, 4], &mut buf).unwrap();
+        assert_eq!(buf.len(), h.len() + 4);
+        buf.extend_from_slice(b"volatile");
+        assert_eq!(parse(&buf).unwrap(), (h, &[1u8, 2, 3, 4][..]));
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        let mut buf = Vec::new();
+        emit(&header(0, 2, vec![2]), &[1, 2], &mut buf).unwrap();
+        assert_eq!(
+            parse(&buf[..buf.len() - 1]),
+            Err(Error::Truncated {
+                needed: buf.len(),
+                len: buf.len() - 1
+            })
+        );
+        assert_eq!(
+            Header::parse(&buf[..20]),
+            Err(Error::Truncated {
+                needed: 48,
+                len: 20
+            })
+        );
+
+        let mut bad = buf.clone();
+        bad[0] ^= 1;
+        assert_eq!(parse(&bad), Err(Error::BadMagic));
+
+        let mut bad = buf.clone();
+        bad[40..48].copy_from_slice(&(MAX_NDIMS + 1).to_le_bytes());
+        assert_eq!(parse(&bad), Err(Error::TooManyDims(MAX_NDIMS + 1)));
+
+        let mut bad = buf.clone();
+        bad[8..16].copy_from_slice(&FLAG_ZSTD.to_le_bytes());
+        assert_eq!(parse(&bad), Err(Error::Unsupported(FLAG_ZSTD)));
+
+        let mut bad = buf;
+        bad[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(parse(&bad), Err(Error::Truncated { .. })));
+    }
+
+    #[test]
+    fn emit_checks_flags_and_size() {
+        let mut buf = Vec::new();
+        assert_eq!(
+            emit(&header(FLAG_CRC, 2, vec![2]), &[1, 2], &mut buf),
+            Err(Error::Unsupported(FLAG_CRC))
+        );
+        assert_eq!(
+            emit(&header(0, 3, vec![3]), &[1, 2], &mut buf),
+            Err(Error::SizeMismatch { header: 3, data: 2 })
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn write_header_needs_room() {
+        let mut buf = [0u8; 55];
+        assert_eq!(
+            write_header(&mut buf, 0, 2, 1, 1, &[1]),
+            Err(Error::Truncated {
+                needed: 56,
+                len: 55
+            })
+        );
+        let mut buf = [0u8; 56];
+        assert_eq!(write_header(&mut buf, 0, 2, 1, 1, &[1]), Ok(56));
+        assert_eq!(Header::parse(&buf).unwrap(), header(0, 1, vec![1]));
+    }
+
+    #[test]
+    fn nelem_is_checked() {
+        assert_eq!(header(0, 0, vec![2, 3]).nelem(), Some(6));
+        assert_eq!(header(0, 0, vec![]).nelem(), Some(1));
+        assert_eq!(header(0, 0, vec![1 << 32, 1 << 32]).nelem(), None);
+    }
+}
@@ -0,0 +1,194 @@
+//! Conversion between arrays and Apache Arrow arrays and record batches,
+//! behind the `arrow` feature, so array data can go to DataFusion, Polars,
+//! and other Arrow-based tools without passing through text.
+//!
+//! Tables follow the convention of the `csv` module: the first dimension
+//! runs down the rows and the second across the columns, so each column of
+//! a `[rows, columns]` array is contiguous in memory and becomes one Arrow
+//! column. A one-dimensional array is a single column, and a record batch
+//! with one column gives a one-dimensional array. Arrow arrays with nulls
+//! can't be converted, since arrays have no way to mark missing elements.
+
+use crate::convert::swap_bytes;
+use crate::{as_u8_slice_mut, DType, Endianness, RawArray, RawArrayType};
+use arrow_array::types::{
+    Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type, UInt32Type,
+    UInt64Type, UInt8Type,
+};
+use arrow_array::{
+    Array, ArrayRef, ArrowNativeTypeOp, ArrowPrimitiveType, PrimitiveArray, RecordBatch,
+};
+use arrow_schema::{Field, Schema};
+use std::io::{self, Error, ErrorKind};
+use std::sync::Arc;
+
+/// Element types with an Arrow primitive type of the same layout.
+pub trait ArrowElement: RawArrayType + ArrowNativeTypeOp {
+    /// The Arrow type whose values are `Self`
+    type ArrowType: ArrowPrimitiveType<Native = Self>;
+}
+
+macro_rules! arrow_element {
+    ($($t:ty => $a:ty),*) => {
+        $(
+            impl ArrowElement for $t {
+                type ArrowType = $a;
+            }
+        )*
+    };
+}
+
+arrow_element!(
+    i8 => Int8Type,
+    i16 => Int16Type,
+    i32 => Int32Type,
+    i64 => Int64Type,
+    u8 => UInt8Type,
+    u16 => UInt16Type,
+    u32 => UInt32Type,
+    u64 => UInt64Type,
+    f32 => Float32Type,
+    f64 => Float64Type
+);
+
+fn invalid_input(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+fn check_no_nulls(a: &dyn Array) -> io::Result<()> {
+    if a.null_count() > 0 {
+        return Err(invalid_input(format!(
+            "Arrow array has {} nulls, which arrays can't hold",
+            a.null_count()
+        )));
+    }
+    Ok(())
+}
+
+impl<T: ArrowElement> RawArray<T> {
+    /// The data in the machine's byte order, whatever order it's held in
+    fn native_data(&self) -> io::Result<Vec<T>> {
+        let mut data = self.data.clone();
+        if self.endianness() != Endianness::native() {
+            swap_bytes(as_u8_slice_mut(&mut data), DType::of::<T>())?;
+        }
+        Ok(data)
+    }
+
+    /// Copy a one-dimensional array into an Arrow array. Fails with
+    /// `ErrorKind::InvalidInput` for arrays of more dimensions.
+    /// ```
+    /// # use std::io;
+    /// use arrow_array::Array;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let ra: RawArray<f32> = vec![1.0, 2.5, -3.0].into();
+    /// let a = ra.to_arrow()?;
+    /// assert_eq!(a.len(), 3);
+    /// assert_eq!(a.value(1), 2.5);
+    /// assert_eq!(RawArray::from_arrow(&a)?, ra);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_arrow(&self) -> io::Result<PrimitiveArray<T::ArrowType>> {
+        if self.dims.len() != 1 {
+            return Err(invalid_input(format!(
+                "only one-dimensional arrays convert to Arrow arrays, not {}-dimensional ones",
+                self.dims.len()
+            )));
+        }
+        Ok(PrimitiveArray::from_iter_values(self.native_data()?))
+    }
+
+    /// Copy an Arrow array into a one-dimensional array. Fails with
+    /// `ErrorKind::InvalidInput` if it has nulls.
+    pub fn from_arrow(a: &PrimitiveArray<T::ArrowType>) -> io::Result<RawArray<T>> {
+        check_no_nulls(a)?;
+        Ok(a.values().to_vec().into())
+    }
+
+    /// Copy a one- or two-dimensional array into a record batch with a
+    /// column for each index of the second dimension. Columns are named
+    /// `names`, or by their indices if `names` is `None`. Fails with
+    /// `ErrorKind::InvalidInput` for arrays of more dimensions or the wrong
+    /// number of names.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// // three rows of two columns
+    /// let ra = RawArray::from_iter_with_dims(vec![1i64, 2, 3, 10, 20, 30], &[3, 2])?;
+    /// let batch = ra.to_record_batch(Some(&["x", "y"]))?;
+    /// assert_eq!((batch.num_rows(), batch.num_columns()), (3, 2));
+    /// assert_eq!(batch.schema().field(1).name(), "y");
+    /// assert_eq!(RawArray::<i64>::from_record_batch(&batch)?, ra);
+    ///
+    /// let unnamed = ra.to_record_batch(None)?;
+    /// assert_eq!(unnamed.schema().field(1).name(), "1");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_record_batch(&self, names: Option<&[&str]>) -> io::Result<RecordBatch> {
+        let (rows, cols) = match self.dims[..] {
+            [rows] => (rows as usize, 1),
+            [rows, cols] => (rows as usize, cols as usize),
+            _ => {
+                return Err(invalid_input(format!(
+                    "{}-dimensional arrays don't convert to record batches",
+                    self.dims.len()
+                )))
+            }
+        };
+        if let Some(names) = names {
+            if names.len() != cols {
+                return Err(invalid_input(format!(
+                    "{} column names given for {} columns",
+                    names.len(),
+                    cols
+                )));
+            }
+        }
+        let data = self.native_data()?;
+        let mut fields = Vec::with_capacity(cols);
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(cols);
+        for j in 0..cols {
+            let name = names.map_or_else(|| j.to_string(), |n| n[j].to_string());
+            fields.push(Field::new(name, T::ArrowType::DATA_TYPE, false));
+            let column = data[j * rows..(j + 1) * rows].iter().copied();
+            columns.push(Arc::new(PrimitiveArray::<T::ArrowType>::from_iter_values(
+                column,
+            )));
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+            .map_err(|e| invalid_input(e.to_string()))
+    }
+
+    /// Copy a record batch whose columns all hold `T`s into an array of
+    /// dimensions `[rows, columns]`, or `[rows]` if there's one column.
+    /// Column names aren't kept. Fails with `ErrorKind::InvalidInput` if a
+    /// column has another type or has nulls.
+    pub fn from_record_batch(batch: &RecordBatch) -> io::Result<RawArray<T>> {
+        let rows = batch.num_rows();
+        let mut data = Vec::with_capacity(rows * batch.num_columns());
+        for (field, column) in batch.schema().fields().iter().zip(batch.columns()) {
+            let column = column
+                .as_any()
+                .downcast_ref::<PrimitiveArray<T::ArrowType>>()
+                .ok_or_else(|| {
+                    invalid_input(format!(
+                        "column {} holds {}, not {}",
+                        field.name(),
+                        column.data_type(),
+                        T::ArrowType::DATA_TYPE
+                    ))
+                })?;
+            check_no_nulls(column)?;
+            data.extend_from_slice(&column.values()[..]);
+        }
+        let dims = match batch.num_columns() {
+            1 => vec![rows as u64],
+            cols => vec![rows as u64, cols as u64],
+        };
+        RawArray::from_iter_with_dims(data, &dims)
+    }
+}
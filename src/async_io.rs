@@ -0,0 +1,143 @@
+//! Reading and writing with tokio's `AsyncRead` and `AsyncWrite`, behind
+//! the `async` feature.
+
+use crate::provenance::attrs_to_write;
+use crate::{as_u8_slice, as_u8_slice_mut, codec, RawArray, RawArrayType};
+use crate::{check_max_bytes, invalid, ReadOptions, ReadReport, FLAG_ATTRS, FLAG_CRC, MAX_NDIMS};
+use std::io::{self, Error, ErrorKind};
+use std::mem;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+async fn read_u64<R: AsyncRead + Unpin>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf).await?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Read `n` elements, growing the vector as they arrive like `read_vec`,
+/// so a header can't make us allocate much more than the reader holds.
+async fn read_vec<T: RawArrayType, R: AsyncRead + Unpin>(
+    r: &mut R,
+    n: usize,
+) -> io::Result<Vec<T>> {
+    let first = (1 << 20) / mem::size_of::<T>().max(1);
+    let mut data: Vec<T> = Vec::new();
+    while data.len() < n {
+        let start = data.len();
+        let end = n.min(start + start.max(first));
+        data.resize(end, unsafe { mem::zeroed::<T>() });
+        r.read_exact(as_u8_slice_mut(&mut data[start..])).await?;
+    }
+    Ok(data)
+}
+
+/// Read exactly `len` bytes, or fail if the reader ends first.
+async fn read_bytes<R: AsyncRead + Unpin>(r: &mut R, len: u64, what: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    r.take(len).read_to_end(&mut bytes).await?;
+    if bytes.len() as u64 != len {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("{} is truncated", what),
+        ));
+    }
+    Ok(bytes)
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Read a `RawArray<T>` from an async reader, such as a
+    /// `tokio::fs::File` or a socket, without blocking the runtime. Like
+    /// `read_from`, exactly one array is consumed, and arrays larger than
+    /// `DEFAULT_MAX_BYTES` are refused.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> io::Result<()> {
+    /// let ra: RawArray<f32> = vec![1.0, 2.0, 3.0].into();
+    /// let mut buf = Vec::new();
+    /// ra.write_async(&mut buf).await?;
+    /// assert_eq!(RawArray::<f32>::read_async(&mut buf.as_slice()).await?, ra);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn read_async<R: AsyncRead + Unpin>(r: &mut R) -> io::Result<RawArray<T>> {
+        Ok(RawArray::read_async_with(r, &ReadOptions::new()).await?.0)
+    }
+
+    /// Read a `RawArray<T>` from an async reader like `read_async`,
+    /// applying the size policy and byte limit of `opts`, as
+    /// `read_from_with` does. Data is allocated as it arrives, so a
+    /// header can't make us allocate much more than the reader holds.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{RawArray, ReadOptions};
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> io::Result<()> {
+    /// let ra: RawArray<u8> = vec![0; 1 << 16].into();
+    /// let mut buf = Vec::new();
+    /// ra.write_async(&mut buf).await?;
+    /// let opts = ReadOptions::new().max_bytes(1 << 10);
+    /// assert!(RawArray::<u8>::read_async_with(&mut buf.as_slice(), &opts).await.is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn read_async_with<R: AsyncRead + Unpin>(
+        r: &mut R,
+        opts: &ReadOptions,
+    ) -> io::Result<(RawArray<T>, ReadReport)> {
+        let mut header = vec![0u8; 48];
+        r.read_exact(&mut header).await?;
+        let mut ndims = [0u8; 8];
+        ndims.copy_from_slice(&header[40..48]);
+        let ndims = u64::from_le_bytes(ndims);
+        if ndims > MAX_NDIMS {
+            return Err(invalid(format!(
+                "header claims {} dimensions, more than the limit of {}",
+                ndims, MAX_NDIMS
+            )));
+        }
+        header.resize(48 + 8 * ndims as usize, 0);
+        r.read_exact(&mut header[48..]).await?;
+        let mut ra = RawArray::default();
+        let report =
+            ra.read_header_with(&mut header.as_slice(), opts.size_policy, opts.max_bytes)?;
+
+        let data = if let Some((id, id_stored)) = codec::codec_for_flags(ra.flags) {
+            let id = if id_stored { read_u64(r).await? } else { id };
+            let len = read_u64(r).await?;
+            check_max_bytes(Some(len), opts.max_bytes)?;
+            let payload = read_bytes(r, len, "encoded data section").await?;
+            ra.decode_payload(id, &payload)?
+        } else {
+            read_vec(r, ra.nelem_in_file()?).await?
+        };
+
+        // the checksum and attributes are small, so gather them and parse
+        // them synchronously
+        let mut trailer = Vec::new();
+        if ra.flags & FLAG_CRC != 0 {
+            trailer.extend_from_slice(&read_u64(r).await?.to_le_bytes());
+        }
+        if ra.flags & FLAG_ATTRS != 0 {
+            let len = read_u64(r).await?;
+            trailer.extend_from_slice(&len.to_le_bytes());
+            trailer.extend_from_slice(&read_bytes(r, len, "attribute section").await?);
+        }
+        ra.finish_data(data, &mut trailer.as_slice())?;
+        Ok((ra, report))
+    }
+
+    /// Write a `RawArray<T>` to an async writer and flush it.
+    pub async fn write_async<W: AsyncWrite + Unpin>(&self, w: &mut W) -> io::Result<()> {
+        let attrs = attrs_to_write(&self.attrs);
+        let mut header = Vec::new();
+        self.write_header(&mut header, self.header_flags(&attrs))?;
+        w.write_all(&header).await?;
+        w.write_all(as_u8_slice(&self.data)).await?;
+        let mut trailer = Vec::new();
+        self.write_trailer(&mut trailer, &attrs)?;
+        w.write_all(&trailer).await?;
+        w.flush().await
+    }
+}
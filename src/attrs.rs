@@ -0,0 +1,281 @@
+//! Named attributes stored with an array, like units and acquisition
+//! parameters.
+//!
+//! When `FLAG_ATTRS` is set, an attribute block follows the data section
+//! (and its checksum, if any):
+//!
+//! | object  | type   | meaning
+//! |---------|--------|--------
+//! | length  | UInt64 | length in bytes of the rest of the block
+//! | count   | UInt64 | number of attributes
+//! | entries |        | `count` entries, each as below
+//!
+//! Each entry is a `UInt64` key length, the UTF-8 key, a `UInt64` type tag,
+//! and the value: 8 bytes for the numeric types, or a `UInt64` length and
+//! then the UTF-8 bytes of a string or the raw bytes of a blob.
+
+use crate::codec::codec_for_flags;
+use crate::header::RawArrayHeader;
+use crate::storage::{FsFile, OpenMode};
+use crate::{RawArray, RawArrayType, FLAG_ATTRS, FLAG_CRC};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const TAG_INT: u64 = 1;
+const TAG_UINT: u64 = 2;
+const TAG_FLOAT: u64 = 3;
+const TAG_STR: u64 = 4;
+const TAG_BYTES: u64 = 5;
+
+/// Value of a named attribute.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttrValue {
+    /// Signed integer
+    Int(i64),
+    /// Unsigned integer
+    UInt(u64),
+    /// Floating point number
+    Float(f64),
+    /// UTF-8 string
+    Str(String),
+    /// Opaque bytes
+    Bytes(Vec<u8>),
+}
+
+impl AttrValue {
+    /// The value as an `f64`, if it is numeric.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            AttrValue::Int(i) => Some(i as f64),
+            AttrValue::UInt(u) => Some(u as f64),
+            AttrValue::Float(f) => Some(f),
+            AttrValue::Str(_) | AttrValue::Bytes(_) => None,
+        }
+    }
+
+    /// The value as an `i64`, if it is an integer that fits.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            AttrValue::Int(i) => Some(i),
+            AttrValue::UInt(u) if u <= i64::MAX as u64 => Some(u as i64),
+            _ => None,
+        }
+    }
+
+    /// The value as a string slice, if it is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AttrValue::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value as a byte slice, if it is a blob.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            AttrValue::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AttrValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AttrValue::Int(i) => write!(f, "{}", i),
+            AttrValue::UInt(u) => write!(f, "{}", u),
+            AttrValue::Float(x) => write!(f, "{}", x),
+            AttrValue::Str(s) => write!(f, "{:?}", s),
+            AttrValue::Bytes(b) => write!(f, "<{} bytes>", b.len()),
+        }
+    }
+}
+
+macro_rules! attr_from {
+    ($variant:ident, $target:ty, $($t:ty),*) => {
+        $(
+            impl From<$t> for AttrValue {
+                fn from(x: $t) -> AttrValue {
+                    AttrValue::$variant(<$target>::from(x))
+                }
+            }
+        )*
+    };
+}
+
+attr_from!(Int, i64, i8, i16, i32, i64);
+attr_from!(UInt, u64, u8, u16, u32, u64);
+attr_from!(Float, f64, f32, f64);
+attr_from!(Str, String, &str, String);
+attr_from!(Bytes, Vec<u8>, &[u8], Vec<u8>);
+
+fn invalid(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("attribute block {}", msg))
+}
+
+/// Take `n` bytes off the front of `buf`.
+fn take<'a>(buf: &mut &'a [u8], n: u64) -> io::Result<&'a [u8]> {
+    if n > buf.len() as u64 {
+        return Err(invalid("is truncated"));
+    }
+    let (head, tail) = buf.split_at(n as usize);
+    *buf = tail;
+    Ok(head)
+}
+
+fn take_u64(buf: &mut &[u8]) -> io::Result<u64> {
+    let mut b = [0u8; 8];
+    b.copy_from_slice(take(buf, 8)?);
+    Ok(u64::from_le_bytes(b))
+}
+
+fn take_string(buf: &mut &[u8]) -> io::Result<String> {
+    let len = take_u64(buf)?;
+    String::from_utf8(take(buf, len)?.to_vec()).map_err(|_| invalid("has invalid UTF-8"))
+}
+
+pub(crate) fn read_attrs<R: Read>(r: &mut R) -> io::Result<BTreeMap<String, AttrValue>> {
+    let mut len = [0u8; 8];
+    r.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+    let mut block = Vec::new();
+    r.take(len).read_to_end(&mut block)?;
+    if (block.len() as u64) < len {
+        return Err(invalid("is truncated"));
+    }
+    let mut buf = &block[..];
+    let mut attrs = BTreeMap::new();
+    let count = take_u64(&mut buf)?;
+    for _ in 0..count {
+        let key = take_string(&mut buf)?;
+        let value = match take_u64(&mut buf)? {
+            TAG_INT => AttrValue::Int(take_u64(&mut buf)? as i64),
+            TAG_UINT => AttrValue::UInt(take_u64(&mut buf)?),
+            TAG_FLOAT => AttrValue::Float(f64::from_bits(take_u64(&mut buf)?)),
+            TAG_STR => AttrValue::Str(take_string(&mut buf)?),
+            TAG_BYTES => {
+                let len = take_u64(&mut buf)?;
+                AttrValue::Bytes(take(&mut buf, len)?.to_vec())
+            }
+            _ => return Err(invalid("has an unknown value type")),
+        };
+        attrs.insert(key, value);
+    }
+    if !buf.is_empty() {
+        return Err(invalid("is longer than its entries"));
+    }
+    Ok(attrs)
+}
+
+fn push_u64(buf: &mut Vec<u8>, n: u64) {
+    buf.extend_from_slice(&n.to_le_bytes());
+}
+
+fn push_str(buf: &mut Vec<u8>, s: &str) {
+    push_u64(buf, s.len() as u64);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Read the attributes of a file without reading its data, which needn't
+/// be of a known type.
+pub(crate) fn read_file_attrs(path: &Path) -> io::Result<BTreeMap<String, AttrValue>> {
+    let mut r = BufReader::new(FsFile::open_fs(path, OpenMode::Read)?);
+    let h = RawArrayHeader::read_from(&mut r)?;
+    if h.flags & FLAG_ATTRS == 0 {
+        return Ok(BTreeMap::new());
+    }
+    let mut word = [0u8; 8];
+    let mut len = h.size;
+    if let Some((_, id_stored)) = codec_for_flags(h.flags) {
+        if id_stored {
+            r.read_exact(&mut word)?;
+        }
+        r.read_exact(&mut word)?;
+        len = u64::from_le_bytes(word);
+    }
+    if h.flags & FLAG_CRC != 0 {
+        len = len
+            .checked_add(8)
+            .ok_or_else(|| invalid("is past the end"))?;
+    }
+    let len = i64::try_from(len).map_err(|_| invalid("is past the end"))?;
+    r.seek(SeekFrom::Current(len))?;
+    read_attrs(&mut r)
+}
+
+pub(crate) fn write_attrs<W: Write>(
+    w: &mut W,
+    attrs: &BTreeMap<String, AttrValue>,
+) -> io::Result<()> {
+    let mut block = Vec::new();
+    push_u64(&mut block, attrs.len() as u64);
+    for (key, value) in attrs {
+        push_str(&mut block, key);
+        match value {
+            AttrValue::Int(i) => {
+                push_u64(&mut block, TAG_INT);
+                push_u64(&mut block, *i as u64);
+            }
+            AttrValue::UInt(u) => {
+                push_u64(&mut block, TAG_UINT);
+                push_u64(&mut block, *u);
+            }
+            AttrValue::Float(f) => {
+                push_u64(&mut block, TAG_FLOAT);
+                push_u64(&mut block, f.to_bits());
+            }
+            AttrValue::Str(s) => {
+                push_u64(&mut block, TAG_STR);
+                push_str(&mut block, s);
+            }
+            AttrValue::Bytes(b) => {
+                push_u64(&mut block, TAG_BYTES);
+                push_u64(&mut block, b.len() as u64);
+                block.extend_from_slice(b);
+            }
+        }
+    }
+    w.write_all(&(block.len() as u64).to_le_bytes())?;
+    w.write_all(&block)
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Set a named attribute, replacing any previous value. Attributes are
+    /// saved by `write()` and restored by `read()`.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{AttrValue, RawArray};
+    /// # fn main() -> io::Result<()> {
+    /// let mut ra: RawArray<f32> = vec![0.0; 8].into();
+    /// ra.set_attr("voxel_size", 1.5);
+    /// ra.set_attr("units", "mm");
+    /// ra.write("attrs.ra")?;
+    /// let ra = RawArray::<f32>::read("attrs.ra")?;
+    /// assert_eq!(ra.get_attr("voxel_size"), Some(&AttrValue::Float(1.5)));
+    /// assert_eq!(ra.get_attr("units").and_then(|v| v.as_str()), Some("mm"));
+    /// # std::fs::remove_file("attrs.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_attr<V: Into<AttrValue>>(&mut self, key: &str, value: V) {
+        self.attrs.insert(key.to_string(), value.into());
+    }
+
+    /// Look up a named attribute.
+    pub fn get_attr(&self, key: &str) -> Option<&AttrValue> {
+        self.attrs.get(key)
+    }
+
+    /// Remove a named attribute, returning its value if it was set.
+    pub fn remove_attr(&mut self, key: &str) -> Option<AttrValue> {
+        self.attrs.remove(key)
+    }
+
+    /// All attributes, sorted by name.
+    pub fn attrs(&self) -> &BTreeMap<String, AttrValue> {
+        &self.attrs
+    }
+}
@@ -0,0 +1,526 @@
+//! Operations along a single axis of a `RawArray`.
+//!
+//! RawArrays are column major, so for a given axis the data can be viewed
+//! as `outer` contiguous blocks, each holding `dims[axis]` runs of `inner`
+//! elements. Everything in here is built on that decomposition.
+
+use crate::{RawArray, RawArrayType};
+use std::io::{self, Error, ErrorKind};
+use std::ops::{Add, Mul, Range};
+
+/// Return `(inner, outer)`: the number of elements in one step along
+/// `axis`, and the number of blocks spanned by the axes after it.
+pub(crate) fn axis_strides(dims: &[u64], axis: usize) -> (usize, usize) {
+    let inner: u64 = dims[..axis].iter().product();
+    let outer: u64 = dims[axis + 1..].iter().product();
+    (inner as usize, outer as usize)
+}
+
+/// Copy out the elements with indices `start..start + len` along `axis`.
+pub(crate) fn take_axis_range<T: Copy>(
+    data: &[T],
+    dims: &[u64],
+    axis: usize,
+    start: usize,
+    len: usize,
+) -> Vec<T> {
+    let (inner, outer) = axis_strides(dims, axis);
+    let n = dims[axis] as usize;
+    let mut out = Vec::with_capacity(inner * len * outer);
+    for o in 0..outer {
+        let begin = inner * (start + n * o);
+        out.extend_from_slice(&data[begin..begin + inner * len]);
+    }
+    out
+}
+
+/// Gather the hyperslices at `indices` along `axis`, in the given order.
+#[cfg_attr(not(feature = "rand"), allow(dead_code))]
+pub(crate) fn take_axis_indices<T: Copy>(
+    data: &[T],
+    dims: &[u64],
+    axis: usize,
+    indices: &[usize],
+) -> Vec<T> {
+    let (inner, outer) = axis_strides(dims, axis);
+    let n = dims[axis] as usize;
+    let mut out = Vec::with_capacity(inner * indices.len() * outer);
+    for o in 0..outer {
+        for &j in indices {
+            let begin = inner * (j + n * o);
+            out.extend_from_slice(&data[begin..begin + inner]);
+        }
+    }
+    out
+}
+
+/// Iterator over windows along one axis of a `RawArray`.
+///
+/// Created by [`RawArray::windows`].
+pub struct Windows<'a, T: RawArrayType> {
+    array: &'a RawArray<T>,
+    axis: usize,
+    size: u64,
+    stride: u64,
+    start: u64,
+}
+
+impl<'a, T: RawArrayType> Iterator for Windows<'a, T> {
+    type Item = RawArray<T>;
+
+    fn next(&mut self) -> Option<RawArray<T>> {
+        if self.start + self.size > self.array.dims[self.axis] {
+            return None;
+        }
+        let data = take_axis_range(
+            &self.array.data,
+            &self.array.dims,
+            self.axis,
+            self.start as usize,
+            self.size as usize,
+        );
+        let mut dims = self.array.dims.clone();
+        dims[self.axis] = self.size;
+        self.start += self.stride;
+        Some(RawArray::from_parts(dims, data))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.array.dims[self.axis];
+        let remaining = if self.start + self.size > n {
+            0
+        } else {
+            ((n - self.size - self.start) / self.stride + 1) as usize
+        };
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T: RawArrayType> ExactSizeIterator for Windows<'a, T> {}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Iterate over windows of `size` elements along `axis`, advancing
+    /// `stride` elements between windows. Each window is a new `RawArray<T>`
+    /// with the same dimensions except along `axis`. Trailing elements that
+    /// don't fill a whole window are skipped.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<u8> = vec![0, 1, 2, 3, 4, 5, 6, 7].into();
+    /// r.reshape(vec![2, 4]);
+    /// let w: Vec<RawArray<u8>> = r.windows(1, 2, 1).collect();
+    /// assert_eq!(w.len(), 3);
+    /// assert_eq!(w[1].dims(), vec![2, 2]);
+    /// assert_eq!(w[1].data(), vec![2, 3, 4, 5]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range or `size` or `stride` is zero.
+    pub fn windows(&self, axis: usize, size: u64, stride: u64) -> Windows<'_, T> {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        assert!(
+            size > 0 && stride > 0,
+            "window size and stride must be nonzero"
+        );
+        Windows {
+            array: self,
+            axis,
+            size,
+            stride,
+            start: 0,
+        }
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Copy out the sub-block spanning `ranges`, one range per dimension.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims(0..24u8, &[4, 3, 2]).unwrap();
+    /// let crop = ra.slice(&[1..3, 0..2, 1..2]);
+    /// assert_eq!(crop.dims(), vec![2, 2, 1]);
+    /// assert_eq!(crop.data(), vec![13, 14, 17, 18]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if there isn't one range per dimension or a range runs past
+    /// the end of its dimension.
+    pub fn slice(&self, ranges: &[Range<u64>]) -> RawArray<T> {
+        assert_eq!(
+            ranges.len(),
+            self.dims.len(),
+            "need one range per dimension"
+        );
+        for (axis, (r, &n)) in ranges.iter().zip(&self.dims).enumerate() {
+            assert!(
+                r.start <= r.end && r.end <= n,
+                "range {:?} out of bounds for axis {} of length {}",
+                r,
+                axis,
+                n
+            );
+        }
+        let dims: Vec<u64> = ranges.iter().map(|r| r.end - r.start).collect();
+        let nelem = dims.iter().product::<u64>() as usize;
+        let mut data = Vec::with_capacity(nelem);
+        if nelem > 0 {
+            // copy runs along the first axis, stepping an odometer over
+            // the coordinates of the remaining axes
+            let run = dims[0] as usize;
+            let mut coords: Vec<u64> = ranges.iter().map(|r| r.start).collect();
+            'runs: loop {
+                let mut offset = 0u64;
+                let mut stride = 1u64;
+                for (&c, &n) in coords.iter().zip(&self.dims) {
+                    offset += c * stride;
+                    stride *= n;
+                }
+                data.extend_from_slice(&self.data[offset as usize..offset as usize + run]);
+                for axis in 1..coords.len() {
+                    coords[axis] += 1;
+                    if coords[axis] < ranges[axis].end {
+                        continue 'runs;
+                    }
+                    coords[axis] = ranges[axis].start;
+                }
+                break;
+            }
+        }
+        let mut ra = RawArray::from_parts(dims, data);
+        ra.flags = self.flags;
+        ra
+    }
+
+    /// Copy out the hyperslices in `range` along `axis`, keeping the other
+    /// dimensions whole.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims(0..6u8, &[2, 3]).unwrap();
+    /// let s = ra.slice_axis(1, 1..3);
+    /// assert_eq!(s.dims(), vec![2, 2]);
+    /// assert_eq!(s.data(), vec![2, 3, 4, 5]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range or `range` runs past the end of it.
+    pub fn slice_axis(&self, axis: usize, range: Range<u64>) -> RawArray<T> {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        assert!(
+            range.start <= range.end && range.end <= self.dims[axis],
+            "range {:?} out of bounds for axis {} of length {}",
+            range,
+            axis,
+            self.dims[axis]
+        );
+        let len = range.end - range.start;
+        let data = take_axis_range(
+            &self.data,
+            &self.dims,
+            axis,
+            range.start as usize,
+            len as usize,
+        );
+        let mut dims = self.dims.clone();
+        dims[axis] = len;
+        let mut ra = RawArray::from_parts(dims, data);
+        ra.flags = self.flags;
+        ra
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Join `arrays` end to end along an existing `axis`. They must have
+    /// the same byte order and the same dimensions except along `axis`,
+    /// or this fails with `ErrorKind::InvalidInput`.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let a = RawArray::from_iter_with_dims(0..4u8, &[2, 2])?;
+    /// let b = RawArray::from_iter_with_dims(4..6u8, &[2, 1])?;
+    /// let ab = RawArray::concat(&[a.clone(), b], 1)?;
+    /// assert_eq!(ab.dims(), vec![2, 3]);
+    /// assert_eq!(ab.data(), vec![0, 1, 2, 3, 4, 5]);
+    /// assert!(RawArray::concat(&[a.clone(), ab], 0).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn concat(arrays: &[RawArray<T>], axis: usize) -> io::Result<RawArray<T>> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+        let first = arrays
+            .first()
+            .ok_or_else(|| invalid("no arrays to concatenate".to_string()))?;
+        if axis >= first.dims.len() {
+            return Err(invalid(format!("axis {} out of range", axis)));
+        }
+        let mut dims = first.dims.clone();
+        dims[axis] = 0;
+        for a in arrays {
+            let fits = a.dims.len() == dims.len()
+                && (0..dims.len()).all(|i| i == axis || a.dims[i] == dims[i]);
+            if !fits {
+                return Err(invalid(format!(
+                    "dims {:?} don't fit with {:?} along axis {}",
+                    a.dims, first.dims, axis
+                )));
+            }
+            if a.endianness() != first.endianness() {
+                return Err(invalid(
+                    "arrays to concatenate have different byte orders".to_string(),
+                ));
+            }
+            dims[axis] += a.dims[axis];
+        }
+        let (inner, outer) = axis_strides(&dims, axis);
+        let mut data = Vec::with_capacity(dims.iter().product::<u64>() as usize);
+        for o in 0..outer {
+            for a in arrays {
+                let block = inner * a.dims[axis] as usize;
+                data.extend_from_slice(&a.data[block * o..block * (o + 1)]);
+            }
+        }
+        let mut ra = RawArray::from_parts(dims, data);
+        ra.flags = first.flags;
+        Ok(ra)
+    }
+
+    /// Join arrays of identical dimensions along a new axis inserted at
+    /// `axis`, such as 2-D slices into a volume with `axis` 2.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let slices: Vec<RawArray<f32>> = (0..3)
+    ///     .map(|z| RawArray::from_iter_with_dims((0..4).map(|i| (10 * z + i) as f32), &[2, 2]))
+    ///     .collect::<io::Result<_>>()?;
+    /// let volume = RawArray::stack(&slices, 2)?;
+    /// assert_eq!(volume.dims(), vec![2, 2, 3]);
+    /// assert_eq!(volume.as_slice()[4..6], [10.0, 11.0]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stack(arrays: &[RawArray<T>], axis: usize) -> io::Result<RawArray<T>> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+        let first = arrays
+            .first()
+            .ok_or_else(|| invalid("no arrays to stack".to_string()))?;
+        if axis > first.dims.len() {
+            return Err(invalid(format!("axis {} out of range", axis)));
+        }
+        if let Some(a) = arrays.iter().find(|a| a.dims != first.dims) {
+            return Err(invalid(format!(
+                "can't stack arrays of dims {:?} and {:?}",
+                first.dims, a.dims
+            )));
+        }
+        if let Some(a) = arrays.iter().find(|a| a.endianness() != first.endianness()) {
+            return Err(invalid(format!(
+                "can't stack arrays in {:?} and {:?} byte order",
+                first.endianness(),
+                a.endianness()
+            )));
+        }
+        let mut dims = first.dims.clone();
+        dims.insert(axis, arrays.len() as u64);
+        let (inner, outer) = axis_strides(&dims, axis);
+        let mut data = Vec::with_capacity(first.data.len() * arrays.len());
+        for o in 0..outer {
+            for a in arrays {
+                data.extend_from_slice(&a.data[inner * o..inner * (o + 1)]);
+            }
+        }
+        let mut ra = RawArray::from_parts(dims, data);
+        ra.flags = first.flags;
+        Ok(ra)
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Reorder the axes, so that axis `i` of the result is axis `perm[i]`
+    /// of this array, copying the data into the new order.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims(0..24u8, &[2, 3, 4]).unwrap();
+    /// let p = ra.permute(&[2, 0, 1]);
+    /// assert_eq!(p.dims(), vec![4, 2, 3]);
+    /// assert_eq!(p[[3, 1, 2]], ra[[1, 2, 3]]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `perm` isn't a permutation of the axes.
+    pub fn permute(&self, perm: &[usize]) -> RawArray<T> {
+        let ndims = self.dims.len();
+        let mut seen = vec![false; ndims];
+        assert!(
+            perm.len() == ndims
+                && perm
+                    .iter()
+                    .all(|&a| a < ndims && !std::mem::replace(&mut seen[a], true)),
+            "{:?} is not a permutation of {} axes",
+            perm,
+            ndims
+        );
+        let mut strides = Vec::with_capacity(ndims);
+        let mut stride = 1usize;
+        for &n in &self.dims {
+            strides.push(stride);
+            stride *= n as usize;
+        }
+        let dims: Vec<u64> = perm.iter().map(|&a| self.dims[a]).collect();
+        let steps: Vec<usize> = perm.iter().map(|&a| strides[a]).collect();
+        let mut data = Vec::with_capacity(self.data.len());
+        if !self.data.is_empty() {
+            // walk the output in order, stepping the source offset along
+            let mut coords = vec![0u64; ndims];
+            let mut offset = 0usize;
+            'elems: loop {
+                data.push(self.data[offset]);
+                for i in 0..ndims {
+                    coords[i] += 1;
+                    offset += steps[i];
+                    if coords[i] < dims[i] {
+                        continue 'elems;
+                    }
+                    offset -= steps[i] * dims[i] as usize;
+                    coords[i] = 0;
+                }
+                break;
+            }
+        }
+        let mut ra = RawArray::from_parts(dims, data);
+        ra.flags = self.flags;
+        ra
+    }
+
+    /// Reverse the order of the axes, like numpy's `transpose`. For a
+    /// matrix this is the ordinary transpose.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let m = RawArray::from_iter_with_dims(0..6u8, &[2, 3]).unwrap();
+    /// let t = m.transpose();
+    /// assert_eq!(t.dims(), vec![3, 2]);
+    /// assert_eq!(t.data(), vec![0, 2, 4, 1, 3, 5]);
+    /// ```
+    pub fn transpose(&self) -> RawArray<T> {
+        let perm: Vec<usize> = (0..self.dims.len()).rev().collect();
+        self.permute(&perm)
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Circularly shift the elements along `axis` by `k` places in place,
+    /// like numpy's `roll`. Positive `k` moves elements toward higher
+    /// indices, and elements shifted off the end wrap around to the start.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<u8> = vec![0, 1, 2, 3, 4, 5].into();
+    /// r.reshape(vec![3, 2]);
+    /// r.roll(0, 1);
+    /// assert_eq!(r.data(), vec![2, 0, 1, 5, 3, 4]);
+    /// r.roll(1, -1);
+    /// assert_eq!(r.data(), vec![5, 3, 4, 2, 0, 1]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range.
+    pub fn roll(&mut self, axis: usize, k: i64) {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        let n = self.dims[axis] as i64;
+        if n == 0 {
+            return;
+        }
+        let (inner, _) = axis_strides(&self.dims, axis);
+        let shift = k.rem_euclid(n) as usize * inner;
+        if shift == 0 {
+            return;
+        }
+        for block in self.data.chunks_exact_mut(inner * n as usize) {
+            block.rotate_right(shift);
+        }
+    }
+
+    /// Move the zero-frequency element to the center of every axis, as
+    /// numpy's `fftshift` does. For odd lengths the center is at `n / 2`.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<i8> = vec![0, 1, 2, -2, -1].into();
+    /// r.fftshift();
+    /// assert_eq!(r.data(), vec![-2, -1, 0, 1, 2]);
+    /// r.ifftshift();
+    /// assert_eq!(r.data(), vec![0, 1, 2, -2, -1]);
+    /// ```
+    pub fn fftshift(&mut self) {
+        for axis in 0..self.dims.len() {
+            let n = self.dims[axis] as i64;
+            self.roll(axis, n / 2);
+        }
+    }
+
+    /// Undo `fftshift`, moving the zero-frequency element back to index 0.
+    pub fn ifftshift(&mut self) {
+        for axis in 0..self.dims.len() {
+            let n = self.dims[axis] as i64;
+            self.roll(axis, -(n / 2));
+        }
+    }
+}
+
+impl<T: RawArrayType + Mul<Output = T> + Add<Output = T>> RawArray<T> {
+    /// Multiply every element by the factor for its index along `axis`,
+    /// in place, such as density compensation weights along a readout.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<f32> = vec![1.0, 1.0, 2.0, 2.0].into();
+    /// r.reshape(vec![2, 2]);
+    /// r.scale_axis(0, &[0.5, 3.0]);
+    /// assert_eq!(r.data(), vec![0.5, 3.0, 1.0, 6.0]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range or there isn't one factor per
+    /// index along it.
+    pub fn scale_axis(&mut self, axis: usize, factors: &[T]) {
+        self.for_each_along(axis, factors.len(), |x, i| *x = *x * factors[i]);
+    }
+
+    /// Apply `x * scales[i] + offsets[i]` to every element, where `i` is its
+    /// index along `axis`, in place.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<i32> = vec![1, 2, 3, 4].into();
+    /// r.reshape(vec![2, 2]);
+    /// r.affine_axis(1, &[1, 10], &[0, -5]);
+    /// assert_eq!(r.data(), vec![1, 2, 25, 35]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range or there isn't one scale and one
+    /// offset per index along it.
+    pub fn affine_axis(&mut self, axis: usize, scales: &[T], offsets: &[T]) {
+        assert_eq!(scales.len(), offsets.len(), "need one offset per scale");
+        self.for_each_along(axis, scales.len(), |x, i| *x = *x * scales[i] + offsets[i]);
+    }
+
+    /// Call `f` on every element with its index along `axis`.
+    fn for_each_along(&mut self, axis: usize, len: usize, f: impl Fn(&mut T, usize)) {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        let n = self.dims[axis] as usize;
+        assert_eq!(len, n, "need one value per index along axis {}", axis);
+        let (inner, _) = axis_strides(&self.dims, axis);
+        if inner == 0 {
+            return;
+        }
+        for (k, run) in self.data.chunks_exact_mut(inner).enumerate() {
+            let i = k % n;
+            for x in run {
+                f(x, i);
+            }
+        }
+    }
+}
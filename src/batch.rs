@@ -0,0 +1,160 @@
+//! Reading and writing many files at once with a bounded pool of threads.
+
+use crate::{RawArray, RawArrayType};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// The per-file failures of a batch operation. Files not listed succeeded.
+#[derive(Debug)]
+pub struct BatchError {
+    /// Each path that failed, with its error, in input order
+    pub failures: Vec<(PathBuf, io::Error)>,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} file(s) failed", self.failures.len())?;
+        for (path, e) in &self.failures {
+            write!(f, "\n  {}: {}", path.display(), e)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for BatchError {}
+
+/// Run `job` on every index in `0..n` using up to `parallelism` threads,
+/// collecting the errors by index.
+fn run_pool<F>(n: usize, parallelism: usize, job: F) -> Vec<(usize, io::Error)>
+where
+    F: Fn(usize) -> io::Result<()> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let errors = Mutex::new(Vec::new());
+    thread::scope(|s| {
+        for _ in 0..parallelism.clamp(1, n.max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= n {
+                    break;
+                }
+                if let Err(e) = job(i) {
+                    errors.lock().unwrap().push((i, e));
+                }
+            });
+        }
+    });
+    let mut errors = errors.into_inner().unwrap();
+    errors.sort_by_key(|(i, _)| *i);
+    errors
+}
+
+/// The number of threads to use when the caller doesn't say
+fn default_parallelism() -> usize {
+    thread::available_parallelism().map_or(4, |n| n.get())
+}
+
+/// Read many files into arrays using a thread for each available core,
+/// for pipelines that load thousands of small files at a time. The results
+/// are in the order of `paths`, so one bad file doesn't lose the others.
+/// ```
+/// use rawarray::RawArray;
+/// # std::fs::create_dir_all("read_all_doc").unwrap();
+/// let paths: Vec<String> = (0..50).map(|i| format!("read_all_doc/{}.ra", i)).collect();
+/// for (i, path) in paths.iter().enumerate() {
+///     RawArray::from(vec![i as u16; 4]).write(path).unwrap();
+/// }
+/// let arrays = rawarray::batch::read_all::<u16, _>(&paths);
+/// assert_eq!(arrays[7].as_ref().unwrap().data(), vec![7; 4]);
+///
+/// let missing = rawarray::batch::read_all::<u16, _>(&["read_all_doc/none.ra"]);
+/// assert!(missing[0].is_err());
+/// # std::fs::remove_dir_all("read_all_doc").unwrap();
+/// ```
+pub fn read_all<T, P>(paths: &[P]) -> Vec<io::Result<RawArray<T>>>
+where
+    T: RawArrayType,
+    P: AsRef<Path> + Sync,
+{
+    let results: Vec<Mutex<Option<io::Result<RawArray<T>>>>> =
+        paths.iter().map(|_| Mutex::new(None)).collect();
+    run_pool(paths.len(), default_parallelism(), |i| {
+        *results[i].lock().unwrap() = Some(RawArray::read(paths[i].as_ref()));
+        Ok(())
+    });
+    results
+        .into_iter()
+        .map(|r| r.into_inner().unwrap().expect("every path was read"))
+        .collect()
+}
+
+/// Write many arrays to their own files using a thread for each available
+/// core, as `write_batch` does without taking ownership of the arrays.
+/// ```
+/// use rawarray::RawArray;
+/// # std::fs::create_dir_all("write_all_doc").unwrap();
+/// let items: Vec<(String, RawArray<f32>)> = (0..50)
+///     .map(|i| (format!("write_all_doc/{}.ra", i), vec![i as f32; 8].into()))
+///     .collect();
+/// rawarray::batch::write_all(&items).unwrap();
+/// assert_eq!(RawArray::<f32>::read("write_all_doc/9.ra").unwrap().data(), vec![9.0; 8]);
+/// # std::fs::remove_dir_all("write_all_doc").unwrap();
+/// ```
+pub fn write_all<T, P>(items: &[(P, RawArray<T>)]) -> Result<(), BatchError>
+where
+    T: RawArrayType,
+    P: AsRef<Path> + Sync,
+{
+    let errors = run_pool(items.len(), default_parallelism(), |i| {
+        let (path, ra) = &items[i];
+        ra.write(path.as_ref())
+    });
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(BatchError {
+        failures: errors
+            .into_iter()
+            .map(|(i, e)| (items[i].0.as_ref().to_path_buf(), e))
+            .collect(),
+    })
+}
+
+/// Write many arrays to their own files, with up to `parallelism` writes
+/// in flight at once. Every item is attempted even if some fail, and the
+/// failures are reported together.
+/// ```
+/// use rawarray::RawArray;
+/// use std::path::PathBuf;
+/// # std::fs::create_dir_all("batch_doc").unwrap();
+/// let items: Vec<(PathBuf, RawArray<u32>)> = (0..100)
+///     .map(|i| (format!("batch_doc/{}.ra", i).into(), vec![i; 16].into()))
+///     .collect();
+/// rawarray::write_batch(items, 8).unwrap();
+/// assert_eq!(RawArray::<u32>::read("batch_doc/42.ra").unwrap().data(), vec![42; 16]);
+/// # std::fs::remove_dir_all("batch_doc").unwrap();
+/// ```
+pub fn write_batch<T: RawArrayType>(
+    items: Vec<(PathBuf, RawArray<T>)>,
+    parallelism: usize,
+) -> Result<(), BatchError> {
+    let errors = run_pool(items.len(), parallelism, |i| {
+        let (path, ra) = &items[i];
+        ra.write(path)
+    });
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let mut paths: Vec<Option<PathBuf>> = items.into_iter().map(|(p, _)| Some(p)).collect();
+    Err(BatchError {
+        failures: errors
+            .into_iter()
+            .map(|(i, e)| (paths[i].take().unwrap(), e))
+            .collect(),
+    })
+}
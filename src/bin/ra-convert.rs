@@ -0,0 +1,181 @@
+//! Batch conversion of a directory tree of `.ra` and `.npy` files.
+
+use rawarray::codec::{Delta, Rle};
+use rawarray::{find_files, npy, recode_file, DType, Endianness, WriteOptions};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+fn print_usage() {
+    println!("Usage:");
+    println!("   ra-convert src-dir dst-dir [options]");
+    println!("Options:");
+    println!("   --to ra|npy              output format (default ra)");
+    println!("   --dtype NAME             element type, like f32 or c64");
+    println!("   --codec none|rle|delta|zstd[:LEVEL]|lz4");
+    println!("   --endian little|big      byte order of the output data");
+    println!("   --checksum on|off        store a CRC32 of the data");
+    println!("   --jobs N                 files converted at once (default 4)");
+    println!("   --dry-run                show what would be done");
+    println!("Convert every .ra and .npy file below src-dir, mirroring the tree in dst-dir.");
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ra,
+    Npy,
+}
+
+fn format_of(path: &Path) -> Option<Format> {
+    match path.extension()?.to_str()? {
+        "ra" => Some(Format::Ra),
+        "npy" => Some(Format::Npy),
+        _ => None,
+    }
+}
+
+fn with_codec(opts: WriteOptions, name: &str) -> Result<WriteOptions, String> {
+    let (name, level) = match name.split_once(':') {
+        Some((n, l)) => (n, Some(l)),
+        None => (name, None),
+    };
+    match (name, level) {
+        ("none", None) => Ok(opts),
+        ("rle", None) => Ok(opts.codec(Rle)),
+        ("delta", None) => Ok(opts.codec(Delta)),
+        #[cfg(feature = "zstd")]
+        ("zstd", level) => {
+            let level = level
+                .map_or(Ok(3), str::parse)
+                .map_err(|e| format!("{}", e))?;
+            Ok(opts.codec(rawarray::codec::Zstd(level)))
+        }
+        #[cfg(feature = "lz4")]
+        ("lz4", None) => Ok(opts.codec(rawarray::codec::Lz4)),
+        _ => Err(format!("unknown or unavailable codec {:?}", name)),
+    }
+}
+
+fn convert(input: &Path, output: &Path, to: Format, opts: &WriteOptions) -> io::Result<()> {
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match (format_of(input), to) {
+        (Some(Format::Npy), Format::Ra) => npy::npy_to_ra(input, output, opts),
+        (Some(Format::Ra), Format::Npy) => npy::ra_to_npy(input, output, opts),
+        (Some(Format::Ra), Format::Ra) => recode_file(input, output, opts),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            ".npy to .npy conversion isn't supported",
+        )),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let (src, dst) = match (args.next(), args.next()) {
+        (Some(s), Some(d)) => (PathBuf::from(s), PathBuf::from(d)),
+        _ => {
+            print_usage();
+            process::exit(2);
+        }
+    };
+    let mut to = Format::Ra;
+    let mut opts = WriteOptions::new();
+    let mut jobs = 4;
+    let mut dry_run = false;
+    while let Some(opt) = args.next() {
+        if opt == "--dry-run" {
+            dry_run = true;
+            continue;
+        }
+        let val = args.next().ok_or(format!("missing value for {}", opt))?;
+        match opt.as_ref() {
+            "--to" => {
+                to = match val.as_ref() {
+                    "ra" => Format::Ra,
+                    "npy" => Format::Npy,
+                    _ => return Err(format!("unknown format {:?}", val).into()),
+                }
+            }
+            "--dtype" => {
+                let dtype = DType::parse(&val).ok_or(format!("unknown dtype {:?}", val))?;
+                opts = opts.dtype(dtype);
+            }
+            "--codec" => opts = with_codec(opts, &val)?,
+            "--endian" => {
+                opts = opts.endianness(match val.as_ref() {
+                    "little" => Endianness::Little,
+                    "big" => Endianness::Big,
+                    _ => return Err(format!("unknown byte order {:?}", val).into()),
+                })
+            }
+            "--checksum" => {
+                opts = opts.checksum(match val.as_ref() {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("--checksum must be on or off, not {:?}", val).into()),
+                })
+            }
+            "--jobs" => jobs = val.parse::<usize>()?.max(1),
+            _ => {
+                print_usage();
+                process::exit(2);
+            }
+        }
+    }
+
+    let inputs: Vec<(PathBuf, PathBuf)> = find_files(&src, &["ra", "npy"])?
+        .into_iter()
+        .filter(|p| !(to == Format::Npy && format_of(p) == Some(Format::Npy)))
+        .map(|p| {
+            let rel = p.strip_prefix(&src).unwrap_or(&p);
+            let ext = if to == Format::Ra { "ra" } else { "npy" };
+            let out = dst.join(rel).with_extension(ext);
+            (p, out)
+        })
+        .collect();
+    let total = inputs.len();
+    if dry_run {
+        for (input, output) in &inputs {
+            println!("{} -> {}", input.display(), output.display());
+        }
+        println!("{} files would be converted", total);
+        return Ok(());
+    }
+
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+    thread::scope(|s| {
+        for _ in 0..jobs.min(total) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some((input, output)) = inputs.get(i) else {
+                    break;
+                };
+                let result = convert(input, output, to, &opts);
+                let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+                match result {
+                    Ok(()) => eprintln!("[{}/{}] {}", n, total, input.display()),
+                    Err(e) => {
+                        eprintln!("[{}/{}] {}: {}", n, total, input.display(), e);
+                        failures.lock().unwrap().push(input.clone());
+                    }
+                }
+            });
+        }
+    });
+    let failures = failures.into_inner().unwrap();
+    if !failures.is_empty() {
+        eprintln!("{} of {} files failed", failures.len(), total);
+        process::exit(1);
+    }
+    Ok(())
+}
@@ -1,65 +1,1220 @@
 //! Command line utility for manipulating `RawArray` files.
 
-use rawarray::RawArrayFile;
-use std::env;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use half::prelude::*;
+use rawarray::csv::{self, CsvOptions};
+use rawarray::json;
+use rawarray::{
+    compare_trees, concat_files, dtype_name, find_ra_files, for_each_difference, hash_contents,
+    hash_file, recode_file, slice_file_with_steps, split_file, validate_file, DType, Endianness,
+    HashAlgorithm, Problem, RawArrayFile, RawArrayHeader, RawArraySet, ValueFormatter,
+    WriteOptions, FLAG_BIG_ENDIAN, FLAG_BITS, FLAG_ENCODED, FLAG_LZ4, FLAG_ZSTD,
+};
+use std::convert::TryInto;
 use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::result::Result;
 
-fn print_usage() {
-    println!("Usage:");
-    println!("   ra <head|flags|eltype|elbyte|size|ndims|dims|data> file.ra");
-    println!("   ra reshape file.ra dim0 dim1 dim2 ...");
-    println!("RawArray file tool");
+/// Flags of data sections whose elements can't be found by their offset
+const UNSEEKABLE: u64 = FLAG_ENCODED | FLAG_BITS | FLAG_ZSTD | FLAG_LZ4;
+
+/// RawArray file tool
+#[derive(Parser)]
+#[command(
+    name = "ra",
+    version,
+    after_help = "Exit status is 0 on success, 1 when diff, tree-diff, validate, or fix find \
+                  differences or problems, 2 on errors, and 3 when a change needs --yes."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print every header field
+    Head { file: String },
+    /// Print the flags, in hex
+    Flags { file: String },
+    /// Print the element type code
+    Eltype { file: String },
+    /// Print the width of one element in bytes
+    Elbyte { file: String },
+    /// Print the length of the data in bytes
+    Size { file: String },
+    /// Print the number of dimensions
+    Ndims { file: String },
+    /// Print the dimensions, first one fastest varying
+    Dims { file: String },
+    /// Print the byte offset of the data
+    Data { file: String },
+    /// Change the dimensions recorded in a file, keeping the data as it is
+    Reshape(ReshapeArgs),
+    /// Compare the elements of two files
+    Diff(DiffArgs),
+    /// List the .ra files below a directory with their type, shape, and size
+    Ls {
+        dir: String,
+        /// Print a JSON array instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compare the .ra files in two directory trees
+    TreeDiff { dir_a: String, dir_b: String },
+    /// Split a file along an axis into several
+    Split(SplitArgs),
+    /// Concatenate files along an axis
+    Cat(CatArgs),
+    /// Check a file against the format and print every problem found
+    Validate { file: String },
+    /// Repair a wrong size field
+    Fix(FixArgs),
+    /// Copy a sub-block of a file into a new one
+    Slice(SliceArgs),
+    /// Convert a file to another element type
+    Cast(CastArgs),
+    /// Print elements at a flat index or at coordinates
+    Peek(PeekArgs),
+    /// Print a file as JSON
+    Json { file: String },
+    /// Print a hash of each file, or of just the array it holds
+    Hash(HashArgs),
+    /// Convert between NIfTI-1 and RawArray, by the input's extension
+    #[cfg(feature = "nifti")]
+    Nifti {
+        /// .nii, .nii.gz, or .ra file
+        input: String,
+        output: String,
+    },
+    /// Write a 2-D array, or one slice of a volume, as a grayscale picture
+    #[cfg(feature = "image")]
+    Png(PngArgs),
+    /// Print the shape of a file each time it's written to
+    #[cfg(feature = "watch")]
+    Watch {
+        file: String,
+        /// Also print summary statistics of the elements written so far
+        #[arg(long)]
+        stats: bool,
+    },
+    /// Convert between a RawArray file and delimited text
+    Csv(CsvArgs),
+    /// Print a shell completion script
+    Completions { shell: clap_complete::Shell },
+}
+
+fn parse_dtype(name: &str) -> Result<DType, String> {
+    DType::parse(name).ok_or_else(|| format!("unknown element type {}", name))
+}
+
+fn parse_algo(name: &str) -> Result<HashAlgorithm, String> {
+    HashAlgorithm::parse(name).ok_or_else(|| format!("unknown hash {}, not xxh3 or sha256", name))
+}
+
+#[derive(Args)]
+struct HashArgs {
+    #[arg(required = true)]
+    files: Vec<String>,
+    /// Hash function: xxh3 or sha256
+    #[arg(long, default_value = "xxh3", value_parser = parse_algo)]
+    algo: HashAlgorithm,
+    /// Hash the element type, dimensions, and decoded data, ignoring flags,
+    /// codec, byte order, checksum, and attributes
+    #[arg(long)]
+    data_only: bool,
+}
+
+/// Print a hash of each file in the format of `sha256sum`.
+fn hash(args: HashArgs) -> Result<(), Box<dyn Error>> {
+    for file in &args.files {
+        let digest = if args.data_only {
+            hash_contents(file, args.algo)?
+        } else {
+            hash_file(file, args.algo)?
+        };
+        println!("{}  {}", digest, file);
+    }
+    Ok(())
+}
+
+/// One scalar component of an element, integers kept at full width.
+#[derive(Clone, Copy)]
+enum Scalar {
+    Int(i128),
+    UInt(u128),
+    Float(f64),
+}
+
+/// Decode one element into its scalar components (two for complex).
+/// Types we don't know how to interpret are compared byte by byte.
+fn decode(eltype: u64, b: &[u8]) -> Vec<Scalar> {
+    macro_rules! le {
+        ($v:ident, $t:ty, $b:expr) => {
+            Scalar::$v(<$t>::from_le_bytes($b.try_into().unwrap()).into())
+        };
+    }
+    match (eltype, b.len()) {
+        (1, 1) => vec![le!(Int, i8, b)],
+        (1, 2) => vec![le!(Int, i16, b)],
+        (1, 4) => vec![le!(Int, i32, b)],
+        (1, 8) => vec![le!(Int, i64, b)],
+        (1, 16) => vec![le!(Int, i128, b)],
+        (2, 1) => vec![le!(UInt, u8, b)],
+        (2, 2) => vec![le!(UInt, u16, b)],
+        (2, 4) => vec![le!(UInt, u32, b)],
+        (2, 8) => vec![le!(UInt, u64, b)],
+        (2, 16) => vec![le!(UInt, u128, b)],
+        (3, 2) => vec![Scalar::Float(
+            f16::from_le_bytes(b.try_into().unwrap()).to_f64(),
+        )],
+        (3, 4) => vec![le!(Float, f32, b)],
+        (3, 8) => vec![le!(Float, f64, b)],
+        (4, 8) => vec![le!(Float, f32, &b[..4]), le!(Float, f32, &b[4..])],
+        (4, 16) => vec![le!(Float, f64, &b[..8]), le!(Float, f64, &b[8..])],
+        (5, 2) => vec![Scalar::Float(
+            bf16::from_le_bytes(b.try_into().unwrap()).to_f64(),
+        )],
+        (6, 2) => vec![le!(Int, i8, &b[..1]), le!(Int, i8, &b[1..])],
+        (6, 4) => vec![le!(Int, i16, &b[..2]), le!(Int, i16, &b[2..])],
+        (6, 8) => vec![le!(Int, i32, &b[..4]), le!(Int, i32, &b[4..])],
+        _ => b.iter().map(|&x| Scalar::UInt(x.into())).collect(),
+    }
+}
+
+/// Whether `a` is within `atol + rtol * |b|` of `b`. Integers are
+/// subtracted exactly, so only nonzero differences meet the tolerances.
+fn is_close(a: Scalar, b: Scalar, rtol: f64, atol: f64) -> bool {
+    let within = |d: u128, b: u128| d == 0 || d as f64 <= atol + rtol * b as f64;
+    match (a, b) {
+        (Scalar::Int(a), Scalar::Int(b)) => within(a.abs_diff(b), b.unsigned_abs()),
+        (Scalar::UInt(a), Scalar::UInt(b)) => within(a.abs_diff(b), b),
+        (Scalar::Float(a), Scalar::Float(b)) => {
+            a == b || (a.is_nan() && b.is_nan()) || (a - b).abs() <= atol + rtol * b.abs()
+        }
+        _ => false,
+    }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let mut args = env::args();
-    if args.len() < 3 {
-        print_usage();
+#[derive(Args)]
+struct DiffArgs {
+    a: String,
+    b: String,
+    /// Relative tolerance
+    #[arg(long, default_value_t = 0.0)]
+    rtol: f64,
+    /// Absolute tolerance
+    #[arg(long, default_value_t = 0.0)]
+    atol: f64,
+    /// Most differing elements to print
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    max: u64,
+    /// Digits after the decimal point
+    #[arg(long)]
+    precision: Option<usize>,
+}
+
+/// Compare two files, printing up to `max_shown` differing elements.
+/// Returns `true` if the files match within tolerance.
+fn diff(
+    path_a: &str,
+    path_b: &str,
+    rtol: f64,
+    atol: f64,
+    max_shown: u64,
+    vf: &ValueFormatter,
+) -> Result<bool, Box<dyn Error>> {
+    let ha = RawArrayHeader::read(path_a)?;
+    let hb = RawArrayHeader::read(path_b)?;
+    let mut same = true;
+    if ha.flags != hb.flags {
+        println!("flags: {:x} != {:x}", ha.flags, hb.flags);
+        same = false;
+    }
+    if ha.eltype != hb.eltype || ha.elbyte != hb.elbyte {
+        println!(
+            "eltype/elbyte: {}/{} != {}/{}",
+            ha.eltype, ha.elbyte, hb.eltype, hb.elbyte
+        );
+        return Ok(false);
+    }
+    if ha.dims != hb.dims {
+        println!("dims: {:?} != {:?}", ha.dims, hb.dims);
+        return Ok(false);
+    }
+    if ha.size != hb.size {
+        println!("size: {} != {}", ha.size, hb.size);
+        return Ok(false);
+    }
+
+    let nelem = ha.size.checked_div(ha.elbyte).unwrap_or(0);
+    let dtype = DType {
+        eltype: ha.eltype,
+        elbyte: ha.elbyte,
+    };
+    let mut ndiff = 0u64;
+    for_each_difference(path_a, path_b, |i, ba, bb| {
+        let va = decode(ha.eltype, ba);
+        let vb = decode(hb.eltype, bb);
+        let close = va
+            .iter()
+            .zip(vb.iter())
+            .all(|(&a, &b)| is_close(a, b, rtol, atol));
+        if !close {
+            if ndiff < max_shown {
+                println!(
+                    "[{}]: {} != {}",
+                    i,
+                    vf.format_bytes(dtype, Endianness::Little, ba),
+                    vf.format_bytes(dtype, Endianness::Little, bb)
+                );
+            }
+            ndiff += 1;
+        }
+    })?;
+    if ndiff > 0 {
+        println!("{} of {} elements differ", ndiff, nelem);
+        same = false;
+    }
+    Ok(same)
+}
+
+/// Print `count` elements starting at a flat index or at coordinates, with
+/// where each one is in the array and in the file.
+#[derive(Args)]
+struct PeekArgs {
+    file: String,
+    /// Flat index of the first element
+    #[arg(long, value_name = "N", conflicts_with = "at")]
+    offset: Option<u64>,
+    /// Coordinates of the first element, like 4,0,2
+    #[arg(long, value_name = "I,J,K")]
+    at: Option<String>,
+    /// Number of elements to print
+    #[arg(long, default_value_t = 10)]
+    count: u64,
+    /// Digits after the decimal point
+    #[arg(long)]
+    precision: Option<usize>,
+}
+
+fn peek(args: PeekArgs) -> Result<(), Box<dyn Error>> {
+    let filename = &args.file;
+    let mut vf = ValueFormatter::new();
+    if let Some(p) = args.precision {
+        vf = vf.precision(p);
+    }
+    let h = RawArrayHeader::read(filename)?;
+    if h.flags & UNSEEKABLE != 0 {
+        return Err(format!(
+            "{}: data is encoded; write a plain copy with ra cast first",
+            filename
+        )
+        .into());
+    }
+    if h.elbyte == 0 {
+        return Err(format!("{}: elbyte is zero", filename).into());
+    }
+    // trust size, not dims, so a corrupt header can't send reads past the data
+    let nelem = h.size / h.elbyte;
+    let start = match (args.offset, args.at) {
+        (Some(i), _) => i,
+        (None, Some(coords)) => {
+            let coords = coords
+                .split(',')
+                .map(|c| c.trim().parse::<u64>())
+                .collect::<Result<Vec<u64>, _>>()?;
+            if coords.len() != h.dims.len() || coords.iter().zip(&h.dims).any(|(c, n)| c >= n) {
+                return Err(format!(
+                    "coordinates {:?} out of range for dims {:?}",
+                    coords, h.dims
+                )
+                .into());
+            }
+            // the first dimension varies fastest
+            coords
+                .iter()
+                .rev()
+                .zip(h.dims.iter().rev())
+                .try_fold(0u64, |acc, (&c, &n)| acc.checked_mul(n)?.checked_add(c))
+                .ok_or("coordinates are past the end of the data")?
+        }
+        (None, None) => 0,
+    };
+    if start >= nelem {
+        return Err(format!("index {} out of range for {} elements", start, nelem).into());
+    }
+    let dtype = DType {
+        eltype: h.eltype,
+        elbyte: h.elbyte,
+    };
+    let endianness = if h.flags & FLAG_BIG_ENDIAN != 0 {
+        Endianness::Big
     } else {
-        let command = args.nth(1).unwrap();
-        let filename = args.next().unwrap();
-        let mut r = RawArrayFile::valid_open(&filename)?;
-        match command.as_ref() {
-            "head" => {
-                let _magic = r.u64()?;
-                println!("flags: {:b}", r.u64()?);
-                println!("eltype: {}", r.u64()?);
-                println!("elbyte: {}", r.u64()?);
-                println!("size: {}", r.u64()?);
-                let ndims = r.u64()?;
-                println!("ndims: {}", ndims);
-                println!("dims: ");
-                for _ in 0..ndims {
-                    println!("\t- {}", r.u64()?);
+        Endianness::Little
+    };
+    let mut r = BufReader::new(File::open(filename)?);
+    // start < nelem, so start * elbyte is within size
+    let first_byte = h
+        .data_offset()
+        .checked_add(start * h.elbyte)
+        .ok_or("size is too large")?;
+    r.seek(SeekFrom::Start(first_byte))?;
+    let mut b = vec![0u8; h.elbyte as usize];
+    for i in start..nelem.min(start.saturating_add(args.count)) {
+        r.read_exact(&mut b)?;
+        let mut rest = i;
+        let coords: Vec<String> = h
+            .dims
+            .iter()
+            .map(|&n| match rest.checked_rem(n) {
+                Some(c) => {
+                    rest /= n;
+                    c.to_string()
                 }
+                // a zero dimension in a header whose size says otherwise
+                None => "?".to_string(),
+            })
+            .collect();
+        println!(
+            "[{}] ({}) byte {}: {}",
+            i,
+            coords.join(", "),
+            first_byte + (i - start) * h.elbyte,
+            vf.format_bytes(dtype, endianness, &b)
+        );
+    }
+    Ok(())
+}
+
+/// Elements of a file being watched that have been summarized so far, so
+/// each change only reads what was appended since the last one.
+#[cfg(feature = "watch")]
+struct Status {
+    stats: bool,
+    /// Flags, element type and width, and header length of the file
+    layout: Option<(u64, u64, u64, usize)>,
+    /// Elements summarized, from the start of the data
+    read: u64,
+    min: f64,
+    max: f64,
+    sum: f64,
+    finite: u64,
+    nonfinite: u64,
+}
+
+#[cfg(feature = "watch")]
+impl Status {
+    /// Elements read per step
+    const CHUNK_ELEMS: u64 = 1 << 16;
+
+    fn new(stats: bool) -> Status {
+        Status {
+            stats,
+            layout: None,
+            read: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            sum: 0.0,
+            finite: 0,
+            nonfinite: 0,
+        }
+    }
+
+    /// Print the shape of a file, and with `stats` a summary of the
+    /// elements written so far. Complex elements are summarized by their
+    /// magnitudes.
+    fn print(&mut self, filename: &str) -> Result<(), Box<dyn Error>> {
+        let h = RawArrayHeader::read(filename)?;
+        let len = fs::metadata(filename)?.len();
+        print!(
+            "{}: {} {:?}, {} bytes of data, file {} bytes",
+            filename,
+            dtype_name(h.eltype, h.elbyte),
+            h.dims,
+            h.size,
+            len
+        );
+        // encoded and bit data only make sense whole
+        if !self.stats || h.elbyte == 0 || h.flags & UNSEEKABLE != 0 {
+            println!();
+            return Ok(());
+        }
+        // count only what has reached the disk, which may trail the header
+        let data_start = h.data_offset();
+        let nelem = h.size / h.elbyte;
+        let written = (len.saturating_sub(data_start) / h.elbyte).min(nelem);
+        // appending may grow the dims, but a new layout or a shorter file
+        // means it was rewritten; start over
+        let layout = (h.flags, h.eltype, h.elbyte, h.len());
+        if self.layout != Some(layout) || written < self.read {
+            *self = Status::new(self.stats);
+        }
+        let mut r = BufReader::new(File::open(filename)?);
+        r.seek(SeekFrom::Start(data_start + self.read * h.elbyte))?;
+        let mut buf = Vec::new();
+        while self.read < written {
+            let n = Status::CHUNK_ELEMS.min(written - self.read);
+            buf.resize((n * h.elbyte) as usize, 0);
+            r.read_exact(&mut buf)?;
+            for b in buf.chunks_exact_mut(h.elbyte as usize) {
+                self.add(&h, b);
             }
-            "flags" => println!("{:x}", r.u64_at(8)?),
-            "eltype" => println!("{}", r.u64_at(16)?),
-            "elbyte" => println!("{}", r.u64_at(24)?),
-            "size" => println!("{}", r.u64_at(32)?),
-            "ndims" => println!("{}", r.u64_at(40)?),
-            "dims" => {
-                r.seek(40)?;
-                let ndims = r.u64()?;
-                for _ in 0..ndims {
-                    print!("{} ", r.u64()?)
-                }
-                println!();
+            self.read += n;
+        }
+        self.layout = Some(layout);
+        print!("; {} of {} elements written", written, nelem);
+        if self.finite > 0 {
+            print!(
+                ", min {}, max {}, mean {}",
+                self.min,
+                self.max,
+                self.sum / self.finite as f64
+            );
+        }
+        if self.nonfinite > 0 {
+            print!(", {} not finite", self.nonfinite);
+        }
+        println!();
+        Ok(())
+    }
+
+    fn add(&mut self, h: &RawArrayHeader, b: &mut [u8]) {
+        if h.flags & FLAG_BIG_ENDIAN != 0 {
+            // the two parts of a complex number are swapped separately
+            let width = if let 4 | 6 = h.eltype {
+                b.len() / 2
+            } else {
+                b.len()
+            };
+            b.chunks_exact_mut(width.max(1)).for_each(|c| c.reverse());
+        }
+        let parts = decode(h.eltype, b);
+        let x = match parts[..] {
+            [re, im] => re.hypot(im),
+            [x] => x,
+            _ => return,
+        };
+        if x.is_finite() {
+            self.min = self.min.min(x);
+            self.max = self.max.max(x);
+            self.sum += x;
+            self.finite += 1;
+        } else {
+            self.nonfinite += 1;
+        }
+    }
+}
+
+/// Print the status of a file now and again each time it changes, until
+/// it's removed or the program is interrupted.
+#[cfg(feature = "watch")]
+fn watch(filename: &str, stats: bool) -> Result<(), Box<dyn Error>> {
+    use notify::{EventKind, RecursiveMode, Watcher};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let path = fs::canonicalize(filename)?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    // watch the directory, so a file replaced by renaming over it is seen
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    let mut status = Status::new(stats);
+    status.print(filename)?;
+    while let Ok(event) = rx.recv() {
+        let event = event?;
+        // our own reads show up as access events, so only follow changes
+        let changed = matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        );
+        if !changed || !event.paths.contains(&path) {
+            continue;
+        }
+        // a writer appending frames sends a burst of events; report once
+        std::thread::sleep(Duration::from_millis(100));
+        rx.try_iter().for_each(drop);
+        if !path.exists() {
+            println!("{}: removed", filename);
+            return Ok(());
+        }
+        if let Err(e) = status.print(filename) {
+            // caught part way through a write; the next event will tell
+            println!("{}: {}", filename, e);
+        }
+    }
+    Ok(())
+}
+
+/// List the `.ra` files below `dir` with their type, shape, and size,
+/// reading only headers.
+fn ls(dir: &str, json: bool) -> Result<(), Box<dyn Error>> {
+    let mut rows = Vec::new();
+    for path in find_ra_files(Path::new(dir))? {
+        let name = path
+            .strip_prefix(dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        match RawArrayHeader::read(&path) {
+            Ok(h) => rows.push((name, h)),
+            Err(e) => eprintln!("{}: {}", path.display(), e),
+        }
+    }
+    if json {
+        let entries: Vec<String> = rows
+            .iter()
+            .map(|(name, h)| {
+                let dims: Vec<String> = h.dims.iter().map(|d| d.to_string()).collect();
+                format!(
+                    "{{\"name\": {}, \"dtype\": {}, \"shape\": [{}], \"size\": {}}}",
+                    json::json_string(name),
+                    json::json_string(&dtype_name(h.eltype, h.elbyte)),
+                    dims.join(", "),
+                    h.size
+                )
+            })
+            .collect();
+        println!("[{}]", entries.join(",\n "));
+        return Ok(());
+    }
+    let width = rows.iter().map(|(n, _)| n.len()).max().unwrap_or(0).max(4);
+    println!(
+        "{:width$}  {:>6}  {:>16}  {:>12}",
+        "name",
+        "dtype",
+        "shape",
+        "size",
+        width = width
+    );
+    for (name, h) in &rows {
+        let shape: Vec<String> = h.dims.iter().map(|d| d.to_string()).collect();
+        println!(
+            "{:width$}  {:>6}  {:>16}  {:>12}",
+            name,
+            dtype_name(h.eltype, h.elbyte),
+            shape.join("x"),
+            h.size,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+/// Compare the `.ra` files in two trees, printing what differs. Returns
+/// whether the trees match.
+fn tree_diff(dir_a: &str, dir_b: &str) -> Result<bool, Box<dyn Error>> {
+    let cmp = compare_trees(dir_a, dir_b)?;
+    for p in &cmp.differing {
+        println!("differs: {}", p.display());
+    }
+    for p in &cmp.only_in_a {
+        println!("only in {}: {}", dir_a, p.display());
+    }
+    for p in &cmp.only_in_b {
+        println!("only in {}: {}", dir_b, p.display());
+    }
+    for (p, e) in &cmp.unreadable {
+        println!("unreadable: {}: {}", p.display(), e);
+    }
+    println!(
+        "{} identical, {} differing, {} only in {}, {} only in {}, {} unreadable",
+        cmp.identical.len(),
+        cmp.differing.len(),
+        cmp.only_in_a.len(),
+        dir_a,
+        cmp.only_in_b.len(),
+        dir_b,
+        cmp.unreadable.len()
+    );
+    Ok(cmp.is_same())
+}
+
+/// Fill in the `%d` or `%0Nd` in `pattern` with `i`.
+fn fill_pattern(pattern: &str, i: usize) -> Result<String, Box<dyn Error>> {
+    let start = pattern
+        .find('%')
+        .ok_or("output pattern needs a %d for the piece number")?;
+    let rest = &pattern[start + 1..];
+    let end = rest.find('d').ok_or("output pattern needs a %d")?;
+    let spec = &rest[..end];
+    let width: usize = if spec.is_empty() { 0 } else { spec.parse()? };
+    let number = if spec.starts_with('0') {
+        format!("{:0width$}", i, width = width)
+    } else {
+        format!("{:width$}", i, width = width)
+    };
+    Ok(format!(
+        "{}{}{}",
+        &pattern[..start],
+        number,
+        &rest[end + 1..]
+    ))
+}
+
+/// Split a file along an axis, one output file per piece. Without
+/// `--pieces`, every slice along the axis gets its own file.
+#[derive(Args)]
+struct SplitArgs {
+    file: String,
+    /// Axis to split along
+    #[arg(long)]
+    axis: usize,
+    /// Output file names, with a %d or %03d for the piece number
+    #[arg(long, value_name = "PATTERN")]
+    out: String,
+    /// Number of pieces; every slice gets its own file by default
+    #[arg(long, value_name = "N")]
+    pieces: Option<usize>,
+}
+
+fn split(args: SplitArgs) -> Result<(), Box<dyn Error>> {
+    let (filename, axis, pattern) = (&args.file, args.axis, &args.out);
+    let h = RawArrayHeader::read(filename)?;
+    let len = *h
+        .dims
+        .get(axis)
+        .ok_or(format!("axis {} out of range", axis))?;
+    let pieces = args.pieces.unwrap_or(len as usize);
+    // check the pattern before writing anything
+    fill_pattern(pattern, 0)?;
+    let names = |i| PathBuf::from(fill_pattern(pattern, i).unwrap());
+    for path in split_file(filename, axis, pieces, names)? {
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+#[derive(Args)]
+struct CatArgs {
+    /// Files to join, in order
+    #[arg(required = true)]
+    inputs: Vec<String>,
+    /// Output file
+    #[arg(short, long)]
+    out: String,
+    /// Axis to join along; one past the last stacks the inputs
+    #[arg(long)]
+    axis: usize,
+}
+
+/// Ranges along each axis and the steps to take through them
+type Ranges = (Vec<Range<u64>>, Vec<u64>);
+
+/// Ranges and steps along each axis, as `start:end:step`, from a spec like
+/// `0:64,::2,10`
+fn parse_ranges(spec: &str, dims: &[u64]) -> Result<Ranges, Box<dyn Error>> {
+    let parts: Vec<&str> = spec.split(',').collect();
+    if parts.len() > dims.len() {
+        return Err(format!("{} ranges for {} dimensions", parts.len(), dims.len()).into());
+    }
+    let mut ranges = Vec::with_capacity(dims.len());
+    let mut steps = Vec::with_capacity(dims.len());
+    for (part, &n) in parts.iter().zip(dims) {
+        let part = part.trim();
+        let fields: Vec<&str> = part.split(':').collect();
+        let (range, step) = match fields[..] {
+            [a, b] | [a, b, ""] => (parse_range(a, b, n)?, 1),
+            [a, b, step] => (parse_range(a, b, n)?, step.parse()?),
+            [i] => {
+                let i: u64 = i.parse()?;
+                let end = i.checked_add(1).ok_or("index out of range")?;
+                (i..end, 1)
             }
-            "data" => {
-                let ndims = r.u64_at(40)?;
-                println!("{}", 40 + ndims * 8);
+            _ => return Err(format!("bad range {:?}", part).into()),
+        };
+        ranges.push(range);
+        steps.push(step);
+    }
+    ranges.extend(dims[parts.len()..].iter().map(|&n| 0..n));
+    steps.resize(dims.len(), 1);
+    Ok((ranges, steps))
+}
+
+/// The range from `a` to `b`, either of which may be left out to mean the
+/// start or end of an axis of length `n`
+fn parse_range(a: &str, b: &str, n: u64) -> Result<Range<u64>, Box<dyn Error>> {
+    let start = if a.is_empty() { 0 } else { a.parse()? };
+    let end = if b.is_empty() { n } else { b.parse()? };
+    Ok(start..end)
+}
+
+#[derive(Args)]
+struct SliceArgs {
+    input: String,
+    output: String,
+    /// Ranges along each axis, like 0:64,:,10 for the first 64 rows and
+    /// slice 10, with an optional step, like ::2 for every other column;
+    /// axes left out are kept whole
+    #[arg(long, value_name = "RANGES", allow_hyphen_values = true)]
+    range: String,
+}
+
+#[derive(Args)]
+struct CastArgs {
+    input: String,
+    /// File to write, which may be the input to convert it in place
+    output: String,
+    /// Element type to write, like f32, i16, or c64
+    #[arg(long, value_name = "TYPE", value_parser = parse_dtype)]
+    to: DType,
+    /// Write the magnitudes of complex elements, so they can become real
+    #[arg(long)]
+    magnitude: bool,
+}
+
+/// One change to a file in place, worked out before anything is written
+enum Change {
+    /// Overwrite a header field
+    Field {
+        offset: u64,
+        name: String,
+        old: u64,
+        new: u64,
+    },
+    /// Replace the header with one of a different length, which moves the
+    /// data and everything after it
+    Rewrite {
+        old: RawArrayHeader,
+        new: RawArrayHeader,
+    },
+}
+
+impl Change {
+    /// Changes that can't be undone by running another command
+    fn is_irreversible(&self) -> bool {
+        matches!(self, Change::Rewrite { .. })
+    }
+}
+
+/// Everything an in-place command would do to a file.
+struct Plan {
+    path: String,
+    changes: Vec<Change>,
+}
+
+impl Plan {
+    fn print(&self) {
+        if self.changes.is_empty() {
+            println!("{}: nothing to change", self.path);
+        }
+        for c in &self.changes {
+            match c {
+                Change::Field {
+                    offset,
+                    name,
+                    old,
+                    new,
+                } => println!(
+                    "{}: bytes {}..{} ({}): {} -> {}",
+                    self.path,
+                    offset,
+                    offset + 8,
+                    name,
+                    old,
+                    new
+                ),
+                Change::Rewrite { old, new } => println!(
+                    "{}: rewrite file, header {} -> {} bytes, dims {:?} -> {:?}, \
+                     data moves from byte {} to {} (irreversible)",
+                    self.path,
+                    old.len(),
+                    new.len(),
+                    old.dims,
+                    new.dims,
+                    old.len(),
+                    new.len()
+                ),
             }
-            "reshape" => {
-                // TODO
+        }
+    }
+
+    fn apply(&self) -> Result<(), Box<dyn Error>> {
+        for c in &self.changes {
+            match c {
+                Change::Field { offset, new, .. } => {
+                    let mut f = RawArrayFile::valid_open_rw(&self.path)?;
+                    f.write_u64_at(*offset, *new)?;
+                    f.sync()?;
+                }
+                Change::Rewrite { old, new } => {
+                    // write a copy next to the file and swap it in, so a
+                    // failure part way leaves the original untouched
+                    let mut r = File::open(&self.path)?;
+                    r.seek(SeekFrom::Start(old.len() as u64))?;
+                    let tmp = format!("{}.tmp", self.path);
+                    let copied = File::create(&tmp).and_then(|f| {
+                        let mut w = BufWriter::new(f);
+                        new.write_to(&mut w)?;
+                        io::copy(&mut r, &mut w)?;
+                        w.into_inner()?.sync_all()
+                    });
+                    if let Err(e) = copied {
+                        let _ = fs::remove_file(&tmp);
+                        return Err(e.into());
+                    }
+                    fs::rename(&tmp, &self.path)?;
+                }
             }
-            _ => {
-                print_usage();
+        }
+        Ok(())
+    }
+
+    /// Print the plan, then carry it out unless this is a dry run.
+    /// Irreversible plans need `yes`.
+    fn run(&self, dry_run: bool, yes: bool) -> Result<(), Box<dyn Error>> {
+        self.print();
+        if dry_run {
+            return Ok(());
+        }
+        if !yes && self.changes.iter().any(Change::is_irreversible) {
+            eprintln!("not changing {}: rerun with --yes to confirm", self.path);
+            process::exit(3);
+        }
+        self.apply()
+    }
+}
+
+#[derive(Args)]
+struct CsvArgs {
+    /// RawArray file, or text file with --to
+    file: String,
+    /// Text file to write, instead of standard output
+    #[arg(long, conflicts_with = "to")]
+    out: Option<String>,
+    /// Read the text file and write this RawArray file
+    #[arg(long, requires = "eltype")]
+    to: Option<String>,
+    /// Element type to store the values as, with --to
+    #[arg(long, value_name = "TYPE", value_parser = parse_dtype)]
+    eltype: Option<DType>,
+    /// Field separator, one character or "tab"; tabs by default for .tsv
+    #[arg(long, value_parser = parse_delimiter)]
+    delimiter: Option<char>,
+    /// Digits after the decimal point
+    #[arg(long)]
+    precision: Option<usize>,
+}
+
+fn parse_delimiter(val: &str) -> Result<char, String> {
+    match val {
+        "tab" | "\\t" => Ok('\t'),
+        _ => {
+            let mut chars = val.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(format!("delimiter {:?} isn't one character", val)),
             }
         }
+    }
+}
 
+/// Convert between a RawArray file and delimited text. With `--to`, the
+/// input is text; otherwise it's a RawArray file written as text to
+/// `--out`, or to standard output. Paths ending in `.tsv` default to tabs.
+fn csv(args: CsvArgs) -> Result<(), Box<dyn Error>> {
+    let CsvArgs {
+        file: filename,
+        out,
+        to,
+        eltype,
+        delimiter,
+        precision,
+    } = args;
+    let filename = filename.as_str();
+    let text_path = match &to {
+        Some(_) => Some(filename),
+        None => out.as_deref(),
+    };
+    let tsv = text_path.is_some_and(|p| p.ends_with(".tsv"));
+    let mut opts = CsvOptions::new().delimiter(delimiter.unwrap_or(if tsv { '\t' } else { ',' }));
+    if let Some(p) = precision {
+        opts = opts.precision(p);
+    }
+    match (to, out) {
+        (Some(to), _) => {
+            let eltype = eltype.ok_or("--eltype is required with --to")?;
+            csv::csv_to_ra(filename, to, eltype, &opts)?
+        }
+        (None, Some(out)) => csv::ra_to_csv(filename, BufWriter::new(File::create(out)?), &opts)?,
+        (None, None) => csv::ra_to_csv(filename, io::stdout().lock(), &opts)?,
     }
+    Ok(())
+}
 
+#[cfg(feature = "image")]
+#[derive(Args)]
+struct PngArgs {
+    file: String,
+    /// .png or .tif file to write
+    output: String,
+    /// Index of the slice of a volume to draw
+    #[arg(long, value_name = "Z")]
+    slice: Option<u64>,
+    /// Values mapped to black and white
+    #[arg(long, num_args = 2, value_names = ["LO", "HI"], allow_negative_numbers = true)]
+    window: Option<Vec<f64>>,
+    /// Bits per pixel, 8 or 16
+    #[arg(long, default_value_t = 8)]
+    bits: u8,
+}
+
+/// Write a two-dimensional array, or one slice of a three-dimensional one,
+/// as a grayscale picture for a quick look.
+#[cfg(feature = "image")]
+fn png(args: PngArgs) -> Result<(), Box<dyn Error>> {
+    use rawarray::image::{self, ColorMap};
+    let (slice, bits) = (args.slice, args.bits);
+    let window = args.window.map(|w| (w[0], w[1]));
+    let mut map = match bits {
+        8 => ColorMap::gray8(),
+        16 => ColorMap::gray16(),
+        _ => return Err(format!("can't write {}-bit pictures", bits).into()),
+    };
+    if let Some((low, high)) = window {
+        map = map.window(low, high);
+    }
+    image::ra_to_image(&args.file, &args.output, slice, &map)?;
     Ok(())
 }
+
+#[derive(Args)]
+struct ReshapeArgs {
+    file: String,
+    /// New dimensions, first one fastest varying
+    #[arg(required = true)]
+    dims: Vec<u64>,
+    /// Show what would change without changing anything
+    #[arg(long, visible_alias = "plan")]
+    dry_run: bool,
+    /// Confirm changes that can't be undone
+    #[arg(long)]
+    yes: bool,
+}
+
+/// Change the dimensions recorded in a file, keeping the data as it is.
+fn reshape(args: ReshapeArgs) -> Result<(), Box<dyn Error>> {
+    let ReshapeArgs {
+        file,
+        dims,
+        dry_run,
+        yes,
+    } = args;
+    let filename = file.as_str();
+    let old = RawArrayHeader::read(filename)?;
+    let new = RawArrayHeader {
+        dims,
+        ..old.clone()
+    };
+    if new.nelem().is_none() || new.nelem() != old.nelem() {
+        return Err(format!("can't reshape dims {:?} to {:?}", old.dims, new.dims).into());
+    }
+    let changes = if new.dims.len() == old.dims.len() {
+        new.dims
+            .iter()
+            .zip(&old.dims)
+            .enumerate()
+            .filter(|(_, (new, old))| new != old)
+            .map(|(i, (&new, &old))| Change::Field {
+                offset: 48 + 8 * i as u64,
+                name: format!("dims[{}]", i),
+                old,
+                new,
+            })
+            .collect()
+    } else {
+        vec![Change::Rewrite { old, new }]
+    };
+    let plan = Plan {
+        path: filename.to_string(),
+        changes,
+    };
+    plan.run(dry_run, yes)
+}
+
+/// Print every problem with a file, and any volatile metadata after the
+/// array. Returns `true` if there were no problems.
+fn validate(filename: &str) -> Result<bool, Box<dyn Error>> {
+    let problems = validate_file(filename)?;
+    let ok = problems.iter().all(Problem::is_informational);
+    for p in &problems {
+        let note = if p.is_informational() { "note: " } else { "" };
+        println!("{}: {}{}", filename, note, p);
+    }
+    if ok {
+        println!("{}: ok", filename);
+    }
+    Ok(ok)
+}
+
+#[derive(Args)]
+struct FixArgs {
+    file: String,
+    /// Show what would change without changing anything
+    #[arg(long, visible_alias = "plan")]
+    dry_run: bool,
+    /// Confirm changes that can't be undone
+    #[arg(long)]
+    yes: bool,
+}
+
+/// Repair what can be repaired safely, which is a `size` that disagrees
+/// with dims the file's length bears out. Bytes after the array are
+/// volatile metadata, and are never touched, nor are set files, whose
+/// other arrays live there. Returns `true` if nothing is left wrong.
+fn fix(args: FixArgs) -> Result<bool, Box<dyn Error>> {
+    let (filename, dry_run, yes) = (args.file.as_str(), args.dry_run, args.yes);
+    if RawArraySet::has_index(filename)? {
+        eprintln!(
+            "{}: holds a set of arrays; not changing it, fix its arrays one at a time",
+            filename
+        );
+        return Ok(false);
+    }
+    let problems = validate_file(filename)?;
+    // fixing size is only safe when the file is exactly as long as the
+    // dims say, since otherwise it isn't clear whether size or dims are wrong
+    let length_wrong = problems
+        .iter()
+        .any(|p| matches!(p, Problem::Truncated { .. } | Problem::TrailingBytes { .. }));
+    let (mut changes, mut unfixed) = (Vec::new(), Vec::new());
+    for p in &problems {
+        println!("{}: {}", filename, p);
+        match *p {
+            Problem::SizeMismatch { size, expected } if !length_wrong => {
+                changes.push(Change::Field {
+                    offset: 32,
+                    name: "size".to_string(),
+                    old: size,
+                    new: expected,
+                })
+            }
+            Problem::TrailingBytes { .. } => {}
+            _ => unfixed.push(p),
+        }
+    }
+    let plan = Plan {
+        path: filename.to_string(),
+        changes,
+    };
+    plan.run(dry_run, yes)?;
+    for p in &unfixed {
+        println!("{}: can't fix: {}", filename, p);
+    }
+    Ok(unfixed.is_empty())
+}
+
+/// Print a header field, or all of them, straight from the file's bytes.
+fn header_field(command: &Command, filename: &str) -> Result<(), Box<dyn Error>> {
+    let mut r = RawArrayFile::valid_open(filename)?;
+    match command {
+        Command::Head { .. } => {
+            let _magic = r.u64()?;
+            println!("flags: {:b}", r.u64()?);
+            println!("eltype: {}", r.u64()?);
+            println!("elbyte: {}", r.u64()?);
+            println!("size: {}", r.u64()?);
+            let ndims = r.u64()?;
+            println!("ndims: {}", ndims);
+            println!("dims: ");
+            for _ in 0..ndims {
+                println!("\t- {}", r.u64()?);
+            }
+        }
+        Command::Flags { .. } => println!("{:x}", r.u64_at(8)?),
+        Command::Eltype { .. } => println!("{}", r.u64_at(16)?),
+        Command::Elbyte { .. } => println!("{}", r.u64_at(24)?),
+        Command::Size { .. } => println!("{}", r.u64_at(32)?),
+        Command::Ndims { .. } => println!("{}", r.u64_at(40)?),
+        Command::Dims { .. } => {
+            r.seek(40)?;
+            let ndims = r.u64()?;
+            for _ in 0..ndims {
+                print!("{} ", r.u64()?)
+            }
+            println!();
+        }
+        Command::Data { .. } => {
+            let ndims = r.u64_at(40)?;
+            println!("{}", 40 + ndims * 8);
+        }
+        _ => unreachable!("not a header field"),
+    }
+    Ok(())
+}
+
+/// Run a command. Returns `false` if it found differences or problems.
+fn run(command: Command) -> Result<bool, Box<dyn Error>> {
+    match command {
+        Command::Head { ref file }
+        | Command::Flags { ref file }
+        | Command::Eltype { ref file }
+        | Command::Elbyte { ref file }
+        | Command::Size { ref file }
+        | Command::Ndims { ref file }
+        | Command::Dims { ref file }
+        | Command::Data { ref file } => header_field(&command, file)?,
+        Command::Reshape(args) => reshape(args)?,
+        Command::Diff(args) => {
+            let mut vf = ValueFormatter::new();
+            if let Some(p) = args.precision {
+                vf = vf.precision(p);
+            }
+            return diff(&args.a, &args.b, args.rtol, args.atol, args.max, &vf);
+        }
+        Command::Ls { dir, json } => ls(&dir, json)?,
+        Command::TreeDiff { dir_a, dir_b } => return tree_diff(&dir_a, &dir_b),
+        Command::Split(args) => split(args)?,
+        Command::Cat(args) => concat_files(&args.inputs, args.axis, args.out)?,
+        Command::Validate { file } => return validate(&file),
+        Command::Fix(args) => return fix(args),
+        Command::Slice(args) => {
+            let h = RawArrayHeader::read(&args.input)?;
+            let (ranges, steps) = parse_ranges(&args.range, &h.dims)?;
+            slice_file_with_steps(&args.input, &ranges, &steps, args.output)?
+        }
+        Command::Cast(args) => {
+            let opts = WriteOptions::new().dtype(args.to).magnitude(args.magnitude);
+            recode_file(args.input, args.output, &opts)?
+        }
+        Command::Peek(args) => peek(args)?,
+        Command::Json { file } => println!("{}", json::ra_to_json(&file)?),
+        Command::Hash(args) => hash(args)?,
+        #[cfg(feature = "nifti")]
+        Command::Nifti { input, output } => {
+            use rawarray::nifti;
+            if input.ends_with(".nii") || input.ends_with(".nii.gz") {
+                nifti::nifti_to_ra(&input, &output)?;
+            } else {
+                nifti::ra_to_nifti(&input, &output)?;
+            }
+        }
+        #[cfg(feature = "image")]
+        Command::Png(args) => png(args)?,
+        #[cfg(feature = "watch")]
+        Command::Watch { file, stats } => watch(&file, stats)?,
+        Command::Csv(args) => csv(args)?,
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "ra", &mut io::stdout())
+        }
+    }
+    Ok(true)
+}
+
+fn main() {
+    // clap exits with 2 on usage errors, and 0 for --help and --version
+    let cli = Cli::parse();
+    match run(cli.command) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("ra: {}", e);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Constructing a `RawArray` with its shape and flags in one go.
+
+use crate::attrs::AttrValue;
+use crate::provenance::Provenance;
+use crate::{codec, RawArray, RawArrayType, ALL_KNOWN_FLAGS, FLAG_ATTRS, FLAG_CRC, NATIVE_FLAGS};
+use std::collections::BTreeMap;
+use std::io::{self, Error, ErrorKind};
+
+/// Builder for a `RawArray<T>`, checking everything at `build()`.
+/// ```
+/// # use std::io;
+/// use rawarray::RawArrayBuilder;
+/// # fn main() -> io::Result<()> {
+/// let ra = RawArrayBuilder::new()
+///     .dims(&[2, 3])
+///     .checksum(true)
+///     .attr("units", "mm")
+///     .data(vec![0u16; 6])
+///     .build()?;
+/// assert_eq!(ra.dims(), vec![2, 3]);
+/// assert!(ra.checksum());
+///
+/// let bad = RawArrayBuilder::new().dims(&[4, 4]).data(vec![0u16; 6]).build();
+/// assert!(bad.is_err());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RawArrayBuilder<T: RawArrayType> {
+    dims: Option<Vec<u64>>,
+    flags: u64,
+    data: Vec<T>,
+    attrs: BTreeMap<String, AttrValue>,
+}
+
+impl<T: RawArrayType> Default for RawArrayBuilder<T> {
+    fn default() -> Self {
+        RawArrayBuilder {
+            dims: None,
+            flags: NATIVE_FLAGS,
+            data: Vec::new(),
+            attrs: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: RawArrayType> RawArrayBuilder<T> {
+    /// An empty, one-dimensional array
+    pub fn new() -> RawArrayBuilder<T> {
+        RawArrayBuilder::default()
+    }
+
+    /// Dimensions, first one fastest varying. Without this the array is
+    /// one-dimensional.
+    pub fn dims(mut self, dims: &[u64]) -> RawArrayBuilder<T> {
+        self.dims = Some(dims.to_vec());
+        self
+    }
+
+    /// Header flags. The big-endian bit describes the byte order of the
+    /// data given to `data()`, which is normally native.
+    pub fn flags(mut self, flags: u64) -> RawArrayBuilder<T> {
+        self.flags = flags;
+        self
+    }
+
+    /// Whether to store a checksum of the data when writing
+    pub fn checksum(mut self, enabled: bool) -> RawArrayBuilder<T> {
+        if enabled {
+            self.flags |= FLAG_CRC;
+        } else {
+            self.flags &= !FLAG_CRC;
+        }
+        self
+    }
+
+    /// A named attribute
+    pub fn attr<V: Into<AttrValue>>(mut self, key: &str, value: V) -> RawArrayBuilder<T> {
+        self.attrs.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Where the array came from; see `Provenance`.
+    pub fn provenance(mut self, provenance: &Provenance) -> RawArrayBuilder<T> {
+        provenance.to_attrs(&mut self.attrs);
+        self
+    }
+
+    /// The elements, in column-major order
+    pub fn data(mut self, data: Vec<T>) -> RawArrayBuilder<T> {
+        self.data = data;
+        self
+    }
+
+    /// Check the pieces fit together and make the array.
+    pub fn build(self) -> io::Result<RawArray<T>> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+        if self.flags & !ALL_KNOWN_FLAGS != 0 {
+            return Err(invalid(format!("unknown flags {:#x}", self.flags)));
+        }
+        if self.flags & (codec::CODEC_FLAGS | FLAG_ATTRS) != 0 {
+            return Err(invalid(
+                "codec and attribute flags are set when writing, not in memory".to_string(),
+            ));
+        }
+        let len = self.data.len() as u64;
+        let dims = self.dims.unwrap_or_else(|| vec![len]);
+        let nelem = dims.iter().try_fold(1u64, |acc, &d| acc.checked_mul(d));
+        if nelem != Some(self.data.len() as u64) {
+            return Err(invalid(format!(
+                "dims {:?} don't match {} elements of data",
+                dims,
+                self.data.len()
+            )));
+        }
+        let mut ra = RawArray::from_parts(dims, self.data);
+        ra.flags = self.flags;
+        ra.attrs = self.attrs;
+        Ok(ra)
+    }
+}
@@ -0,0 +1,142 @@
+//! Conversion between arrays and `candle_core::Tensor`, behind the `candle`
+//! feature, so `.ra` files can be fed to models as inputs or weights.
+//!
+//! Shapes are kept as they are, element `[i, j]` of the array being
+//! element `[i, j]` of the tensor. Tensors are row major, so the data is
+//! reordered on the way in and out.
+
+use crate::convert::swap_bytes;
+use crate::{as_u8_slice, as_u8_slice_mut, DType, Endianness, RawArray, RawArrayType};
+use candle_core::{Device, Tensor};
+use half::{bf16, f16};
+use std::convert::TryFrom;
+use std::io::{self, Error, ErrorKind};
+
+/// Element types that candle has a tensor type for.
+pub trait CandleElement: RawArrayType {
+    /// The matching tensor element type
+    const DTYPE: candle_core::DType;
+
+    /// The elements of `t`, flattened in row-major order
+    fn from_tensor(t: &Tensor) -> candle_core::Result<Vec<Self>>;
+}
+
+macro_rules! candle_element {
+    ($($t:ty => $d:ident),*) => {
+        $(
+            impl CandleElement for $t {
+                const DTYPE: candle_core::DType = candle_core::DType::$d;
+
+                fn from_tensor(t: &Tensor) -> candle_core::Result<Vec<$t>> {
+                    t.flatten_all()?.to_vec1()
+                }
+            }
+        )*
+    };
+}
+
+candle_element!(u8 => U8, u32 => U32, i16 => I16, i32 => I32, i64 => I64, f32 => F32, f64 => F64);
+
+// candle uses another major version of `half`, so half floats go by way of
+// `f32`, which holds every one of their values exactly
+macro_rules! candle_half {
+    ($($t:ty => $d:ident),*) => {
+        $(
+            impl CandleElement for $t {
+                const DTYPE: candle_core::DType = candle_core::DType::$d;
+
+                fn from_tensor(t: &Tensor) -> candle_core::Result<Vec<$t>> {
+                    let data = t
+                        .flatten_all()?
+                        .to_dtype(candle_core::DType::F32)?
+                        .to_vec1::<f32>()?;
+                    Ok(data.into_iter().map(<$t>::from_f32).collect())
+                }
+            }
+        )*
+    };
+}
+
+candle_half!(f16 => F16, bf16 => BF16);
+
+fn candle_error(e: candle_core::Error) -> Error {
+    Error::other(e.to_string())
+}
+
+impl<T: CandleElement> RawArray<T> {
+    /// Copy the array into a tensor of the same shape on `device`.
+    /// ```
+    /// # use std::io;
+    /// use candle_core::{Device, Tensor};
+    /// use half::bf16;
+    /// use rawarray::RawArray;
+    /// use std::convert::TryFrom;
+    /// # fn main() -> io::Result<()> {
+    /// let ra = RawArray::from_iter_with_dims((0..6).map(|i| i as f32), &[2, 3])?;
+    /// let t = ra.to_tensor(&Device::Cpu)?;
+    /// assert_eq!(t.dims(), &[2, 3]);
+    /// assert_eq!(t.to_vec2::<f32>().unwrap(), vec![vec![0.0, 2.0, 4.0], vec![1.0, 3.0, 5.0]]);
+    ///
+    /// assert_eq!(RawArray::<f32>::try_from(&t)?, ra);
+    /// assert!(RawArray::<i64>::try_from(&t).is_err());
+    ///
+    /// let weights: RawArray<bf16> = vec![bf16::from_f32(0.5); 4].into();
+    /// assert_eq!(RawArray::<bf16>::try_from(Tensor::from(&weights))?, weights);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_tensor(&self, device: &Device) -> io::Result<Tensor> {
+        let mut data = self.to_row_major();
+        if self.endianness() != Endianness::native() {
+            swap_bytes(as_u8_slice_mut(&mut data), DType::of::<T>())?;
+        }
+        let shape: Vec<usize> = self.dims.iter().map(|&d| d as usize).collect();
+        Tensor::from_raw_buffer(as_u8_slice(&data), T::DTYPE, &shape, device).map_err(candle_error)
+    }
+}
+
+impl<T: CandleElement> From<&RawArray<T>> for Tensor {
+    /// Copy the array into a tensor of the same shape on the CPU.
+    fn from(ra: &RawArray<T>) -> Tensor {
+        ra.to_tensor(&Device::Cpu)
+            .expect("tensor on the CPU of the array's shape")
+    }
+}
+
+impl<T: CandleElement> From<RawArray<T>> for Tensor {
+    /// Copy the array into a tensor of the same shape on the CPU.
+    fn from(ra: RawArray<T>) -> Tensor {
+        Tensor::from(&ra)
+    }
+}
+
+impl<T: CandleElement> TryFrom<&Tensor> for RawArray<T> {
+    type Error = Error;
+
+    /// Copy a tensor on any device into an array of the same shape. A
+    /// scalar tensor becomes an array of one element. Fails with
+    /// `ErrorKind::InvalidInput` if the tensor's elements aren't `T`s;
+    /// convert it with `Tensor::to_dtype` first.
+    fn try_from(t: &Tensor) -> io::Result<RawArray<T>> {
+        if t.dtype() != T::DTYPE {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("tensor holds {:?} elements, not {:?}", t.dtype(), T::DTYPE),
+            ));
+        }
+        let mut dims: Vec<u64> = t.dims().iter().map(|&d| d as u64).collect();
+        if dims.is_empty() {
+            dims.push(1);
+        }
+        RawArray::from_row_major(T::from_tensor(t).map_err(candle_error)?, &dims)
+    }
+}
+
+impl<T: CandleElement> TryFrom<Tensor> for RawArray<T> {
+    type Error = Error;
+
+    /// Copy a tensor into an array, as for `&Tensor`.
+    fn try_from(t: Tensor) -> io::Result<RawArray<T>> {
+        RawArray::try_from(&t)
+    }
+}
@@ -0,0 +1,198 @@
+//! RawArray files in cloud object stores, behind the `object_store`
+//! feature, so a cluster can write results straight to S3, Google Cloud
+//! Storage, or Azure Blob Storage.
+//!
+//! Objects are named by URIs such as `s3://bucket/scans/042.ra`,
+//! `gs://bucket/042.ra`, or `az://container/042.ra`, with credentials and
+//! regions taken from the usual `AWS_`, `GOOGLE_`, and `AZURE_`
+//! environment variables. Reads fetch only the byte ranges asked for.
+//! Writes are streamed to the store as a multipart upload, a few
+//! megabytes at a time, and the object appears only once the whole file
+//! has been written. Objects can't be modified in place, so appending is
+//! not supported.
+
+use crate::{OpenMode, RaStorage, RawArray, RawArrayType};
+use object_store::path::Path as ObjectPath;
+use object_store::{ObjectStore, WriteMultipart};
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::runtime::Runtime;
+
+/// Parts being uploaded at once by one writer
+const MAX_UPLOADS: usize = 8;
+
+fn store_error(e: object_store::Error) -> Error {
+    match e {
+        object_store::Error::NotFound { .. } => Error::new(ErrorKind::NotFound, e),
+        e => Error::other(e),
+    }
+}
+
+fn unsupported(msg: &str) -> Error {
+    Error::new(ErrorKind::Unsupported, msg)
+}
+
+/// Objects in a cloud object store, for `RawArray::read_from_storage`,
+/// `write_to_storage`, and the like. Paths are URIs, or keys within the
+/// store given to `with_store`.
+/// ```
+/// # use std::io;
+/// use object_store::memory::InMemory;
+/// use rawarray::cloud::ObjectStorage;
+/// use rawarray::RawArray;
+/// use std::sync::Arc;
+/// # fn main() -> io::Result<()> {
+/// let bucket = ObjectStorage::with_store(Arc::new(InMemory::new()))?;
+/// let ra = RawArray::from_iter_with_dims(0..12u32, &[3, 4])?;
+/// ra.write_to_storage(&bucket, "scans/042.ra")?;
+/// assert_eq!(RawArray::<u32>::read_from_storage(&bucket, "scans/042.ra")?, ra);
+/// assert!(RawArray::append_to_storage(&bucket, "scans/042.ra", &[12u32]).is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub struct ObjectStorage {
+    store: Option<Arc<dyn ObjectStore>>,
+    runtime: Runtime,
+}
+
+/// An open object: one being read, or one being uploaded
+pub struct Object {
+    store: Arc<dyn ObjectStore>,
+    location: ObjectPath,
+    len: u64,
+    upload: Option<WriteMultipart>,
+}
+
+impl ObjectStorage {
+    /// Objects named by URIs, in whichever store each URI names
+    pub fn new() -> io::Result<ObjectStorage> {
+        Ok(ObjectStorage {
+            store: None,
+            runtime: tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?,
+        })
+    }
+
+    /// Objects in `store`, named by their keys in it
+    pub fn with_store(store: Arc<dyn ObjectStore>) -> io::Result<ObjectStorage> {
+        let mut storage = ObjectStorage::new()?;
+        storage.store = Some(store);
+        Ok(storage)
+    }
+
+    /// The store holding `path` and its key there
+    fn locate(&self, path: &Path) -> io::Result<(Arc<dyn ObjectStore>, ObjectPath)> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "object path isn't UTF-8"))?;
+        let bad_path = |e: object_store::path::Error| Error::new(ErrorKind::InvalidInput, e);
+        if let Some(store) = &self.store {
+            return Ok((store.clone(), ObjectPath::parse(path).map_err(bad_path)?));
+        }
+        let url = url::Url::parse(path).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        // the builders read the same variables as their from_env()
+        let options = std::env::vars()
+            .filter(|(k, _)| {
+                ["AWS_", "GOOGLE_", "AZURE_"]
+                    .iter()
+                    .any(|p| k.starts_with(p))
+            })
+            .map(|(k, v)| (k.to_ascii_lowercase(), v));
+        let (store, location) = object_store::parse_url_opts(&url, options).map_err(store_error)?;
+        Ok((Arc::from(store), location))
+    }
+}
+
+impl RaStorage for ObjectStorage {
+    type Handle = Object;
+
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<Object> {
+        let (store, location) = self.locate(path)?;
+        let (len, upload) = match mode {
+            OpenMode::Read => {
+                let meta = self.runtime.block_on(store.head(&location));
+                (meta.map_err(store_error)?.size, None)
+            }
+            OpenMode::ReadWrite => {
+                return Err(unsupported("objects can't be modified in place"));
+            }
+            OpenMode::Create => {
+                let upload = self.runtime.block_on(store.put_multipart(&location));
+                (0, Some(WriteMultipart::new(upload.map_err(store_error)?)))
+            }
+        };
+        Ok(Object {
+            store,
+            location,
+            len,
+            upload,
+        })
+    }
+
+    fn read_at(&self, object: &Object, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= object.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let end = object.len.min(offset + buf.len() as u64);
+        let bytes = self
+            .runtime
+            .block_on(object.store.get_range(&object.location, offset..end))
+            .map_err(store_error)?;
+        buf[..bytes.len()].copy_from_slice(&bytes);
+        Ok(bytes.len())
+    }
+
+    fn write_at(&self, object: &mut Object, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        let upload = object
+            .upload
+            .as_mut()
+            .ok_or_else(|| unsupported("object isn't open for writing"))?;
+        if offset != object.len {
+            return Err(unsupported("objects can only be written front to back"));
+        }
+        self.runtime
+            .block_on(async {
+                // parts are uploaded as they fill, while the writer waits
+                upload.write(buf);
+                upload.wait_for_capacity(MAX_UPLOADS).await
+            })
+            .map_err(store_error)?;
+        object.len += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn len(&self, object: &Object) -> io::Result<u64> {
+        Ok(object.len)
+    }
+
+    fn set_len(&self, _: &mut Object, _: u64) -> io::Result<()> {
+        Err(unsupported("objects can't be resized"))
+    }
+
+    /// Finish the upload, which makes the object visible. An upload that
+    /// is never finished leaves no object behind.
+    fn flush(&self, object: &mut Object) -> io::Result<()> {
+        if let Some(upload) = object.upload.take() {
+            self.runtime
+                .block_on(upload.finish())
+                .map_err(store_error)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Read the array at `uri`, such as `s3://bucket/scan.ra`, from a
+    /// cloud object store. See the `cloud` module.
+    pub fn read_uri(uri: &str) -> io::Result<RawArray<T>> {
+        RawArray::read_from_storage(&ObjectStorage::new()?, uri)
+    }
+
+    /// Upload the array to `uri`, such as `s3://bucket/scan.ra`, in a
+    /// cloud object store. See the `cloud` module.
+    pub fn write_uri(&self, uri: &str) -> io::Result<()> {
+        self.write_to_storage(&ObjectStorage::new()?, uri)
+    }
+}
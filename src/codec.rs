@@ -0,0 +1,452 @@
+//! Encodings for the data section.
+//!
+//! An encoded file keeps the decoded length in the `size` header field, so
+//! the header stays consistent with `dims`. How the data section is laid
+//! out depends on the flags:
+//!
+//! * `FLAG_ZSTD` or `FLAG_LZ4`: a `u64` payload length, then the payload.
+//!   These are shorthand for the two common compressors.
+//! * `FLAG_ENCODED`: a `u64` codec ID, a `u64` payload length, then the
+//!   payload. This covers every other codec, including ones registered by
+//!   applications with `register_codec`.
+//!
+//! `FLAG_ENCODED` is bit 1, which the original format reserved for
+//! run-length encoding without a codec ID; see the README for what this
+//! means for readers in other languages.
+//!
+//! Zstd and LZ4 sit behind cargo features of the same name. Files using a
+//! codec that isn't available fail to read with `ErrorKind::Unsupported`.
+
+use crate::FLAG_ENCODED;
+use std::io::{self, Error, ErrorKind};
+use std::sync::{Arc, OnceLock, RwLock};
+
+pub(crate) use rawarray_core::{FLAG_LZ4, FLAG_ZSTD};
+
+pub(crate) const CODEC_FLAGS: u64 = FLAG_ZSTD | FLAG_LZ4 | FLAG_ENCODED;
+
+/// Codec IDs below this are reserved for the crate's built-in codecs.
+pub const FIRST_USER_CODEC_ID: u64 = 1 << 16;
+
+const RLE_ID: u64 = 1;
+const DELTA_ID: u64 = 2;
+const ZSTD_ID: u64 = 3;
+const LZ4_ID: u64 = 4;
+
+/// An encoding of the data section, identified in files by its ID.
+///
+/// Implement this to store data in a domain-specific encoding, e.g.
+/// wavelet compression, then call `register_codec` so files using it can
+/// be read back.
+/// ```
+/// # use std::io;
+/// use rawarray::codec::{register_codec, Codec, FIRST_USER_CODEC_ID};
+/// use rawarray::RawArray;
+///
+/// /// Stores bytes inverted, as a stand-in for something useful
+/// struct Invert;
+///
+/// impl Codec for Invert {
+///     fn id(&self) -> u64 { FIRST_USER_CODEC_ID + 1 }
+///     fn name(&self) -> &str { "invert" }
+///     fn encode(&self, data: &[u8], _elbyte: usize) -> io::Result<Vec<u8>> {
+///         Ok(data.iter().map(|b| !b).collect())
+///     }
+///     fn decode(&self, payload: &[u8], out: &mut [u8], _elbyte: usize) -> io::Result<()> {
+///         for (o, b) in out.iter_mut().zip(payload) {
+///             *o = !b;
+///         }
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() -> io::Result<()> {
+/// register_codec(Invert)?;
+/// let ra: RawArray<u16> = vec![1, 2, 3].into();
+/// ra.write_compressed("inverted.ra", &Invert)?;
+/// assert_eq!(RawArray::<u16>::read("inverted.ra")?, ra);
+/// # std::fs::remove_file("inverted.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait Codec: Send + Sync {
+    /// ID recorded in the file. Must be unique, and at least
+    /// `FIRST_USER_CODEC_ID` for codecs defined outside this crate.
+    fn id(&self) -> u64;
+
+    /// Short name for messages
+    fn name(&self) -> &str;
+
+    /// Encode the raw little-endian bytes of the data section, made of
+    /// elements `elbyte` bytes wide.
+    fn encode(&self, data: &[u8], elbyte: usize) -> io::Result<Vec<u8>>;
+
+    /// Decode `payload` into `out`, which is exactly the decoded length.
+    fn decode(&self, payload: &[u8], out: &mut [u8], elbyte: usize) -> io::Result<()>;
+
+    /// The most bytes `payload` could decode to, if the codec can bound
+    /// it. Readers refuse a header claiming more than this before
+    /// allocating for the decoded data. The default gives no bound.
+    fn max_decoded_len(&self, _payload: &[u8], _elbyte: usize) -> Option<u64> {
+        None
+    }
+}
+
+fn registry() -> &'static RwLock<Vec<Arc<dyn Codec>>> {
+    static REGISTRY: OnceLock<RwLock<Vec<Arc<dyn Codec>>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        RwLock::new(vec![
+            Arc::new(Rle),
+            Arc::new(Delta),
+            Arc::new(Zstd(0)),
+            Arc::new(Lz4),
+        ])
+    })
+}
+
+/// Make a codec available for decoding files that use its ID, replacing
+/// any codec previously registered with the same ID. IDs below
+/// `FIRST_USER_CODEC_ID` are refused, so the built-in codecs can't be
+/// replaced.
+/// ```
+/// # use std::io;
+/// use rawarray::codec::{register_codec, Codec};
+///
+/// struct Rle2;
+///
+/// impl Codec for Rle2 {
+///     fn id(&self) -> u64 { 1 }
+///     fn name(&self) -> &str { "rle2" }
+///     fn encode(&self, data: &[u8], _elbyte: usize) -> io::Result<Vec<u8>> {
+///         Ok(data.to_vec())
+///     }
+///     fn decode(&self, payload: &[u8], out: &mut [u8], _elbyte: usize) -> io::Result<()> {
+///         out.copy_from_slice(payload);
+///         Ok(())
+///     }
+/// }
+///
+/// assert_eq!(register_codec(Rle2).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+/// ```
+pub fn register_codec<C: Codec + 'static>(codec: C) -> io::Result<()> {
+    if codec.id() < FIRST_USER_CODEC_ID {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "codec {} has ID {}, but IDs below {} are reserved for built-in codecs",
+                codec.name(),
+                codec.id(),
+                FIRST_USER_CODEC_ID
+            ),
+        ));
+    }
+    let mut codecs = registry().write().unwrap();
+    codecs.retain(|c| c.id() != codec.id());
+    codecs.push(Arc::new(codec));
+    Ok(())
+}
+
+/// Look up a codec by the ID recorded in a file.
+pub(crate) fn lookup(id: u64) -> io::Result<Arc<dyn Codec>> {
+    registry()
+        .read()
+        .unwrap()
+        .iter()
+        .find(|c| c.id() == id)
+        .cloned()
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::Unsupported,
+                format!("data is encoded with unknown codec {}", id),
+            )
+        })
+}
+
+/// Flag bit marking data encoded with `codec`.
+pub(crate) fn flag_for(codec: &dyn Codec) -> u64 {
+    match codec.id() {
+        ZSTD_ID => FLAG_ZSTD,
+        LZ4_ID => FLAG_LZ4,
+        _ => FLAG_ENCODED,
+    }
+}
+
+/// Codec for data written with the codec flags in `flags`, and whether
+/// its ID is stored in the data section.
+pub(crate) fn codec_for_flags(flags: u64) -> Option<(u64, bool)> {
+    if flags & FLAG_ZSTD != 0 {
+        Some((ZSTD_ID, false))
+    } else if flags & FLAG_LZ4 != 0 {
+        Some((LZ4_ID, false))
+    } else if flags & FLAG_ENCODED != 0 {
+        Some((0, true))
+    } else {
+        None
+    }
+}
+
+#[cfg(not(all(feature = "zstd", feature = "lz4")))]
+fn unsupported(name: &str) -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        format!(
+            "{} compression requires building rawarray with the `{}` feature",
+            name, name
+        ),
+    )
+}
+
+/// Check that `payload` could decode to the `size` bytes a header
+/// declares, before anything that size is allocated.
+pub(crate) fn check_decoded_len(
+    codec: &dyn Codec,
+    payload: &[u8],
+    size: u64,
+    elbyte: usize,
+) -> io::Result<()> {
+    match codec.max_decoded_len(payload, elbyte) {
+        Some(max) if size > max => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "header declares {} bytes of data, but its {} payload holds at most {}",
+                size,
+                codec.name(),
+                max
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn length_mismatch() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "decoded data length doesn't match header size",
+    )
+}
+
+/// Zstandard at the given level (1–22, 3 is a good default).
+/// Requires the `zstd` feature.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Zstd(pub i32);
+
+impl Codec for Zstd {
+    fn id(&self) -> u64 {
+        ZSTD_ID
+    }
+
+    fn name(&self) -> &str {
+        "zstd"
+    }
+
+    #[cfg(feature = "zstd")]
+    fn encode(&self, data: &[u8], _elbyte: usize) -> io::Result<Vec<u8>> {
+        zstd::bulk::compress(data, self.0)
+    }
+
+    #[cfg(feature = "zstd")]
+    fn decode(&self, payload: &[u8], out: &mut [u8], _elbyte: usize) -> io::Result<()> {
+        if zstd::bulk::decompress_to_buffer(payload, out)? != out.len() {
+            return Err(length_mismatch());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn encode(&self, _data: &[u8], _elbyte: usize) -> io::Result<Vec<u8>> {
+        Err(unsupported("zstd"))
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    fn decode(&self, _payload: &[u8], _out: &mut [u8], _elbyte: usize) -> io::Result<()> {
+        Err(unsupported("zstd"))
+    }
+
+    fn max_decoded_len(&self, payload: &[u8], _elbyte: usize) -> Option<u64> {
+        // a zstd block of one repeated byte takes 4 bytes to describe up
+        // to 128 KiB, which is as far as the format goes
+        Some((payload.len() as u64).saturating_mul(1 << 15))
+    }
+}
+
+/// LZ4 block compression, faster but weaker than zstd.
+/// Requires the `lz4` feature.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lz4;
+
+impl Codec for Lz4 {
+    fn id(&self) -> u64 {
+        LZ4_ID
+    }
+
+    fn name(&self) -> &str {
+        "lz4"
+    }
+
+    #[cfg(feature = "lz4")]
+    fn encode(&self, data: &[u8], _elbyte: usize) -> io::Result<Vec<u8>> {
+        Ok(lz4_flex::block::compress(data))
+    }
+
+    #[cfg(feature = "lz4")]
+    fn decode(&self, payload: &[u8], out: &mut [u8], _elbyte: usize) -> io::Result<()> {
+        let n = lz4_flex::block::decompress_into(payload, out)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if n != out.len() {
+            return Err(length_mismatch());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "lz4"))]
+    fn encode(&self, _data: &[u8], _elbyte: usize) -> io::Result<Vec<u8>> {
+        Err(unsupported("lz4"))
+    }
+
+    #[cfg(not(feature = "lz4"))]
+    fn decode(&self, _payload: &[u8], _out: &mut [u8], _elbyte: usize) -> io::Result<()> {
+        Err(unsupported("lz4"))
+    }
+
+    fn max_decoded_len(&self, payload: &[u8], _elbyte: usize) -> Option<u64> {
+        // each byte of an LZ4 match length adds at most 255 bytes of output
+        Some(
+            (payload.len() as u64)
+                .saturating_mul(256)
+                .saturating_add(64),
+        )
+    }
+}
+
+/// Run-length encoding of whole elements, for integer data with long
+/// constant stretches such as masks and label maps. Each run is stored as
+/// a little-endian `u32` count followed by one element.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rle;
+
+impl Codec for Rle {
+    fn id(&self) -> u64 {
+        RLE_ID
+    }
+
+    fn name(&self) -> &str {
+        "rle"
+    }
+
+    fn encode(&self, data: &[u8], elbyte: usize) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut elems = data.chunks_exact(elbyte).peekable();
+        while let Some(e) = elems.next() {
+            let mut count: u32 = 1;
+            while count < u32::MAX && elems.peek() == Some(&e) {
+                elems.next();
+                count += 1;
+            }
+            out.extend_from_slice(&count.to_le_bytes());
+            out.extend_from_slice(e);
+        }
+        Ok(out)
+    }
+
+    fn decode(&self, payload: &[u8], out: &mut [u8], elbyte: usize) -> io::Result<()> {
+        let mut pos = 0;
+        for run in payload.chunks(4 + elbyte) {
+            if run.len() != 4 + elbyte {
+                return Err(Error::new(ErrorKind::InvalidData, "truncated RLE run"));
+            }
+            let mut count = [0u8; 4];
+            count.copy_from_slice(&run[..4]);
+            let n = u32::from_le_bytes(count) as usize * elbyte;
+            let dest = out.get_mut(pos..pos + n).ok_or_else(length_mismatch)?;
+            for chunk in dest.chunks_exact_mut(elbyte) {
+                chunk.copy_from_slice(&run[4..]);
+            }
+            pos += n;
+        }
+        if pos != out.len() {
+            return Err(length_mismatch());
+        }
+        Ok(())
+    }
+
+    fn max_decoded_len(&self, payload: &[u8], elbyte: usize) -> Option<u64> {
+        let runs = payload.chunks_exact(4 + elbyte);
+        Some(runs.fold(0u64, |n, run| {
+            let mut count = [0u8; 4];
+            count.copy_from_slice(&run[..4]);
+            let len = u64::from(u32::from_le_bytes(count)) * elbyte as u64;
+            n.saturating_add(len)
+        }))
+    }
+}
+
+/// Delta encoding: each element is replaced by its difference from the
+/// previous one, as wrapping unsigned integers of width `elbyte`. The
+/// payload is the same size as the data, but slowly varying integer
+/// signals (timestamps, counters, sorted indices) become mostly small
+/// numbers that compress well externally. Only 1, 2, 4, and 8 byte
+/// elements are supported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Delta;
+
+macro_rules! delta {
+    ($t:ty, $data:expr, $out:expr, $op:ident, $decode:expr) => {{
+        const W: usize = std::mem::size_of::<$t>();
+        let mut prev: $t = 0;
+        for (src, dst) in $data.chunks_exact(W).zip($out.chunks_exact_mut(W)) {
+            let mut b = [0u8; W];
+            b.copy_from_slice(src);
+            let x = <$t>::from_le_bytes(b);
+            let y = x.$op(prev);
+            dst.copy_from_slice(&y.to_le_bytes());
+            prev = if $decode { y } else { x };
+        }
+    }};
+}
+
+fn delta(data: &[u8], out: &mut [u8], elbyte: usize, decode: bool) -> io::Result<()> {
+    match (elbyte, decode) {
+        (1, false) => delta!(u8, data, out, wrapping_sub, false),
+        (2, false) => delta!(u16, data, out, wrapping_sub, false),
+        (4, false) => delta!(u32, data, out, wrapping_sub, false),
+        (8, false) => delta!(u64, data, out, wrapping_sub, false),
+        (1, true) => delta!(u8, data, out, wrapping_add, true),
+        (2, true) => delta!(u16, data, out, wrapping_add, true),
+        (4, true) => delta!(u32, data, out, wrapping_add, true),
+        (8, true) => delta!(u64, data, out, wrapping_add, true),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("delta encoding doesn't support {}-byte elements", elbyte),
+            ))
+        }
+    }
+    Ok(())
+}
+
+impl Codec for Delta {
+    fn id(&self) -> u64 {
+        DELTA_ID
+    }
+
+    fn name(&self) -> &str {
+        "delta"
+    }
+
+    fn encode(&self, data: &[u8], elbyte: usize) -> io::Result<Vec<u8>> {
+        let mut out = vec![0u8; data.len()];
+        delta(data, &mut out, elbyte, false)?;
+        Ok(out)
+    }
+
+    fn decode(&self, payload: &[u8], out: &mut [u8], elbyte: usize) -> io::Result<()> {
+        if payload.len() != out.len() {
+            return Err(length_mismatch());
+        }
+        delta(payload, out, elbyte, true)
+    }
+
+    fn max_decoded_len(&self, payload: &[u8], _elbyte: usize) -> Option<u64> {
+        Some(payload.len() as u64)
+    }
+}
@@ -0,0 +1,68 @@
+//! Splitting complex arrays into real ones, such as turning complex MRI
+//! images into magnitude images.
+//!
+//! The results keep the dimensions and attributes of the complex array.
+
+use crate::{Endianness, RawArray, RawArrayType};
+use num_complex::Complex;
+use num_traits::Float;
+
+impl<F> RawArray<Complex<F>>
+where
+    F: Float + RawArrayType,
+    Complex<F>: RawArrayType,
+{
+    fn map_real(&self, f: impl Fn(&Complex<F>) -> F) -> RawArray<F> {
+        assert!(
+            self.endianness() == Endianness::native(),
+            "complex helpers need data in native byte order"
+        );
+        let mut ra = RawArray::from_parts(self.dims.clone(), self.data.iter().map(f).collect());
+        ra.attrs = self.attrs.clone();
+        ra
+    }
+
+    /// Magnitude of each element.
+    /// ```
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// let z: RawArray<Complex<f32>> = vec![Complex::new(3.0, 4.0), Complex::new(0.0, -2.0)].into();
+    /// assert_eq!(z.abs().data(), vec![5.0, 2.0]);
+    /// assert_eq!(z.real().data(), vec![3.0, 0.0]);
+    /// assert_eq!(z.imag().data(), vec![4.0, -2.0]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This and the other complex helpers panic if the data isn't in
+    /// native byte order.
+    pub fn abs(&self) -> RawArray<F> {
+        self.map_real(|z| z.norm())
+    }
+
+    /// Phase of each element in radians, between -π and π.
+    /// ```
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// let z: RawArray<Complex<f64>> = vec![Complex::new(0.0, 1.0), Complex::new(-1.0, 0.0)].into();
+    /// assert_eq!(z.angle().data(), vec![std::f64::consts::FRAC_PI_2, std::f64::consts::PI]);
+    /// ```
+    pub fn angle(&self) -> RawArray<F> {
+        self.map_real(|z| z.arg())
+    }
+
+    /// Real part of each element
+    pub fn real(&self) -> RawArray<F> {
+        self.map_real(|z| z.re)
+    }
+
+    /// Imaginary part of each element
+    pub fn imag(&self) -> RawArray<F> {
+        self.map_real(|z| z.im)
+    }
+
+    /// Magnitude and phase together, as `(abs(), angle())`.
+    pub fn to_polar(&self) -> (RawArray<F>, RawArray<F>) {
+        (self.abs(), self.angle())
+    }
+}
@@ -0,0 +1,156 @@
+//! Joining files along an axis into one, the reverse of `split_file`.
+
+use crate::attrs::write_attrs;
+use crate::header::RawArrayHeader;
+use crate::recode::{open_source, write_or_remove, Source};
+use crate::{dtype_name, FLAG_ATTRS, FLAG_BIG_ENDIAN, FLAG_BITS, FLAG_CRC};
+use std::fs::File;
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+/// Concatenate the arrays in `inputs`, in order, along `axis` into a new
+/// file at `output`. The inputs must have the same element type and the
+/// same dimensions except along `axis`, which may be one past their last
+/// dimension to stack them. Files are streamed through a block at a time,
+/// so they needn't fit in memory, unless their data is encoded.
+///
+/// The output takes its byte order, checksum setting, and attributes from
+/// the first input. Checksums of the inputs are verified along the way.
+/// The output replaces `output` only once it's complete, so it can be
+/// one of the inputs, to append to it.
+/// ```
+/// # use std::io;
+/// use rawarray::{concat_files, RawArray};
+/// # fn main() -> io::Result<()> {
+/// RawArray::from_iter_with_dims(0..4u8, &[2, 2])?.write("frame0.ra")?;
+/// RawArray::from_iter_with_dims(4..8u8, &[2, 2])?.write("frame1.ra")?;
+/// concat_files(&["frame0.ra", "frame1.ra"], 2, "movie.ra")?;
+/// let movie = RawArray::<u8>::read("movie.ra")?;
+/// assert_eq!(movie.dims(), vec![2, 2, 2]);
+/// assert_eq!(movie.data(), (0..8).collect::<Vec<u8>>());
+///
+/// concat_files(&["frame0.ra", "frame1.ra"], 1, "wide.ra")?;
+/// assert_eq!(RawArray::<u8>::read("wide.ra")?.dims(), vec![2, 4]);
+/// concat_files(&["frame0.ra", "movie.ra"], 0, "bad.ra").unwrap_err();
+///
+/// concat_files(&["wide.ra", "frame1.ra"], 1, "wide.ra")?;
+/// assert_eq!(RawArray::<u8>::read("wide.ra")?.dims(), vec![2, 6]);
+/// # for f in &["frame0.ra", "frame1.ra", "movie.ra", "wide.ra"] { std::fs::remove_file(f)?; }
+/// # Ok(())
+/// # }
+/// ```
+pub fn concat_files<P: AsRef<Path>, Q: AsRef<Path>>(
+    inputs: &[P],
+    axis: usize,
+    output: Q,
+) -> io::Result<()> {
+    let invalid = |path: &Path, msg: String| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{}: {}", path.display(), msg),
+        )
+    };
+    let mut sources: Vec<Source<_>> = Vec::with_capacity(inputs.len());
+    let mut dims = Vec::new();
+    for path in inputs {
+        let path = path.as_ref();
+        let src = open_source(path)?;
+        let h = &src.header;
+        if h.flags & FLAG_BITS != 0 {
+            return Err(invalid(
+                path,
+                "can't concatenate arrays of single bits".to_string(),
+            ));
+        }
+        if axis > h.dims.len() {
+            return Err(invalid(path, format!("axis {} out of range", axis)));
+        }
+        // pad with ones, so stacking along a new last axis is like any other
+        let mut d = h.dims.clone();
+        d.resize(d.len().max(axis + 1), 1);
+        match sources.first() {
+            None => dims = d,
+            Some(first) => {
+                let want = &first.header;
+                if (h.eltype, h.elbyte) != (want.eltype, want.elbyte) {
+                    return Err(invalid(
+                        path,
+                        format!(
+                            "holds {} elements, not {}",
+                            dtype_name(h.eltype, h.elbyte),
+                            dtype_name(want.eltype, want.elbyte)
+                        ),
+                    ));
+                }
+                if d.len() != dims.len() || (0..d.len()).any(|i| i != axis && d[i] != dims[i]) {
+                    return Err(invalid(
+                        path,
+                        format!("dims {:?} don't match {:?} off axis {}", h.dims, dims, axis),
+                    ));
+                }
+                dims[axis] = dims[axis]
+                    .checked_add(d[axis])
+                    .ok_or_else(|| invalid(path, format!("axis {} grows too long", axis)))?;
+            }
+        }
+        sources.push(src);
+    }
+    if sources.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "no files to concatenate",
+        ));
+    }
+    let h = sources[0].header.clone();
+    let (dtype, endianness) = (sources[0].dtype(), sources[0].endianness());
+    let product = |dims: &[u64]| {
+        dims.iter()
+            .try_fold(1u64, |n, &d| n.checked_mul(d))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "output dims are too large"))
+    };
+    let size = product(&dims)?
+        .checked_mul(h.elbyte)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "output is too large"))?;
+    let inner = product(&dims[..axis])?;
+    let outer = product(&dims[axis + 1..])?;
+    let flags = h.flags & (FLAG_BIG_ENDIAN | FLAG_CRC | FLAG_ATTRS);
+
+    write_or_remove(output.as_ref(), |out| {
+        let mut w = BufWriter::new(File::create(out)?);
+        RawArrayHeader {
+            flags,
+            eltype: h.eltype,
+            elbyte: h.elbyte,
+            size,
+            dims: dims.clone(),
+        }
+        .write_to(&mut w)?;
+        // the first axes vary fastest, so each input gives one block in turn
+        // for every index along the axes after `axis`
+        let mut hasher = crc32fast::Hasher::new();
+        for _ in 0..outer {
+            for src in sources.iter_mut() {
+                let len = src.header.dims.get(axis).copied().unwrap_or(1);
+                src.convert_next(inner * len, dtype, endianness, |bytes| {
+                    hasher.update(bytes);
+                    w.write_all(bytes)
+                })?;
+            }
+        }
+        if flags & FLAG_CRC != 0 {
+            w.write_all(&u64::from(hasher.finalize()).to_le_bytes())?;
+        }
+        let mut sources = sources.drain(..);
+        let attrs = match sources.next() {
+            Some(src) => src.finish()?,
+            None => Default::default(),
+        };
+        for src in sources {
+            src.finish()?;
+        }
+        if !attrs.is_empty() {
+            write_attrs(&mut w, &attrs)?;
+        }
+        w.flush()
+    })
+}
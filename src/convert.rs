@@ -0,0 +1,596 @@
+//! Conversion of raw element bytes between element types and byte orders.
+//!
+//! Elements are handled as one scalar component, or two for complex
+//! numbers. Conversions follow Rust's `as` semantics for floats to integers
+//! (round toward zero, saturate, NaN becomes 0), saturate between integer
+//! widths, and give real values a zero imaginary part when converting to
+//! complex. Complex to real is refused, because there is more than one
+//! sensible answer, unless magnitudes are asked for.
+
+use crate::{as_u8_slice, as_u8_slice_mut, dtype_name, zeroed_vec, RawArrayType};
+use half::prelude::*;
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{self, Error, ErrorKind};
+use std::mem;
+
+/// Element type as recorded in a header: a type code and a width in bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DType {
+    /// Type code, as returned by `RawArrayType::ra_type_code`
+    pub eltype: u64,
+    /// Width of one element in bytes
+    pub elbyte: u64,
+}
+
+impl DType {
+    /// The element type of `T`.
+    /// ```
+    /// use rawarray::DType;
+    /// assert_eq!(DType::of::<f32>(), DType { eltype: 3, elbyte: 4 });
+    /// ```
+    pub fn of<T: RawArrayType>() -> DType {
+        DType {
+            eltype: T::ra_type_code(),
+            elbyte: mem::size_of::<T>() as u64,
+        }
+    }
+
+    /// Parse a name like `f32`, `u8`, `bf16`, `c64` (two `f32`s), or `ci32`
+    /// (two `i16`s), as produced by `dtype_name`.
+    /// ```
+    /// use rawarray::DType;
+    /// assert_eq!(DType::parse("c64"), Some(DType { eltype: 4, elbyte: 8 }));
+    /// assert_eq!(DType::parse("f24"), None);
+    /// ```
+    pub fn parse(name: &str) -> Option<DType> {
+        let (eltype, bits) = if let Some(b) = name.strip_prefix("bf") {
+            (5, b)
+        } else if let Some(b) = name.strip_prefix("ci") {
+            (6, b)
+        } else {
+            let code = match name.get(..1)? {
+                "i" => 1,
+                "u" => 2,
+                "f" => 3,
+                "c" => 4,
+                _ => return None,
+            };
+            (code, &name[1..])
+        };
+        let dtype = DType {
+            eltype,
+            elbyte: bits.parse::<u64>().ok()? / 8,
+        };
+        if dtype.is_known() && dtype_name(dtype.eltype, dtype.elbyte) == name {
+            Some(dtype)
+        } else {
+            None
+        }
+    }
+
+    /// Whether this crate knows how to interpret the element bytes
+    pub(crate) fn is_known(self) -> bool {
+        matches!(
+            (self.eltype, self.elbyte),
+            (1, 1)
+                | (1, 2)
+                | (1, 4)
+                | (1, 8)
+                | (1, 16)
+                | (2, 1)
+                | (2, 2)
+                | (2, 4)
+                | (2, 8)
+                | (2, 16)
+                | (3, 2)
+                | (3, 4)
+                | (3, 8)
+                | (4, 8)
+                | (4, 16)
+                | (5, 2)
+                | (6, 2)
+                | (6, 4)
+                | (6, 8)
+        )
+    }
+
+    /// Number of scalar components in one element
+    pub(crate) fn components(self) -> usize {
+        match self.eltype {
+            4 | 6 => 2,
+            _ => 1,
+        }
+    }
+
+    /// Real type wide enough for the magnitudes of complex elements of
+    /// this type: `f64` for `c128` and `ci64`, `f32` otherwise
+    pub(crate) fn magnitude(self) -> DType {
+        let wide = (self.eltype, self.elbyte) == (4, 16) || (self.eltype, self.elbyte) == (6, 8);
+        DType {
+            eltype: 3,
+            elbyte: if wide { 8 } else { 4 },
+        }
+    }
+
+    /// Type code and width of one scalar component
+    pub(crate) fn component(self) -> (u64, usize) {
+        match self.eltype {
+            4 => (3, self.elbyte as usize / 2),
+            6 => (1, self.elbyte as usize / 2),
+            code => (code, self.elbyte as usize),
+        }
+    }
+}
+
+impl fmt::Display for DType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&dtype_name(self.eltype, self.elbyte))
+    }
+}
+
+/// Byte order of the elements in a data section. Headers are always
+/// little endian.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Endianness {
+    /// Least significant byte first, the format's default
+    #[default]
+    Little,
+    /// Most significant byte first
+    Big,
+}
+
+impl Endianness {
+    /// Byte order of the machine we're running on
+    pub fn native() -> Endianness {
+        if cfg!(target_endian = "big") {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        }
+    }
+}
+
+/// Reverse the bytes of every scalar component of `dtype` elements in
+/// `bytes`, converting between little and big endian.
+pub(crate) fn swap_bytes(bytes: &mut [u8], dtype: DType) -> io::Result<()> {
+    if dtype.eltype == 0 && dtype.elbyte > 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "can't change the byte order of user-defined elements",
+        ));
+    }
+    match dtype.component().1 {
+        1 => {}
+        2 => swap_fixed::<2>(bytes),
+        4 => swap_fixed::<4>(bytes),
+        8 => swap_fixed::<8>(bytes),
+        16 => swap_fixed::<16>(bytes),
+        width => {
+            for c in bytes.chunks_exact_mut(width) {
+                c.reverse();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reverse every `W` bytes. With the width known, this compiles to vector
+/// shuffles rather than a loop per element.
+fn swap_fixed<const W: usize>(bytes: &mut [u8]) {
+    for c in bytes.chunks_exact_mut(W) {
+        c.reverse();
+    }
+}
+
+/// Copy `data` into a new buffer of little-endian bytes, the format's
+/// default layout. User-defined elements are copied as they are in memory.
+/// ```
+/// let bytes = rawarray::elements_to_le_bytes(&[1u16, 0x0203]);
+/// assert_eq!(bytes, vec![1, 0, 3, 2]);
+/// ```
+pub fn elements_to_le_bytes<T: RawArrayType>(data: &[T]) -> Vec<u8> {
+    let mut bytes = as_u8_slice(data).to_vec();
+    let dtype = DType::of::<T>();
+    if Endianness::native() == Endianness::Big && dtype.eltype != 0 {
+        swap_bytes(&mut bytes, dtype).expect("only user-defined types can't be swapped");
+    }
+    bytes
+}
+
+/// Interpret `bytes` as elements of type `T` stored in byte order
+/// `endianness`. The bytes don't need to be aligned.
+///
+/// Fails if the length isn't a whole number of elements, or if the
+/// byte order of user-defined elements would need to change.
+/// ```
+/// use rawarray::Endianness;
+/// let v: Vec<i16> =
+///     rawarray::elements_from_bytes_with_endianness(&[0, 1, 255, 254], Endianness::Big)
+///         .unwrap();
+/// assert_eq!(v, vec![1, -2]);
+/// ```
+pub fn elements_from_bytes_with_endianness<T: RawArrayType>(
+    bytes: &[u8],
+    endianness: Endianness,
+) -> io::Result<Vec<T>> {
+    let width = mem::size_of::<T>();
+    if width == 0 || bytes.len() % width != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} bytes is not a whole number of {} byte elements",
+                bytes.len(),
+                width
+            ),
+        ));
+    }
+    let mut data: Vec<T> = zeroed_vec(bytes.len() / width);
+    let out = as_u8_slice_mut(&mut data);
+    out.copy_from_slice(bytes);
+    if endianness != Endianness::native() {
+        swap_bytes(out, DType::of::<T>())?;
+    }
+    Ok(data)
+}
+
+#[derive(Clone, Copy)]
+enum Num {
+    I(i128),
+    U(u128),
+    F(f64),
+}
+
+impl Num {
+    fn to_i128(self) -> i128 {
+        match self {
+            Num::I(i) => i,
+            Num::U(u) => u.min(i128::MAX as u128) as i128,
+            Num::F(f) => f as i128,
+        }
+    }
+
+    fn to_u128(self) -> u128 {
+        match self {
+            Num::I(i) => i.max(0) as u128,
+            Num::U(u) => u,
+            Num::F(f) => f as u128,
+        }
+    }
+
+    fn to_f64(self) -> f64 {
+        match self {
+            Num::I(i) => i as f64,
+            Num::U(u) => u as f64,
+            Num::F(f) => f,
+        }
+    }
+}
+
+/// Read a little-endian scalar component
+fn get(code: u64, b: &[u8]) -> Num {
+    let mut buf = [0u8; 16];
+    buf[..b.len()].copy_from_slice(b);
+    match (code, b.len()) {
+        (1, w) => {
+            // sign-extend from the top bit of the component
+            let shift = 128 - 8 * w as u32;
+            Num::I(i128::from_le_bytes(buf) << shift >> shift)
+        }
+        (2, _) => Num::U(u128::from_le_bytes(buf)),
+        (3, 2) => Num::F(f16::from_le_bytes([b[0], b[1]]).to_f64()),
+        (3, 4) => Num::F(f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))),
+        (3, 8) => Num::F(f64::from_le_bytes([
+            b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7],
+        ])),
+        (5, 2) => Num::F(bf16::from_le_bytes([b[0], b[1]]).to_f64()),
+        _ => unreachable!("unchecked dtype"),
+    }
+}
+
+/// Write a little-endian scalar component
+fn put(code: u64, n: Num, out: &mut [u8]) {
+    let w = out.len();
+    match (code, w) {
+        (1, _) => {
+            let bits = 8 * w as u32;
+            let (lo, hi) = if bits == 128 {
+                (i128::MIN, i128::MAX)
+            } else {
+                (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+            };
+            out.copy_from_slice(&n.to_i128().clamp(lo, hi).to_le_bytes()[..w]);
+        }
+        (2, _) => {
+            let bits = 8 * w as u32;
+            let hi = if bits == 128 {
+                u128::MAX
+            } else {
+                (1u128 << bits) - 1
+            };
+            out.copy_from_slice(&n.to_u128().min(hi).to_le_bytes()[..w]);
+        }
+        (3, 2) => out.copy_from_slice(&f16::from_f64(n.to_f64()).to_le_bytes()),
+        (3, 4) => out.copy_from_slice(&(n.to_f64() as f32).to_le_bytes()),
+        (3, 8) => out.copy_from_slice(&n.to_f64().to_le_bytes()),
+        (5, 2) => out.copy_from_slice(&bf16::from_f64(n.to_f64()).to_le_bytes()),
+        _ => unreachable!("unchecked dtype"),
+    }
+}
+
+/// Whether every value of `from` is exactly representable as a `to`, so
+/// converting can't lose anything: same kind and at least as wide, an
+/// unsigned integer into a wider signed one, an integer into a float with
+/// enough mantissa bits, `bf16` into a wider float, or any of those as the
+/// components of a complex number.
+pub(crate) fn is_widening(from: DType, to: DType) -> bool {
+    if !from.is_known() || !to.is_known() || from.components() > to.components() {
+        return false;
+    }
+    let ((from_code, from_width), (to_code, to_width)) = (from.component(), to.component());
+    match (from_code, to_code) {
+        (a, b) if a == b => to_width >= from_width,
+        (2, 1) => to_width > from_width,
+        (1, 3) | (2, 3) => {
+            let mantissa_bits = match to_width {
+                2 => 11,
+                4 => 24,
+                _ => 53,
+            };
+            from_width * 8 <= mantissa_bits
+        }
+        (5, 3) => to_width >= 4,
+        _ => false,
+    }
+}
+
+/// Check that elements of `from` can be converted to `to`.
+pub(crate) fn check_conversion(from: DType, to: DType) -> io::Result<()> {
+    if from == to {
+        return Ok(());
+    }
+    for d in [from, to].iter() {
+        if !d.is_known() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("can't convert elements of type {}", d),
+            ));
+        }
+    }
+    if from.components() > to.components() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "converting {} to {} would discard the imaginary part",
+                from, to
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Real scalar types with a cast loop of their own, for conversions too
+/// common to go through `get` and `put` an element at a time
+trait Scalar: RawArrayType {
+    const WIDTH: usize;
+    fn load(b: &[u8], big: bool) -> Self;
+    fn store(self, out: &mut [u8], big: bool);
+    fn to_num(self) -> Num;
+    fn from_num(n: Num) -> Self;
+}
+
+macro_rules! scalar {
+    ($($t:ty => $variant:ident, $from_num:expr);* $(;)?) => {
+        $(
+            impl Scalar for $t {
+                const WIDTH: usize = mem::size_of::<$t>();
+
+                #[inline(always)]
+                fn load(b: &[u8], big: bool) -> $t {
+                    let b = b.try_into().unwrap();
+                    if big {
+                        <$t>::from_be_bytes(b)
+                    } else {
+                        <$t>::from_le_bytes(b)
+                    }
+                }
+
+                #[inline(always)]
+                fn store(self, out: &mut [u8], big: bool) {
+                    out.copy_from_slice(&if big {
+                        self.to_be_bytes()
+                    } else {
+                        self.to_le_bytes()
+                    });
+                }
+
+                #[inline(always)]
+                fn to_num(self) -> Num {
+                    Num::$variant(self.into())
+                }
+
+                #[inline(always)]
+                fn from_num(n: Num) -> $t {
+                    $from_num(n)
+                }
+            }
+        )*
+    };
+}
+
+// the same saturating conversions as `put`
+macro_rules! saturate {
+    ($t:ty) => {
+        |n: Num| n.to_i128().clamp(<$t>::MIN.into(), <$t>::MAX.into()) as $t
+    };
+}
+
+scalar!(
+    i8 => I, saturate!(i8);
+    i16 => I, saturate!(i16);
+    i32 => I, saturate!(i32);
+    i64 => I, saturate!(i64);
+    u8 => U, |n: Num| n.to_u128().min(u8::MAX.into()) as u8;
+    u16 => U, |n: Num| n.to_u128().min(u16::MAX.into()) as u16;
+    u32 => U, |n: Num| n.to_u128().min(u32::MAX.into()) as u32;
+    u64 => U, |n: Num| n.to_u128().min(u64::MAX.into()) as u64;
+    f32 => F, |n: Num| n.to_f64() as f32;
+    f64 => F, |n: Num| n.to_f64();
+);
+
+fn cast_loop<A: Scalar, B: Scalar>(src: &[u8], from_big: bool, dst: &mut [u8], to_big: bool) {
+    for (s, d) in src
+        .chunks_exact(A::WIDTH)
+        .zip(dst.chunks_exact_mut(B::WIDTH))
+    {
+        B::from_num(A::load(s, from_big).to_num()).store(d, to_big);
+    }
+}
+
+/// Convert with a loop specialized to the two types, if both are `Scalar`
+/// types. Returns whether it did.
+pub(crate) fn cast_fast(
+    src: &[u8],
+    from: DType,
+    from_big: bool,
+    dst: &mut [u8],
+    to: DType,
+    to_big: bool,
+) -> bool {
+    macro_rules! to {
+        ($a:ty; $($b:ty),*) => {
+            $(
+                if to == DType::of::<$b>() {
+                    cast_loop::<$a, $b>(src, from_big, dst, to_big);
+                    return true;
+                }
+            )*
+        };
+    }
+    macro_rules! from {
+        ($($a:ty),*) => {
+            $(
+                if from == DType::of::<$a>() {
+                    to!($a; i8, i16, i32, i64, u8, u16, u32, u64, f32, f64);
+                }
+            )*
+        };
+    }
+    from!(i8, i16, i32, i64, u8, u16, u32, u64, f32, f64);
+    false
+}
+
+/// Convert the elements in `src`, stored as `from` in byte order
+/// `from_endian`, to `to` in byte order `to_endian`, appending them to
+/// `dst`. Call `check_conversion` first.
+pub(crate) fn convert_bytes(
+    src: &[u8],
+    from: DType,
+    from_endian: Endianness,
+    dst: &mut Vec<u8>,
+    to: DType,
+    to_endian: Endianness,
+) -> io::Result<()> {
+    let start = dst.len();
+    if from == to {
+        dst.extend_from_slice(src);
+        if from_endian != to_endian {
+            swap_bytes(&mut dst[start..], to)?;
+        }
+        return Ok(());
+    }
+    let nelem = src.len() / from.elbyte as usize;
+    dst.resize(start + nelem * to.elbyte as usize, 0);
+    let (from_big, to_big) = (from_endian == Endianness::Big, to_endian == Endianness::Big);
+    if !cast_fast(src, from, from_big, &mut dst[start..], to, to_big) {
+        cast_each(src, from, from_big, &mut dst[start..], to, to_big);
+    }
+    Ok(())
+}
+
+/// Convert any known types an element at a time, through `get` and `put`
+pub(crate) fn cast_each(
+    src: &[u8],
+    from: DType,
+    from_big: bool,
+    dst: &mut [u8],
+    to: DType,
+    to_big: bool,
+) {
+    let (from_code, from_w) = from.component();
+    let (to_code, to_w) = to.component();
+    let mut comp = [0u8; 16];
+    for (s, d) in src
+        .chunks_exact(from.elbyte as usize)
+        .zip(dst.chunks_exact_mut(to.elbyte as usize))
+    {
+        for (i, out) in d.chunks_exact_mut(to_w).enumerate() {
+            let n = if i < from.components() {
+                let c = &mut comp[..from_w];
+                c.copy_from_slice(&s[i * from_w..(i + 1) * from_w]);
+                if from_big {
+                    c.reverse();
+                }
+                get(from_code, c)
+            } else {
+                Num::F(0.0)
+            };
+            put(to_code, n, out);
+            if to_big {
+                out.reverse();
+            }
+        }
+    }
+}
+
+/// Check that complex elements of `from` can be converted to the real
+/// magnitudes of type `to`.
+pub(crate) fn check_magnitude(from: DType, to: DType) -> io::Result<()> {
+    let known = from.is_known() && to.is_known();
+    if !known || from.components() != 2 || to.components() != 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("can't take magnitudes of {} as {}", from, to),
+        ));
+    }
+    Ok(())
+}
+
+/// Convert the complex elements in `src` to their magnitudes, as
+/// `convert_bytes` does for other conversions. Call `check_magnitude`
+/// first.
+pub(crate) fn magnitude_bytes(
+    src: &[u8],
+    from: DType,
+    from_endian: Endianness,
+    dst: &mut Vec<u8>,
+    to: DType,
+    to_endian: Endianness,
+) -> io::Result<()> {
+    let (from_code, from_w) = from.component();
+    let start = dst.len();
+    dst.resize(
+        start + src.len() / from.elbyte as usize * to.elbyte as usize,
+        0,
+    );
+    let mut comp = [0u8; 16];
+    for (s, out) in src
+        .chunks_exact(from.elbyte as usize)
+        .zip(dst[start..].chunks_exact_mut(to.elbyte as usize))
+    {
+        let mut parts = [0.0; 2];
+        for (i, part) in parts.iter_mut().enumerate() {
+            let c = &mut comp[..from_w];
+            c.copy_from_slice(&s[i * from_w..(i + 1) * from_w]);
+            if from_endian == Endianness::Big {
+                c.reverse();
+            }
+            *part = get(from_code, c).to_f64();
+        }
+        put(to.eltype, Num::F(parts[0].hypot(parts[1])), out);
+        if to_endian == Endianness::Big {
+            out.reverse();
+        }
+    }
+    Ok(())
+}
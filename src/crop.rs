@@ -0,0 +1,220 @@
+//! Cutting a sub-block out of a file on disk, reading only the parts of it
+//! that are kept.
+
+use crate::attrs::{read_attrs, write_attrs};
+use crate::codec::CODEC_FLAGS;
+use crate::header::RawArrayHeader;
+use crate::recode::{write_or_remove, Source};
+use crate::{DEFAULT_MAX_BYTES, FLAG_ATTRS, FLAG_BIG_ENDIAN, FLAG_BITS, FLAG_CRC};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Cursor, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// Copy every `steps[i]`th element along each axis `i` of the sub-block
+/// spanning `ranges` out of the data section that starts at `base` in `r`,
+/// passing it to `sink` one run along the first axis at a time.
+fn copy_runs<R: Read + Seek>(
+    r: &mut BufReader<R>,
+    base: u64,
+    h: &RawArrayHeader,
+    ranges: &[Range<u64>],
+    steps: &[u64],
+    mut sink: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    if ranges.iter().any(|r| r.start == r.end) {
+        return Ok(());
+    }
+    let elbyte = h.elbyte as usize;
+    // a run reaches from the first element kept along the first axis to
+    // the last, and every `steps[0]`th element of it is kept
+    let (first, step) = ranges.first().map_or((0..1, 1), |r| (r.clone(), steps[0]));
+    let span = (first.end - first.start - 1) / step * step + 1;
+    let run = span * h.elbyte;
+    let mut buf = vec![0u8; run as usize];
+    let mut kept = Vec::new();
+    let mut pos = r.seek(SeekFrom::Start(base))?;
+    // step an odometer over the coordinates of every axis but the first,
+    // as `RawArray::slice` does in memory
+    let mut coords: Vec<u64> = ranges.iter().map(|r| r.start).collect();
+    'runs: loop {
+        let mut offset = 0;
+        let mut stride = h.elbyte;
+        for (&c, &n) in coords.iter().zip(&h.dims) {
+            offset += c * stride;
+            stride *= n;
+        }
+        // runs only move forward, and nearby ones are often still buffered
+        r.seek_relative((base + offset) as i64 - pos as i64)?;
+        r.read_exact(&mut buf)?;
+        pos = base + offset + run;
+        if step == 1 {
+            sink(&buf)?;
+        } else {
+            kept.clear();
+            for elem in buf.chunks_exact(elbyte).step_by(step as usize) {
+                kept.extend_from_slice(elem);
+            }
+            sink(&kept)?;
+        }
+        for axis in 1..coords.len() {
+            coords[axis] += steps[axis];
+            if coords[axis] < ranges[axis].end {
+                continue 'runs;
+            }
+            coords[axis] = ranges[axis].start;
+        }
+        return Ok(());
+    }
+}
+
+/// Copy the sub-block spanning `ranges`, one range per dimension, out of
+/// the array in `input` into a new file at `output`. Only the runs of
+/// elements kept are read, so cropping a huge file is quick, but its
+/// checksum, if any, isn't verified. Encoded data has to be decoded whole
+/// first. The element type needn't be known.
+///
+/// The output keeps the byte order, checksum setting, and attributes of
+/// the input. It replaces `output` only once it's complete, so `output`
+/// may be `input`.
+/// ```
+/// # use std::io;
+/// use rawarray::{slice_file, RawArray};
+/// # fn main() -> io::Result<()> {
+/// RawArray::from_iter_with_dims(0..24u8, &[4, 3, 2])?.write("volume.ra")?;
+/// slice_file("volume.ra", &[1..3, 0..2, 1..2], "crop.ra")?;
+/// let crop = RawArray::<u8>::read("crop.ra")?;
+/// assert_eq!(crop.dims(), vec![2, 2, 1]);
+/// assert_eq!(crop.data(), vec![13, 14, 17, 18]);
+/// assert!(slice_file("volume.ra", &[0..5, 0..3, 0..2], "crop.ra").is_err());
+/// # std::fs::remove_file("volume.ra")?;
+/// # std::fs::remove_file("crop.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn slice_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    ranges: &[Range<u64>],
+    output: Q,
+) -> io::Result<()> {
+    slice_file_with_steps(input, ranges, &vec![1; ranges.len()], output)
+}
+
+/// Copy every `steps[i]`th element along each axis `i` of the sub-block
+/// spanning `ranges` out of the array in `input` into a new file at
+/// `output`, as `slice_file` does, starting from the start of each range.
+/// Along the first axis, the elements in between are read and skipped;
+/// along the others, they aren't read at all.
+/// ```
+/// # use std::io;
+/// use rawarray::{slice_file_with_steps, RawArray};
+/// # fn main() -> io::Result<()> {
+/// RawArray::from_iter_with_dims(0..24u8, &[4, 3, 2])?.write("strided.ra")?;
+/// slice_file_with_steps("strided.ra", &[0..4, 0..3, 1..2], &[3, 2, 1], "every_other.ra")?;
+/// let crop = RawArray::<u8>::read("every_other.ra")?;
+/// assert_eq!(crop.dims(), vec![2, 2, 1]);
+/// assert_eq!(crop.data(), vec![12, 15, 20, 23]);
+/// assert!(slice_file_with_steps("strided.ra", &[0..4, 0..3, 0..2], &[0, 1, 1], "every_other.ra").is_err());
+/// # std::fs::remove_file("strided.ra")?;
+/// # std::fs::remove_file("every_other.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn slice_file_with_steps<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    ranges: &[Range<u64>],
+    steps: &[u64],
+    output: Q,
+) -> io::Result<()> {
+    let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+    let mut r = BufReader::new(File::open(input.as_ref())?);
+    let h = RawArrayHeader::read_from(&mut r)?;
+    if h.flags & FLAG_BITS != 0 {
+        return Err(invalid("can't slice arrays of single bits".to_string()));
+    }
+    if ranges.len() != h.dims.len() {
+        return Err(invalid(format!(
+            "need {} ranges, one per dimension, not {}",
+            h.dims.len(),
+            ranges.len()
+        )));
+    }
+    if steps.len() != ranges.len() || steps.contains(&0) {
+        return Err(invalid(format!(
+            "need {} positive steps, one per dimension, not {:?}",
+            h.dims.len(),
+            steps
+        )));
+    }
+    for (axis, (r, &n)) in ranges.iter().zip(&h.dims).enumerate() {
+        if r.start > r.end || r.end > n {
+            return Err(invalid(format!(
+                "range {:?} out of bounds for axis {} of length {}",
+                r, axis, n
+            )));
+        }
+    }
+    // attributes come after the data, but are needed for the header
+    let base = h.data_offset();
+    let (decoded, attrs) = if h.flags & CODEC_FLAGS != 0 {
+        let mut src = Source::new(h.clone(), &mut r, DEFAULT_MAX_BYTES)?;
+        let mut bytes = Vec::with_capacity(crate::to_usize(h.size)?);
+        let (dtype, endianness) = (src.dtype(), src.endianness());
+        src.convert(dtype, endianness, |chunk| {
+            bytes.extend_from_slice(chunk);
+            Ok(())
+        })?;
+        (Some(bytes), src.finish()?)
+    } else if h.flags & FLAG_ATTRS != 0 {
+        let crc_len = if h.flags & FLAG_CRC != 0 { 8 } else { 0 };
+        r.seek(SeekFrom::Start(base + h.size + crc_len))?;
+        (None, read_attrs(&mut r)?)
+    } else {
+        (None, BTreeMap::new())
+    };
+    let dims: Vec<u64> = ranges
+        .iter()
+        .zip(steps)
+        .map(|(r, &step)| (r.end - r.start).div_ceil(step))
+        .collect();
+    let mut flags = h.flags & (FLAG_BIG_ENDIAN | FLAG_CRC);
+    if !attrs.is_empty() {
+        flags |= FLAG_ATTRS;
+    }
+    let header = RawArrayHeader {
+        flags,
+        eltype: h.eltype,
+        elbyte: h.elbyte,
+        size: dims.iter().product::<u64>() * h.elbyte,
+        dims,
+    };
+
+    write_or_remove(output.as_ref(), |path| {
+        let mut w = BufWriter::new(File::create(path)?);
+        header.write_to(&mut w)?;
+        let mut hasher = crc32fast::Hasher::new();
+        let sink = |bytes: &[u8]| {
+            hasher.update(bytes);
+            w.write_all(bytes)
+        };
+        match decoded {
+            Some(bytes) => copy_runs(
+                &mut BufReader::new(Cursor::new(bytes)),
+                0,
+                &h,
+                ranges,
+                steps,
+                sink,
+            )?,
+            None => copy_runs(&mut r, base, &h, ranges, steps, sink)?,
+        }
+        if flags & FLAG_CRC != 0 {
+            w.write_all(&u64::from(hasher.finalize()).to_le_bytes())?;
+        }
+        if !attrs.is_empty() {
+            write_attrs(&mut w, &attrs)?;
+        }
+        w.flush()
+    })
+}
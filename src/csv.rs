@@ -0,0 +1,320 @@
+//! Conversion between one- and two-dimensional arrays and delimited text,
+//! so small arrays can be looked at in a spreadsheet.
+//!
+//! The first dimension runs down the rows and the second across the
+//! columns, so a `[3, 2]` array is three lines of two values. A
+//! one-dimensional array is a single column. Reading a table with one
+//! column gives a one-dimensional array.
+//!
+//! Values are written by `ValueFormatter`, with complex values in
+//! algebraic form, like `1.5-2i`, and read back from the same forms.
+//! There is no header line and no quoting.
+
+use crate::convert::{convert_bytes, elements_from_bytes_with_endianness, DType, Endianness};
+use crate::format::{parse_component, ValueFormatter};
+use crate::header::RawArrayHeader;
+use crate::recode::{open_source, write_or_remove};
+use crate::{as_u8_slice, RawArray, RawArrayType};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+/// Settings for reading and writing delimited text.
+/// ```
+/// use rawarray::csv::CsvOptions;
+/// let tsv = CsvOptions::new().delimiter('\t').precision(3);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CsvOptions {
+    delimiter: char,
+    precision: Option<usize>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            delimiter: ',',
+            precision: None,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Comma separated, with floats written in the shortest form that reads
+    /// back as the same value
+    pub fn new() -> CsvOptions {
+        CsvOptions::default()
+    }
+
+    /// Character between values on a line, such as `'\t'` for TSV
+    pub fn delimiter(mut self, delimiter: char) -> CsvOptions {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Digits after the decimal point for floating point values. Reading
+    /// ignores this.
+    pub fn precision(mut self, digits: usize) -> CsvOptions {
+        self.precision = Some(digits);
+        self
+    }
+
+    fn formatter(&self) -> ValueFormatter {
+        match self.precision {
+            Some(p) => ValueFormatter::new().precision(p),
+            None => ValueFormatter::new(),
+        }
+    }
+}
+
+/// Write the elements of `dtype` in `le`, little-endian, as a table of
+/// shape `dims`.
+fn write_table<W: Write>(
+    w: &mut W,
+    dtype: DType,
+    dims: &[u64],
+    le: &[u8],
+    opts: &CsvOptions,
+) -> io::Result<()> {
+    if dims.len() > 2 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("can't write {}-dimensional arrays as a table", dims.len()),
+        ));
+    }
+    if dtype.eltype == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "user-defined elements can't be written as text",
+        ));
+    }
+    let rows = dims.first().copied().unwrap_or(1) as usize;
+    let cols = dims.get(1).copied().unwrap_or(1) as usize;
+    let vf = opts.formatter();
+    let elbyte = dtype.elbyte as usize;
+    let mut delim = [0u8; 4];
+    let delim = opts.delimiter.encode_utf8(&mut delim).as_bytes();
+    for i in 0..rows {
+        for j in 0..cols {
+            if j > 0 {
+                w.write_all(delim)?;
+            }
+            let k = (i + rows * j) * elbyte;
+            let text = vf.format_bytes(dtype, Endianness::Little, &le[k..k + elbyte]);
+            w.write_all(text.as_bytes())?;
+        }
+        w.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Split `s`, like `1.5-2i`, into its real and imaginary parts. A value
+/// without an imaginary part has a zero one.
+fn split_complex(s: &str) -> Option<(&str, &str)> {
+    let body = match s.strip_suffix('i') {
+        Some(body) => body,
+        None => return Some((s, "0")),
+    };
+    let b = body.as_bytes();
+    // the sign of the imaginary part is the last one not in an exponent
+    let at = (1..b.len())
+        .rev()
+        .find(|&k| (b[k] == b'+' || b[k] == b'-') && !matches!(b[k - 1], b'e' | b'E'))?;
+    Some((&body[..at], &body[at..]))
+}
+
+/// Read a table of `dtype` elements from `text`, returning its dimensions
+/// and the elements, little-endian, in column-major order.
+fn parse_table(text: &str, dtype: DType, opts: &CsvOptions) -> io::Result<(Vec<u64>, Vec<u8>)> {
+    let (code, width) = dtype.component();
+    let mut probe = Vec::new();
+    if parse_component(code, width, "0", &mut probe).is_none() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("elements of type {} can't be read from text", dtype),
+        ));
+    }
+    // blank lines are skipped, but errors give line numbers in the file
+    let mut fields: Vec<(usize, Vec<&str>)> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: Vec<&str> = line.split(opts.delimiter).map(str::trim).collect();
+        if let Some((_, first)) = fields.first() {
+            if row.len() != first.len() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "line {} has {} values, but the first has {}",
+                        n + 1,
+                        row.len(),
+                        first.len()
+                    ),
+                ));
+            }
+        }
+        fields.push((n + 1, row));
+    }
+    let rows = fields.len();
+    let cols = fields.first().map_or(0, |(_, row)| row.len());
+    let mut le = Vec::with_capacity(rows * cols * dtype.elbyte as usize);
+    for j in 0..cols {
+        for (n, row) in &fields {
+            let s = row[j];
+            let ok = if dtype.components() == 2 {
+                split_complex(s).and_then(|(re, im)| {
+                    parse_component(code, width, re, &mut le)?;
+                    parse_component(code, width, im, &mut le)
+                })
+            } else {
+                parse_component(code, width, s, &mut le)
+            };
+            if ok.is_none() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "line {}, column {}: {:?} isn't a valid {}",
+                        n,
+                        j + 1,
+                        s,
+                        dtype
+                    ),
+                ));
+            }
+        }
+    }
+    let dims = if cols == 1 {
+        vec![rows as u64]
+    } else {
+        vec![rows as u64, cols as u64]
+    };
+    Ok((dims, le))
+}
+
+/// Write the one- or two-dimensional array in the RawArray file `input` as
+/// delimited text to `out`, whatever its element type.
+/// ```
+/// # use std::io;
+/// use rawarray::csv::{self, CsvOptions};
+/// use rawarray::RawArray;
+/// # fn main() -> io::Result<()> {
+/// RawArray::from_iter_with_dims(1..=6u16, &[3, 2])?.write("table.ra")?;
+/// let mut text = Vec::new();
+/// csv::ra_to_csv("table.ra", &mut text, &CsvOptions::new())?;
+/// assert_eq!(text, b"1,4\n2,5\n3,6\n");
+/// # std::fs::remove_file("table.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn ra_to_csv<P: AsRef<Path>, W: Write>(
+    input: P,
+    mut out: W,
+    opts: &CsvOptions,
+) -> io::Result<()> {
+    let mut src = open_source(input.as_ref())?;
+    let dtype = src.dtype();
+    let dims = src.header.dims.clone();
+    let mut le = Vec::new();
+    src.convert(dtype, Endianness::Little, |bytes| {
+        le.extend_from_slice(bytes);
+        Ok(())
+    })?;
+    src.finish()?;
+    write_table(&mut out, dtype, &dims, &le, opts)?;
+    out.flush()
+}
+
+/// Read delimited text from `input` into a new RawArray file at `output`
+/// with elements of type `eltype`.
+/// ```
+/// # use std::io;
+/// use rawarray::csv::{self, CsvOptions};
+/// use rawarray::{DType, RawArray};
+/// # fn main() -> io::Result<()> {
+/// std::fs::write("in.tsv", "1.5\t-2\n0.25\t8\n")?;
+/// let tsv = CsvOptions::new().delimiter('\t');
+/// csv::csv_to_ra("in.tsv", "from_tsv.ra", DType::parse("f32").unwrap(), &tsv)?;
+/// let ra = RawArray::<f32>::read("from_tsv.ra")?;
+/// assert_eq!(ra.dims(), vec![2, 2]);
+/// assert_eq!(ra.data(), vec![1.5, 0.25, -2.0, 8.0]);
+/// # std::fs::remove_file("in.tsv")?;
+/// # std::fs::remove_file("from_tsv.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn csv_to_ra<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    eltype: DType,
+    opts: &CsvOptions,
+) -> io::Result<()> {
+    let text = fs::read_to_string(input)?;
+    let (dims, le) = parse_table(&text, eltype, opts)?;
+    write_or_remove(output.as_ref(), |out| {
+        let mut w = BufWriter::new(File::create(out)?);
+        RawArrayHeader {
+            flags: 0,
+            eltype: eltype.eltype,
+            elbyte: eltype.elbyte,
+            size: le.len() as u64,
+            dims,
+        }
+        .write_to(&mut w)?;
+        w.write_all(&le)?;
+        w.flush()
+    })
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Write a one- or two-dimensional array to `path` as comma separated
+    /// values. See the `csv` module for the layout.
+    /// ```
+    /// # use std::io;
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let z: RawArray<Complex<f64>> = vec![Complex::new(1.5, -2.0), Complex::new(0.0, 1e-7)].into();
+    /// z.to_csv("z.csv")?;
+    /// assert_eq!(std::fs::read_to_string("z.csv")?, "1.5-2.0i\n0.0+1e-7i\n");
+    /// assert_eq!(RawArray::<Complex<f64>>::from_csv("z.csv")?, z);
+    /// # std::fs::remove_file("z.csv")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_csv<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.to_csv_with(path, &CsvOptions::new())
+    }
+
+    /// Like `to_csv`, with the delimiter and precision given by `opts`.
+    pub fn to_csv_with<P: AsRef<Path>>(&self, path: P, opts: &CsvOptions) -> io::Result<()> {
+        let dtype = DType::of::<T>();
+        let mut le = Vec::with_capacity(self.data.len() * dtype.elbyte as usize);
+        convert_bytes(
+            as_u8_slice(&self.data),
+            dtype,
+            self.endianness(),
+            &mut le,
+            dtype,
+            Endianness::Little,
+        )?;
+        write_or_remove(path.as_ref(), |out| {
+            let mut w = BufWriter::new(File::create(out)?);
+            write_table(&mut w, dtype, &self.dims, &le, opts)?;
+            w.flush()
+        })
+    }
+
+    /// Read comma separated values from `path` into an array of `T`.
+    pub fn from_csv<P: AsRef<Path>>(path: P) -> io::Result<RawArray<T>> {
+        RawArray::from_csv_with(path, &CsvOptions::new())
+    }
+
+    /// Like `from_csv`, with the delimiter given by `opts`.
+    pub fn from_csv_with<P: AsRef<Path>>(path: P, opts: &CsvOptions) -> io::Result<RawArray<T>> {
+        let text = fs::read_to_string(path)?;
+        let (dims, le) = parse_table(&text, DType::of::<T>(), opts)?;
+        let data = elements_from_bytes_with_endianness(&le, Endianness::Little)?;
+        Ok(RawArray::from_parts(dims, data))
+    }
+}
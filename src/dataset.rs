@@ -0,0 +1,299 @@
+//! Helpers for treating a directory of `.ra` files as a dataset.
+
+use crate::manifest::{find_ra_files, read_manifest};
+use crate::{DType, LazyRawArray, RawArray, RawArrayHeader, RawArrayType};
+use std::fs::File;
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// 64-bit FNV-1a, chosen because its output is fixed by its definition,
+/// unlike `std`'s hashers, which may change between releases.
+fn fnv1a(seed: u64, bytes: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in seed.to_le_bytes().iter().chain(bytes) {
+        h ^= u64::from(*b);
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+    h
+}
+
+/// Partition a list of files into groups with the given relative sizes,
+/// e.g. `[0.8, 0.1, 0.1]` for train/validation/test.
+///
+/// Each file is assigned by hashing its file name with `seed`, so the
+/// assignment is deterministic, does not depend on the order or the
+/// other contents of `paths`, and survives moving the files to another
+/// directory. Adding files to a dataset later never moves existing files
+/// between groups. The group sizes match `fractions` only approximately,
+/// as with any hash-based split. Files keep their input order within each
+/// group.
+/// ```
+/// let files: Vec<String> = (0..100).map(|i| format!("frame{:03}.ra", i)).collect();
+/// let split = rawarray::split_dataset(&files, &[0.8, 0.2], 1);
+/// assert_eq!(split.len(), 2);
+/// assert_eq!(split[0].len() + split[1].len(), 100);
+/// assert_eq!(split, rawarray::split_dataset(&files[..50], &[0.8, 0.2], 1)
+///     .into_iter()
+///     .zip(rawarray::split_dataset(&files[50..], &[0.8, 0.2], 1))
+///     .map(|(mut a, b)| { a.extend(b); a })
+///     .collect::<Vec<_>>());
+/// ```
+///
+/// # Panics
+///
+/// Panics if `fractions` is empty, contains a negative or non-finite value,
+/// or sums to zero.
+pub fn split_dataset<P: AsRef<Path>>(
+    paths: &[P],
+    fractions: &[f64],
+    seed: u64,
+) -> Vec<Vec<PathBuf>> {
+    assert!(!fractions.is_empty(), "need at least one fraction");
+    assert!(
+        fractions.iter().all(|f| f.is_finite() && *f >= 0.0),
+        "fractions must be finite and nonnegative"
+    );
+    let total: f64 = fractions.iter().sum();
+    assert!(total > 0.0, "fractions must not sum to zero");
+
+    let mut bounds = Vec::with_capacity(fractions.len());
+    let mut acc = 0.0;
+    for f in fractions {
+        acc += f / total;
+        bounds.push(acc);
+    }
+
+    let mut groups = vec![Vec::new(); fractions.len()];
+    for p in paths {
+        let p = p.as_ref();
+        let name = p.file_name().unwrap_or(p.as_os_str());
+        let h = fnv1a(seed, name.to_string_lossy().as_bytes());
+        let u = (h >> 11) as f64 / (1u64 << 53) as f64;
+        let g = bounds
+            .iter()
+            .position(|&b| u < b)
+            .unwrap_or(fractions.len() - 1);
+        groups[g].push(p.to_path_buf());
+    }
+    groups
+}
+
+/// Write a list of files to a plain-text manifest, one path per line,
+/// e.g. to record the groups returned by `split_dataset`.
+/// ```
+/// # use std::io;
+/// # fn main() -> io::Result<()> {
+/// let split = rawarray::split_dataset(&["a.ra", "b.ra", "c.ra"], &[0.5, 0.5], 0);
+/// rawarray::write_file_list("train.txt", &split[0])?;
+/// # std::fs::remove_file("train.txt")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn write_file_list<P: AsRef<Path>, Q: AsRef<Path>>(path: P, files: &[Q]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    for f in files {
+        writeln!(w, "{}", f.as_ref().display())?;
+    }
+    w.flush()
+}
+
+/// One file of a `Dataset`, described by its header alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DatasetEntry {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Element type of the array
+    pub dtype: DType,
+    /// Dimensions of the array
+    pub dims: Vec<u64>,
+}
+
+/// An index of the `.ra` files in a directory tree, letting the directories
+/// be the dataset's hierarchy. Only headers are read to build the index;
+/// arrays are read as they're asked for. A dataset can be narrowed to the
+/// files of one element type or shape, shuffled, and sampled, each of which
+/// gives a new dataset.
+/// ```
+/// # use std::io;
+/// use rawarray::{Dataset, RawArray};
+/// # fn main() -> io::Result<()> {
+/// # std::fs::create_dir_all("dataset_doc/train")?;
+/// for i in 0..10u8 {
+///     RawArray::from_iter_with_dims(vec![i; 6], &[3, 2])?
+///         .write(format!("dataset_doc/train/{}.ra", i))?;
+/// }
+/// RawArray::<f32>::from(vec![0.5; 4]).write("dataset_doc/mean.ra")?;
+///
+/// let all = Dataset::open("dataset_doc")?;
+/// assert_eq!(all.len(), 11);
+/// let images = all.of_type::<u8>().with_dims(&[3, 2]);
+/// assert_eq!(images.len(), 10);
+///
+/// let batch = images.shuffled(7).sample(4, 1);
+/// assert_eq!(batch.len(), 4);
+/// for ra in batch.arrays::<u8>() {
+///     assert_eq!(ra?.dims(), vec![3, 2]);
+/// }
+/// assert_eq!(images.load::<u8>(2)?.data(), vec![2; 6]);
+/// # std::fs::remove_dir_all("dataset_doc")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Dataset {
+    entries: Vec<DatasetEntry>,
+}
+
+impl Dataset {
+    /// Index every `.ra` file below `dir`, reading each one's header. Files
+    /// are in sorted order of their paths.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Dataset> {
+        let mut entries = Vec::new();
+        for path in find_ra_files(dir.as_ref())? {
+            let h = RawArrayHeader::read(&path)?;
+            entries.push(DatasetEntry {
+                path,
+                dtype: DType {
+                    eltype: h.eltype,
+                    elbyte: h.elbyte,
+                },
+                dims: h.dims,
+            });
+        }
+        Ok(Dataset { entries })
+    }
+
+    /// Index the files listed in the manifest that `write_manifest` left in
+    /// `dir`, without opening them, for directories too large to walk each
+    /// time. Fails with `ErrorKind::InvalidData` if the manifest has been
+    /// altered; the files themselves aren't checked.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{Dataset, RawArray};
+    /// # fn main() -> io::Result<()> {
+    /// # std::fs::create_dir_all("dataset_manifest_doc")?;
+    /// RawArray::<i16>::from(vec![1, 2, 3]).write("dataset_manifest_doc/a.ra")?;
+    /// rawarray::write_manifest("dataset_manifest_doc")?;
+    /// let ds = Dataset::from_manifest("dataset_manifest_doc")?;
+    /// assert_eq!(ds, Dataset::open("dataset_manifest_doc")?);
+    /// # std::fs::remove_dir_all("dataset_manifest_doc")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_manifest<P: AsRef<Path>>(dir: P) -> io::Result<Dataset> {
+        let dir = dir.as_ref();
+        let corrupt = |msg: &str| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("manifest has a line with {}", msg),
+            )
+        };
+        let mut entries = Vec::new();
+        for [_, dtype, dims, name] in read_manifest(dir)? {
+            let dtype = match dtype.strip_prefix("user") {
+                Some(elbyte) => elbyte
+                    .parse()
+                    .ok()
+                    .map(|elbyte| DType { eltype: 0, elbyte }),
+                None => DType::parse(&dtype),
+            }
+            .ok_or_else(|| corrupt("an unknown dtype"))?;
+            let dims = dims
+                .split(',')
+                .filter(|d| !d.is_empty())
+                .map(str::parse)
+                .collect::<Result<Vec<u64>, _>>()
+                .map_err(|_| corrupt("malformed dims"))?;
+            entries.push(DatasetEntry {
+                path: dir.join(name),
+                dtype,
+                dims,
+            });
+        }
+        Ok(Dataset { entries })
+    }
+
+    /// Number of files in the dataset
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the dataset has no files
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The files, in order
+    pub fn entries(&self) -> &[DatasetEntry] {
+        &self.entries
+    }
+
+    /// The paths of the files, in order, e.g. for `batch::read_all`
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.entries.iter().map(|e| e.path.clone()).collect()
+    }
+
+    /// The files for which `keep` returns true
+    pub fn filter<F: FnMut(&DatasetEntry) -> bool>(&self, mut keep: F) -> Dataset {
+        Dataset {
+            entries: self.entries.iter().filter(|e| keep(e)).cloned().collect(),
+        }
+    }
+
+    /// The files holding elements of type `T`
+    pub fn of_type<T: RawArrayType>(&self) -> Dataset {
+        self.filter(|e| e.dtype == DType::of::<T>())
+    }
+
+    /// The files of dimensions `dims`
+    pub fn with_dims(&self, dims: &[u64]) -> Dataset {
+        self.filter(|e| e.dims == dims)
+    }
+
+    /// The files in a random order determined by `seed`. As with
+    /// `split_dataset`, each file's place depends only on its path and the
+    /// seed, so the order is the same on every platform and release.
+    pub fn shuffled(&self, seed: u64) -> Dataset {
+        let mut keyed: Vec<(u64, &DatasetEntry)> = self
+            .entries
+            .iter()
+            .map(|e| (fnv1a(seed, e.path.to_string_lossy().as_bytes()), e))
+            .collect();
+        // the sort is stable, so repeated paths keep their order
+        keyed.sort_by_key(|(h, _)| *h);
+        Dataset {
+            entries: keyed.into_iter().map(|(_, e)| e.clone()).collect(),
+        }
+    }
+
+    /// `n` files chosen at random by `seed` without replacement, or all of
+    /// them if there are fewer, in a random order.
+    pub fn sample(&self, n: usize, seed: u64) -> Dataset {
+        let mut sample = self.shuffled(seed);
+        sample.entries.truncate(n);
+        sample
+    }
+
+    /// Read the `i`th file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of range.
+    pub fn load<T: RawArrayType>(&self, i: usize) -> io::Result<RawArray<T>> {
+        RawArray::read(&self.entries[i].path)
+    }
+
+    /// A handle to the `i`th file that reads its data only when asked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of range.
+    pub fn lazy<T: RawArrayType>(&self, i: usize) -> io::Result<LazyRawArray<T>> {
+        LazyRawArray::open(&self.entries[i].path)
+    }
+
+    /// Read the files one at a time, in order, as the iterator is advanced.
+    pub fn arrays<T: RawArrayType>(&self) -> impl Iterator<Item = io::Result<RawArray<T>>> + '_ {
+        self.entries.iter().map(|e| RawArray::read(&e.path))
+    }
+}
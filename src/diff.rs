@@ -0,0 +1,90 @@
+//! Element-by-element comparison of two files, whatever their byte order
+//! or codec.
+
+use crate::recode::open_source;
+use crate::{dtype_name, Endianness};
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+/// Elements compared per step
+const CHUNK_ELEMS: u64 = 1 << 16;
+
+/// Call `differ` with the flat index and the two elements of every place
+/// where the arrays in files `a` and `b` hold different bytes. Elements are
+/// given little endian, and decoded if the files are encoded, so files that
+/// differ only in byte order or codec have no differences. The files must
+/// hold the same number of elements of the same type, and are read a chunk
+/// at a time, with their checksums verified.
+/// ```
+/// # use std::io;
+/// use rawarray::{for_each_difference, Endianness, RawArray, WriteOptions};
+/// # fn main() -> io::Result<()> {
+/// RawArray::from(vec![1u16, 2, 3]).write("diff_a.ra")?;
+/// let opts = WriteOptions::new().endianness(Endianness::Big);
+/// RawArray::from(vec![1u16, 5, 3]).write_with("diff_b.ra", &opts)?;
+/// let mut found = Vec::new();
+/// for_each_difference("diff_a.ra", "diff_b.ra", |i, a, b| {
+///     found.push((i, a.to_vec(), b.to_vec()))
+/// })?;
+/// assert_eq!(found, vec![(1, vec![2, 0], vec![5, 0])]);
+///
+/// RawArray::from(vec![1u32, 2, 3]).write("diff_b.ra")?;
+/// assert!(for_each_difference("diff_a.ra", "diff_b.ra", |_, _, _| ()).is_err());
+/// # std::fs::remove_file("diff_a.ra")?;
+/// # std::fs::remove_file("diff_b.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn for_each_difference<P: AsRef<Path>, Q: AsRef<Path>>(
+    a: P,
+    b: Q,
+    mut differ: impl FnMut(u64, &[u8], &[u8]),
+) -> io::Result<()> {
+    let mut src_a = open_source(a.as_ref())?;
+    let mut src_b = open_source(b.as_ref())?;
+    let dtype = src_a.dtype();
+    if src_b.dtype() != dtype {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "can't compare {} elements with {} elements",
+                dtype_name(dtype.eltype, dtype.elbyte),
+                dtype_name(src_b.dtype().eltype, src_b.dtype().elbyte)
+            ),
+        ));
+    }
+    let nelem = src_a.header.size / dtype.elbyte;
+    if src_b.header.size / dtype.elbyte != nelem {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "can't compare {} elements with {}",
+                nelem,
+                src_b.header.size / dtype.elbyte
+            ),
+        ));
+    }
+    let elbyte = dtype.elbyte as usize;
+    let (mut buf_a, mut buf_b) = (Vec::new(), Vec::new());
+    let mut first = 0;
+    while first < nelem {
+        let n = CHUNK_ELEMS.min(nelem - first);
+        for (src, buf) in [(&mut src_a, &mut buf_a), (&mut src_b, &mut buf_b)] {
+            buf.clear();
+            src.convert_next(n, dtype, Endianness::Little, |bytes| {
+                buf.extend_from_slice(bytes);
+                Ok(())
+            })?;
+        }
+        let pairs = buf_a.chunks_exact(elbyte).zip(buf_b.chunks_exact(elbyte));
+        for (i, (x, y)) in pairs.enumerate() {
+            if x != y {
+                differ(first + i as u64, x, y);
+            }
+        }
+        first += n;
+    }
+    src_a.finish()?;
+    src_b.finish()?;
+    Ok(())
+}
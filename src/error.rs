@@ -0,0 +1,71 @@
+//! Errors specific to the RawArray format.
+//!
+//! All fallible functions in this crate return `io::Result`, so that
+//! format problems and operating system problems travel through the same
+//! `?` chain. Format problems are wrapped in the `io::Error` as a
+//! `RawArrayError`, which can be recovered with `get_ref()` and
+//! `downcast_ref()` when the caller needs to tell them apart.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+
+/// A problem with the contents of a RawArray file.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RawArrayError {
+    /// The checksum stored after the data section doesn't match the data.
+    ChecksumMismatch {
+        /// Checksum recorded in the file
+        stored: u64,
+        /// Checksum computed from the data as read
+        computed: u64,
+    },
+    /// The header declares more data than the reader was allowed to
+    /// allocate, so it wasn't read.
+    TooLarge {
+        /// Bytes of memory the array would take
+        bytes: u64,
+        /// Most bytes allowed, as set by `ReadOptions::max_bytes`
+        limit: u64,
+    },
+}
+
+impl fmt::Display for RawArrayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawArrayError::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "data checksum mismatch (stored {:08x}, computed {:08x}), file is corrupt",
+                stored, computed
+            ),
+            RawArrayError::TooLarge { bytes, limit } => write!(
+                f,
+                "array of {} bytes is over the read limit of {} bytes",
+                bytes, limit
+            ),
+        }
+    }
+}
+
+impl Error for RawArrayError {}
+
+impl From<RawArrayError> for io::Error {
+    fn from(e: RawArrayError) -> io::Error {
+        io::Error::new(ErrorKind::InvalidData, e)
+    }
+}
+
+impl RawArrayError {
+    /// Recover a `RawArrayError` from an `io::Error` returned by this crate,
+    /// if that is what caused it.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArrayError;
+    /// let e: io::Error = RawArrayError::ChecksumMismatch { stored: 1, computed: 2 }.into();
+    /// assert!(RawArrayError::from_io(&e).is_some());
+    /// ```
+    pub fn from_io(e: &io::Error) -> Option<&RawArrayError> {
+        e.get_ref()
+            .and_then(|inner| inner.downcast_ref::<RawArrayError>())
+    }
+}
@@ -0,0 +1,260 @@
+//! A C API, behind the `ffi` feature, so C and C++ code can use this crate
+//! as its RawArray library, declared in `include/rawarray.h`. Build the
+//! shared library with
+//! ```text
+//! cargo build --release --features ffi
+//! ```
+//! or a static one with
+//! ```text
+//! cargo rustc --release --features ffi --crate-type staticlib
+//! ```
+//! Building with the feature also generates the header in `OUT_DIR`, and
+//! the tests check that `include/rawarray.h` matches it, so copy it over
+//! after changing this module. Functions that can fail return `NULL` or
+//! `-1`, and `ra_last_error` then says why.
+//! ```c
+//! RaArray *ra = ra_read("image.ra");
+//! if (!ra) {
+//!     fprintf(stderr, "%s\n", ra_last_error());
+//!     return 1;
+//! }
+//! const float *pixels = ra_data(ra);
+//! uint64_t width = ra_dims(ra)[0];
+//! ra_free(ra);
+//! ```
+
+use crate::convert::{DType, Endianness};
+use crate::header::RawArrayHeader;
+use crate::recode::{open_source_with, write_or_remove};
+use crate::{to_usize, DEFAULT_MAX_BYTES, NATIVE_FLAGS};
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::os::raw::{c_char, c_int, c_void};
+use std::path::Path;
+use std::{ptr, slice};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Remember `e` for `ra_last_error`.
+fn set_error(e: io::Error) {
+    // messages never contain NULs, but don't lose the error if one does
+    let msg = CString::new(e.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(msg));
+}
+
+/// The path a C string names
+unsafe fn path_from<'a>(path: *const c_char) -> io::Result<&'a Path> {
+    if path.is_null() {
+        return Err(Error::new(ErrorKind::InvalidInput, "path is NULL"));
+    }
+    CStr::from_ptr(path)
+        .to_str()
+        .map(Path::new)
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "path isn't UTF-8"))
+}
+
+/// An array read from a file, with its elements in this machine's byte
+/// order
+pub struct RaArray {
+    header: RawArrayHeader,
+    // u64s, so the elements are aligned for any C type
+    data: Vec<u64>,
+}
+
+fn read(path: &Path, max_bytes: u64) -> io::Result<RaArray> {
+    let mut src = open_source_with(path, max_bytes)?;
+    let dtype = src.dtype();
+    // grows as the elements arrive, not by what the header claims
+    let mut data: Vec<u64> = Vec::new();
+    let mut at = 0;
+    src.convert(dtype, Endianness::native(), |bytes| {
+        let end = at + bytes.len();
+        data.resize(end.div_ceil(8), 0);
+        // sound, since any bytes are valid u64s
+        let dst =
+            unsafe { slice::from_raw_parts_mut(data.as_mut_ptr().cast::<u8>(), data.len() * 8) };
+        dst[at..end].copy_from_slice(bytes);
+        at = end;
+        Ok(())
+    })?;
+    let mut header = src.header.clone();
+    header.flags = NATIVE_FLAGS;
+    src.finish()?;
+    Ok(RaArray { header, data })
+}
+
+/// Read the RawArray file at `path`, returning `NULL` on failure. Free the
+/// result with `ra_free`.
+///
+/// # Safety
+///
+/// `path` must be `NULL` or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ra_read(path: *const c_char) -> *mut RaArray {
+    ra_read_limited(path, DEFAULT_MAX_BYTES)
+}
+
+/// Read the RawArray file at `path` as `ra_read` does, but fail if its data
+/// is more than `max_bytes`, rather than 64 GiB, so that a hostile or
+/// corrupt file can't exhaust memory.
+///
+/// # Safety
+///
+/// `path` must be `NULL` or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ra_read_limited(path: *const c_char, max_bytes: u64) -> *mut RaArray {
+    match path_from(path).and_then(|path| read(path, max_bytes)) {
+        Ok(ra) => Box::into_raw(Box::new(ra)),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Free an array from `ra_read`. `NULL` is ignored.
+///
+/// # Safety
+///
+/// `ra` must be `NULL` or from `ra_read`, and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn ra_free(ra: *mut RaArray) {
+    if !ra.is_null() {
+        drop(Box::from_raw(ra));
+    }
+}
+
+/// Element type code of the array
+///
+/// # Safety
+///
+/// `ra` must be a live array from `ra_read`, as for all the accessors.
+#[no_mangle]
+pub unsafe extern "C" fn ra_eltype(ra: *const RaArray) -> u64 {
+    (*ra).header.eltype
+}
+
+/// Width of one element in bytes
+///
+/// # Safety
+///
+/// See `ra_eltype`.
+#[no_mangle]
+pub unsafe extern "C" fn ra_elbyte(ra: *const RaArray) -> u64 {
+    (*ra).header.elbyte
+}
+
+/// Length of the data in bytes
+///
+/// # Safety
+///
+/// See `ra_eltype`.
+#[no_mangle]
+pub unsafe extern "C" fn ra_size(ra: *const RaArray) -> u64 {
+    (*ra).header.size
+}
+
+/// Number of dimensions
+///
+/// # Safety
+///
+/// See `ra_eltype`.
+#[no_mangle]
+pub unsafe extern "C" fn ra_ndims(ra: *const RaArray) -> u64 {
+    (*ra).header.ndims()
+}
+
+/// The `ra_ndims` dimensions, first one fastest varying, valid until the
+/// array is freed
+///
+/// # Safety
+///
+/// See `ra_eltype`.
+#[no_mangle]
+pub unsafe extern "C" fn ra_dims(ra: *const RaArray) -> *const u64 {
+    (*ra).header.dims.as_ptr()
+}
+
+/// The elements, in this machine's byte order, valid until the array is
+/// freed. They are aligned for any element type.
+///
+/// # Safety
+///
+/// See `ra_eltype`.
+#[no_mangle]
+pub unsafe extern "C" fn ra_data(ra: *const RaArray) -> *const c_void {
+    (*ra).data.as_ptr().cast()
+}
+
+fn write(path: &Path, dtype: DType, dims: &[u64], data: &[u8]) -> io::Result<()> {
+    if dtype.elbyte == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "elbyte is zero"));
+    }
+    write_or_remove(path, |out| {
+        let mut w = BufWriter::new(File::create(out)?);
+        RawArrayHeader {
+            flags: NATIVE_FLAGS,
+            eltype: dtype.eltype,
+            elbyte: dtype.elbyte,
+            size: data.len() as u64,
+            dims: dims.to_vec(),
+        }
+        .write_to(&mut w)?;
+        w.write_all(data)?;
+        w.flush()
+    })
+}
+
+/// Write an array of `ndims` dimensions `dims`, whose elements of type
+/// `eltype` and `elbyte` bytes each are at `data` in this machine's byte
+/// order, to a RawArray file at `path`. Returns 0 on success and -1 on
+/// failure.
+///
+/// # Safety
+///
+/// `path` must be `NULL` or a NUL-terminated string, `dims` must point to
+/// `ndims` values, and `data` must point to as many bytes as the
+/// dimensions and `elbyte` call for.
+#[no_mangle]
+pub unsafe extern "C" fn ra_write(
+    path: *const c_char,
+    eltype: u64,
+    elbyte: u64,
+    dims: *const u64,
+    ndims: u64,
+    data: *const c_void,
+) -> c_int {
+    let result = path_from(path).and_then(|path| {
+        let dims = match ndims {
+            0 => &[][..],
+            n => slice::from_raw_parts(dims, to_usize(n)?),
+        };
+        let size = dims
+            .iter()
+            .try_fold(elbyte, |acc, &d| acc.checked_mul(d))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "array is too large"))?;
+        let data = match size {
+            0 => &[][..],
+            n => slice::from_raw_parts(data.cast::<u8>(), to_usize(n)?),
+        };
+        write(path, DType { eltype, elbyte }, dims, data)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            set_error(e);
+            -1
+        }
+    }
+}
+
+/// Why the last failed call on this thread failed, or `NULL` if none has.
+/// The message is valid until the next failure on this thread.
+#[no_mangle]
+pub extern "C" fn ra_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
@@ -0,0 +1,122 @@
+//! Fast Fourier transforms of complex arrays, behind the `fft` feature, for
+//! moving MRI data between k-space and image space.
+//!
+//! Transforms follow NumPy's conventions: the forward transform is
+//! unnormalized and the inverse divides by the length, so `ifft` undoes
+//! `fft`, and the zero frequency is at index 0. Use `fftshift` and
+//! `ifftshift` to move it to the center.
+
+use crate::axis::axis_strides;
+use crate::{Endianness, RawArray, RawArrayType};
+use num_complex::Complex;
+use rustfft::{FftDirection, FftNum, FftPlanner};
+
+impl<F> RawArray<Complex<F>>
+where
+    F: FftNum,
+    Complex<F>: RawArrayType,
+{
+    /// Transform along `axis` in the given direction, scaling by `1 / n`
+    /// when inverting.
+    fn transform(&mut self, axis: usize, direction: FftDirection) {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        assert!(
+            self.endianness() == Endianness::native(),
+            "transforms need data in native byte order"
+        );
+        let n = self.dims[axis] as usize;
+        if n == 0 {
+            return;
+        }
+        let fft = FftPlanner::new().plan_fft(n, direction);
+        let scale = match direction {
+            FftDirection::Forward => F::one(),
+            FftDirection::Inverse => F::one() / F::from_usize(n).unwrap(),
+        };
+        let (inner, _) = axis_strides(&self.dims, axis);
+        let zero = rustfft::num_complex::Complex::new(F::zero(), F::zero());
+        let mut line = vec![zero; n];
+        let mut scratch = vec![zero; fft.get_inplace_scratch_len()];
+        for block in self.data.chunks_exact_mut(inner * n) {
+            for i in 0..inner {
+                for (k, x) in line.iter_mut().enumerate() {
+                    let c = block[i + k * inner];
+                    *x = rustfft::num_complex::Complex::new(c.re, c.im);
+                }
+                fft.process_with_scratch(&mut line, &mut scratch);
+                for (k, x) in line.iter().enumerate() {
+                    block[i + k * inner] = Complex::new(x.re * scale, x.im * scale);
+                }
+            }
+        }
+    }
+
+    /// Fourier transform along `axis`, in place.
+    /// ```
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// let mut ra: RawArray<Complex<f64>> =
+    ///     vec![1.0, 2.0, 3.0, 4.0].into_iter().map(|x| Complex::new(x, 0.0)).collect();
+    /// ra.fft(0);
+    /// assert_eq!(
+    ///     ra.data(),
+    ///     vec![Complex::new(10.0, 0.0), Complex::new(-2.0, 2.0), Complex::new(-2.0, 0.0), Complex::new(-2.0, -2.0)]
+    /// );
+    /// ra.ifft(0);
+    /// assert!((ra.data()[3] - Complex::new(4.0, 0.0)).norm() < 1e-12);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range or the data isn't in native byte
+    /// order.
+    pub fn fft(&mut self, axis: usize) {
+        self.transform(axis, FftDirection::Forward);
+    }
+
+    /// Inverse Fourier transform along `axis`, in place, divided by the
+    /// length of the axis.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range or the data isn't in native byte
+    /// order.
+    pub fn ifft(&mut self, axis: usize) {
+        self.transform(axis, FftDirection::Inverse);
+    }
+
+    /// Fourier transform along every axis, such as from k-space to image
+    /// space.
+    /// ```
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// // a single k-space sample at the zero frequency is a flat image
+    /// let mut kspace = RawArray::from_iter_with_dims(vec![Complex::new(0.0f32, 0.0); 16], &[4, 4]).unwrap();
+    /// kspace.as_mut_slice()[0] = Complex::new(16.0, 0.0);
+    /// let mut image = kspace.clone();
+    /// image.ifftn();
+    /// assert!(image.data().iter().all(|z| (z - Complex::new(1.0, 0.0)).norm() < 1e-6));
+    /// image.fftn();
+    /// assert!((image.data()[0] - kspace.data()[0]).norm() < 1e-5);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data isn't in native byte order.
+    pub fn fftn(&mut self) {
+        for axis in 0..self.dims.len() {
+            self.fft(axis);
+        }
+    }
+
+    /// Inverse Fourier transform along every axis, undoing `fftn`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data isn't in native byte order.
+    pub fn ifftn(&mut self) {
+        for axis in 0..self.dims.len() {
+            self.ifft(axis);
+        }
+    }
+}
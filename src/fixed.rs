@@ -0,0 +1,193 @@
+//! Arrays whose number of dimensions is part of their type, for code that
+//! only makes sense for, say, images or volumes.
+
+use crate::{RawArray, RawArrayType};
+use ndarray::{Array, Dim, Dimension, IntoDimension, Ix, ShapeBuilder};
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Error, ErrorKind};
+use std::ops::{Index, IndexMut};
+use std::path::Path;
+
+/// A `RawArray<T>` with exactly `N` dimensions, known at compile time, so
+/// that its dimensions are a `[u64; N]` and indexing with the wrong number
+/// of coordinates doesn't compile. The array can't be reshaped to another
+/// number of dimensions; convert it back to a `RawArray<T>` for that.
+/// ```
+/// # use std::io;
+/// use rawarray::{RawArray, RawArray2, RawArray3};
+/// # fn main() -> io::Result<()> {
+/// let image = RawArray2::from_vec((0..6u16).collect(), [3, 2])?;
+/// assert_eq!(image.dims(), [3, 2]);
+/// assert_eq!(image[[2, 1]], 5);
+/// assert_eq!(image.get([3, 0]), None);
+/// image.write("image.ra")?;
+///
+/// let image = RawArray2::<u16>::read("image.ra")?;
+/// assert_eq!(image.as_slice(), &[0, 1, 2, 3, 4, 5]);
+/// assert!(RawArray3::<u16>::read("image.ra").is_err());
+///
+/// // element [i, j] of the array is element [i, j] of the ndarray
+/// let a = image.into_ndarray();
+/// assert_eq!(a.shape(), &[3, 2]);
+/// assert_eq!(a[[2, 1]], 5);
+/// assert_eq!(RawArray2::from(a)[[2, 1]], 5);
+/// let c = ndarray::arr2(&[[1, 2, 3], [4, 5, 6]]);
+/// assert_eq!(RawArray2::from(c).as_slice(), &[1, 4, 2, 5, 3, 6]);
+/// # std::fs::remove_file("image.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Coordinates for the wrong number of dimensions are caught by the
+/// compiler:
+/// ```compile_fail
+/// let image = rawarray::RawArray2::from_vec(vec![0u8; 4], [2, 2]).unwrap();
+/// let x = image[[0, 0, 0]];
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawArrayFixed<T: RawArrayType, const N: usize> {
+    array: RawArray<T>,
+}
+
+/// A two-dimensional array, such as an image
+pub type RawArray2<T> = RawArrayFixed<T, 2>;
+
+/// A three-dimensional array, such as a volume
+pub type RawArray3<T> = RawArrayFixed<T, 3>;
+
+impl<T: RawArrayType, const N: usize> RawArrayFixed<T, N> {
+    /// An array of dimensions `dims` holding `data`, which must have as
+    /// many elements as the dimensions call for.
+    pub fn from_vec(data: Vec<T>, dims: [u64; N]) -> io::Result<RawArrayFixed<T, N>> {
+        RawArray::from_iter_with_dims(data, &dims)?.try_into()
+    }
+
+    /// Read a file, checking that it has `N` dimensions.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<RawArrayFixed<T, N>> {
+        RawArray::read(path.as_ref())?
+            .try_into()
+            .map_err(|e: Error| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{}: {}", path.as_ref().display(), e),
+                )
+            })
+    }
+
+    /// Write to a file, as `RawArray::write` does.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.array.write(path)
+    }
+
+    /// Dimensions of the array
+    pub fn dims(&self) -> [u64; N] {
+        let mut dims = [0; N];
+        dims.copy_from_slice(self.array.dims_as_ref());
+        dims
+    }
+
+    /// The element at coordinates `idx`, if they're in range
+    pub fn get(&self, idx: [u64; N]) -> Option<&T> {
+        self.array.get(&idx)
+    }
+
+    /// Mutable access to the element at coordinates `idx`, if they're in
+    /// range
+    pub fn get_mut(&mut self, idx: [u64; N]) -> Option<&mut T> {
+        self.array.get_mut(&idx)
+    }
+
+    /// The data in column-major order
+    pub fn as_slice(&self) -> &[T] {
+        self.array.as_slice()
+    }
+
+    /// The data in column-major order, for changing in place
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        self.array.as_mut_slice()
+    }
+
+    /// The array with its dimensions unchecked, for the methods of
+    /// `RawArray<T>` such as attribute access
+    pub fn as_raw_array(&self) -> &RawArray<T> {
+        &self.array
+    }
+
+    /// The array with its dimensions unchecked, for reshaping
+    pub fn into_raw_array(self) -> RawArray<T> {
+        self.array
+    }
+}
+
+impl<T: RawArrayType, const N: usize> RawArrayFixed<T, N>
+where
+    [Ix; N]: IntoDimension<Dim = Dim<[Ix; N]>>,
+    Dim<[Ix; N]>: Dimension,
+{
+    /// Convert to an `ndarray` array of the same shape. The data isn't
+    /// copied, only laid out in column-major order.
+    pub fn into_ndarray(self) -> Array<T, Dim<[Ix; N]>> {
+        let mut shape = [0; N];
+        for (s, &d) in shape.iter_mut().zip(self.array.dims_as_ref()) {
+            *s = d as Ix;
+        }
+        let data: Vec<T> = self.array.into();
+        Array::from_shape_vec(shape.into_dimension().f(), data).expect("dims match the data length")
+    }
+}
+
+impl<T: RawArrayType, const N: usize> From<Array<T, Dim<[Ix; N]>>> for RawArrayFixed<T, N>
+where
+    Dim<[Ix; N]>: Dimension,
+{
+    /// Copy an `ndarray` array of any memory layout, keeping element
+    /// `[i, j, ...]` at the same coordinates.
+    fn from(a: Array<T, Dim<[Ix; N]>>) -> RawArrayFixed<T, N> {
+        let dims: Vec<u64> = a.shape().iter().map(|&d| d as u64).collect();
+        // iterating the transpose in logical order gives column-major order
+        let data = a.reversed_axes();
+        RawArrayFixed {
+            array: RawArray::from_iter_with_dims(data.iter().copied(), &dims)
+                .expect("shape matches the data"),
+        }
+    }
+}
+
+impl<T: RawArrayType, const N: usize> TryFrom<RawArray<T>> for RawArrayFixed<T, N> {
+    type Error = Error;
+
+    /// Check that `array` has `N` dimensions.
+    fn try_from(array: RawArray<T>) -> io::Result<RawArrayFixed<T, N>> {
+        if array.dims_as_ref().len() != N {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "array has {} dimensions, not {}",
+                    array.dims_as_ref().len(),
+                    N
+                ),
+            ));
+        }
+        Ok(RawArrayFixed { array })
+    }
+}
+
+impl<T: RawArrayType, const N: usize> From<RawArrayFixed<T, N>> for RawArray<T> {
+    fn from(a: RawArrayFixed<T, N>) -> RawArray<T> {
+        a.array
+    }
+}
+
+impl<T: RawArrayType, const N: usize> Index<[usize; N]> for RawArrayFixed<T, N> {
+    type Output = T;
+
+    fn index(&self, idx: [usize; N]) -> &T {
+        &self.array[idx]
+    }
+}
+
+impl<T: RawArrayType, const N: usize> IndexMut<[usize; N]> for RawArrayFixed<T, N> {
+    fn index_mut(&mut self, idx: [usize; N]) -> &mut T {
+        &mut self.array[idx]
+    }
+}
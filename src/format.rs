@@ -0,0 +1,558 @@
+//! Formatting element values as text, in one place for `Display`, exports,
+//! and the command line tools.
+
+use crate::convert::{self, swap_bytes, DType, Endianness};
+use crate::{as_u8_slice, RawArray, RawArrayType};
+use half::prelude::*;
+use std::convert::TryInto;
+use std::fmt::{self, Debug, LowerExp, Write};
+use std::iter;
+use std::slice;
+
+/// How floating point values are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Notation {
+    /// Shortest text that reads back as the same value, switching to
+    /// scientific notation for very large and very small magnitudes
+    #[default]
+    Auto,
+    /// Always positional, like `1234.5`
+    Fixed,
+    /// Always scientific, like `1.2345e3`
+    Scientific,
+}
+
+/// How complex values are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ComplexStyle {
+    /// `1.5-2i`
+    #[default]
+    Algebraic,
+    /// `(1.5, -2)`
+    Pair,
+}
+
+/// Settings for writing element values as text.
+/// ```
+/// use num_complex::Complex;
+/// use rawarray::{ComplexStyle, Notation, ValueFormatter};
+/// let f = ValueFormatter::new().precision(2);
+/// assert_eq!(f.format(&3.14159f64), "3.14");
+/// assert_eq!(f.format(&7u8), "7");
+/// assert_eq!(f.format(&Complex::new(1.0f32, -0.5)), "1.00-0.50i");
+///
+/// let f = ValueFormatter::new()
+///     .notation(Notation::Scientific)
+///     .complex_style(ComplexStyle::Pair);
+/// assert_eq!(f.format(&Complex::new(1500.0f64, 0.25)), "(1.5e3, 2.5e-1)");
+/// assert_eq!(ValueFormatter::new().format(&0.1f32), "0.1");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ValueFormatter {
+    precision: Option<usize>,
+    notation: Notation,
+    complex_style: ComplexStyle,
+}
+
+impl ValueFormatter {
+    /// Shortest round-tripping floats and algebraic complex numbers
+    pub fn new() -> ValueFormatter {
+        ValueFormatter::default()
+    }
+
+    /// Digits after the decimal point for floating point values. Integers
+    /// are always written in full.
+    pub fn precision(mut self, digits: usize) -> ValueFormatter {
+        self.precision = Some(digits);
+        self
+    }
+
+    /// Fixed, scientific, or automatic notation for floating point values
+    pub fn notation(mut self, notation: Notation) -> ValueFormatter {
+        self.notation = notation;
+        self
+    }
+
+    /// How to write complex values
+    pub fn complex_style(mut self, style: ComplexStyle) -> ValueFormatter {
+        self.complex_style = style;
+        self
+    }
+
+    /// Write one element. User-defined elements use their `Display` impl.
+    pub fn format<T: RawArrayType>(&self, x: &T) -> String {
+        let dtype = DType::of::<T>();
+        if dtype.eltype == 0 {
+            return x.to_string();
+        }
+        self.format_bytes(dtype, Endianness::native(), as_u8_slice(slice::from_ref(x)))
+    }
+
+    /// Write the elements of `data` as a bracketed, comma-separated list.
+    pub fn format_slice<T: RawArrayType>(&self, data: &[T]) -> String {
+        self.format_list(data, Endianness::native())
+    }
+
+    /// Like `format_slice`, for data in byte order `endianness`
+    pub(crate) fn format_list<T: RawArrayType>(
+        &self,
+        data: &[T],
+        endianness: Endianness,
+    ) -> String {
+        let dtype = DType::of::<T>();
+        let items: Vec<String> = if dtype.eltype == 0 {
+            data.iter().map(|x| x.to_string()).collect()
+        } else {
+            as_u8_slice(data)
+                .chunks_exact(dtype.elbyte as usize)
+                .map(|b| self.format_bytes(dtype, endianness, b))
+                .collect()
+        };
+        format!("[{}]", items.join(", "))
+    }
+
+    /// Write one element of type `dtype` stored in `bytes` in byte order
+    /// `endianness`, as read straight from a file. Types this crate can't
+    /// interpret are written as hex bytes.
+    /// ```
+    /// use rawarray::{DType, Endianness, ValueFormatter};
+    /// let f = ValueFormatter::new();
+    /// let ci16 = DType::parse("ci32").unwrap();
+    /// assert_eq!(f.format_bytes(ci16, Endianness::Big, &[0, 3, 255, 254]), "3-2i");
+    /// let user = DType { eltype: 0, elbyte: 2 };
+    /// assert_eq!(f.format_bytes(user, Endianness::Little, &[0xca, 0xfe]), "0xcafe");
+    /// ```
+    pub fn format_bytes(&self, dtype: DType, endianness: Endianness, bytes: &[u8]) -> String {
+        let mut le = bytes.to_vec();
+        if endianness == Endianness::Big && swap_bytes(&mut le, dtype).is_err() {
+            return hex(bytes);
+        }
+        let (code, width) = dtype.component();
+        if width == 0 || le.len() != dtype.elbyte as usize {
+            return hex(bytes);
+        }
+        let parts: Option<Vec<String>> = le
+            .chunks_exact(width)
+            .map(|c| self.format_component(code, c))
+            .collect();
+        match parts.as_deref() {
+            Some([x]) => x.clone(),
+            Some([re, im]) => match self.complex_style {
+                ComplexStyle::Algebraic => match im.strip_prefix('-') {
+                    Some(abs) => format!("{}-{}i", re, abs),
+                    None => format!("{}+{}i", re, im),
+                },
+                ComplexStyle::Pair => format!("({}, {})", re, im),
+            },
+            _ => hex(bytes),
+        }
+    }
+
+    /// Write one little-endian scalar component, or `None` if its type is
+    /// unknown.
+    fn format_component(&self, code: u64, b: &[u8]) -> Option<String> {
+        macro_rules! int {
+            ($t:ty) => {
+                <$t>::from_le_bytes(b.try_into().ok()?).to_string()
+            };
+        }
+        let s = match (code, b.len()) {
+            (1, 1) => int!(i8),
+            (1, 2) => int!(i16),
+            (1, 4) => int!(i32),
+            (1, 8) => int!(i64),
+            (1, 16) => int!(i128),
+            (2, 1) => int!(u8),
+            (2, 2) => int!(u16),
+            (2, 4) => int!(u32),
+            (2, 8) => int!(u64),
+            (2, 16) => int!(u128),
+            (3, 2) => {
+                let x = f16::from_le_bytes(b.try_into().ok()?);
+                self.format_float(x, x.to_f64())
+            }
+            (3, 4) => {
+                let x = f32::from_le_bytes(b.try_into().ok()?);
+                self.format_float(x, f64::from(x))
+            }
+            (3, 8) => {
+                let x = f64::from_le_bytes(b.try_into().ok()?);
+                self.format_float(x, x)
+            }
+            (5, 2) => {
+                let x = bf16::from_le_bytes(b.try_into().ok()?);
+                self.format_float(x, x.to_f64())
+            }
+            _ => return None,
+        };
+        Some(s)
+    }
+
+    /// Write `x`, whose value as an `f64` is `v`, in the chosen notation.
+    /// Formatting `x` rather than `v` keeps the shortest text for narrow
+    /// types, so an `f32` 0.1 stays `0.1`.
+    fn format_float<F: fmt::Display + Debug + LowerExp>(&self, x: F, v: f64) -> String {
+        let scientific = match self.notation {
+            Notation::Scientific => true,
+            Notation::Fixed => false,
+            Notation::Auto => v != 0.0 && v.is_finite() && !(1e-5..1e16).contains(&v.abs()),
+        };
+        match (scientific, self.precision) {
+            (true, Some(p)) => format!("{:.*e}", p, x),
+            (true, None) => format!("{:e}", x),
+            (false, Some(p)) => format!("{:.*}", p, x),
+            (false, None) if self.notation == Notation::Fixed => format!("{}", x),
+            (false, None) => format!("{:?}", x),
+        }
+    }
+}
+
+/// Parse one scalar component of type code `code` and width `width` into
+/// `out`, little-endian, or return `None` if it isn't a valid value of
+/// that type. The counterpart of `format_component`.
+pub(crate) fn parse_component(code: u64, width: usize, s: &str, out: &mut Vec<u8>) -> Option<()> {
+    macro_rules! num {
+        ($t:ty) => {
+            out.extend_from_slice(&s.parse::<$t>().ok()?.to_le_bytes())
+        };
+    }
+    match (code, width) {
+        (1, 1) => num!(i8),
+        (1, 2) => num!(i16),
+        (1, 4) => num!(i32),
+        (1, 8) => num!(i64),
+        (1, 16) => num!(i128),
+        (2, 1) => num!(u8),
+        (2, 2) => num!(u16),
+        (2, 4) => num!(u32),
+        (2, 8) => num!(u64),
+        (2, 16) => num!(u128),
+        (3, 2) => out.extend_from_slice(&f16::from_f64(s.parse().ok()?).to_le_bytes()),
+        (3, 4) => num!(f32),
+        (3, 8) => num!(f64),
+        (5, 2) => out.extend_from_slice(&bf16::from_f64(s.parse().ok()?).to_le_bytes()),
+        _ => return None,
+    }
+    Some(())
+}
+
+/// An array written out with its shape, like NumPy prints arrays, made by
+/// `RawArray::pretty`. Coordinates nest first dimension outermost, so a
+/// two-dimensional array prints as a matrix with one row per value of the
+/// first coordinate. Arrays of more than `threshold` elements are
+/// shortened to the first and last `edge_items` along each dimension.
+/// ```
+/// use rawarray::RawArray;
+/// let ra = RawArray::from_iter_with_dims((0..6).map(|i| i as f32 / 4.0), &[2, 3]).unwrap();
+/// assert_eq!(ra.pretty().to_string(), "[[ 0.0,  0.5,  1.0],\n [0.25, 0.75, 1.25]]");
+/// assert_eq!(
+///     format!("{:.1}", ra.pretty()),
+///     "[[0.0, 0.5, 1.0],\n [0.2, 0.8, 1.2]]"
+/// );
+///
+/// let long: RawArray<u16> = (0..2000).collect::<Vec<u16>>().into();
+/// assert_eq!(long.pretty().to_string(), "[   0,    1,    2, ..., 1997, 1998, 1999]");
+/// assert_eq!(long.pretty().edge_items(1).to_string(), "[   0, ..., 1999]");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Pretty<'a, T: RawArrayType> {
+    array: &'a RawArray<T>,
+    formatter: ValueFormatter,
+    edge_items: usize,
+    threshold: usize,
+}
+
+impl<'a, T: RawArrayType> Pretty<'a, T> {
+    /// How to write each element
+    pub fn formatter(mut self, formatter: ValueFormatter) -> Pretty<'a, T> {
+        self.formatter = formatter;
+        self
+    }
+
+    /// Digits after the decimal point for floating point values, the same
+    /// as `{:.N}` in the format string
+    pub fn precision(mut self, digits: usize) -> Pretty<'a, T> {
+        self.formatter = self.formatter.precision(digits);
+        self
+    }
+
+    /// Elements to show at each end of a dimension when shortening
+    /// (default 3)
+    pub fn edge_items(mut self, n: usize) -> Pretty<'a, T> {
+        self.edge_items = n;
+        self
+    }
+
+    /// Most elements to print in full before shortening (default 1000)
+    pub fn threshold(mut self, n: usize) -> Pretty<'a, T> {
+        self.threshold = n;
+        self
+    }
+
+    /// Coordinates to show along each dimension, `None` standing for the
+    /// elided middle
+    fn shown(&self) -> Vec<Vec<Option<usize>>> {
+        let shorten = self.array.data.len() > self.threshold;
+        self.array
+            .dims
+            .iter()
+            .map(|&n| {
+                let (n, e) = (n as usize, self.edge_items);
+                if shorten && n > 2 * e {
+                    (0..e)
+                        .map(Some)
+                        .chain(iter::once(None))
+                        .chain((n - e..n).map(Some))
+                        .collect()
+                } else {
+                    (0..n).map(Some).collect()
+                }
+            })
+            .collect()
+    }
+
+    fn element(&self, vf: &ValueFormatter, i: usize) -> String {
+        let dtype = DType::of::<T>();
+        if dtype.eltype == 0 {
+            return self.array.data[i].to_string();
+        }
+        let bytes = as_u8_slice(&self.array.data[i..i + 1]);
+        vf.format_bytes(dtype, self.array.endianness(), bytes)
+    }
+
+    /// Write the elements shown along `axis` and beyond, starting from
+    /// flat offset `offset`, or with `out` unset just measure them.
+    fn nest(
+        &self,
+        vf: &ValueFormatter,
+        layout: &Layout,
+        axis: usize,
+        offset: usize,
+        out: &mut Option<&mut String>,
+        width: &mut usize,
+    ) {
+        let ndims = layout.shown.len();
+        let innermost = axis + 1 == ndims;
+        if let Some(out) = out {
+            out.push('[');
+        }
+        for (n, &i) in layout.shown[axis].iter().enumerate() {
+            if let Some(out) = out {
+                if n > 0 && innermost {
+                    out.push_str(", ");
+                } else if n > 0 {
+                    // rows on their own lines, with a blank line between
+                    // blocks for each level above rows
+                    out.push(',');
+                    out.push_str(&"\n".repeat(ndims - axis - 1));
+                    out.push_str(&" ".repeat(axis + 1));
+                }
+            }
+            match i {
+                None => {
+                    if let Some(out) = out {
+                        out.push_str("...");
+                    }
+                }
+                Some(i) if innermost => {
+                    let text = self.element(vf, offset + i * layout.strides[axis]);
+                    match out {
+                        Some(out) => {
+                            let _ = write!(out, "{:>w$}", text, w = *width);
+                        }
+                        None => *width = (*width).max(text.chars().count()),
+                    }
+                }
+                Some(i) => {
+                    let offset = offset + i * layout.strides[axis];
+                    self.nest(vf, layout, axis + 1, offset, out, width);
+                }
+            }
+        }
+        if let Some(out) = out {
+            out.push(']');
+        }
+    }
+}
+
+/// The elements a `Pretty` shows, and how to find them in the data
+struct Layout {
+    shown: Vec<Vec<Option<usize>>>,
+    strides: Vec<usize>,
+}
+
+impl<T: RawArrayType> fmt::Display for Pretty<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut vf = self.formatter;
+        if let Some(p) = f.precision() {
+            vf = vf.precision(p);
+        }
+        if self.array.dims.is_empty() || self.array.data.is_empty() {
+            return f.write_str("[]");
+        }
+        let strides = self
+            .array
+            .dims
+            .iter()
+            .scan(1, |stride, &n| {
+                let s = *stride;
+                *stride *= n as usize;
+                Some(s)
+            })
+            .collect();
+        let layout = Layout {
+            shown: self.shown(),
+            strides,
+        };
+        let mut width = 0;
+        self.nest(&vf, &layout, 0, 0, &mut None, &mut width);
+        let mut out = String::new();
+        self.nest(&vf, &layout, 0, 0, &mut Some(&mut out), &mut width);
+        f.write_str(&out)
+    }
+}
+
+/// Lines of data a summary shows at most
+const SUMMARY_LINES: usize = 16;
+
+/// Attributes a summary lists at most
+const SUMMARY_ATTRS: usize = 5;
+
+impl<T: RawArrayType> RawArray<T> {
+    /// The array written out with its shape, shortened if it's large. See
+    /// `Pretty` for the settings.
+    pub fn pretty(&self) -> Pretty<'_, T> {
+        Pretty {
+            array: self,
+            formatter: ValueFormatter::new(),
+            edge_items: 3,
+            threshold: 1000,
+        }
+    }
+
+    /// A description of the array that fits on one screen however large
+    /// it is: its type and shape, a few attributes, the range and mean of
+    /// its values, and the corners of its data. Complex values are
+    /// described by their magnitudes.
+    /// ```
+    /// use rawarray::RawArray;
+    /// let mut ra = RawArray::from_iter_with_dims((0..100_000).map(|i| i as f64), &[100, 1000])
+    ///     .unwrap();
+    /// ra.set_attr("units", "mV");
+    /// let s = ra.summary();
+    /// assert!(s.starts_with("f64 [100, 1000], 100000 elements\n"));
+    /// assert!(s.contains("units: \"mV\""));
+    /// assert!(s.contains("min 0, max 99999, mean 49999.5"));
+    /// assert!(s.lines().count() <= 24);
+    /// ```
+    pub fn summary(&self) -> String {
+        let mut s = format!(
+            "{} {:?}, {} elements",
+            DType::of::<T>(),
+            self.dims,
+            self.data.len()
+        );
+        if self.endianness() == Endianness::Big {
+            s.push_str(", big endian");
+        }
+        if self.checksum() {
+            s.push_str(", checksummed");
+        }
+        s.push('\n');
+        for (key, value) in self.attrs.iter().take(SUMMARY_ATTRS) {
+            let value = value.to_string();
+            if value.chars().count() > 60 {
+                let short: String = value.chars().take(57).collect();
+                let _ = writeln!(s, "{}: {}...", key, short);
+            } else {
+                let _ = writeln!(s, "{}: {}", key, value);
+            }
+        }
+        if self.attrs.len() > SUMMARY_ATTRS {
+            let _ = writeln!(
+                s,
+                "and {} more attributes",
+                self.attrs.len() - SUMMARY_ATTRS
+            );
+        }
+        if let Some(stats) = self.value_stats() {
+            s.push_str(&stats);
+            s.push('\n');
+        }
+        // fewer corner elements for arrays of many dimensions
+        let mut data = self.pretty().to_string();
+        if data.lines().count() > SUMMARY_LINES {
+            data = self.pretty().edge_items(1).to_string();
+        }
+        for (n, line) in data.lines().enumerate() {
+            if n + 1 == SUMMARY_LINES {
+                s.push_str(" ...\n");
+                break;
+            }
+            s.push_str(line);
+            s.push('\n');
+        }
+        s.pop();
+        s
+    }
+
+    /// Range and mean of the values, or of the magnitudes of complex
+    /// values, if the element type is one this crate can convert
+    fn value_stats(&self) -> Option<String> {
+        let dtype = DType::of::<T>();
+        let f64s = DType::of::<f64>();
+        let magnitude = dtype.components() == 2;
+        if !dtype.is_known() || self.data.is_empty() {
+            return None;
+        }
+        let (mut min, mut max, mut sum) = (f64::INFINITY, f64::NEG_INFINITY, 0.0);
+        let (mut finite, mut nonfinite) = (0u64, 0u64);
+        let mut buf = Vec::new();
+        for chunk in self.data.chunks(1 << 16) {
+            buf.clear();
+            let bytes = as_u8_slice(chunk);
+            let native = Endianness::native();
+            if magnitude {
+                convert::magnitude_bytes(bytes, dtype, self.endianness(), &mut buf, f64s, native)
+            } else {
+                convert::convert_bytes(bytes, dtype, self.endianness(), &mut buf, f64s, native)
+            }
+            .ok()?;
+            for b in buf.chunks_exact(8) {
+                let x = f64::from_ne_bytes(b.try_into().ok()?);
+                if x.is_finite() {
+                    min = min.min(x);
+                    max = max.max(x);
+                    sum += x;
+                    finite += 1;
+                } else {
+                    nonfinite += 1;
+                }
+            }
+        }
+        let mut s = if magnitude { "magnitude " } else { "" }.to_string();
+        if finite > 0 {
+            // the extremes are element values, so narrow floats print as
+            // they were stored rather than with spurious digits
+            let (min, max) = match dtype.component() {
+                (3, w) | (5, w) if w <= 4 && !magnitude => {
+                    ((min as f32).to_string(), (max as f32).to_string())
+                }
+                _ => (min.to_string(), max.to_string()),
+            };
+            let _ = write!(s, "min {}, max {}, mean {}", min, max, sum / finite as f64);
+        }
+        if nonfinite > 0 {
+            if finite > 0 {
+                s.push_str(", ");
+            }
+            let _ = write!(s, "{} not finite", nonfinite);
+        }
+        Some(s)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", digits)
+}
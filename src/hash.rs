@@ -0,0 +1,131 @@
+//! Hashes of whole files or of just the arrays they hold, for finding
+//! duplicates in a dataset and recording what went into a result.
+
+use crate::recode::open_source;
+use crate::Endianness;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use xxhash_rust::xxh3::Xxh3;
+
+/// A hash function for `hash_file` and `hash_contents`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// 64-bit XXH3, as `xxhsum -H3` prints it: fast, for spotting
+    /// duplicates
+    Xxh3,
+    /// SHA-256, as `sha256sum` prints it: slower, but safe against
+    /// deliberate collisions
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Parse a name, `xxh3` or `sha256`.
+    pub fn parse(name: &str) -> Option<HashAlgorithm> {
+        match name {
+            "xxh3" => Some(HashAlgorithm::Xxh3),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+}
+
+enum Hasher {
+    Xxh3(Box<Xxh3>),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn new(algo: HashAlgorithm) -> Hasher {
+        match algo {
+            HashAlgorithm::Xxh3 => Hasher::Xxh3(Box::default()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Xxh3(h) => h.update(bytes),
+            Hasher::Sha256(h) => h.update(bytes),
+        }
+    }
+
+    /// The digest in lowercase hex
+    fn finish(self) -> String {
+        match self {
+            Hasher::Xxh3(h) => format!("{:016x}", h.digest()),
+            Hasher::Sha256(h) => h.finalize().iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
+
+/// Hash every byte of the file at `path`, in lowercase hex.
+/// ```
+/// # use std::io;
+/// use rawarray::{hash_file, HashAlgorithm, RawArray};
+/// # fn main() -> io::Result<()> {
+/// RawArray::<u8>::from(vec![]).write("empty_hash.ra")?;
+/// assert_eq!(hash_file("empty_hash.ra", HashAlgorithm::Sha256)?.len(), 64);
+/// assert_eq!(hash_file("empty_hash.ra", HashAlgorithm::Xxh3)?.len(), 16);
+/// # std::fs::remove_file("empty_hash.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn hash_file<P: AsRef<Path>>(path: P, algo: HashAlgorithm) -> io::Result<String> {
+    let mut r = BufReader::new(File::open(path)?);
+    let mut hasher = Hasher::new(algo);
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = r.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Hash the array in the file at `path` rather than the file itself, so
+/// that the same array gives the same hash however it's stored: whatever
+/// its flags, codec, byte order, checksum, or attributes. The hash covers
+/// the element type code, element width, number of dimensions, and
+/// dimensions, as little-endian `u64`s, followed by the decoded data in
+/// little-endian order. A stored checksum is verified along the way.
+/// ```
+/// # use std::io;
+/// use rawarray::{hash_contents, hash_file, HashAlgorithm, RawArray, WriteOptions};
+/// # fn main() -> io::Result<()> {
+/// let mut ra = RawArray::from_iter_with_dims(0..1000u32, &[10, 100])?;
+/// ra.write("plain_hash.ra")?;
+/// ra.set_attr("note", "same data");
+/// ra.write_with("big_hash.ra", &WriteOptions::new().endianness(rawarray::Endianness::Big))?;
+/// assert_ne!(hash_file("plain_hash.ra", HashAlgorithm::Xxh3)?, hash_file("big_hash.ra", HashAlgorithm::Xxh3)?);
+/// assert_eq!(hash_contents("plain_hash.ra", HashAlgorithm::Xxh3)?, hash_contents("big_hash.ra", HashAlgorithm::Xxh3)?);
+///
+/// ra.reshape(vec![100, 10]);
+/// ra.write("plain_hash.ra")?;
+/// assert_ne!(hash_contents("plain_hash.ra", HashAlgorithm::Xxh3)?, hash_contents("big_hash.ra", HashAlgorithm::Xxh3)?);
+/// # std::fs::remove_file("plain_hash.ra")?;
+/// # std::fs::remove_file("big_hash.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn hash_contents<P: AsRef<Path>>(path: P, algo: HashAlgorithm) -> io::Result<String> {
+    let mut src = open_source(path.as_ref())?;
+    let dtype = src.dtype();
+    let dims = &src.header.dims;
+    let mut hasher = Hasher::new(algo);
+    for n in [dtype.eltype, dtype.elbyte, dims.len() as u64]
+        .iter()
+        .chain(dims)
+    {
+        hasher.update(&n.to_le_bytes());
+    }
+    src.convert(dtype, Endianness::Little, |bytes| {
+        hasher.update(bytes);
+        Ok(())
+    })?;
+    src.finish()?;
+    Ok(hasher.finish())
+}
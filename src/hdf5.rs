@@ -0,0 +1,370 @@
+//! HDF5 datasets, behind the `hdf5` feature, for pulling single arrays out
+//! of HDF5 archives and pushing them back. The feature links against the
+//! system HDF5 library, version 1.10 or later.
+//!
+//! Integer and floating point datasets are handled, along with complex
+//! ones stored as h5py does, as a compound of two floats named `r` and
+//! `i`; compounds with other member names aren't read. Attributes aren't
+//! copied in either direction.
+//!
+//! HDF5 stores arrays row major, so dimensions are reversed on the way in
+//! and out: a dataset of shape `(3, 4)` in HDF5 is an array of
+//! dimensions `[4, 3]` here, with the same elements in the same order.
+
+use crate::convert::{check_conversion, convert_bytes, elements_from_bytes_with_endianness};
+use crate::{as_u8_slice, DType, Endianness, RawArray, RawArrayType};
+use hdf5_sys::h5::{herr_t, hsize_t, H5open};
+use hdf5_sys::h5d::{H5Dclose, H5Dcreate2, H5Dget_space, H5Dget_type, H5Dopen2, H5Dread, H5Dwrite};
+use hdf5_sys::h5f::{H5Fclose, H5Fcreate, H5Fopen, H5F_ACC_RDONLY, H5F_ACC_RDWR, H5F_ACC_TRUNC};
+use hdf5_sys::h5i::hid_t;
+use hdf5_sys::h5l::{H5Ldelete, H5Lexists};
+use hdf5_sys::h5p::{
+    H5Pclose, H5Pcreate, H5Pset_create_intermediate_group, H5P_CLS_LINK_CREATE, H5P_DEFAULT,
+};
+use hdf5_sys::h5s::{
+    H5Sclose, H5Screate_simple, H5Sget_simple_extent_dims, H5Sget_simple_extent_ndims, H5S_ALL,
+};
+use hdf5_sys::h5t::{
+    H5T_class_t, H5T_sign_t, H5Tclose, H5Tcreate, H5Tget_class, H5Tget_member_class,
+    H5Tget_member_index, H5Tget_member_type, H5Tget_nmembers, H5Tget_sign, H5Tget_size, H5Tinsert,
+    H5T_NATIVE_DOUBLE, H5T_NATIVE_FLOAT, H5T_NATIVE_INT16, H5T_NATIVE_INT32, H5T_NATIVE_INT64,
+    H5T_NATIVE_INT8, H5T_NATIVE_UINT16, H5T_NATIVE_UINT32, H5T_NATIVE_UINT64, H5T_NATIVE_UINT8,
+};
+use std::ffi::CString;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+use std::ptr;
+use std::sync::Mutex;
+
+/// The HDF5 library is only safe to call from one thread at a time unless
+/// it was built thread-safe, which can't be relied on.
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// An open HDF5 object, closed when dropped
+struct Handle(hid_t, unsafe extern "C" fn(hid_t) -> herr_t);
+
+impl Handle {
+    /// Take ownership of `id`, or fail with `what` if the call that
+    /// returned it failed.
+    fn new(
+        id: hid_t,
+        close: unsafe extern "C" fn(hid_t) -> herr_t,
+        kind: ErrorKind,
+        what: impl FnOnce() -> String,
+    ) -> io::Result<Handle> {
+        if id < 0 {
+            Err(Error::new(kind, what()))
+        } else {
+            Ok(Handle(id, close))
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe {
+            (self.1)(self.0);
+        }
+    }
+}
+
+fn check(status: herr_t, what: &str) -> io::Result<()> {
+    if status < 0 {
+        Err(Error::other(what.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+fn c_string(s: &str) -> io::Result<CString> {
+    CString::new(s).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{:?} contains a NUL byte", s),
+        )
+    })
+}
+
+fn path_c_string(path: &Path) -> io::Result<CString> {
+    let s = path.to_str().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} isn't valid UTF-8", path.display()),
+        )
+    })?;
+    c_string(s)
+}
+
+/// Element type of the HDF5 datatype `ty`, if RawArray has one
+unsafe fn dtype_of(ty: hid_t) -> Option<DType> {
+    let size = H5Tget_size(ty) as u64;
+    match H5Tget_class(ty) {
+        H5T_class_t::H5T_INTEGER => {
+            let eltype = match H5Tget_sign(ty) {
+                H5T_sign_t::H5T_SGN_2 => 1,
+                H5T_sign_t::H5T_SGN_NONE => 2,
+                _ => return None,
+            };
+            Some(DType {
+                eltype,
+                elbyte: size,
+            })
+        }
+        H5T_class_t::H5T_FLOAT => Some(DType {
+            eltype: 3,
+            elbyte: size,
+        }),
+        H5T_class_t::H5T_COMPOUND if H5Tget_nmembers(ty) == 2 => {
+            let floats = (0..2).all(|i| H5Tget_member_class(ty, i) == H5T_class_t::H5T_FLOAT);
+            // the memory type picks members out by these names, so others
+            // would read as zeros
+            let named = H5Tget_member_index(ty, b"r\0".as_ptr().cast()) == 0
+                && H5Tget_member_index(ty, b"i\0".as_ptr().cast()) == 1;
+            if !floats || !named {
+                return None;
+            }
+            let re = Handle(H5Tget_member_type(ty, 0), H5Tclose);
+            let im = Handle(H5Tget_member_type(ty, 1), H5Tclose);
+            let part = H5Tget_size(re.0) as u64;
+            if part != H5Tget_size(im.0) as u64 || 2 * part != size {
+                return None;
+            }
+            Some(DType {
+                eltype: 4,
+                elbyte: size,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Native HDF5 datatype for elements of type `d`
+unsafe fn native_type(d: DType) -> Option<Handle> {
+    // predefined types are never closed, so dropping them does nothing
+    unsafe extern "C" fn keep(_: hid_t) -> herr_t {
+        0
+    }
+    let id = match (d.eltype, d.elbyte) {
+        (1, 1) => *H5T_NATIVE_INT8,
+        (1, 2) => *H5T_NATIVE_INT16,
+        (1, 4) => *H5T_NATIVE_INT32,
+        (1, 8) => *H5T_NATIVE_INT64,
+        (2, 1) => *H5T_NATIVE_UINT8,
+        (2, 2) => *H5T_NATIVE_UINT16,
+        (2, 4) => *H5T_NATIVE_UINT32,
+        (2, 8) => *H5T_NATIVE_UINT64,
+        (3, 4) => *H5T_NATIVE_FLOAT,
+        (3, 8) => *H5T_NATIVE_DOUBLE,
+        (4, 8) | (4, 16) => {
+            let part = native_type(DType {
+                eltype: 3,
+                elbyte: d.elbyte / 2,
+            })?;
+            let ty = Handle(
+                H5Tcreate(H5T_class_t::H5T_COMPOUND, d.elbyte as usize),
+                H5Tclose,
+            );
+            if ty.0 < 0
+                || H5Tinsert(ty.0, b"r\0".as_ptr().cast(), 0, part.0) < 0
+                || H5Tinsert(ty.0, b"i\0".as_ptr().cast(), d.elbyte as usize / 2, part.0) < 0
+            {
+                return None;
+            }
+            return Some(ty);
+        }
+        _ => return None,
+    };
+    Some(Handle(id, keep))
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Read `dataset`, such as `"/scans/run1/image"`, from the HDF5 file
+    /// at `path`. Elements are converted to `T` as by `convert`, so an
+    /// `int16` dataset can be read as `f32`, and a complex one needs a
+    /// complex `T`.
+    /// ```no_run
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let image = RawArray::<f32>::from_hdf5("archive.h5", "/scans/run1/image")?;
+    /// image.write("image.ra")?;
+    /// image.to_hdf5("copy.h5", "/image")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_hdf5<P: AsRef<Path>>(path: P, dataset: &str) -> io::Result<RawArray<T>> {
+        let path = path.as_ref();
+        let c_path = path_c_string(path)?;
+        let c_name = c_string(dataset)?;
+        let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        unsafe {
+            check(H5open(), "can't initialize the HDF5 library")?;
+            let file = Handle::new(
+                H5Fopen(c_path.as_ptr(), H5F_ACC_RDONLY, H5P_DEFAULT),
+                H5Fclose,
+                ErrorKind::InvalidData,
+                || format!("can't open {} as an HDF5 file", path.display()),
+            )?;
+            let dset = Handle::new(
+                H5Dopen2(file.0, c_name.as_ptr(), H5P_DEFAULT),
+                H5Dclose,
+                ErrorKind::NotFound,
+                || format!("no dataset named {}", dataset),
+            )?;
+            let space = Handle::new(H5Dget_space(dset.0), H5Sclose, ErrorKind::Other, || {
+                format!("can't get the shape of {}", dataset)
+            })?;
+            let rank = H5Sget_simple_extent_ndims(space.0);
+            if rank < 0 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} isn't a simple array", dataset),
+                ));
+            }
+            let mut shape: Vec<hsize_t> = vec![0; rank as usize];
+            if rank > 0 {
+                let status =
+                    H5Sget_simple_extent_dims(space.0, shape.as_mut_ptr(), ptr::null_mut());
+                if status < 0 {
+                    return Err(Error::other(format!("can't get the shape of {}", dataset)));
+                }
+            }
+            let mut dims: Vec<u64> = shape.iter().rev().copied().collect();
+            if dims.is_empty() {
+                dims.push(1);
+            }
+
+            let file_type = Handle::new(H5Dget_type(dset.0), H5Tclose, ErrorKind::Other, || {
+                format!("can't get the type of {}", dataset)
+            })?;
+            let unsupported = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} isn't an integer, float, or complex dataset", dataset),
+                )
+            };
+            let from = dtype_of(file_type.0).ok_or_else(unsupported)?;
+            let to = DType::of::<T>();
+            check_conversion(from, to)?;
+            let mem_type = native_type(from).ok_or_else(unsupported)?;
+
+            let too_large = || {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} of shape {:?} is too large", dataset, shape),
+                )
+            };
+            let n = dims
+                .iter()
+                .try_fold(1u64, |n, &d| n.checked_mul(d))
+                .ok_or_else(too_large)?;
+            let len = crate::to_usize(n.checked_mul(from.elbyte).ok_or_else(too_large)?)?;
+            let mut bytes = vec![0u8; len];
+            check(
+                H5Dread(
+                    dset.0,
+                    mem_type.0,
+                    H5S_ALL,
+                    H5S_ALL,
+                    H5P_DEFAULT,
+                    bytes.as_mut_ptr().cast(),
+                ),
+                "can't read the dataset",
+            )?;
+
+            let mut out = Vec::with_capacity(len / from.elbyte as usize * to.elbyte as usize);
+            let native = Endianness::native();
+            convert_bytes(&bytes, from, native, &mut out, to, native)?;
+            let data = elements_from_bytes_with_endianness(&out, native)?;
+            Ok(RawArray::from_parts(dims, data))
+        }
+    }
+
+    /// Write the array to `dataset` in the HDF5 file at `path`, creating
+    /// the file and any groups on the way to the dataset as needed, and
+    /// replacing the dataset if it's already there. Other datasets in the
+    /// file are left alone. Attributes aren't written.
+    pub fn to_hdf5<P: AsRef<Path>>(&self, path: P, dataset: &str) -> io::Result<()> {
+        let path = path.as_ref();
+        let c_path = path_c_string(path)?;
+        let c_name = c_string(dataset)?;
+        let dtype = DType::of::<T>();
+        let mut bytes = Vec::with_capacity(self.data.len() * dtype.elbyte as usize);
+        let native = Endianness::native();
+        convert_bytes(
+            as_u8_slice(&self.data),
+            dtype,
+            self.endianness(),
+            &mut bytes,
+            dtype,
+            native,
+        )?;
+        let shape: Vec<hsize_t> = self.dims.iter().rev().map(|&d| d as hsize_t).collect();
+
+        let _guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        unsafe {
+            check(H5open(), "can't initialize the HDF5 library")?;
+            let mem_type = native_type(dtype).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("HDF5 datasets can't hold elements of type {}", dtype),
+                )
+            })?;
+            let id = if path.exists() {
+                H5Fopen(c_path.as_ptr(), H5F_ACC_RDWR, H5P_DEFAULT)
+            } else {
+                H5Fcreate(c_path.as_ptr(), H5F_ACC_TRUNC, H5P_DEFAULT, H5P_DEFAULT)
+            };
+            let file = Handle::new(id, H5Fclose, ErrorKind::Other, || {
+                format!("can't open {} as an HDF5 file for writing", path.display())
+            })?;
+            if H5Lexists(file.0, c_name.as_ptr(), H5P_DEFAULT) > 0 {
+                check(
+                    H5Ldelete(file.0, c_name.as_ptr(), H5P_DEFAULT),
+                    "can't replace the existing dataset",
+                )?;
+            }
+            let lcpl = Handle::new(
+                H5Pcreate(*H5P_CLS_LINK_CREATE),
+                H5Pclose,
+                ErrorKind::Other,
+                || "can't create a link property list".to_string(),
+            )?;
+            check(
+                H5Pset_create_intermediate_group(lcpl.0, 1),
+                "can't set up group creation",
+            )?;
+            let space = Handle::new(
+                H5Screate_simple(shape.len() as _, shape.as_ptr(), ptr::null()),
+                H5Sclose,
+                ErrorKind::Other,
+                || "can't create the dataset's shape".to_string(),
+            )?;
+            let dset = Handle::new(
+                H5Dcreate2(
+                    file.0,
+                    c_name.as_ptr(),
+                    mem_type.0,
+                    space.0,
+                    lcpl.0,
+                    H5P_DEFAULT,
+                    H5P_DEFAULT,
+                ),
+                H5Dclose,
+                ErrorKind::Other,
+                || format!("can't create dataset {}", dataset),
+            )?;
+            check(
+                H5Dwrite(
+                    dset.0,
+                    mem_type.0,
+                    H5S_ALL,
+                    H5S_ALL,
+                    H5P_DEFAULT,
+                    bytes.as_ptr().cast(),
+                ),
+                "can't write the dataset",
+            )
+        }
+    }
+}
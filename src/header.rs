@@ -0,0 +1,162 @@
+//! The fixed part of a RawArray file: everything before the data section.
+
+use crate::storage::{FsFile, OpenMode};
+use crate::MAGIC_NUMBER;
+use rawarray_core::{header_len, Error as CoreError};
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+/// Header fields of a RawArray file, without the data.
+///
+/// Besides streams, headers can be written to and parsed from plain byte
+/// slices, for building frames in preallocated buffers.
+/// ```
+/// use rawarray::RawArrayHeader;
+/// let h = RawArrayHeader {
+///     flags: 0,
+///     eltype: 3,
+///     elbyte: 4,
+///     size: 24,
+///     dims: vec![2, 3],
+/// };
+/// let mut frame = [0u8; 128];
+/// let n = h.write_to_buf(&mut frame);
+/// assert_eq!(n, h.len());
+/// assert_eq!(h.data_offset(), 64);
+/// assert_eq!(RawArrayHeader::parse_from_buf(&frame[..n]).unwrap(), h);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawArrayHeader {
+    /// Format flags, as listed in the README
+    pub flags: u64,
+    /// Element type code
+    pub eltype: u64,
+    /// Width of one element in bytes
+    pub elbyte: u64,
+    /// Length of the decoded data section in bytes
+    pub size: u64,
+    /// Array dimensions, first one fastest varying
+    pub dims: Vec<u64>,
+}
+
+impl From<rawarray_core::Header> for RawArrayHeader {
+    fn from(h: rawarray_core::Header) -> RawArrayHeader {
+        RawArrayHeader {
+            flags: h.flags,
+            eltype: h.eltype,
+            elbyte: h.elbyte,
+            size: h.size,
+            dims: h.dims,
+        }
+    }
+}
+
+impl RawArrayHeader {
+    /// Parse a header from the start of a stream, leaving the stream at
+    /// the data section.
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<RawArrayHeader> {
+        let mut buf = vec![0u8; header_len(0)];
+        r.read_exact(&mut buf)?;
+        if let Err(CoreError::Truncated { .. }) = rawarray_core::Header::parse(&buf) {
+            // the magic number and ndims passed, so read the dims too
+            let mut ndims = [0u8; 8];
+            ndims.copy_from_slice(&buf[40..48]);
+            buf.resize(header_len(u64::from_le_bytes(ndims) as usize), 0);
+            r.read_exact(&mut buf[header_len(0)..])?;
+        }
+        Ok(rawarray_core::Header::parse(&buf)?.into())
+    }
+
+    /// Read just the header of a file, without touching the data, which
+    /// makes scanning many files cheap.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{RawArray, RawArrayHeader};
+    /// # fn main() -> io::Result<()> {
+    /// let mut ra: RawArray<f64> = vec![0.0; 6].into();
+    /// ra.reshape(vec![2, 3]);
+    /// ra.write("header_only.ra")?;
+    /// let h = RawArrayHeader::read("header_only.ra")?;
+    /// assert_eq!((h.ndims(), h.dims.clone(), h.size), (2, vec![2, 3], 48));
+    /// # std::fs::remove_file("header_only.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<RawArrayHeader> {
+        // a small buffer, since we only want the first few hundred bytes
+        let f = FsFile::open_fs(path.as_ref(), OpenMode::Read)?;
+        let mut r = BufReader::with_capacity(512, f);
+        RawArrayHeader::read_from(&mut r)
+    }
+
+    /// Parse a header from the start of `buf`. Anything after the header
+    /// is ignored; use `len()` to find where the data section starts.
+    pub fn parse_from_buf(buf: &[u8]) -> io::Result<RawArrayHeader> {
+        Ok(rawarray_core::Header::parse(buf)?.into())
+    }
+
+    /// Write the header to a stream.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&MAGIC_NUMBER.to_le_bytes())?;
+        for field in self.fields().iter() {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        for d in &self.dims {
+            w.write_all(&d.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Write the header to the start of `buf` without allocating, and
+    /// return the number of bytes written, which is always `len()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` is shorter than `len()`.
+    pub fn write_to_buf(&self, buf: &mut [u8]) -> usize {
+        let (flags, eltype, elbyte, size) = (self.flags, self.eltype, self.elbyte, self.size);
+        match rawarray_core::write_header(buf, flags, eltype, elbyte, size, &self.dims) {
+            Ok(n) => n,
+            Err(_) => panic!(
+                "buffer of {} bytes is too small for a {} byte header",
+                buf.len(),
+                self.len()
+            ),
+        }
+    }
+
+    /// Number of dimensions
+    pub fn ndims(&self) -> u64 {
+        self.dims.len() as u64
+    }
+
+    /// Number of elements, or `None` if the dimensions are too large for
+    /// their product to fit in a `u64`, as they can be in a corrupt file.
+    pub fn nelem(&self) -> Option<u64> {
+        self.dims.iter().try_fold(1u64, |n, &d| n.checked_mul(d))
+    }
+
+    /// Length of the encoded header in bytes
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        header_len(self.dims.len())
+    }
+
+    /// Byte offset of the data section, which immediately follows the
+    /// header, so always `48 + 8 * ndims`. As for `RawArray::data_offset`,
+    /// element `i` of plainly stored data is at `data_offset() + i * elbyte`.
+    pub fn data_offset(&self) -> u64 {
+        self.len() as u64
+    }
+
+    /// Header fields after the magic number and before the dimensions
+    fn fields(&self) -> [u64; 5] {
+        [
+            self.flags,
+            self.eltype,
+            self.elbyte,
+            self.size,
+            self.dims.len() as u64,
+        ]
+    }
+}
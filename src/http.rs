@@ -0,0 +1,215 @@
+//! RawArray files on web servers, behind the `http` feature, read with
+//! HTTP range requests so that browsing a cloud-hosted dataset only
+//! downloads the parts looked at.
+//!
+//! The server must answer range requests with `206 Partial Content`, as
+//! static file servers and object stores do.
+
+use crate::convert::elements_from_bytes_with_endianness;
+use crate::storage::StorageFile;
+use crate::{codec, to_usize, OpenMode, RaStorage, RawArray, RawArrayType, SizePolicy, FLAG_BITS};
+use std::io::{self, BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::mem;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Connections are kept open between requests, so share one agent
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(ureq::Agent::new)
+}
+
+fn http_error(e: ureq::Error) -> Error {
+    match e {
+        ureq::Error::Status(404, r) => Error::new(ErrorKind::NotFound, r.get_url().to_string()),
+        ureq::Error::Status(code, r) => {
+            Error::other(format!("{}: {} {}", r.get_url(), code, r.status_text()))
+        }
+        ureq::Error::Transport(t) => Error::other(t.to_string()),
+    }
+}
+
+fn read_only() -> Error {
+    Error::new(ErrorKind::Unsupported, "files over HTTP are read only")
+}
+
+/// Fetch bytes `first..=last` of `url`, returning the response body and
+/// the length of the whole file.
+fn get_range(url: &str, first: u64, last: u64) -> io::Result<(impl Read, u64)> {
+    let r = agent()
+        .get(url)
+        .set("Range", &format!("bytes={}-{}", first, last))
+        .call()
+        .map_err(http_error)?;
+    if r.status() != 206 {
+        return Err(Error::other(format!(
+            "{} doesn't support range requests",
+            url
+        )));
+    }
+    // Content-Range: bytes 0-0/1234
+    let len = r
+        .header("Content-Range")
+        .and_then(|range| range.rsplit('/').next())
+        .and_then(|len| len.trim().parse().ok())
+        .ok_or_else(|| Error::other(format!("{} gave no length for its range", url)))?;
+    Ok((r.into_reader(), len))
+}
+
+/// Files on web servers, addressed by their URLs as paths, for
+/// `RawArray::read_from_storage` and the like. Only reading is supported.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HttpStorage;
+
+/// A file on a web server
+pub struct HttpFile {
+    url: String,
+    len: u64,
+}
+
+impl RaStorage for HttpStorage {
+    type Handle = HttpFile;
+
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<HttpFile> {
+        if mode != OpenMode::Read {
+            return Err(read_only());
+        }
+        let url = path
+            .to_str()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "URL isn't UTF-8"))?
+            .to_string();
+        // asking for the first byte checks that ranges work and gives the length
+        let (_, len) = get_range(&url, 0, 0)?;
+        Ok(HttpFile { url, len })
+    }
+
+    fn read_at(&self, file: &HttpFile, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        if offset >= file.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let n = (file.len - offset).min(buf.len() as u64);
+        let (mut body, _) = get_range(&file.url, offset, offset + n - 1)?;
+        body.read_exact(&mut buf[..n as usize])?;
+        Ok(n as usize)
+    }
+
+    fn write_at(&self, _: &mut HttpFile, _: u64, _: &[u8]) -> io::Result<usize> {
+        Err(read_only())
+    }
+
+    fn len(&self, file: &HttpFile) -> io::Result<u64> {
+        Ok(file.len)
+    }
+
+    fn set_len(&self, _: &mut HttpFile, _: u64) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn flush(&self, _: &mut HttpFile) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Download the whole array at `url`.
+    /// ```no_run
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let ra = RawArray::<f32>::read_url("https://example.com/data/brain.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_url(url: &str) -> io::Result<RawArray<T>> {
+        RawArray::read_from_storage(&HttpStorage, url)
+    }
+}
+
+/// An array on a web server, of which only the header is downloaded until
+/// slices of it are asked for. Data compressed or encoded by a codec can't
+/// be read in slices; use `RawArray::read_url` for those.
+/// ```no_run
+/// # use std::io;
+/// use rawarray::http::RemoteRawArray;
+/// # fn main() -> io::Result<()> {
+/// let mut volume = RemoteRawArray::<i16>::open("https://example.com/data/ct.ra")?;
+/// println!("{:?}", volume.dims());
+/// // fetches only slices 100 to 109 along the last axis
+/// let slab = volume.read_slice(100, 10)?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RemoteRawArray<T: RawArrayType> {
+    header: RawArray<T>,
+    reader: BufReader<StorageFile<'static, HttpStorage>>,
+}
+
+impl<T: RawArrayType> RemoteRawArray<T> {
+    /// Fetch the header of the array at `url`.
+    pub fn open(url: &str) -> io::Result<RemoteRawArray<T>> {
+        // only slices are ever fetched, so any size will do
+        let (header, _, reader) = RawArray::<T>::open_checked_in(
+            &HttpStorage,
+            Path::new(url),
+            SizePolicy::Strict,
+            u64::MAX,
+        )?;
+        if header.flags & (codec::CODEC_FLAGS | FLAG_BITS) != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{}: encoded data can't be read in slices", url),
+            ));
+        }
+        Ok(RemoteRawArray { header, reader })
+    }
+
+    /// Dimensions of the array
+    pub fn dims(&self) -> &[u64] {
+        &self.header.dims
+    }
+
+    /// Read `len` slices starting at index `start` along the last axis,
+    /// in one request.
+    pub fn read_slice(&mut self, start: u64, len: u64) -> io::Result<RawArray<T>> {
+        let too_large = || Error::new(ErrorKind::InvalidData, "dims are too large");
+        let (last, inner) = match self.header.dims.split_last() {
+            Some((&last, inner)) => (
+                last,
+                inner
+                    .iter()
+                    .try_fold(1u64, |n, &d| n.checked_mul(d))
+                    .ok_or_else(too_large)?,
+            ),
+            None => (1, 1),
+        };
+        if start.checked_add(len).map_or(true, |end| end > last) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "slices {}..{} out of range for {} along the last axis",
+                    start,
+                    start.saturating_add(len),
+                    last
+                ),
+            ));
+        }
+        let slab = inner
+            .checked_mul(mem::size_of::<T>() as u64)
+            .ok_or_else(too_large)?;
+        let bytes_of = |n: u64| n.checked_mul(slab).ok_or_else(too_large);
+        let offset = self
+            .header
+            .data_offset()
+            .checked_add(bytes_of(start)?)
+            .ok_or_else(too_large)?;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![0u8; to_usize(bytes_of(len)?)?];
+        self.reader.read_exact(&mut bytes)?;
+        let data = elements_from_bytes_with_endianness(&bytes, self.header.endianness())?;
+        let mut dims = self.header.dims.clone();
+        if let Some(last) = dims.last_mut() {
+            *last = len;
+        }
+        Ok(RawArray::from_parts(dims, data))
+    }
+}
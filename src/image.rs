@@ -0,0 +1,252 @@
+//! Grayscale PNG and TIFF pictures of two-dimensional arrays, behind the
+//! `image` feature, for a quick look at image data without a viewer that
+//! reads RawArrays.
+//!
+//! The first dimension runs across the picture and the second down it, so
+//! an array of dimensions `[640, 480]` is a 640 by 480 picture. Values
+//! are mapped to gray levels linearly over a window, black at its low end
+//! and white at its high end; NaNs are black.
+
+use crate::convert::{
+    check_conversion, convert_bytes, elements_from_bytes_with_endianness, DType, Endianness,
+};
+use crate::recode::{open_source, write_or_remove};
+use crate::{as_u8_slice, RawArray, RawArrayType};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+/// How array values become gray levels.
+/// ```
+/// use rawarray::image::ColorMap;
+/// // 16 bits per pixel, with values from 0 to 4095 spread over the range
+/// let map = ColorMap::gray16().window(0.0, 4095.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorMap {
+    bits: u8,
+    window: Option<(f64, f64)>,
+}
+
+impl ColorMap {
+    /// 8-bit gray levels, with the window set by the smallest and largest
+    /// finite values
+    pub fn gray8() -> ColorMap {
+        ColorMap {
+            bits: 8,
+            window: None,
+        }
+    }
+
+    /// 16-bit gray levels, with the window set by the smallest and largest
+    /// finite values
+    pub fn gray16() -> ColorMap {
+        ColorMap {
+            bits: 16,
+            window: None,
+        }
+    }
+
+    /// Map `low` and below to black and `high` and above to white.
+    pub fn window(mut self, low: f64, high: f64) -> ColorMap {
+        self.window = Some((low, high));
+        self
+    }
+
+    /// Gray levels for `values`, scaled to `0..=max`
+    fn levels(&self, values: &[f64]) -> Vec<u16> {
+        let (low, high) = self.window.unwrap_or_else(|| {
+            values
+                .iter()
+                .filter(|v| v.is_finite())
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+                    (lo.min(v), hi.max(v))
+                })
+        });
+        let max = f64::from(if self.bits == 8 { 0xff } else { 0xffff });
+        let span = high - low;
+        values
+            .iter()
+            .map(|&v| {
+                let t = if span > 0.0 { (v - low) / span } else { 0.0 };
+                // NaN clamps to NaN, and casting NaN gives zero
+                (t.clamp(0.0, 1.0) * max).round() as u16
+            })
+            .collect()
+    }
+}
+
+/// Write `values`, `width` across, as a picture at `path`, in the format
+/// named by its extension.
+fn write_picture(
+    path: &Path,
+    width: u64,
+    height: u64,
+    values: &[f64],
+    map: &ColorMap,
+) -> io::Result<()> {
+    let too_big = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} by {} is too large for a picture", width, height),
+        )
+    };
+    let w = u32::try_from(width).map_err(|_| too_big())?;
+    let h = u32::try_from(height).map_err(|_| too_big())?;
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    let levels = map.levels(values);
+    write_or_remove(path, |path| {
+        let mut out = BufWriter::new(File::create(path)?);
+        match ext.as_deref() {
+            Some("png") => {
+                let mut enc = png::Encoder::new(&mut out, w, h);
+                enc.set_color(png::ColorType::Grayscale);
+                let bytes: Vec<u8> = if map.bits == 8 {
+                    enc.set_depth(png::BitDepth::Eight);
+                    levels.iter().map(|&l| l as u8).collect()
+                } else {
+                    enc.set_depth(png::BitDepth::Sixteen);
+                    levels.iter().flat_map(|l| l.to_be_bytes()).collect()
+                };
+                let mut writer = enc.write_header()?;
+                writer.write_image_data(&bytes)?;
+                writer.finish()?;
+            }
+            Some("tif") | Some("tiff") => {
+                let tiff_err = |e: tiff::TiffError| Error::other(e.to_string());
+                let mut enc = tiff::encoder::TiffEncoder::new(&mut out).map_err(tiff_err)?;
+                if map.bits == 8 {
+                    let bytes: Vec<u8> = levels.iter().map(|&l| l as u8).collect();
+                    enc.write_image::<tiff::encoder::colortype::Gray8>(w, h, &bytes)
+                } else {
+                    enc.write_image::<tiff::encoder::colortype::Gray16>(w, h, &levels)
+                }
+                .map_err(tiff_err)?;
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("{} isn't a .png, .tif, or .tiff file", path.display()),
+                ))
+            }
+        }
+        out.flush()
+    })
+}
+
+fn not_a_picture(dims: &[u64]) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("an array of dimensions {:?} isn't a picture", dims),
+    )
+}
+
+/// Write slice `slice` along the third dimension of the array in the
+/// RawArray file `input`, or the whole array if it's two-dimensional, as
+/// a picture at `output`. Any real element type can be written.
+/// ```
+/// # use std::io;
+/// use rawarray::image::{self, ColorMap};
+/// use rawarray::RawArray;
+/// # fn main() -> io::Result<()> {
+/// let volume = RawArray::from_iter_with_dims((0..24).map(|x| x as i16), &[4, 3, 2])?;
+/// volume.write("volume.ra")?;
+/// image::ra_to_image("volume.ra", "slice1.tif", Some(1), &ColorMap::gray16())?;
+/// assert!(image::ra_to_image("volume.ra", "slice2.tif", Some(2), &ColorMap::gray16()).is_err());
+/// # std::fs::remove_file("volume.ra")?;
+/// # std::fs::remove_file("slice1.tif")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn ra_to_image<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    slice: Option<u64>,
+    map: &ColorMap,
+) -> io::Result<()> {
+    let mut src = open_source(input.as_ref())?;
+    let dims = src.header.dims.clone();
+    let z = slice.unwrap_or(0);
+    if dims.len() < 2 || dims.len() > 3 {
+        return Err(not_a_picture(&dims));
+    }
+    let planes = dims.get(2).copied().unwrap_or(1);
+    if slice.is_none() && planes != 1 {
+        return Err(not_a_picture(&dims));
+    }
+    if z >= planes {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("slice {} is out of range for dimensions {:?}", z, dims),
+        ));
+    }
+    let too_large = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("dims {:?} are too large", dims),
+        )
+    };
+    let plane = dims[0].checked_mul(dims[1]).ok_or_else(too_large)?;
+    let before = z.checked_mul(plane).ok_or_else(too_large)?;
+    let after = (planes - z - 1).checked_mul(plane).ok_or_else(too_large)?;
+    // only the chosen plane is converted; the others are read past, to
+    // check the checksum
+    let (dtype, endianness) = (src.dtype(), src.endianness());
+    src.convert_next(before, dtype, endianness, |_| Ok(()))?;
+    let mut values = Vec::new();
+    src.convert_next(plane, DType::of::<f64>(), Endianness::native(), |bytes| {
+        for b in bytes.chunks_exact(8) {
+            let mut v = [0u8; 8];
+            v.copy_from_slice(b);
+            values.push(f64::from_ne_bytes(v));
+        }
+        Ok(())
+    })?;
+    src.convert_next(after, dtype, endianness, |_| Ok(()))?;
+    src.finish()?;
+    write_picture(output.as_ref(), dims[0], dims[1], &values, map)
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Write a two-dimensional array of real values as a grayscale picture
+    /// at `path`, a PNG or TIFF file as its extension says. Dimensions
+    /// after the second must be 1, so a slice of a volume taken with
+    /// `slice_axis` can be written as it is. Take `abs()` of complex
+    /// arrays first.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::image::ColorMap;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let volume = RawArray::from_iter_with_dims((0..60).map(f64::from), &[5, 4, 3])?;
+    /// let slice = volume.slice_axis(2, 1..2);
+    /// slice.to_image("slice.png", &ColorMap::gray8().window(20.0, 39.0))?;
+    /// assert!(volume.to_image("volume.png", &ColorMap::gray8()).is_err());
+    /// # std::fs::remove_file("slice.png")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_image<P: AsRef<Path>>(&self, path: P, map: &ColorMap) -> io::Result<()> {
+        if self.dims.len() < 2 || self.dims[2..].iter().any(|&d| d != 1) {
+            return Err(not_a_picture(&self.dims));
+        }
+        let dtype = DType::of::<T>();
+        let to = DType::of::<f64>();
+        check_conversion(dtype, to)?;
+        let mut bytes = Vec::with_capacity(self.data.len() * 8);
+        convert_bytes(
+            as_u8_slice(&self.data),
+            dtype,
+            self.endianness(),
+            &mut bytes,
+            to,
+            Endianness::native(),
+        )?;
+        let values: Vec<f64> = elements_from_bytes_with_endianness(&bytes, Endianness::native())?;
+        write_picture(path.as_ref(), self.dims[0], self.dims[1], &values, map)
+    }
+}
@@ -0,0 +1,86 @@
+//! Element access by n-dimensional coordinates.
+//!
+//! Coordinates are given first dimension first, and since RawArrays are
+//! column major, the first coordinate is the fastest varying one.
+
+use crate::{RawArray, RawArrayType};
+use std::ops::{Index, IndexMut};
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Offset into the data of the element at `idx`, or `None` if `idx`
+    /// has the wrong number of coordinates or any is out of range.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims(0..24u8, &[2, 3, 4]).unwrap();
+    /// assert_eq!(ra.flat_index(&[1, 2, 3]), Some(1 + 2 * 2 + 3 * 6));
+    /// assert_eq!(ra.flat_index(&[2, 0, 0]), None);
+    /// assert_eq!(ra.flat_index(&[0, 0]), None);
+    /// ```
+    pub fn flat_index(&self, idx: &[u64]) -> Option<usize> {
+        self.offset_of(idx.iter().copied())
+    }
+
+    fn offset_of<I: ExactSizeIterator<Item = u64>>(&self, idx: I) -> Option<usize> {
+        if idx.len() != self.dims.len() {
+            return None;
+        }
+        let mut offset = 0u64;
+        let mut stride = 1u64;
+        for (i, &n) in idx.zip(&self.dims) {
+            if i >= n {
+                return None;
+            }
+            offset += i * stride;
+            stride *= n;
+        }
+        Some(offset as usize)
+    }
+
+    /// The element at coordinates `idx`, if they're in range.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut ra = RawArray::from_iter_with_dims(0..6i32, &[2, 3]).unwrap();
+    /// assert_eq!(ra.get(&[1, 2]), Some(&5));
+    /// assert_eq!(ra.get(&[1, 3]), None);
+    /// *ra.get_mut(&[0, 1]).unwrap() = -1;
+    /// assert_eq!(ra[[0, 1]], -1);
+    /// ra[[1, 1]] = 7;
+    /// assert_eq!(ra.data(), vec![0, 1, -1, 7, 4, 5]);
+    /// ```
+    pub fn get(&self, idx: &[u64]) -> Option<&T> {
+        self.flat_index(idx).map(|i| &self.data[i])
+    }
+
+    /// Mutable access to the element at coordinates `idx`, if they're in
+    /// range.
+    pub fn get_mut(&mut self, idx: &[u64]) -> Option<&mut T> {
+        self.flat_index(idx).map(move |i| &mut self.data[i])
+    }
+
+    fn checked_index(&self, idx: &[usize]) -> usize {
+        match self.offset_of(idx.iter().map(|&i| i as u64)) {
+            Some(i) => i,
+            None => panic!(
+                "index {:?} out of bounds for array of dims {:?}",
+                idx, self.dims
+            ),
+        }
+    }
+}
+
+/// Index by coordinates, panicking if they are out of range or don't
+/// match the number of dimensions.
+impl<T: RawArrayType, const N: usize> Index<[usize; N]> for RawArray<T> {
+    type Output = T;
+
+    fn index(&self, idx: [usize; N]) -> &T {
+        &self.data[self.checked_index(&idx)]
+    }
+}
+
+impl<T: RawArrayType, const N: usize> IndexMut<[usize; N]> for RawArray<T> {
+    fn index_mut(&mut self, idx: [usize; N]) -> &mut T {
+        let i = self.checked_index(&idx);
+        &mut self.data[i]
+    }
+}
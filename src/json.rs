@@ -0,0 +1,450 @@
+//! JSON for small arrays, for scripts and web frontends:
+//!
+//! ```json
+//! {"dims": [2, 2], "eltype": "c64", "data": [[1.5, -2.0], [0.0, 1.0], [3.0, 0.0], ["NaN", 0.0]]}
+//! ```
+//!
+//! `eltype` is a name as given by `dtype_name`, and `data` lists the
+//! elements in file order, so the first dimension varies fastest. Complex
+//! elements are `[re, im]` pairs. JSON has no NaN or infinities, so those
+//! are written as the strings `"NaN"`, `"Infinity"`, and `"-Infinity"`.
+//! User-defined elements are written as strings and can't be read back.
+
+use crate::convert::{convert_bytes, elements_from_bytes_with_endianness, DType, Endianness};
+use crate::format::{parse_component, ValueFormatter};
+use crate::recode::open_source;
+use crate::{as_u8_slice, dtype_name, invalid, RawArray, RawArrayType};
+use std::io;
+use std::path::Path;
+
+/// Deepest nesting `parse` accepts, well beyond what an array needs
+const MAX_DEPTH: usize = 64;
+
+/// A parsed JSON value. Numbers keep their text so integers are read
+/// exactly into any width.
+pub(crate) enum Json {
+    Null,
+    Bool,
+    Number(String),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(crate) fn describe(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool => "a boolean",
+            Json::Number(_) => "a number",
+            Json::Str(_) => "a string",
+            Json::Array(_) => "an array",
+            Json::Object(_) => "an object",
+        }
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, msg: &str) -> io::Error {
+        invalid(format!("{} at byte {} of JSON", msg, self.pos))
+    }
+
+    fn skip_ws(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.s.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        self.skip_ws();
+        if self.s.get(self.pos) == Some(&b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> io::Result<()> {
+        if self.eat(b) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", b as char)))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> io::Result<Json> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.skip_ws();
+        let rest = &self.s[self.pos..];
+        for (word, value) in [
+            ("null", Json::Null),
+            ("true", Json::Bool),
+            ("false", Json::Bool),
+        ] {
+            if rest.starts_with(word.as_bytes()) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        match rest.first() {
+            Some(b'"') => Ok(Json::Str(self.string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_ws();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        members.push((key, self.value(depth + 1)?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Json::Object(members))
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+                    self.s.get(self.pos)
+                {
+                    self.pos += 1;
+                }
+                // the slice is ASCII, so this can't fail
+                let text = std::str::from_utf8(&self.s[start..self.pos]).unwrap_or_default();
+                Ok(Json::Number(text.to_string()))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn hex4(&mut self) -> io::Result<u32> {
+        let digits = self
+            .s
+            .get(self.pos..self.pos + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("bad \\u escape"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        if self.s.get(self.pos) != Some(&b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let b = *self
+                .s
+                .get(self.pos)
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let e = *self
+                        .s
+                        .get(self.pos)
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    let c = match e {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.s[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("bad \\u escape"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            char::from_u32(code).ok_or_else(|| self.error("bad \\u escape"))?
+                        }
+                        _ => return Err(self.error("bad escape")),
+                    };
+                    let mut buf = [0u8; 4];
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                _ => out.push(b),
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("string isn't valid UTF-8"))
+    }
+}
+
+pub(crate) fn parse(text: &str) -> io::Result<Json> {
+    let mut p = Parser {
+        s: text.as_bytes(),
+        pos: 0,
+    };
+    let value = p.value(0)?;
+    p.skip_ws();
+    if p.pos != p.s.len() {
+        return Err(p.error("unexpected text after the value"));
+    }
+    Ok(value)
+}
+
+/// Quote `s` as a JSON string, escaping quotes, backslashes, and control
+/// characters.
+/// ```
+/// assert_eq!(rawarray::json::json_string("a\"b\n"), r#""a\"b\u000a""#);
+/// ```
+pub fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// One element of `dtype`, little-endian in `le`, as JSON
+fn element_json(vf: &ValueFormatter, dtype: DType, le: &[u8]) -> String {
+    if dtype.eltype == 0 {
+        return json_string(&vf.format_bytes(dtype, Endianness::Little, le));
+    }
+    let (code, width) = dtype.component();
+    let part = DType {
+        eltype: code,
+        elbyte: width as u64,
+    };
+    let parts: Vec<String> = le
+        .chunks_exact(width)
+        .map(
+            |c| match vf.format_bytes(part, Endianness::Little, c).as_str() {
+                "NaN" => "\"NaN\"".to_string(),
+                "inf" => "\"Infinity\"".to_string(),
+                "-inf" => "\"-Infinity\"".to_string(),
+                s => s.to_string(),
+            },
+        )
+        .collect();
+    if parts.len() == 1 {
+        parts.into_iter().next().unwrap_or_default()
+    } else {
+        format!("[{}]", parts.join(", "))
+    }
+}
+
+/// Elements of `dtype`, little-endian in `le`, as JSON
+fn elements_json(dtype: DType, le: &[u8]) -> Vec<String> {
+    let vf = ValueFormatter::new();
+    if dtype.elbyte == 0 {
+        return Vec::new();
+    }
+    le.chunks_exact(dtype.elbyte as usize)
+        .map(|b| element_json(&vf, dtype, b))
+        .collect()
+}
+
+/// The whole document, given each element already as JSON
+fn document(dtype: DType, dims: &[u64], data: &[String]) -> String {
+    let dims: Vec<String> = dims.iter().map(|d| d.to_string()).collect();
+    format!(
+        "{{\"dims\": [{}], \"eltype\": {}, \"data\": [{}]}}",
+        dims.join(", "),
+        json_string(&dtype_name(dtype.eltype, dtype.elbyte)),
+        data.join(", ")
+    )
+}
+
+/// The array in the RawArray file `input` as JSON, whatever its element
+/// type.
+/// ```
+/// # use std::io;
+/// use rawarray::{json, RawArray};
+/// # fn main() -> io::Result<()> {
+/// let ra: RawArray<u8> = vec![1, 2, 3].into();
+/// ra.write("small.ra")?;
+/// assert_eq!(json::ra_to_json("small.ra")?, r#"{"dims": [3], "eltype": "u8", "data": [1, 2, 3]}"#);
+/// # std::fs::remove_file("small.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn ra_to_json<P: AsRef<Path>>(input: P) -> io::Result<String> {
+    let mut src = open_source(input.as_ref())?;
+    let dtype = src.dtype();
+    let dims = src.header.dims.clone();
+    let mut le = Vec::new();
+    src.convert(dtype, Endianness::Little, |bytes| {
+        le.extend_from_slice(bytes);
+        Ok(())
+    })?;
+    src.finish()?;
+    Ok(document(dtype, &dims, &elements_json(dtype, &le)))
+}
+
+/// Text of a JSON scalar as a number, with the strings standing in for
+/// NaN and infinities mapped to what `str::parse` accepts
+pub(crate) fn scalar_text(v: &Json) -> Option<&str> {
+    match v {
+        Json::Number(s) => Some(s),
+        Json::Str(s) => match s.as_str() {
+            "NaN" => Some("NaN"),
+            "Infinity" => Some("inf"),
+            "-Infinity" => Some("-inf"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// The array as JSON, with its dimensions, element type, and data. See
+    /// the `json` module for the layout.
+    /// ```
+    /// # use std::io;
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let z = RawArray::from_iter_with_dims(vec![Complex::new(0.5f32, f32::NAN); 2], &[1, 2])?;
+    /// let text = z.to_json();
+    /// assert_eq!(text, r#"{"dims": [1, 2], "eltype": "c64", "data": [[0.5, "NaN"], [0.5, "NaN"]]}"#);
+    /// let back = RawArray::<Complex<f32>>::from_json(&text)?;
+    /// assert_eq!(back.dims(), vec![1, 2]);
+    /// assert!(back.data()[1].im.is_nan());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_json(&self) -> String {
+        let dtype = DType::of::<T>();
+        let data = if dtype.eltype == 0 {
+            let vf = ValueFormatter::new();
+            self.data
+                .iter()
+                .map(|x| json_string(&vf.format(x)))
+                .collect()
+        } else {
+            let mut le = Vec::with_capacity(self.data.len() * dtype.elbyte as usize);
+            // swapping a known type to little-endian can't fail
+            let _ = convert_bytes(
+                as_u8_slice(&self.data),
+                dtype,
+                self.endianness(),
+                &mut le,
+                dtype,
+                Endianness::Little,
+            );
+            elements_json(dtype, &le)
+        };
+        document(dtype, &self.dims, &data)
+    }
+
+    /// Read an array from JSON laid out as `to_json` writes it. Values are
+    /// read as `T` whatever `eltype` says, so integers can be read into a
+    /// float array, and `eltype` may be left out.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let ra = RawArray::<f64>::from_json(r#"{"dims": [2], "data": [1, -2.5e3]}"#)?;
+    /// assert_eq!(ra.data(), vec![1.0, -2500.0]);
+    /// assert!(RawArray::<u8>::from_json(r#"{"dims": [1], "data": [300]}"#).is_err());
+    /// let huge = r#"{"dims": [4294967296, 4294967296], "data": []}"#;
+    /// assert!(RawArray::<u8>::from_json(huge).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_json(text: &str) -> io::Result<RawArray<T>> {
+        let members = match parse(text)? {
+            Json::Object(members) => members,
+            other => {
+                return Err(invalid(format!(
+                    "expected an object, not {}",
+                    other.describe()
+                )))
+            }
+        };
+        let get = |key: &str| members.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+        let dims = match get("dims") {
+            Some(Json::Array(dims)) => dims
+                .iter()
+                .map(|d| match d {
+                    Json::Number(s) => s.parse::<u64>().ok(),
+                    _ => None,
+                })
+                .collect::<Option<Vec<u64>>>()
+                .ok_or_else(|| invalid("dims must be non-negative integers"))?,
+            _ => return Err(invalid("missing dims array")),
+        };
+        let items = match get("data") {
+            Some(Json::Array(items)) => items,
+            _ => return Err(invalid("missing data array")),
+        };
+        let nelem = dims.iter().try_fold(1u64, |n, &d| n.checked_mul(d));
+        if nelem != Some(items.len() as u64) {
+            return Err(invalid(format!(
+                "dims {:?} don't match {} data values",
+                dims,
+                items.len()
+            )));
+        }
+
+        let dtype = DType::of::<T>();
+        let (code, width) = dtype.component();
+        let mut le = Vec::with_capacity(items.len() * dtype.elbyte as usize);
+        for (i, item) in items.iter().enumerate() {
+            let scalars = match item {
+                Json::Array(parts) if dtype.components() == 2 && parts.len() == 2 => &parts[..],
+                _ if dtype.components() == 1 => std::slice::from_ref(item),
+                _ => {
+                    return Err(invalid(format!(
+                        "data[{}] is {}, not an element of type {}",
+                        i,
+                        item.describe(),
+                        dtype
+                    )))
+                }
+            };
+            for v in scalars {
+                scalar_text(v)
+                    .and_then(|s| parse_component(code, width, s, &mut le))
+                    .ok_or_else(|| invalid(format!("data[{}] isn't a valid {}", i, dtype)))?;
+            }
+        }
+        let data = elements_from_bytes_with_endianness(&le, Endianness::Little)?;
+        Ok(RawArray::from_parts(dims, data))
+    }
+}
@@ -0,0 +1,121 @@
+//! Handles to arrays on disk whose data is read only when it's needed, so
+//! a cache over thousands of files can keep its memory bounded.
+
+use crate::{dtype_name, RawArray, RawArrayHeader, RawArrayType};
+use std::io::{self, Error, ErrorKind};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+/// An array on disk of which only the header is read on opening. The data
+/// is read on first access, or by `load`, and can be dropped again with
+/// `unload` while the handle stays usable.
+/// ```
+/// # use std::io;
+/// use rawarray::{LazyRawArray, RawArray};
+/// # fn main() -> io::Result<()> {
+/// RawArray::from_iter_with_dims(0..6i32, &[3, 2])?.write("lazy.ra")?;
+/// let mut lazy = LazyRawArray::<i32>::open("lazy.ra")?;
+/// assert_eq!(lazy.dims(), &[3, 2]);
+/// assert!(!lazy.is_loaded());
+/// assert_eq!(lazy.data()?[4], 4);
+/// assert!(lazy.is_loaded());
+/// lazy.unload();
+/// assert!(!lazy.is_loaded());
+/// assert_eq!(lazy.load()?.dims(), vec![3, 2]);
+/// assert!(LazyRawArray::<f32>::open("lazy.ra").is_err());
+/// # std::fs::remove_file("lazy.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LazyRawArray<T: RawArrayType> {
+    path: PathBuf,
+    header: RawArrayHeader,
+    array: Option<RawArray<T>>,
+}
+
+impl<T: RawArrayType> LazyRawArray<T> {
+    /// Read the header of the file at `path`, checking that it holds `T`s.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<LazyRawArray<T>> {
+        let header = RawArrayHeader::read(path.as_ref())?;
+        let want = (T::ra_type_code(), mem::size_of::<T>() as u64);
+        if (header.eltype, header.elbyte) != want {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{}: file holds {} elements, not {}",
+                    path.as_ref().display(),
+                    dtype_name(header.eltype, header.elbyte),
+                    dtype_name(want.0, want.1)
+                ),
+            ));
+        }
+        if header.nelem().is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{}: dims {:?} are too large",
+                    path.as_ref().display(),
+                    header.dims
+                ),
+            ));
+        }
+        Ok(LazyRawArray {
+            path: path.as_ref().to_path_buf(),
+            header,
+            array: None,
+        })
+    }
+
+    /// Path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Header of the file, as read on opening
+    pub fn header(&self) -> &RawArrayHeader {
+        &self.header
+    }
+
+    /// Dimensions of the array
+    pub fn dims(&self) -> &[u64] {
+        &self.header.dims
+    }
+
+    /// Number of elements in the array
+    pub fn nelem(&self) -> u64 {
+        self.header.nelem().expect("dims checked on opening")
+    }
+
+    /// Whether the data is in memory
+    pub fn is_loaded(&self) -> bool {
+        self.array.is_some()
+    }
+
+    /// Read the whole array, unless it's already in memory. The file is
+    /// read afresh after `unload`, so changes made to it since are seen.
+    pub fn load(&mut self) -> io::Result<&RawArray<T>> {
+        if self.array.is_none() {
+            let array = RawArray::read(&self.path)?;
+            self.header.dims = array.dims();
+            self.array = Some(array);
+        }
+        Ok(self.array.as_ref().unwrap())
+    }
+
+    /// The data, read first if it isn't in memory
+    pub fn data(&mut self) -> io::Result<&[T]> {
+        Ok(self.load()?.as_slice())
+    }
+
+    /// Drop the data, keeping the header, to free its memory.
+    pub fn unload(&mut self) {
+        self.array = None;
+    }
+
+    /// The whole array, read first if it isn't in memory
+    pub fn into_array(mut self) -> io::Result<RawArray<T>> {
+        self.load()?;
+        Ok(self.array.unwrap())
+    }
+}
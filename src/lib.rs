@@ -34,23 +34,158 @@
 #![deny(warnings, missing_docs)]
 
 use half::prelude::*;
-use ndarray::{Array, Array1, ArrayD};
+use ndarray::{Array, Array1, ArrayBase, ArrayD, Data, Dimension};
 use num_complex::Complex;
+use num_traits::AsPrimitive;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
 use std::fmt::{Debug, Display};
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::iter::FromIterator;
+use std::ops::Range;
 use std::path::Path;
+use std::time::Instant;
 use std::{fmt, mem, slice};
 
-const FLAG_BIG_ENDIAN: u64 = 1;
-const FLAG_ENCODED: u64 = 2; // run-length encoding for Ints
-const FLAG_BITS: u64 = 4; // array element is a single bit
-const ALL_KNOWN_FLAGS: u64 = FLAG_BIG_ENDIAN | FLAG_ENCODED | FLAG_BITS;
-// TODO: see if reading > 2 GB is a problem in Rust
-//const MAX_BYTES       : u64 = 1<<31;
-//
-//const MAGIC_NUMBER    : u64 = 0x79_61_72_72_61_77_61_72;
-const MAGIC_NUMBER: u64 = 0x79_61_72_72_61_77_61_72u64;
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "async")]
+mod async_io;
+mod attrs;
+mod axis;
+pub mod batch;
+mod builder;
+#[cfg(feature = "candle")]
+mod candle;
+#[cfg(feature = "object_store")]
+pub mod cloud;
+pub mod codec;
+mod coil;
+mod complex;
+mod concat;
+mod convert;
+mod crop;
+pub mod csv;
+mod dataset;
+mod diff;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fft")]
+mod fft;
+mod fixed;
+mod format;
+mod hash;
+#[cfg(feature = "hdf5")]
+mod hdf5;
+mod header;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "image")]
+pub mod image;
+mod index;
+pub mod json;
+mod lazy;
+mod manifest;
+#[cfg(feature = "mat")]
+mod mat;
+mod narrow;
+pub mod net;
+#[cfg(feature = "nifti")]
+pub mod nifti;
+mod nonfinite;
+pub mod npy;
+mod ops;
+mod options;
+mod order;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod patch;
+pub mod prelude;
+mod provenance;
+#[cfg(feature = "python")]
+mod python;
+mod quant;
+mod recode;
+mod reduce;
+#[cfg(feature = "rand")]
+mod sample;
+mod search;
+mod set;
+mod spectrum;
+mod split;
+mod storage;
+mod stream;
+mod throttle;
+mod timelog;
+mod validate;
+mod vconcat;
+mod view;
+mod window;
+mod zarr;
+
+#[cfg(feature = "arrow")]
+pub use arrow::ArrowElement;
+pub use attrs::AttrValue;
+pub use axis::Windows;
+pub use batch::{write_batch, BatchError};
+pub use builder::RawArrayBuilder;
+#[cfg(feature = "candle")]
+pub use candle::CandleElement;
+pub use codec::Codec;
+pub use concat::concat_files;
+pub use convert::{elements_from_bytes_with_endianness, elements_to_le_bytes, DType, Endianness};
+pub use crop::{slice_file, slice_file_with_steps};
+pub use dataset::{split_dataset, write_file_list, Dataset, DatasetEntry};
+pub use diff::for_each_difference;
+pub use error::RawArrayError;
+pub use fixed::{RawArray2, RawArray3, RawArrayFixed};
+pub use format::{ComplexStyle, Notation, Pretty, ValueFormatter};
+pub use hash::{hash_contents, hash_file, HashAlgorithm};
+pub use header::RawArrayHeader;
+pub use lazy::LazyRawArray;
+pub use manifest::{
+    compare_trees, find_files, find_ra_files, verify_manifest, write_manifest, ManifestReport,
+    TreeComparison, MANIFEST_NAME,
+};
+pub use narrow::{narrow_in_place, recover_narrow};
+pub use nonfinite::FloatElement;
+pub use options::{
+    ReadOptions, ReadReport, ReadStats, SizeMismatch, SizePolicy, WriteOptions, DEFAULT_MAX_BYTES,
+};
+pub use patch::RawArrayFileMut;
+pub use provenance::{Provenance, ProvenanceInput};
+pub use quant::QuantizedElement;
+pub use recode::recode_file;
+pub use set::{RawArraySet, RawArraySetIter, RawArraySetWriter};
+pub use spectrum::half_spectrum_dims;
+pub use split::split_file;
+pub use storage::{FsStorage, OpenMode, RaStorage};
+pub use stream::RawArrayStream;
+pub use throttle::RateLimited;
+pub use timelog::TimeLog;
+pub use validate::{validate_file, Problem};
+pub use vconcat::{Chunks, VirtualConcat};
+pub use view::RawArrayView;
+pub use window::GrayLevel;
+
+use provenance::attrs_to_write;
+use storage::{FsFile, StorageFile};
+
+pub use rawarray_core::{
+    FLAG_ATTRS, FLAG_BIG_ENDIAN, FLAG_BITS, FLAG_CRC, FLAG_ENCODED, FLAG_LZ4, FLAG_ZSTD,
+};
+use rawarray_core::{MAGIC_NUMBER, MAX_NDIMS};
+
+const ALL_KNOWN_FLAGS: u64 =
+    FLAG_BIG_ENDIAN | FLAG_ENCODED | FLAG_BITS | FLAG_CRC | codec::CODEC_FLAGS | FLAG_ATTRS;
+/// Flags for data in this machine's byte order
+const NATIVE_FLAGS: u64 = if cfg!(target_endian = "big") {
+    FLAG_BIG_ENDIAN
+} else {
+    0
+};
 //6172 6177 7272 7961
 
 /*
@@ -63,12 +198,16 @@ enum ElementType {
 }
 */
 
+mod sealed {
+    /// Keeps `RawArrayType` implementations inside this crate
+    pub trait Sealed {}
+}
+
 /// Helper trait to constrain to elemental types that make sense.
-// ```rust
-// assert_eq!(i8::ra_type_code(), 1);
-// assert_eq!(i8::ra_elbyte(), 1);
-// ``
-pub trait RawArrayType: Clone + Copy + Debug + Display + Send + Sync {
+///
+/// This trait is sealed: it's implemented for the numeric types the
+/// format knows about, and for any type implementing `UserElement`.
+pub trait RawArrayType: sealed::Sealed + Clone + Copy + Debug + Display + Send + Sync {
     /// Integer type code representing class of element type:
     ///
     /// 0. user defined
@@ -77,11 +216,12 @@ pub trait RawArrayType: Clone + Copy + Debug + Display + Send + Sync {
     /// 3. IEEE floating point
     /// 4. complex
     /// 5. brain floating point (bfloat16)
+    /// 6. complex signed integer
     ///
-    /// 6 and higher are reserved for future use, like maybe
+    /// 7 and higher are reserved for future use, like maybe
     /// Unicode or SIMD types
     ///
-    /// The default type code is 0, because it puts the burden
+    /// User-defined types get type code 0, because it puts the burden
     /// on the user to deal with unknown types, hopefully through
     /// a pull request to this repo!
     /// ```
@@ -91,108 +231,235 @@ pub trait RawArrayType: Clone + Copy + Debug + Display + Send + Sync {
     /// assert_eq!(u8::ra_type_code(), 2);
     /// assert_eq!(f32::ra_type_code(), 3);
     /// assert_eq!(Complex::<f32>::ra_type_code(), 4);
+    /// assert_eq!(Complex::<i16>::ra_type_code(), 6);
     /// ```
     fn ra_type_code() -> u64 {
         0
     }
 }
 
-impl RawArrayType for i8 {
-    fn ra_type_code() -> u64 {
-        1
-    }
-}
-impl RawArrayType for i16 {
-    fn ra_type_code() -> u64 {
-        1
-    }
-}
-impl RawArrayType for i32 {
-    fn ra_type_code() -> u64 {
-        1
-    }
-}
-impl RawArrayType for i64 {
-    fn ra_type_code() -> u64 {
-        1
-    }
-}
-impl RawArrayType for i128 {
-    fn ra_type_code() -> u64 {
-        1
-    }
-}
-impl RawArrayType for u8 {
-    fn ra_type_code() -> u64 {
-        2
-    }
-}
-impl RawArrayType for u16 {
-    fn ra_type_code() -> u64 {
-        2
-    }
-}
-impl RawArrayType for u32 {
-    fn ra_type_code() -> u64 {
-        2
-    }
-}
-impl RawArrayType for u64 {
-    fn ra_type_code() -> u64 {
-        2
-    }
+macro_rules! builtin_element {
+    ($($t:ty => $code:expr),* $(,)?) => {
+        $(
+            impl sealed::Sealed for $t {}
+            impl RawArrayType for $t {
+                fn ra_type_code() -> u64 {
+                    $code
+                }
+            }
+        )*
+    };
 }
-impl RawArrayType for u128 {
-    fn ra_type_code() -> u64 {
-        2
-    }
-}
-impl RawArrayType for f32 {
-    fn ra_type_code() -> u64 {
-        3
-    }
-}
-impl RawArrayType for f64 {
-    fn ra_type_code() -> u64 {
-        3
-    }
-}
-impl RawArrayType for Complex<f32> {
-    fn ra_type_code() -> u64 {
-        4
-    }
-}
-impl RawArrayType for Complex<f64> {
-    fn ra_type_code() -> u64 {
-        4
-    }
-}
-impl RawArrayType for bf16 {
-    fn ra_type_code() -> u64 {
-        5
-    }
-}
-impl RawArrayType for f16 {
-    fn ra_type_code() -> u64 {
-        3
-    }
+
+builtin_element!(
+    i8 => 1,
+    i16 => 1,
+    i32 => 1,
+    i64 => 1,
+    i128 => 1,
+    u8 => 2,
+    u16 => 2,
+    u32 => 2,
+    u64 => 2,
+    u128 => 2,
+    f32 => 3,
+    f64 => 3,
+    Complex<f32> => 4,
+    Complex<f64> => 4,
+    Complex<i8> => 6,
+    Complex<i16> => 6,
+    Complex<i32> => 6,
+    bf16 => 5,
+    f16 => 3,
+);
+
+/// Element types defined outside this crate, like `#[repr(C)]` structs,
+/// which are stored with type code 0. Implementing this is the only way to
+/// add element types, since `RawArrayType` is sealed.
+/// ```
+/// use rawarray::{RawArray, RawArrayType, UserElement};
+/// use std::fmt;
+///
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// #[repr(C)]
+/// struct Point {
+///     x: f32,
+///     y: f32,
+/// }
+/// impl fmt::Display for Point {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "({}, {})", self.x, self.y)
+///     }
+/// }
+/// // Safety: two f32s, no padding, and any bits are a valid Point
+/// unsafe impl UserElement for Point {}
+///
+/// assert_eq!(Point::ra_type_code(), 0);
+/// let ra: RawArray<Point> = vec![Point { x: 1.0, y: 2.0 }].into();
+/// assert_eq!(ra.elbyte(), 8);
+/// ```
+///
+/// # Safety
+///
+/// Elements are copied to and from files as raw bytes and may start out
+/// zeroed, so the type must have no padding bytes, no pointers or
+/// references, and every bit pattern must be a valid value. It should be
+/// `#[repr(C)]`, since the layout of other structs, and so of the files
+/// they're written to, may change from one compiler version to the next.
+pub unsafe trait UserElement: Clone + Copy + Debug + Display + Send + Sync {}
+
+/// Types that `user_element!` accepts as fields: element types and arrays
+/// of them.
+#[doc(hidden)]
+pub trait PlainField {}
+impl<T: RawArrayType> PlainField for T {}
+impl<T: PlainField, const N: usize> PlainField for [T; N] {}
+
+/// Implement `UserElement` for a struct without writing `unsafe`, checking
+/// at compile time what its safety section asks for: the fields listed
+/// must be all of the struct's fields, each must itself be a
+/// `RawArrayType` or an array of them, and together they must fill the
+/// struct with no padding.
+///
+/// The struct must be `#[repr(C)]`, so that its layout in files is fixed.
+/// The macro can't see the attribute, but checks that each field starts
+/// where the one listed before it ends, which fails to compile for structs
+/// the compiler has reordered.
+/// Records that would be padded, like the one below, can be declared
+/// `#[repr(C, packed)]`; their fields must then be copied out, as in
+/// `{ self.re }`, rather than borrowed.
+/// ```
+/// use rawarray::{user_element, RawArray, RawArrayType};
+/// use std::fmt;
+///
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// #[repr(C, packed)]
+/// struct Sample {
+///     re: i16,
+///     im: i16,
+///     coil: u8,
+/// }
+/// impl fmt::Display for Sample {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "{}{:+}i (coil {})", { self.re }, { self.im }, { self.coil })
+///     }
+/// }
+/// user_element!(Sample { re: i16, im: i16, coil: u8 });
+///
+/// # fn main() -> std::io::Result<()> {
+/// let samples = vec![Sample { re: 3, im: -4, coil: 0 }, Sample { re: 1, im: 2, coil: 7 }];
+/// RawArray::from(samples.clone()).write("samples.ra")?;
+/// let ra = RawArray::<Sample>::read("samples.ra")?;
+/// assert_eq!((ra.eltype(), ra.elbyte()), (0, 5));
+/// assert_eq!(ra.data(), samples);
+/// assert_eq!(Sample::ra_type_code(), 0);
+/// # std::fs::remove_file("samples.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Without `packed`, a byte of padding follows `coil`, so this fails to
+/// compile:
+/// ```compile_fail
+/// # use rawarray::user_element;
+/// # use std::fmt;
+/// #[derive(Clone, Copy, Debug)]
+/// #[repr(C)]
+/// struct Sample {
+///     re: i16,
+///     im: i16,
+///     coil: u8,
+/// }
+/// # impl fmt::Display for Sample {
+/// #     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { Ok(()) }
+/// # }
+/// user_element!(Sample { re: i16, im: i16, coil: u8 });
+/// ```
+///
+/// Nor does this, since without `repr(C)` the compiler puts `index` first:
+/// ```compile_fail
+/// # use rawarray::user_element;
+/// # use std::fmt;
+/// #[derive(Clone, Copy, Debug)]
+/// struct Tagged {
+///     tag: u8,
+///     index: u32,
+///     rest: [u8; 3],
+/// }
+/// # impl fmt::Display for Tagged {
+/// #     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { Ok(()) }
+/// # }
+/// user_element!(Tagged { tag: u8, index: u32, rest: [u8; 3] });
+/// ```
+#[macro_export]
+macro_rules! user_element {
+    ($name:ident { $($field:ident: $t:ty),* $(,)? }) => {
+        const _: () = {
+            fn is_element<T: $crate::PlainField>() {}
+            // every field named, with the type given, and nothing else
+            #[allow(dead_code)]
+            fn fields(v: $name) {
+                let $name { $($field: _),* } = v;
+                $(
+                    is_element::<$t>();
+                    let _: $t = v.$field;
+                )*
+            }
+            assert!(
+                ::core::mem::size_of::<$name>() == 0 $(+ ::core::mem::size_of::<$t>())*,
+                concat!(stringify!($name), " has padding between or after its fields")
+            );
+            // laid out in the order listed, as repr(C) does
+            let mut _at = 0;
+            $(
+                assert!(
+                    ::core::mem::offset_of!($name, $field) == _at,
+                    concat!(
+                        stringify!($name),
+                        " must be #[repr(C)], with its fields listed in order"
+                    )
+                );
+                _at += ::core::mem::size_of::<$t>();
+            )*
+        };
+        // Safety: the checks above
+        unsafe impl $crate::UserElement for $name {}
+    };
 }
 
-/// Combine the two necessary traits for efficient file parsing
-trait RawArrayIO: BufRead + Seek {}
+impl<T: UserElement> sealed::Sealed for T {}
+impl<T: UserElement> RawArrayType for T {}
 
-/// Wraps reading for some simpler parsing code
-pub struct RawArrayFile(Box<dyn RawArrayIO>);
+/// Reads little-endian header-style fields of one type
+macro_rules! le_reader {
+    ($($name:ident: $t:ty),*) => {
+        $(
+            #[doc = concat!("Return the next `", stringify!($t), "` in the stream, read little-endian as header fields are")]
+            pub fn $name(&mut self) -> io::Result<$t> {
+                let mut buf = [0u8; mem::size_of::<$t>()];
+                self.r.read_exact(&mut buf)?;
+                Ok(<$t>::from_le_bytes(buf))
+            }
+        )*
+    };
+}
 
-impl<T: Read + Seek> RawArrayIO for BufReader<T> {}
+/// Wraps reading, and patching in place, for some simpler parsing code
+pub struct RawArrayFile {
+    r: BufReader<FsFile>,
+    writable: bool,
+    /// The flags, eltype, and elbyte fields, once `read_elems` has needed
+    /// them
+    layout: Option<[u64; 3]>,
+}
 
 impl RawArrayFile {
-    /// Open and validate a `RawArray` file and return a `File` handle,
-    /// but don't attempt to parse.
-    pub fn valid_open<P: AsRef<Path>>(path: P) -> io::Result<RawArrayFile> {
-        let f = File::open(path)?;
-        let r = BufReader::new(f);
-        let mut raf = RawArrayFile(Box::new(r));
+    fn check_magic(file: FsFile, writable: bool) -> io::Result<RawArrayFile> {
+        let mut raf = RawArrayFile {
+            r: BufReader::new(file),
+            writable,
+            layout: None,
+        };
         let magic = raf.u64_at(0)?;
         if magic != MAGIC_NUMBER {
             return Err(Error::new(
@@ -203,29 +470,259 @@ impl RawArrayFile {
         Ok(raf)
     }
 
+    /// Open and validate a `RawArray` file and return a `File` handle,
+    /// but don't attempt to parse.
+    pub fn valid_open<P: AsRef<Path>>(path: P) -> io::Result<RawArrayFile> {
+        RawArrayFile::check_magic(FsFile::open_fs(path.as_ref(), OpenMode::Read)?, false)
+    }
+
+    /// Like `valid_open`, but also allow the `write_` methods, which patch
+    /// the file in place without changing its length.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{RawArray, RawArrayFile};
+    /// # fn main() -> io::Result<()> {
+    /// RawArray::from_iter_with_dims(0..6u16, &[3, 2])?.write("patched.ra")?;
+    /// let mut f = RawArrayFile::valid_open_rw("patched.ra")?;
+    /// // swap the two dims, which keeps the size the same
+    /// f.write_u64_at(48, 2)?;
+    /// f.write_u64_at(56, 3)?;
+    /// f.write_bytes_at(64, &7u16.to_le_bytes())?;
+    /// f.sync()?;
+    /// let ra = RawArray::<u16>::read("patched.ra")?;
+    /// assert_eq!(ra.dims(), vec![2, 3]);
+    /// assert_eq!(ra.data()[0], 7);
+    /// # std::fs::remove_file("patched.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn valid_open_rw<P: AsRef<Path>>(path: P) -> io::Result<RawArrayFile> {
+        RawArrayFile::check_magic(FsFile::open_fs(path.as_ref(), OpenMode::ReadWrite)?, true)
+    }
+
     /// Return next `u64` in the stream
     pub fn u64(&mut self) -> io::Result<u64> {
         let mut buf = [0u8; 8];
-        self.0.read_exact(&mut buf)?;
+        self.r.read_exact(&mut buf)?;
         Ok(u64::from_le_bytes(buf))
     }
 
-    /// Seek to given position in a RawArrayFile
+    le_reader!(u32: u32, i32: i32, i64: i64, f32: f32, f64: f64);
+
+    /// Skip `loc` bytes forward from the current position in a
+    /// RawArrayFile. Use `seek_to` to seek from the start or end.
     pub fn seek(&mut self, loc: u64) -> io::Result<()> {
-        self.0.seek(SeekFrom::Current(loc as i64))?;
+        self.r.seek(SeekFrom::Current(loc as i64))?;
         Ok(())
     }
 
+    /// Seek to `pos`, returning the new position from the start of the
+    /// file.
+    pub fn seek_to(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.r.seek(pos)
+    }
+
+    /// Current position from the start of the file
+    pub fn position(&mut self) -> io::Result<u64> {
+        self.r.stream_position()
+    }
+
     /// Return a `u64` located at an offset within the file
     /// without affecting current reading location
     pub fn u64_at(&mut self, offset: u64) -> io::Result<u64> {
-        let cur_loc = self.0.seek(SeekFrom::Current(0))?;
-        self.0.seek(SeekFrom::Start(offset))?;
         let mut buf = [0u8; 8];
-        self.0.read_exact(&mut buf)?;
-        self.0.seek(SeekFrom::Start(cur_loc))?;
+        self.read_exact_at(offset, &mut buf)?;
         Ok(u64::from_le_bytes(buf))
     }
+
+    fn read_exact_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let cur_loc = self.r.stream_position()?;
+        self.r.seek(SeekFrom::Start(offset))?;
+        self.r.read_exact(buf)?;
+        self.r.seek(SeekFrom::Start(cur_loc))?;
+        Ok(())
+    }
+
+    /// Return the next `len` bytes in the stream
+    pub fn read_bytes(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        self.r.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Return the bytes in `range` without affecting the current reading
+    /// location
+    pub fn bytes_at(&mut self, range: Range<u64>) -> io::Result<Vec<u8>> {
+        let len = range.end.checked_sub(range.start).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("byte range {:?} runs backwards", range),
+            )
+        })?;
+        let mut buf = vec![0u8; to_usize(len)?];
+        self.read_exact_at(range.start, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Return the next `n` elements in the stream, in the byte order the
+    /// header gives. The header must say the file holds `T`s, stored
+    /// plainly rather than encoded.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{RawArray, RawArrayFile};
+    /// use std::io::SeekFrom;
+    /// # fn main() -> io::Result<()> {
+    /// RawArray::from(vec![1.5f32, 2.5, 3.5]).write("typed.ra")?;
+    /// let mut f = RawArrayFile::valid_open("typed.ra")?;
+    /// f.seek_to(SeekFrom::Start(56))?;
+    /// assert_eq!(f.f32()?, 1.5);
+    /// assert_eq!(f.read_elems::<f32>(2)?, vec![2.5, 3.5]);
+    /// assert!(f.read_elems::<f32>(1).is_err());
+    /// assert!(f.read_elems::<f32>(usize::MAX).is_err());
+    /// assert!(f.read_elems::<u64>(0).is_err());
+    /// # std::fs::remove_file("typed.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_elems<T: RawArrayType>(&mut self, n: usize) -> io::Result<Vec<T>> {
+        let [flags, eltype, elbyte] = match self.layout {
+            Some(layout) => layout,
+            None => {
+                let mut buf = [0u8; 24];
+                self.read_exact_at(8, &mut buf)?;
+                let mut layout = [0u64; 3];
+                for (field, bytes) in layout.iter_mut().zip(buf.chunks_exact(8)) {
+                    *field = u64::from_le_bytes(bytes.try_into().expect("8 bytes"));
+                }
+                *self.layout.insert(layout)
+            }
+        };
+        if (eltype, elbyte) != (T::ra_type_code(), mem::size_of::<T>() as u64) {
+            return Err(invalid(format!(
+                "file holds {} elements, not {}",
+                dtype_name(eltype, elbyte),
+                dtype_name(T::ra_type_code(), mem::size_of::<T>() as u64)
+            )));
+        }
+        if flags & (codec::CODEC_FLAGS | FLAG_BITS) != 0 {
+            return Err(invalid(
+                "data is encoded, so elements can't be read in place",
+            ));
+        }
+        let len = n.checked_mul(mem::size_of::<T>()).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} elements are too many to read at once", n),
+            )
+        })?;
+        let bytes = self.read_bytes(len)?;
+        let endianness = if flags & FLAG_BIG_ENDIAN != 0 {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        };
+        elements_from_bytes_with_endianness(&bytes, endianness)
+    }
+
+    /// Overwrite the bytes at `offset` with `bytes`, without affecting the
+    /// current reading location. The file must have been opened with
+    /// `valid_open_rw`, and isn't grown: writes past its end fail.
+    pub fn write_bytes_at(&mut self, offset: u64, bytes: &[u8]) -> io::Result<()> {
+        if !self.writable {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "file was opened read only; open it with valid_open_rw",
+            ));
+        }
+        let len = self.r.get_ref().len()?;
+        if offset
+            .checked_add(bytes.len() as u64)
+            .map_or(true, |end| end > len)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "writing {} bytes at {} runs past the end of the {} byte file",
+                    bytes.len(),
+                    offset,
+                    len
+                ),
+            ));
+        }
+        if offset < 32 {
+            // a patched flags, eltype, or elbyte field
+            self.layout = None;
+        }
+        let cur_loc = self.r.stream_position()?;
+        // seeking the reader drops its buffer, so it can't go stale
+        self.r.seek(SeekFrom::Start(offset))?;
+        self.r.get_mut().write_all(bytes)?;
+        self.r.seek(SeekFrom::Start(cur_loc))?;
+        Ok(())
+    }
+
+    /// Overwrite the `u64` at `offset`, such as a header field
+    pub fn write_u64_at(&mut self, offset: u64, value: u64) -> io::Result<()> {
+        self.write_bytes_at(offset, &value.to_le_bytes())
+    }
+
+    /// Make everything written so far durable
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.r.get_mut().finish(true)
+    }
+}
+
+/// Reconcile a header's `size` with its dimensions as `policy` says,
+/// returning the mismatch, if any, that was worked around.
+fn resolve_size(
+    dims: &mut Vec<u64>,
+    size: &mut u64,
+    elbyte: u64,
+    policy: SizePolicy,
+) -> io::Result<Option<SizeMismatch>> {
+    let bytes = dims.iter().try_fold(elbyte, |acc, &d| acc.checked_mul(d));
+    if bytes == Some(*size) {
+        return Ok(None);
+    }
+    let mismatch = SizeMismatch {
+        policy,
+        header_size: *size,
+        header_dims: dims.clone(),
+    };
+    match policy {
+        SizePolicy::Strict => {
+            return Err(invalid(format!(
+                "header size {} doesn't match dims {:?} of {} byte elements",
+                size, dims, elbyte
+            )))
+        }
+        SizePolicy::TrustDims => match bytes {
+            Some(bytes) => *size = bytes,
+            None => return Err(invalid(format!("dims {:?} overflow", dims))),
+        },
+        SizePolicy::TrustSize => {
+            if elbyte == 0 || *size % elbyte != 0 {
+                return Err(invalid(format!(
+                    "header size {} is not a whole number of {} byte elements",
+                    size, elbyte
+                )));
+            }
+            *dims = vec![*size / elbyte];
+        }
+    }
+    Ok(Some(mismatch))
+}
+
+/// Refuse to allocate `bytes` for an array if that's more than `limit`.
+fn check_max_bytes(bytes: Option<u64>, limit: u64) -> io::Result<()> {
+    match bytes {
+        Some(bytes) if bytes <= limit => Ok(()),
+        bytes => Err(RawArrayError::TooLarge {
+            bytes: bytes.unwrap_or(u64::MAX),
+            limit,
+        }
+        .into()),
+    }
 }
 
 /// Container type for RawArrays
@@ -238,6 +735,7 @@ pub struct RawArray<T: RawArrayType> {
     ndims: u64,
     dims: Vec<u64>,
     data: Vec<T>,
+    attrs: BTreeMap<String, AttrValue>,
 }
 
 /*
@@ -245,10 +743,21 @@ pub struct RawArray<T: RawArrayType> {
  * and binary reading
  */
 
-fn read_u64<T: Read>(r: &mut T) -> u64 {
+fn read_u64<T: Read>(r: &mut T) -> io::Result<u64> {
     let mut buf = [0u8; 8];
-    r.read_exact(&mut buf).expect("unable to read a u64");
-    u64::from_le_bytes(buf)
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: E) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Convert a length read from a file to `usize`, failing instead of
+/// truncating where it is more than the platform can address.
+fn to_usize(n: u64) -> io::Result<usize> {
+    n.try_into()
+        .map_err(|_| invalid(format!("length {} is too large for this platform", n)))
 }
 
 fn write_u64<T: Write>(r: &mut T, n: u64) -> io::Result<()> {
@@ -256,29 +765,90 @@ fn write_u64<T: Write>(r: &mut T, n: u64) -> io::Result<()> {
     Ok(())
 }
 
-fn from_u8<T: RawArrayType>(v: Vec<u8>) -> Vec<T> {
-    let data = v.as_ptr();
-    let len = v.len();
-    let capacity = v.capacity();
-    let element_size = mem::size_of::<T>();
-
-    // Make sure we have a proper amount of capacity (may be overkill)
-    assert_eq!(capacity % element_size, 0);
-    // Make sure we are going to read a full chunk of stuff
-    assert_eq!(len % element_size, 0);
+/// Write a header for elements of type `T`
+fn write_header_fields<T: RawArrayType, W: Write>(
+    mut w: &mut W,
+    flags: u64,
+    size: u64,
+    dims: &[u64],
+) -> io::Result<()> {
+    write_u64(&mut w, MAGIC_NUMBER)?;
+    write_u64(&mut w, flags)?;
+    write_u64(&mut w, T::ra_type_code())?;
+    write_u64(&mut w, mem::size_of::<T>() as u64)?;
+    write_u64(&mut w, size)?;
+    write_u64(&mut w, dims.len() as u64)?;
+    for d in dims.iter() {
+        write_u64(&mut w, *d)?;
+    }
+    Ok(())
+}
 
-    unsafe {
-        // Don't allow the current vector to be dropped
-        // (which would invalidate the memory)
-        mem::forget(v);
+fn zeroed_vec<T: RawArrayType>(n: usize) -> Vec<T> {
+    // RawArray element types are plain numbers, for which all zeros is valid
+    vec![unsafe { mem::zeroed::<T>() }; n]
+}
 
-        Vec::from_raw_parts(data as *mut T, len / element_size, capacity / element_size)
+/// Read `n` elements from `r`, growing the vector as the data arrives
+/// rather than allocating all of it up front, so that a header claiming
+/// more data than a stream holds fails with `UnexpectedEof` having
+/// allocated little more than what was received.
+fn read_vec<T: RawArrayType, R: Read>(r: &mut R, n: usize) -> io::Result<Vec<T>> {
+    // the first step is 1 MiB, and each after that doubles the vector
+    let first = (1 << 20) / mem::size_of::<T>().max(1);
+    let mut data: Vec<T> = Vec::new();
+    while data.len() < n {
+        let start = data.len();
+        let end = n.min(start + start.max(first));
+        data.resize(end, unsafe { mem::zeroed::<T>() });
+        r.read_exact(as_u8_slice_mut(&mut data[start..]))?;
     }
+    Ok(data)
 }
 
 fn as_u8_slice<T: RawArrayType>(v: &[T]) -> &[u8] {
-    let element_size = mem::size_of::<T>();
-    unsafe { slice::from_raw_parts(v.as_ptr() as *const u8, v.len() * element_size) }
+    unsafe { slice::from_raw_parts(v.as_ptr() as *const u8, mem::size_of_val(v)) }
+}
+
+/// The bytes of `data`, which is in native order, in byte order
+/// `endianness`, copying only if they need swapping
+fn bytes_in_order<T: RawArrayType>(
+    data: &[T],
+    endianness: Endianness,
+) -> io::Result<Cow<'_, [u8]>> {
+    if endianness == Endianness::native() {
+        Ok(Cow::Borrowed(as_u8_slice(data)))
+    } else {
+        let mut bytes = as_u8_slice(data).to_vec();
+        convert::swap_bytes(&mut bytes, DType::of::<T>())?;
+        Ok(Cow::Owned(bytes))
+    }
+}
+
+fn as_u8_slice_mut<T: RawArrayType>(v: &mut [T]) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(v.as_mut_ptr() as *mut u8, mem::size_of_val(v)) }
+}
+
+/// Short name for an element type, like `f32`, `u16`, `c64` for a
+/// complex number made of two `f32`s, or `ci32` for one made of two `i16`s.
+/// Sizes are in bits.
+/// ```
+/// assert_eq!(rawarray::dtype_name(3, 4), "f32");
+/// assert_eq!(rawarray::dtype_name(4, 16), "c128");
+/// assert_eq!(rawarray::dtype_name(6, 4), "ci32");
+/// assert_eq!(rawarray::dtype_name(0, 80), "user80");
+/// ```
+pub fn dtype_name(eltype: u64, elbyte: u64) -> String {
+    let bits = elbyte.saturating_mul(8);
+    match eltype {
+        1 => format!("i{}", bits),
+        2 => format!("u{}", bits),
+        3 => format!("f{}", bits),
+        4 => format!("c{}", bits),
+        5 => format!("bf{}", bits),
+        6 => format!("ci{}", bits),
+        _ => format!("user{}", elbyte),
+    }
 }
 
 fn flags_as_string(flags: u64) -> String {
@@ -289,10 +859,22 @@ fn flags_as_string(flags: u64) -> String {
         s.push_str("LittleEndian ");
     }
     if flags & FLAG_ENCODED != 0 {
-        s.push_str("RLE ");
+        s.push_str("Encoded ");
     }
     if flags & FLAG_BITS != 0 {
-        s.push_str("BitArray");
+        s.push_str("BitArray ");
+    }
+    if flags & FLAG_CRC != 0 {
+        s.push_str("CRC ");
+    }
+    if flags & codec::FLAG_ZSTD != 0 {
+        s.push_str("Zstd ");
+    }
+    if flags & codec::FLAG_LZ4 != 0 {
+        s.push_str("LZ4 ");
+    }
+    if flags & FLAG_ATTRS != 0 {
+        s.push_str("Attrs");
     }
     s
 }
@@ -300,13 +882,14 @@ fn flags_as_string(flags: u64) -> String {
 impl<T: RawArrayType> Default for RawArray<T> {
     fn default() -> Self {
         RawArray {
-            flags: 0,
+            flags: NATIVE_FLAGS,
             eltype: T::ra_type_code(),
             elbyte: mem::size_of::<T>() as u64,
             size: 0,
             ndims: 0,
             dims: Vec::new(),
             data: Vec::new(),
+            attrs: BTreeMap::new(),
         }
     }
 }
@@ -319,17 +902,53 @@ impl<T: RawArrayType> From<Vec<T>> for RawArray<T> {
         let elbyte = mem::size_of::<T>() as u64;
         let dims = vec![v.len() as u64];
         RawArray {
-            flags: 0,
+            flags: NATIVE_FLAGS,
             eltype,
             elbyte,
             size,
             ndims: 1,
             dims,
             data: v,
+            attrs: BTreeMap::new(),
         }
     }
 }
 
+impl<T: RawArrayType> FromIterator<T> for RawArray<T> {
+    /// Collect the elements into a one-dimensional `RawArray<T>`
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> RawArray<T> {
+        iter.into_iter().collect::<Vec<T>>().into()
+    }
+}
+
+impl<T: RawArrayType> IntoIterator for RawArray<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    /// Consume the array, yielding its elements in column-major order
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T: RawArrayType> IntoIterator for &'a RawArray<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
+impl<'a, T: RawArrayType> IntoIterator for &'a mut RawArray<T> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter_mut()
+    }
+}
+
 impl<T: RawArrayType> From<ArrayD<T>> for RawArray<T> {
     /// Create a `RawArray<T>` from an `ArrayD<T>`
     fn from(a: ArrayD<T>) -> RawArray<T> {
@@ -337,17 +956,57 @@ impl<T: RawArrayType> From<ArrayD<T>> for RawArray<T> {
     }
 }
 
-impl<T: RawArrayType> Into<Vec<T>> for RawArray<T> {
+impl<T: RawArrayType> From<RawArray<T>> for Vec<T> {
     /// Create a `Vec<T>` from a `RawArray<T>`
-    fn into(self) -> Vec<T> {
-        self.data
+    fn from(ra: RawArray<T>) -> Vec<T> {
+        ra.data
     }
 }
 
-impl<T: RawArrayType> Into<Array1<T>> for RawArray<T> {
-    /// Create a `Vec<T>` from a `RawArray<T>`
-    fn into(self) -> Array1<T> {
-        Array::from(self.data)
+impl<T: RawArrayType> From<RawArray<T>> for Array1<T> {
+    /// Create an `Array1<T>` from a `RawArray<T>`
+    fn from(ra: RawArray<T>) -> Array1<T> {
+        Array::from(ra.data)
+    }
+}
+
+impl<I> RawArray<Complex<I>>
+where
+    I: AsPrimitive<f32> + AsPrimitive<f64>,
+    Complex<I>: RawArrayType,
+{
+    /// Convert complex integer data, such as MRI raw data, to
+    /// single-precision complex floats. Dimensions, flags, and attributes
+    /// are kept.
+    /// ```
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// let raw: RawArray<Complex<i16>> = vec![Complex::new(3, -4)].into();
+    /// assert_eq!(raw.to_complex_f32().data(), vec![Complex::new(3.0f32, -4.0)]);
+    /// ```
+    pub fn to_complex_f32(&self) -> RawArray<Complex<f32>> {
+        self.map_complex::<f32>(AsPrimitive::as_)
+    }
+
+    /// Convert complex integer data to double-precision complex floats.
+    pub fn to_complex_f64(&self) -> RawArray<Complex<f64>> {
+        self.map_complex::<f64>(AsPrimitive::as_)
+    }
+
+    fn map_complex<F>(&self, f: impl Fn(I) -> F) -> RawArray<Complex<F>>
+    where
+        F: Clone,
+        Complex<F>: RawArrayType,
+    {
+        let data = self
+            .data
+            .iter()
+            .map(|c| Complex::new(f(c.re), f(c.im)))
+            .collect();
+        let mut ra = RawArray::from_parts(self.dims.clone(), data);
+        ra.flags = self.flags;
+        ra.attrs = self.attrs.clone();
+        ra
     }
 }
 
@@ -359,7 +1018,17 @@ impl<T: RawArrayType> Display for RawArray<T> {
         writeln!(f, "size: {}", self.size)?;
         writeln!(f, "ndims: {}", self.ndims)?;
         writeln!(f, "dims: {:?}", self.dims)?;
-        write!(f, "data: {:?}", self.data)
+        for (key, value) in &self.attrs {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        // a precision, as in `{:.3}`, applies to the elements
+        let mut pretty = self.pretty();
+        if let Some(p) = f.precision() {
+            pretty = pretty.precision(p);
+        }
+        // continuation lines line up under the first
+        let data = pretty.to_string().replace("\n ", "\n       ");
+        write!(f, "data: {}", data)
     }
 }
 
@@ -369,23 +1038,289 @@ impl<T: RawArrayType> RawArray<T> {
         RawArray::default()
     }
 
-    /// Create a new `RawArray<T>` with same type and dimensions but new data
-    pub fn clone_with_data(&self, data: Vec<T>) -> RawArray<T> {
+    /// Fill a new `RawArray<T>` of shape `dims` straight from an iterator,
+    /// in column-major order, without collecting into a temporary first.
+    /// Fails with `ErrorKind::InvalidInput` if the iterator yields more or
+    /// fewer than the number of elements `dims` calls for.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims((0..6).map(|i| i as f32 * 0.5), &[2, 3]).unwrap();
+    /// assert_eq!(ra.dims(), vec![2, 3]);
+    /// assert_eq!(ra.as_slice()[5], 2.5);
+    /// assert!(RawArray::from_iter_with_dims(0..5u8, &[2, 3]).is_err());
+    /// assert!(RawArray::from_iter_with_dims(0..7u8, &[2, 3]).is_err());
+    /// // no room is reserved for elements the iterator doesn't have
+    /// assert!(RawArray::from_iter_with_dims(0..5u8, &[1 << 31, 1 << 31]).is_err());
+    /// ```
+    pub fn from_iter_with_dims<I>(iter: I, dims: &[u64]) -> io::Result<RawArray<T>>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+        let nelem: usize = dims
+            .iter()
+            .try_fold(1u64, |acc, &d| acc.checked_mul(d))
+            .and_then(|n| n.try_into().ok())
+            .ok_or_else(|| invalid(format!("dims {:?} are too large", dims)))?;
+        let mut iter = iter.into_iter();
+        // dims may be untrusted, so reserve no more than the iterator
+        // promises, and grow as it delivers
+        let mut data = Vec::with_capacity(nelem.min(iter.size_hint().0));
+        data.extend(iter.by_ref().take(nelem));
+        if data.len() < nelem {
+            return Err(invalid(format!(
+                "iterator ended after {} of {} elements",
+                data.len(),
+                nelem
+            )));
+        }
+        if iter.next().is_some() {
+            return Err(invalid(format!(
+                "iterator yielded more than the {} elements of dims {:?}",
+                nelem, dims
+            )));
+        }
+        Ok(RawArray::from_parts(dims.to_vec(), data))
+    }
+
+    /// Iterate over the elements in column-major order.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra: RawArray<i16> = vec![1, -2, 3].into();
+    /// let squared: RawArray<f32> = ra.iter().map(|&x| f32::from(x * x)).collect();
+    /// assert_eq!(squared.data(), vec![1.0, 4.0, 9.0]);
+    /// let total: i16 = (&ra).into_iter().sum();
+    /// assert_eq!(total, 2);
+    /// ```
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    /// Iterate mutably over the elements in column-major order.
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.data.iter_mut()
+    }
+
+    /// Create a `RawArray<T>` from data and dimensions that are already
+    /// known to agree.
+    fn from_parts(dims: Vec<u64>, data: Vec<T>) -> RawArray<T> {
+        debug_assert_eq!(dims.iter().product::<u64>(), data.len() as u64);
+        RawArray {
+            flags: NATIVE_FLAGS,
+            eltype: T::ra_type_code(),
+            elbyte: mem::size_of::<T>() as u64,
+            size: (data.len() * mem::size_of::<T>()) as u64,
+            ndims: dims.len() as u64,
+            dims,
+            data,
+            attrs: BTreeMap::new(),
+        }
+    }
+
+    /// Create a new `RawArray<T>` with the same dimensions, flags, and
+    /// attributes but new data, in the same byte order. Fails with
+    /// `ErrorKind::InvalidInput` if `data` has the wrong number of elements
+    /// for the dimensions; use `from_iter_with_dims` for new dimensions.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut ra = RawArray::from_iter_with_dims(0..6u8, &[3, 2]).unwrap();
+    /// ra.set_attr("units", "mm");
+    /// let copy = ra.clone_with_data(vec![9; 6]).unwrap();
+    /// assert_eq!(copy.dims(), vec![3, 2]);
+    /// assert_eq!(copy.get_attr("units"), ra.get_attr("units"));
+    /// assert!(ra.clone_with_data(vec![9; 5]).is_err());
+    /// ```
+    pub fn clone_with_data(&self, data: Vec<T>) -> io::Result<RawArray<T>> {
+        if self.dims.iter().product::<u64>() != data.len() as u64 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} elements don't fill dimensions {:?}",
+                    data.len(),
+                    self.dims
+                ),
+            ));
+        }
+        Ok(self.with_data(data))
+    }
+
+    /// `clone_with_data` for data already known to be the right length
+    fn with_data(&self, data: Vec<T>) -> RawArray<T> {
+        debug_assert_eq!(self.dims.iter().product::<u64>(), data.len() as u64);
         RawArray {
             flags: self.flags,
             eltype: self.eltype,
             elbyte: self.elbyte,
-            size: self.size,
+            size: (data.len() * mem::size_of::<T>()) as u64,
             ndims: self.ndims,
             dims: self.dims.clone(),
             data,
+            attrs: self.attrs.clone(),
+        }
+    }
+
+    /// Apply `f` to every element, giving an array of the same dimensions
+    /// and attributes.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims(0..6u8, &[3, 2]).unwrap();
+    /// let halves = ra.map(|x| f32::from(x) / 2.0);
+    /// assert_eq!(halves.dims(), vec![3, 2]);
+    /// assert_eq!(halves.data()[5], 2.5);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data isn't in native byte order.
+    pub fn map<U: RawArrayType, F: FnMut(T) -> U>(&self, f: F) -> RawArray<U> {
+        assert!(
+            self.endianness() == Endianness::native(),
+            "map needs data in native byte order"
+        );
+        let mut out = RawArray::from_parts(
+            self.dims.clone(),
+            self.data.iter().copied().map(f).collect(),
+        );
+        out.attrs = self.attrs.clone();
+        out
+    }
+
+    /// Replace every element `x` with `f(x)`, in place.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut ra: RawArray<i32> = vec![-2, 0, 3].into();
+    /// ra.map_inplace(|x| x.abs());
+    /// assert_eq!(ra.data(), vec![2, 0, 3]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data isn't in native byte order.
+    pub fn map_inplace<F: FnMut(T) -> T>(&mut self, mut f: F) {
+        assert!(
+            self.endianness() == Endianness::native(),
+            "map_inplace needs data in native byte order"
+        );
+        for x in self.data.iter_mut() {
+            *x = f(*x);
         }
     }
 
+    /// Combine the elements of two arrays of the same dimensions pairwise
+    /// with `f`, keeping the attributes of this one.
+    /// ```
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// let re = RawArray::from_iter_with_dims(vec![1.0f32, 2.0, 3.0, 4.0], &[2, 2]).unwrap();
+    /// let im = re.map(|x| -x);
+    /// let z = re.zip_with(&im, Complex::new);
+    /// assert_eq!(z.dims(), vec![2, 2]);
+    /// assert_eq!(z.data()[3], Complex::new(4.0, -4.0));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the dimensions differ, or if either array's data isn't in
+    /// native byte order.
+    pub fn zip_with<U, V, F>(&self, other: &RawArray<U>, mut f: F) -> RawArray<V>
+    where
+        U: RawArrayType,
+        V: RawArrayType,
+        F: FnMut(T, U) -> V,
+    {
+        assert_eq!(
+            self.dims, other.dims,
+            "can't combine arrays of dims {:?} and {:?}",
+            self.dims, other.dims
+        );
+        assert!(
+            self.endianness() == Endianness::native() && other.endianness() == Endianness::native(),
+            "zip_with needs data in native byte order"
+        );
+        let data = self
+            .data
+            .iter()
+            .zip(&other.data)
+            .map(|(&a, &b)| f(a, b))
+            .collect();
+        let mut out = RawArray::from_parts(self.dims.clone(), data);
+        out.attrs = self.attrs.clone();
+        out
+    }
+
     /// Boolean feature flags, endianness, etc.
     pub fn flags(&self) -> u64 {
         self.flags
     }
+    /// Byte order of the data in memory. Arrays are created and read in
+    /// the machine's native order; only `to_big_endian` and
+    /// `to_little_endian` change it.
+    pub fn endianness(&self) -> Endianness {
+        if self.flags & FLAG_BIG_ENDIAN != 0 {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        }
+    }
+
+    /// Swap the data in place to big-endian byte order, so that it's
+    /// written that way, for consumers on big-endian machines. On a
+    /// little-endian machine the element values look scrambled until the
+    /// array is converted back, so do this just before writing. Fails for
+    /// user-defined element types, whose layout isn't known.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{Endianness, RawArray};
+    /// # fn main() -> io::Result<()>{
+    /// let mut ra: RawArray<u32> = vec![1, 2, 3].into();
+    /// ra.to_big_endian()?;
+    /// assert_eq!(ra.endianness(), Endianness::Big);
+    /// ra.write("big.ra")?;
+    /// // reading always gives native byte order
+    /// assert_eq!(RawArray::<u32>::read("big.ra")?.data(), vec![1, 2, 3]);
+    /// # std::fs::remove_file("big.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_big_endian(&mut self) -> io::Result<()> {
+        self.set_endianness(Endianness::Big)
+    }
+
+    /// Swap the data in place to little-endian byte order, the format's
+    /// default. See `to_big_endian`.
+    pub fn to_little_endian(&mut self) -> io::Result<()> {
+        self.set_endianness(Endianness::Little)
+    }
+
+    fn set_endianness(&mut self, endianness: Endianness) -> io::Result<()> {
+        if self.endianness() != endianness {
+            convert::swap_bytes(as_u8_slice_mut(&mut self.data), DType::of::<T>())?;
+            self.flags ^= FLAG_BIG_ENDIAN;
+        }
+        Ok(())
+    }
+
+    /// Whether a CRC32 of the data section is stored with the array.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<u8> = vec![1, 2, 3].into();
+    /// assert!(!r.checksum());
+    /// r.set_checksum(true);
+    /// assert!(r.checksum());
+    /// ```
+    pub fn checksum(&self) -> bool {
+        self.flags & FLAG_CRC != 0
+    }
+    /// Choose whether `write()` appends a CRC32 of the data section. When
+    /// the flag is set in a file, `read()` verifies the data against it and
+    /// fails with `RawArrayError::ChecksumMismatch` if they disagree.
+    pub fn set_checksum(&mut self, enabled: bool) {
+        if enabled {
+            self.flags |= FLAG_CRC;
+        } else {
+            self.flags &= !FLAG_CRC;
+        }
+    }
     /// Elemental type code.
     /// ```
     /// # use rawarray::RawArray;
@@ -441,6 +1376,23 @@ impl<T: RawArrayType> RawArray<T> {
     pub fn data(&self) -> Vec<T> {
         self.data.clone()
     }
+    /// Byte offset of the data section in the file this array is written
+    /// to, which is always `48 + 8 * ndims`. This is a guarantee of the
+    /// format, not an implementation detail: the data section immediately
+    /// follows the dimensions in every version of the format, and in an
+    /// uncompressed file element `i` (in column-major order) lives at
+    /// `data_offset() + i * elbyte()`. External tools can rely on this to
+    /// mmap or patch the data directly.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<u8> = vec![0; 24].into();
+    /// assert_eq!(r.data_offset(), 56);
+    /// r.reshape(vec![2, 3, 4]);
+    /// assert_eq!(r.data_offset(), 72);
+    /// ```
+    pub fn data_offset(&self) -> u64 {
+        48 + 8 * self.ndims
+    }
     /// Get a reference to the dims vector.
     /// ```
     /// # use rawarray::RawArray;
@@ -451,11 +1403,31 @@ impl<T: RawArrayType> RawArray<T> {
         &self.dims
     }
 
-    /// Get a reference to the data vector.
+    /// The array data as a slice, without copying.
     /// ```
     /// # use rawarray::RawArray;
     /// let r: RawArray<u16> = vec![1, 0, 1, 0].into();
-    /// assert_eq!(*r.data_as_ref(), vec![1, 0, 1, 0]);
+    /// assert_eq!(r.as_slice().iter().sum::<u16>(), 2);
+    /// ```
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+    /// The array data as a mutable slice, for in-place updates that keep
+    /// the shape.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<u16> = vec![1, 0, 1, 0].into();
+    /// r.as_mut_slice()[1] = 7;
+    /// assert_eq!(r.data(), vec![1, 7, 1, 0]);
+    /// ```
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+    /// Get a reference to the data vector.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let r: RawArray<u16> = vec![1, 0, 1, 0].into();
+    /// assert_eq!(*r.data_as_ref(), vec![1, 0, 1, 0]);
     /// ```
     pub fn data_as_ref(&self) -> &Vec<T> {
         &self.data
@@ -472,47 +1444,109 @@ impl<T: RawArrayType> RawArray<T> {
         let new_nelem: u64 = new_dims.iter().product();
         let old_nelem: u64 = self.dims.iter().product();
         assert_eq!(new_nelem, old_nelem);
+        self.ndims = new_dims.len() as u64;
         self.dims = new_dims;
     }
 
-    /// Read the file header
-    fn read_header<R: Read>(&mut self, mut r: &mut R) -> io::Result<()> {
-        // read header, which should always be LittleEndian
-        let magic = read_u64(&mut r);
-        assert_eq!(magic, MAGIC_NUMBER);
+    /// Read the file header, requiring `size` to match the dimensions
+    fn read_header<R: Read>(&mut self, r: &mut R) -> io::Result<()> {
+        self.read_header_with(r, SizePolicy::Strict, DEFAULT_MAX_BYTES)
+            .map(|_| ())
+    }
 
-        self.flags = read_u64(&mut r);
-        if self.flags & ALL_KNOWN_FLAGS != 0 {
-            panic!(
-                "Unknown flags encounter in header. This file must have been written
-                    with a newer version of the library. Please upgrade your RawArray
-                    installation by running `cargo update`."
-            );
+    /// Read the file header, reconciling `size` and the dimensions
+    /// according to `policy`, and refusing data of more than `max_bytes`
+    fn read_header_with<R: Read>(
+        &mut self,
+        r: &mut R,
+        policy: SizePolicy,
+        max_bytes: u64,
+    ) -> io::Result<ReadReport> {
+        let h = RawArrayHeader::read_from(r)?;
+        if h.flags & !ALL_KNOWN_FLAGS != 0 {
+            return Err(invalid(format!(
+                "unknown flags {:#x} in header; this file must have been written with \
+                 a newer version of the library, so please upgrade rawarray",
+                h.flags & !ALL_KNOWN_FLAGS
+            )));
         }
-        self.eltype = read_u64(&mut r);
-        assert_eq!(self.eltype, T::ra_type_code());
-        self.elbyte = read_u64(&mut r);
-        assert_eq!(self.elbyte, mem::size_of::<T>() as u64);
-        self.size = read_u64(&mut r);
-        self.ndims = read_u64(&mut r);
-
-        // read dimensions
-        //let mut dims: Vec<u64> = Vec::with_capacity(ndims as usize);
-        self.dims.reserve(self.ndims as usize);
-        for _ in 0..self.ndims {
-            self.dims.push(read_u64(&mut r));
-        }
-        let nelem: u64 = self.dims.iter().product(); //fold(1, |acc, x| acc * x);
-        assert_eq!(nelem * self.elbyte, self.size);
-        Ok(())
+        if (h.eltype, h.elbyte) != (T::ra_type_code(), mem::size_of::<T>() as u64) {
+            return Err(invalid(format!(
+                "file holds {} elements, not {}",
+                dtype_name(h.eltype, h.elbyte),
+                dtype_name(T::ra_type_code(), mem::size_of::<T>() as u64)
+            )));
+        }
+        self.flags = h.flags;
+        self.eltype = h.eltype;
+        self.elbyte = h.elbyte;
+        self.size = h.size;
+        self.dims = h.dims;
+        let size_mismatch = resolve_size(&mut self.dims, &mut self.size, self.elbyte, policy)?;
+        check_max_bytes(Some(self.size), max_bytes)?;
+        self.ndims = self.dims.len() as u64;
+        Ok(ReadReport {
+            size_mismatch,
+            ..ReadReport::default()
+        })
+    }
+
+    /// Read the data section, decoding it if needed, and verify its
+    /// checksum if it has one. Anything after that is volatile metadata and
+    /// is left unread.
+    fn read_data<R: Read>(&mut self, mut r: &mut R) -> io::Result<()> {
+        let data = if let Some((id, id_stored)) = codec::codec_for_flags(self.flags) {
+            let id = if id_stored { read_u64(&mut r)? } else { id };
+            let len = read_u64(&mut r)?;
+            let mut payload = Vec::new();
+            r.take(len).read_to_end(&mut payload)?;
+            if payload.len() as u64 != len {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "encoded data section is truncated",
+                ));
+            }
+            self.decode_payload(id, &payload)?
+        } else {
+            read_vec(r, self.nelem_in_file()?)?
+        };
+        self.finish_data(data, r)
+    }
+
+    /// Number of elements the header declares, as a `usize`.
+    fn nelem_in_file(&self) -> io::Result<usize> {
+        to_usize(self.size / self.elbyte)
     }
 
-    /// Read the data section
-    fn read_data<R: Read>(&mut self, r: &mut R) -> io::Result<()> {
-        let mut byte_data: Vec<u8> = Vec::with_capacity(self.size as usize);
-        let bytes_read = r.read_to_end(&mut byte_data)? as u64;
-        assert_eq!(bytes_read, self.size);
-        self.data = from_u8::<T>(byte_data);
+    /// Decode an encoded data section with the codec `id`.
+    fn decode_payload(&mut self, id: u64, payload: &[u8]) -> io::Result<Vec<T>> {
+        let codec = codec::lookup(id)?;
+        codec::check_decoded_len(codec.as_ref(), payload, self.size, self.elbyte as usize)?;
+        let mut data: Vec<T> = zeroed_vec(self.nelem_in_file()?);
+        codec.decode(payload, as_u8_slice_mut(&mut data), self.elbyte as usize)?;
+        // the array in memory is plain data
+        self.flags &= !codec::CODEC_FLAGS;
+        Ok(data)
+    }
+
+    /// Check the data against its checksum, read the attributes that
+    /// follow it, and convert it to native byte order.
+    fn finish_data<R: Read>(&mut self, mut data: Vec<T>, mut r: &mut R) -> io::Result<()> {
+        if self.flags & FLAG_CRC != 0 {
+            let stored = read_u64(&mut r)?;
+            let computed = u64::from(crc32fast::hash(as_u8_slice(&data)));
+            if stored != computed {
+                return Err(RawArrayError::ChecksumMismatch { stored, computed }.into());
+            }
+        }
+        if self.flags & FLAG_ATTRS != 0 {
+            self.attrs = attrs::read_attrs(r)?;
+        }
+        if self.endianness() != Endianness::native() {
+            convert::swap_bytes(as_u8_slice_mut(&mut data), DType::of::<T>())?;
+            self.flags ^= FLAG_BIG_ENDIAN;
+        }
+        self.data = data;
         Ok(())
     }
 
@@ -532,29 +1566,313 @@ impl<T: RawArrayType> RawArray<T> {
     /// # }
     /// ```
     pub fn read<P: AsRef<Path>>(path: P) -> io::Result<RawArray<T>> {
-        let f = File::open(path)?;
+        RawArray::read_from_storage(&FsStorage, path)
+    }
+
+    /// Read a `RawArray<T>` from a file in `storage`. See `RaStorage`.
+    pub fn read_from_storage<S: RaStorage, P: AsRef<Path>>(
+        storage: &S,
+        path: P,
+    ) -> io::Result<RawArray<T>> {
+        RawArray::read_file(
+            storage,
+            path.as_ref(),
+            SizePolicy::Strict,
+            DEFAULT_MAX_BYTES,
+        )
+        .map(|(ra, ..)| ra)
+    }
+
+    /// Read a file, checking the header against the file length before
+    /// allocating room for the data. Also gives the number of bytes read.
+    fn read_file<S: RaStorage>(
+        storage: &S,
+        path: &Path,
+        policy: SizePolicy,
+        max_bytes: u64,
+    ) -> io::Result<(RawArray<T>, ReadReport, u64)> {
+        let (mut ra, report, mut r) = RawArray::open_checked_in(storage, path, policy, max_bytes)?;
+        ra.read_data(&mut r)?;
+        let bytes = r.stream_position()?;
+        Ok((ra, report, bytes))
+    }
+
+    /// Read the header of a file and check it against the file length
+    /// and `max_bytes`, returning the reader positioned at the data section.
+    fn open_checked_in<'a, S: RaStorage>(
+        storage: &'a S,
+        path: &Path,
+        policy: SizePolicy,
+        max_bytes: u64,
+    ) -> io::Result<(RawArray<T>, ReadReport, BufReader<StorageFile<'a, S>>)> {
+        let f = StorageFile::open(storage, path, OpenMode::Read)?;
+        let file_len = f.len()?;
         let mut r = BufReader::new(f);
         let mut ra = RawArray::default();
-        ra.read_header(&mut r)?;
-        ra.read_data(&mut r)?;
-        Ok(ra)
+        let report = ra.read_header_with(&mut r, policy, max_bytes)?;
+        ra.check_data_fits(file_len.saturating_sub(ra.data_offset()))?;
+        Ok((ra, report, r))
     }
 
-    fn write_header<W: Write>(&self, mut w: &mut W) -> io::Result<()> {
-        write_u64(&mut w, MAGIC_NUMBER)?;
-        write_u64(&mut w, self.flags)?;
-        write_u64(&mut w, self.eltype)?;
-        write_u64(&mut w, self.elbyte)?;
-        write_u64(&mut w, self.size)?;
-        write_u64(&mut w, self.ndims)?;
-        for d in self.dims.iter() {
-            write_u64(&mut w, *d)?;
+    /// Check that plain data of the size the header declares, and its
+    /// checksum, fit in the `remaining` bytes after the header.
+    fn check_data_fits(&self, remaining: u64) -> io::Result<()> {
+        let mut needed = self.size;
+        if self.flags & FLAG_CRC != 0 {
+            needed = needed.saturating_add(8);
+        }
+        if codec::codec_for_flags(self.flags).is_none() && needed > remaining {
+            return Err(invalid(format!(
+                "header declares {} bytes of data, but only {} remain",
+                self.size, remaining
+            )));
         }
         Ok(())
     }
 
-    fn write_data<W: Write>(&self, w: &mut W) -> io::Result<()> {
-        w.write_all(as_u8_slice(&self.data))?;
+    /// Read a `RawArray<T>` from a file, applying `opts` to recover from
+    /// header problems that `read()` rejects. The report says what, if
+    /// anything, had to be fixed.
+    ///
+    /// With `SizePolicy::TrustSize`, the array is read as one-dimensional,
+    /// since the dimensions can't be relied on; the report keeps the
+    /// dimensions from the header.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{RawArray, ReadOptions, SizePolicy};
+    /// # fn main() -> io::Result<()>{
+    /// let ra: RawArray<u8> = vec![1, 2, 3, 4].into();
+    /// ra.write("badsize.ra")?;
+    /// // a buggy writer recorded 2 bytes of data instead of 4
+    /// let mut bytes = std::fs::read("badsize.ra")?;
+    /// bytes[32] = 2;
+    /// std::fs::write("badsize.ra", &bytes)?;
+    /// assert!(RawArray::<u8>::read("badsize.ra").is_err());
+    ///
+    /// let opts = ReadOptions::new().size_policy(SizePolicy::TrustDims);
+    /// let (ra, report) = RawArray::<u8>::read_with("badsize.ra", &opts)?;
+    /// assert_eq!(ra.data(), vec![1, 2, 3, 4]);
+    /// assert_eq!(report.size_mismatch.unwrap().header_size, 2);
+    ///
+    /// let opts = ReadOptions::new().size_policy(SizePolicy::TrustSize);
+    /// let (ra, report) = RawArray::<u8>::read_with("badsize.ra", &opts)?;
+    /// assert_eq!(ra.data(), vec![1, 2]);
+    /// assert_eq!(report.size_mismatch.unwrap().header_dims, vec![4]);
+    /// # std::fs::remove_file("badsize.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_with<P: AsRef<Path>>(
+        path: P,
+        opts: &ReadOptions,
+    ) -> io::Result<(RawArray<T>, ReadReport)> {
+        RawArray::read_from_storage_with(&FsStorage, path, opts)
+    }
+
+    /// Read a file in `storage` as `read_with()` does. See `RaStorage`.
+    pub fn read_from_storage_with<S: RaStorage, P: AsRef<Path>>(
+        storage: &S,
+        path: P,
+        opts: &ReadOptions,
+    ) -> io::Result<(RawArray<T>, ReadReport)> {
+        let start = Instant::now();
+        let mut widen = false;
+        if opts.allow_width_promotion {
+            let f = StorageFile::open(storage, path.as_ref(), OpenMode::Read)?;
+            let h = RawArrayHeader::read_from(&mut BufReader::new(f))?;
+            let from = DType {
+                eltype: h.eltype,
+                elbyte: h.elbyte,
+            };
+            widen = from != DType::of::<T>() && convert::is_widening(from, DType::of::<T>());
+        }
+        let (ra, mut report, bytes) = if widen {
+            RawArray::read_widened(storage, path.as_ref(), opts.size_policy, opts.max_bytes)?
+        } else {
+            RawArray::read_file(storage, path.as_ref(), opts.size_policy, opts.max_bytes)?
+        };
+        if opts.collect_stats {
+            report.stats = Some(ReadStats::new(bytes, start.elapsed()));
+        }
+        Ok((ra, report))
+    }
+
+    /// Read a file of a narrower element type than `T`, converting each
+    /// element as it's read. Also gives the number of bytes read.
+    fn read_widened<S: RaStorage>(
+        storage: &S,
+        path: &Path,
+        policy: SizePolicy,
+        max_bytes: u64,
+    ) -> io::Result<(RawArray<T>, ReadReport, u64)> {
+        let f = StorageFile::open(storage, path, OpenMode::Read)?;
+        let file_len = f.len()?;
+        let mut r = BufReader::new(f);
+        let mut h = RawArrayHeader::read_from(&mut r)?;
+        if h.flags & !ALL_KNOWN_FLAGS != 0 || h.flags & FLAG_BITS != 0 {
+            return Err(invalid(format!(
+                "can't widen data with flags {:#x}",
+                h.flags
+            )));
+        }
+        let size_mismatch = resolve_size(&mut h.dims, &mut h.size, h.elbyte, policy)?;
+        check_max_bytes(
+            (h.size / h.elbyte).checked_mul(mem::size_of::<T>() as u64),
+            max_bytes,
+        )?;
+        let remaining = file_len.saturating_sub(h.data_offset());
+        if codec::codec_for_flags(h.flags).is_none() && h.size > remaining {
+            return Err(invalid(format!(
+                "header declares {} bytes of data, but only {} remain in the file",
+                h.size, remaining
+            )));
+        }
+        let from = DType {
+            eltype: h.eltype,
+            elbyte: h.elbyte,
+        };
+        // the array in memory is plain, native data
+        let flags = NATIVE_FLAGS | (h.flags & (FLAG_CRC | FLAG_ATTRS));
+        let dims = h.dims.clone();
+        let mut src = recode::Source::new(h, &mut r, max_bytes)?;
+        let mut data: Vec<T> = zeroed_vec(to_usize(dims.iter().product())?);
+        let out = as_u8_slice_mut(&mut data);
+        let mut at = 0;
+        src.convert(DType::of::<T>(), Endianness::native(), |bytes| {
+            out[at..at + bytes.len()].copy_from_slice(bytes);
+            at += bytes.len();
+            Ok(())
+        })?;
+        let mut ra = RawArray::from_parts(dims, data);
+        ra.flags = flags;
+        ra.attrs = src.finish()?;
+        let report = ReadReport {
+            size_mismatch,
+            widened_from: Some(from),
+            ..ReadReport::default()
+        };
+        Ok((ra, report, r.stream_position()?))
+    }
+
+    /// Read a `RawArray<T>` from any reader, such as a socket or an
+    /// in-memory buffer. Exactly one array is consumed, so the reader is
+    /// left just past its data, checksum, and attributes.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()>{
+    /// let ra: RawArray<i8> = vec![1, -1, 2].into();
+    /// let mut buf = Vec::new();
+    /// ra.write_to(&mut buf)?;
+    /// assert_eq!(RawArray::<i8>::read_from(&mut buf.as_slice())?, ra);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<RawArray<T>> {
+        let mut ra = RawArray::default();
+        ra.read_header(r)?;
+        ra.read_data(r)?;
+        Ok(ra)
+    }
+
+    /// Read a `RawArray<T>` from any reader like `read_from`, applying the
+    /// size policy and byte limit of `opts`, which is the way to read
+    /// from a source that can't be trusted. Width promotion and stats are
+    /// only available for files, with `read_with`.
+    pub fn read_from_with<R: Read>(
+        r: &mut R,
+        opts: &ReadOptions,
+    ) -> io::Result<(RawArray<T>, ReadReport)> {
+        let mut ra = RawArray::default();
+        let report = ra.read_header_with(r, opts.size_policy, opts.max_bytes)?;
+        ra.read_data(r)?;
+        Ok((ra, report))
+    }
+
+    /// Read a `RawArray<T>` from the bytes of a file already in memory,
+    /// such as one fetched over HTTP by a browser. This and `to_bytes`
+    /// need no filesystem, so they work on `wasm32-unknown-unknown`, where
+    /// the path-based methods always fail.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()>{
+    /// let ra: RawArray<u16> = vec![1, 2, 3].into();
+    /// let bytes = ra.to_bytes();
+    /// assert_eq!(RawArray::<u16>::read_from_bytes(&bytes)?, ra);
+    /// assert!(RawArray::<u16>::read_from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    ///
+    /// // a header claiming more data than there is fails before allocating
+    /// let mut bytes = RawArray::<u8>::from(vec![1, 2, 3]).to_bytes();
+    /// bytes[32..40].copy_from_slice(&(1u64 << 35).to_le_bytes());
+    /// bytes[48..56].copy_from_slice(&(1u64 << 35).to_le_bytes());
+    /// let e = RawArray::<u8>::read_from_bytes(&bytes).unwrap_err();
+    /// assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_from_bytes(mut bytes: &[u8]) -> io::Result<RawArray<T>> {
+        let mut ra = RawArray::default();
+        ra.read_header(&mut bytes)?;
+        ra.check_data_fits(bytes.len() as u64)?;
+        ra.read_data(&mut bytes)?;
+        Ok(ra)
+    }
+
+    /// The bytes of the file `write` would create, header and all
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.data.len() * mem::size_of::<T>() + 48);
+        // writing to a `Vec` can't fail
+        self.write_to(&mut buf).expect("write to Vec");
+        buf
+    }
+
+    /// Flags to write to the header, given what's present in memory and
+    /// the attributes `attrs` to write with it
+    fn header_flags(&self, attrs: &BTreeMap<String, AttrValue>) -> u64 {
+        if attrs.is_empty() {
+            self.flags & !FLAG_ATTRS
+        } else {
+            self.flags | FLAG_ATTRS
+        }
+    }
+
+    fn write_header<W: Write>(&self, w: &mut W, flags: u64) -> io::Result<()> {
+        write_header_fields::<T, W>(w, flags, self.size, &self.dims)
+    }
+
+    fn write_data<W: Write>(
+        &self,
+        mut w: &mut W,
+        codec: Option<&dyn Codec>,
+        attrs: &BTreeMap<String, AttrValue>,
+    ) -> io::Result<()> {
+        let bytes = as_u8_slice(&self.data);
+        if let Some(codec) = codec {
+            let payload = codec.encode(bytes, self.elbyte as usize)?;
+            if codec::flag_for(codec) == FLAG_ENCODED {
+                write_u64(&mut w, codec.id())?;
+            }
+            write_u64(&mut w, payload.len() as u64)?;
+            w.write_all(&payload)?;
+        } else {
+            w.write_all(bytes)?;
+        }
+        self.write_trailer(w, attrs)
+    }
+
+    /// Write what follows the data section: the checksum and attributes.
+    fn write_trailer<W: Write>(
+        &self,
+        mut w: &mut W,
+        attrs: &BTreeMap<String, AttrValue>,
+    ) -> io::Result<()> {
+        if self.flags & FLAG_CRC != 0 {
+            write_u64(&mut w, u64::from(crc32fast::hash(as_u8_slice(&self.data))))?;
+        }
+        if !attrs.is_empty() {
+            attrs::write_attrs(w, attrs)?;
+        }
         Ok(())
     }
 
@@ -569,16 +1887,1275 @@ impl<T: RawArrayType> RawArray<T> {
     /// # }
     /// ```
     pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        let f = File::create(path)?;
-        let mut w = BufWriter::new(f);
-        self.write_header(&mut w)?;
-        self.write_data(&mut w)?;
-        Ok(())
+        self.write_to_storage(&FsStorage, path)
+    }
+
+    /// Write a `RawArray<T>` to a file in `storage`. See `RaStorage`.
+    pub fn write_to_storage<S: RaStorage, P: AsRef<Path>>(
+        &self,
+        storage: &S,
+        path: P,
+    ) -> io::Result<()> {
+        let mut w = BufWriter::new(StorageFile::open(storage, path.as_ref(), OpenMode::Create)?);
+        self.write_to(&mut w)?;
+        w.get_mut().finish(false)
+    }
+
+    /// Write a `RawArray<T>` to any writer and flush it. Wrap unbuffered
+    /// writers such as sockets in a `BufWriter` first.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let attrs = attrs_to_write(&self.attrs);
+        self.write_header(w, self.header_flags(&attrs))?;
+        self.write_data(w, None, &attrs)?;
+        w.flush()
+    }
+
+    /// Write an ndarray array or view straight to file, without first
+    /// copying it into a `RawArray<T>`. The view doesn't need to be
+    /// contiguous. Dimensions are kept as they are, so element `[i, j]` of
+    /// the view is element `[i, j]` of the file; since RawArray is column
+    /// major, the data is written with the first axis varying fastest.
+    /// ```
+    /// # use std::io;
+    /// use ndarray::{s, Array2};
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()>{
+    /// let a = Array2::from_shape_fn((4, 3), |(i, j)| (10 * i + j) as u32);
+    /// let v = a.slice(s![1..3, ..]);
+    /// RawArray::write_from_view("view.ra", &v)?;
+    /// let ra = RawArray::<u32>::read("view.ra")?;
+    /// assert_eq!(ra.dims(), vec![2, 3]);
+    /// assert_eq!(ra.data(), vec![10, 20, 11, 21, 12, 22]);
+    /// # std::fs::remove_file("view.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_from_view<P, S, D>(path: P, view: &ArrayBase<S, D>) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        S: Data<Elem = T>,
+        D: Dimension,
+    {
+        const CHUNK: usize = 1 << 16;
+        let dims: Vec<u64> = view.shape().iter().map(|&d| d as u64).collect();
+        let size = (view.len() * mem::size_of::<T>()) as u64;
+        let empty = RawArray::<T>::default();
+        let attrs = attrs_to_write(&empty.attrs);
+        let flags = empty.header_flags(&attrs);
+        let mut w = BufWriter::new(FsFile::open_fs(path.as_ref(), OpenMode::Create)?);
+        write_header_fields::<T, _>(&mut w, flags, size, &dims)?;
+        let mut buf = Vec::with_capacity(CHUNK.min(view.len()));
+        // iterating the transpose in logical order is column-major order
+        for x in view.t().iter() {
+            buf.push(*x);
+            if buf.len() == CHUNK {
+                w.write_all(as_u8_slice(&buf))?;
+                buf.clear();
+            }
+        }
+        w.write_all(as_u8_slice(&buf))?;
+        if !attrs.is_empty() {
+            attrs::write_attrs(&mut w, &attrs)?;
+        }
+        w.flush()?;
+        w.get_mut().finish(false)
+    }
+
+    /// Write a `RawArray<T>` to file as described by `opts`, converting the
+    /// byte order and element type on the way out and encoding the data if
+    /// a codec is given, and syncing the file to disk if asked to. The
+    /// array itself is left unchanged.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{DType, Endianness, RawArray, WriteOptions};
+    /// # fn main() -> io::Result<()>{
+    /// let ra: RawArray<f64> = vec![1.5, -2.0].into();
+    /// let opts = WriteOptions::new()
+    ///     .endianness(Endianness::Big)
+    ///     .dtype(DType::of::<f32>())
+    ///     .checksum(true);
+    /// ra.write_with("options.ra", &opts)?;
+    /// let back = RawArray::<f32>::read("options.ra")?;
+    /// assert_eq!(back.data(), vec![1.5, -2.0]);
+    /// assert!(back.checksum());
+    /// # std::fs::remove_file("options.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_with<P: AsRef<Path>>(&self, path: P, opts: &WriteOptions) -> io::Result<()> {
+        let from = DType::of::<T>();
+        let magnitude = opts.magnitude && from.components() == 2;
+        let to = match opts.dtype {
+            Some(to) => to,
+            None if magnitude => from.magnitude(),
+            None => from,
+        };
+        let raw = as_u8_slice(&self.data);
+        let bytes = if magnitude {
+            convert::check_magnitude(from, to)?;
+            let mut out = Vec::with_capacity(self.data.len() * to.elbyte as usize);
+            convert::magnitude_bytes(raw, from, self.endianness(), &mut out, to, opts.endianness)?;
+            Cow::Owned(out)
+        } else if to == from && opts.endianness == self.endianness() {
+            Cow::Borrowed(raw)
+        } else {
+            convert::check_conversion(from, to)?;
+            let mut out = Vec::with_capacity(self.data.len() * to.elbyte as usize);
+            convert::convert_bytes(raw, from, self.endianness(), &mut out, to, opts.endianness)?;
+            Cow::Owned(out)
+        };
+
+        let attrs = attrs_to_write(&self.attrs);
+        let mut flags = self.header_flags(&attrs) & !(FLAG_BIG_ENDIAN | FLAG_CRC);
+        if opts.endianness == Endianness::Big {
+            flags |= FLAG_BIG_ENDIAN;
+        }
+        let checksum = opts.checksum.unwrap_or(self.flags & FLAG_CRC != 0);
+        if checksum {
+            flags |= FLAG_CRC;
+        }
+        if let Some(codec) = &opts.codec {
+            flags |= codec::flag_for(codec.as_ref());
+        }
+        let mut w = BufWriter::new(FsFile::open_fs(path.as_ref(), OpenMode::Create)?);
+        RawArrayHeader {
+            flags,
+            eltype: to.eltype,
+            elbyte: to.elbyte,
+            size: bytes.len() as u64,
+            dims: self.dims.clone(),
+        }
+        .write_to(&mut w)?;
+        match &opts.codec {
+            Some(codec) => {
+                let payload = codec.encode(&bytes, to.elbyte as usize)?;
+                if codec::flag_for(codec.as_ref()) == FLAG_ENCODED {
+                    write_u64(&mut w, codec.id())?;
+                }
+                write_u64(&mut w, payload.len() as u64)?;
+                w.write_all(&payload)?;
+            }
+            None => w.write_all(&bytes)?,
+        }
+        if checksum {
+            write_u64(&mut w, u64::from(crc32fast::hash(&bytes)))?;
+        }
+        if !attrs.is_empty() {
+            attrs::write_attrs(&mut w, &attrs)?;
+        }
+        w.flush()?;
+        w.get_mut().finish(opts.sync)
+    }
+
+    /// Write a `RawArray<T>` to file with its data section encoded by
+    /// `codec`, usually for compression. Encoded files are decoded
+    /// transparently by `read()`.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::codec::{Rle, Zstd};
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()>{
+    /// let ra: RawArray<f32> = vec![0.0; 1000].into();
+    /// ra.write_compressed("zeros.ra", &Rle)?;
+    /// assert_eq!(RawArray::<f32>::read("zeros.ra")?, ra);
+    /// # #[cfg(feature = "zstd")]
+    /// ra.write_compressed("zeros.ra", &Zstd(3))?;
+    /// # std::fs::remove_file("zeros.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_compressed<P: AsRef<Path>>(&self, path: P, codec: &dyn Codec) -> io::Result<()> {
+        let mut w = BufWriter::new(FsFile::open_fs(path.as_ref(), OpenMode::Create)?);
+        let attrs = attrs_to_write(&self.attrs);
+        self.write_header(&mut w, self.header_flags(&attrs) | codec::flag_for(codec))?;
+        self.write_data(&mut w, Some(codec), &attrs)?;
+        w.flush()?;
+        w.get_mut().finish(false)
+    }
+
+    /// Grow an existing file along its last dimension by appending `data`
+    /// to the data section and patching `size`, the last dimension, and the
+    /// checksum if there is one. The length of `data` must be a whole
+    /// number of slices along the last dimension. Named attributes are
+    /// kept, but any other volatile metadata after the data section is
+    /// discarded. Compressed files can't be appended to.
+    ///
+    /// The header is patched last, so if the file ends with its data, an
+    /// interrupted append leaves the old array readable. A checksum or
+    /// attributes sit where the new data goes, though, and are overwritten
+    /// before the header is patched, so an append to such a file that is
+    /// interrupted can leave it unreadable. Copy the file first if that
+    /// matters.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()>{
+    /// let mut ra: RawArray<u16> = vec![1, 2, 3, 4].into();
+    /// ra.reshape(vec![2, 2]);
+    /// ra.write("growing.ra")?;
+    /// RawArray::append("growing.ra", &[5u16, 6])?;
+    /// let ra = RawArray::<u16>::read("growing.ra")?;
+    /// assert_eq!(ra.dims(), vec![2, 3]);
+    /// assert_eq!(ra.data(), vec![1, 2, 3, 4, 5, 6]);
+    /// # std::fs::remove_file("growing.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn append<P: AsRef<Path>>(path: P, data: &[T]) -> io::Result<()> {
+        RawArray::append_to_storage(&FsStorage, path, data)
+    }
+
+    /// Append to a file in `storage` as `append()` does. See `RaStorage`.
+    pub fn append_to_storage<S: RaStorage, P: AsRef<Path>>(
+        storage: &S,
+        path: P,
+        data: &[T],
+    ) -> io::Result<()> {
+        let mut f = StorageFile::open(storage, path.as_ref(), OpenMode::ReadWrite)?;
+        let mut ra = RawArray::<T>::default();
+        ra.read_header(&mut f)?;
+        if ra.flags & codec::CODEC_FLAGS != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "cannot append to a compressed RawArray",
+            ));
+        }
+        let last = match ra.dims.len() {
+            0 => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "cannot append to a zero-dimensional RawArray",
+                ))
+            }
+            n => n - 1,
+        };
+        let slice_len: u64 = ra.dims[..last].iter().product();
+        if slice_len == 0 || data.len() as u64 % slice_len != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "appended length {} is not a multiple of the slice length {}",
+                    data.len(),
+                    slice_len
+                ),
+            ));
+        }
+
+        let end = ra.data_offset() + ra.size;
+        let bytes = bytes_in_order(data, ra.endianness())?;
+        let bytes = &bytes[..];
+        let mut new_end = end + bytes.len() as u64;
+        let crc = if ra.flags & FLAG_CRC != 0 {
+            f.seek(SeekFrom::Start(end))?;
+            let mut hasher = crc32fast::Hasher::new_with_initial(read_u64(&mut f)? as u32);
+            hasher.update(bytes);
+            new_end += 8;
+            Some(u64::from(hasher.finalize()))
+        } else {
+            None
+        };
+        if ra.flags & FLAG_ATTRS != 0 {
+            f.seek(SeekFrom::Start(if crc.is_some() { end + 8 } else { end }))?;
+            ra.attrs = attrs::read_attrs(&mut f)?;
+        }
+
+        // the header goes last, but the new data overwrites any checksum
+        // and attributes, so only a file without them survives an
+        // interrupted append intact
+        f.seek(SeekFrom::Start(end))?;
+        f.write_all(bytes)?;
+        if let Some(crc) = crc {
+            write_u64(&mut f, crc)?;
+        }
+        if !ra.attrs.is_empty() {
+            attrs::write_attrs(&mut f, &ra.attrs)?;
+            new_end = f.stream_position()?;
+        }
+        f.set_len(new_end)?;
+        f.seek(SeekFrom::Start(32))?;
+        write_u64(&mut f, ra.size + bytes.len() as u64)?;
+        f.seek(SeekFrom::Start(48 + 8 * last as u64))?;
+        write_u64(&mut f, ra.dims[last] + data.len() as u64 / slice_len)?;
+        f.finish(false)
+    }
+
+    /// Overwrite elements of an existing file in place, starting at flat
+    /// element index `offset_elems`, without rewriting the rest of the
+    /// file. If the file carries a checksum, it is recomputed. This opens,
+    /// writes, and syncs a `RawArrayFileMut`, so the file must be stored
+    /// plainly; use one directly to patch several places in one go.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()>{
+    /// let ra: RawArray<i32> = vec![0; 6].into();
+    /// ra.write("patched.ra")?;
+    /// RawArray::patch_data_at("patched.ra", 2, &[7i32, 8])?;
+    /// assert_eq!(RawArray::<i32>::read("patched.ra")?.data(), vec![0, 0, 7, 8, 0, 0]);
+    /// assert!(RawArray::patch_data_at("patched.ra", 5, &[1i32, 2]).is_err());
+    /// # std::fs::remove_file("patched.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn patch_data_at<P: AsRef<Path>>(path: P, offset_elems: u64, data: &[T]) -> io::Result<()> {
+        let mut f = RawArrayFileMut::<T>::open(path)?;
+        let end = offset_elems
+            .checked_add(data.len() as u64)
+            .filter(|&end| end <= f.nelem())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "patch of {} elements at {} runs past the end of {} elements",
+                        data.len(),
+                        offset_elems,
+                        f.nelem()
+                    ),
+                )
+            })?;
+        f.set_slice(offset_elems..end, data)?;
+        f.sync()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn lz4_with_checksum() {
+        use super::*;
+        use std::fs;
+        let mut ra: RawArray<i32> = (0..1000).map(|i| i / 10).collect::<Vec<i32>>().into();
+        ra.set_checksum(true);
+        ra.write_compressed("test_lz4.ra", &codec::Lz4).unwrap();
+        let compressed_len = fs::metadata("test_lz4.ra").unwrap().len();
+        let ra2 = RawArray::<i32>::read("test_lz4.ra").unwrap();
+        fs::remove_file("test_lz4.ra").expect("unable to remove file");
+
+        assert!(compressed_len < ra.size());
+        assert_eq!(ra, ra2);
+    }
+    #[test]
+    fn flags_string_separates_names() {
+        use super::*;
+        let flags = FLAG_BIG_ENDIAN | FLAG_CRC | codec::FLAG_ZSTD | codec::FLAG_LZ4 | FLAG_ATTRS;
+        assert_eq!(flags_as_string(flags), "BigEndian CRC Zstd LZ4 Attrs");
+        assert_eq!(
+            flags_as_string(codec::FLAG_ZSTD | FLAG_ATTRS),
+            "LittleEndian Zstd Attrs"
+        );
+    }
+    #[test]
+    fn append_with_checksum() {
+        use super::*;
+        use std::fs;
+        let mut ra: RawArray<f32> = vec![1.0, 2.0, 3.0].into();
+        ra.set_checksum(true);
+        ra.set_attr("rate", 2.5);
+        ra.write("test_append_crc.ra").unwrap();
+        RawArray::append("test_append_crc.ra", &[4.0f32, 5.0]).unwrap();
+        let ra2 = RawArray::<f32>::read("test_append_crc.ra").unwrap();
+        fs::remove_file("test_append_crc.ra").expect("unable to remove file");
+
+        assert_eq!(ra2.dims(), vec![5]);
+        assert_eq!(ra2.data(), vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert!(ra2.checksum());
+        assert_eq!(ra2.get_attr("rate"), Some(&AttrValue::Float(2.5)));
+    }
+
+    #[test]
+    fn wide_integer_round_trips() {
+        use super::*;
+        use std::fs;
+        let signed: RawArray<i128> = vec![i128::MIN, -1, 0, 1, i128::MAX].into();
+        let unsigned: RawArray<u128> = vec![0, 1, u128::MAX / 3, u128::MAX].into();
+        signed
+            .write_with(
+                "test_i128.ra",
+                &WriteOptions::new().endianness(Endianness::Big),
+            )
+            .unwrap();
+        unsigned.write("test_u128.ra").unwrap();
+        let signed2 = RawArray::<i128>::read("test_i128.ra").unwrap();
+        let unsigned2 = RawArray::<u128>::read("test_u128.ra").unwrap();
+        let opts = ReadOptions::new().allow_width_promotion(true);
+        let narrow: RawArray<i32> = vec![i32::MIN, -7, i32::MAX].into();
+        narrow.write("test_i128_promote.ra").unwrap();
+        let (promoted, report) =
+            RawArray::<i128>::read_with("test_i128_promote.ra", &opts).unwrap();
+        let from_u128 = RawArray::<i128>::read_with("test_u128.ra", &opts);
+        for f in &["test_i128.ra", "test_u128.ra", "test_i128_promote.ra"] {
+            fs::remove_file(f).expect("unable to remove file");
+        }
+
+        assert_eq!(signed2, signed);
+        assert_eq!(unsigned2, unsigned);
+        assert_eq!(
+            promoted.data(),
+            vec![i32::MIN as i128, -7, i32::MAX as i128]
+        );
+        assert_eq!(promoted.elbyte(), 16);
+        assert_eq!(report.widened_from, Some(DType::of::<i32>()));
+        assert!(from_u128.is_err());
+    }
+
+    #[test]
+    fn fast_casts_match_generic() {
+        use super::convert::{cast_each, cast_fast};
+        use super::DType;
+        let values = [
+            0.0,
+            -0.0,
+            0.5,
+            -1.5,
+            2.5,
+            127.0,
+            -129.0,
+            255.9,
+            65535.0,
+            -32768.7,
+            4e9,
+            -3e18,
+            1e19,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NAN,
+        ];
+        let names = [
+            "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "f32", "f64",
+        ];
+        for from in names.iter().map(|n| DType::parse(n).unwrap()) {
+            // every value as a `from`, made by the generic path itself
+            let f64s: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+            let mut src = vec![0u8; values.len() * from.elbyte as usize];
+            cast_each(&f64s, DType::of::<f64>(), false, &mut src, from, false);
+            for to in names.iter().map(|n| DType::parse(n).unwrap()) {
+                for &(from_big, to_big) in &[(false, false), (false, true), (true, false)] {
+                    let mut fast = vec![0u8; values.len() * to.elbyte as usize];
+                    let mut each = fast.clone();
+                    assert!(cast_fast(&src, from, from_big, &mut fast, to, to_big));
+                    cast_each(&src, from, from_big, &mut each, to, to_big);
+                    assert_eq!(fast, each, "{} to {}", from, to);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn width_promotion_rules() {
+        use super::convert::is_widening;
+        use super::DType;
+        let d = |name| DType::parse(name).unwrap();
+        for (from, to) in &[
+            ("u8", "u16"),
+            ("u32", "i64"),
+            ("i16", "f32"),
+            ("u32", "f64"),
+            ("u8", "f16"),
+            ("f16", "f64"),
+            ("bf16", "f32"),
+            ("f32", "c64"),
+            ("ci16", "c64"),
+            ("c64", "c128"),
+        ] {
+            assert!(is_widening(d(from), d(to)), "{} to {}", from, to);
+        }
+        for (from, to) in &[
+            ("i16", "i8"),
+            ("i8", "u16"),
+            ("u32", "i32"),
+            ("i32", "f32"),
+            ("i64", "f64"),
+            ("f16", "bf16"),
+            ("f64", "f32"),
+            ("c64", "f64"),
+        ] {
+            assert!(!is_widening(d(from), d(to)), "{} to {}", from, to);
+        }
+    }
+
+    #[test]
+    fn set_without_index() {
+        use super::*;
+        use std::fs;
+        let a: RawArray<i64> = vec![1, 2, 3].into();
+        let mut b: RawArray<i64> = vec![4, 5].into();
+        b.set_checksum(true);
+        {
+            // dropped without finish(), so no index is written
+            let mut w = RawArraySetWriter::create("test_set.ra").unwrap();
+            w.push(&a).unwrap();
+            w.push(&b).unwrap();
+        }
+        let mut set = RawArraySet::open("test_set.ra").unwrap();
+        let arrays: Vec<RawArray<i64>> = set.iter().collect::<io::Result<_>>().unwrap();
+        fs::remove_file("test_set.ra").expect("unable to remove file");
+
+        assert_eq!(arrays, vec![a, b]);
+    }
+
+    #[test]
+    fn set_rejects_overflowing_record() {
+        use super::*;
+        use std::fs;
+        let a: RawArray<u8> = vec![1, 2, 3].into();
+        a.write("test_set_overflow.ra").unwrap();
+        for (size, ndims) in [(u64::MAX, 1), (0, 1 << 62)] {
+            let mut bytes = fs::read("test_set_overflow.ra").unwrap();
+            for word in [MAGIC_NUMBER, 0, 1, 1, size, ndims] {
+                bytes.extend_from_slice(&word.to_le_bytes());
+            }
+            fs::write("test_set_overflow_2.ra", &bytes).unwrap();
+            let err = RawArraySet::open("test_set_overflow_2.ra").err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        fs::remove_file("test_set_overflow.ra").expect("unable to remove file");
+        fs::remove_file("test_set_overflow_2.ra").expect("unable to remove file");
+    }
+
+    #[test]
+    fn attrs_reject_bad_block_length() {
+        use super::*;
+        use std::fs;
+        let mut a: RawArray<u8> = vec![1, 2, 3].into();
+        a.set_attr("k", 1i64);
+        a.write("test_attrs_len.ra").unwrap();
+        let bytes = fs::read("test_attrs_len.ra").unwrap();
+        // the block length follows the three data bytes
+        let at = a.data_offset() as usize + 3;
+        let len = u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        for (new_len, pad) in [(len + 8, 8), (len - 8, 0)] {
+            let mut bad = bytes.clone();
+            bad[at..at + 8].copy_from_slice(&u64::to_le_bytes(new_len));
+            bad.resize(bytes.len() + pad, 0);
+            fs::write("test_attrs_len.ra", &bad).unwrap();
+            let err = RawArray::<u8>::read("test_attrs_len.ra").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        let mut bad = bytes.clone();
+        let flags = u64::from_le_bytes(bad[8..16].try_into().unwrap()) | FLAG_CRC;
+        bad[8..16].copy_from_slice(&flags.to_le_bytes());
+        bad[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write("test_attrs_len.ra", &bad).unwrap();
+        let err = Provenance::read("test_attrs_len.ra").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        fs::remove_file("test_attrs_len.ra").expect("unable to remove file");
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn sampling_keeps_flags_and_attrs() {
+        use super::*;
+        use std::fs;
+        let mut a = RawArray::from_iter_with_dims(0..8u16, &[2, 4]).unwrap();
+        a.set_checksum(true);
+        a.set_attr("units", "mm");
+        for s in [a.shuffle_axis(1, 3), a.sample_axis(1, 2, 3)] {
+            s.write("test_sample_attrs.ra").unwrap();
+            let back = RawArray::<u16>::read("test_sample_attrs.ra").unwrap();
+            assert_eq!(back.flags & FLAG_CRC, FLAG_CRC);
+            assert_eq!(back.get_attr("units").and_then(|v| v.as_str()), Some("mm"));
+            assert_eq!((back.dims(), back.data()), (s.dims(), s.data()));
+        }
+        fs::remove_file("test_sample_attrs.ra").expect("unable to remove file");
+    }
+
+    #[test]
+    fn write_batch_reports_failures() {
+        use super::*;
+        use std::fs;
+        use std::path::PathBuf;
+        let items: Vec<(PathBuf, RawArray<u8>)> = vec![
+            ("test_batch_ok.ra".into(), vec![1].into()),
+            ("no/such/dir/a.ra".into(), vec![2].into()),
+            ("no/such/dir/b.ra".into(), vec![3].into()),
+        ];
+        let err = write_batch(items, 2).unwrap_err();
+        fs::remove_file("test_batch_ok.ra").expect("batch skipped a good file");
+
+        let failed: Vec<&PathBuf> = err.failures.iter().map(|(p, _)| p).collect();
+        assert_eq!(
+            failed,
+            vec![
+                &PathBuf::from("no/such/dir/a.ra"),
+                &PathBuf::from("no/such/dir/b.ra")
+            ]
+        );
+    }
+
+    #[test]
+    fn builtin_codecs() {
+        use super::*;
+        use std::fs;
+        let ra: RawArray<i16> = vec![5, 5, 5, -3, -3, 7, 0, 0, 0, 0].into();
+        for c in [&codec::Rle as &dyn Codec, &codec::Delta].iter() {
+            ra.write_compressed("test_codecs.ra", *c).unwrap();
+            assert_eq!(RawArray::<i16>::read("test_codecs.ra").unwrap(), ra);
+        }
+        fs::remove_file("test_codecs.ra").expect("unable to remove file");
+    }
+
+    #[test]
+    fn recode_big_endian_rle() {
+        use super::*;
+        use std::fs;
+        let mut ra: RawArray<i32> = vec![7, 7, 7, -1, 70000, 70000].into();
+        ra.set_checksum(true);
+        ra.set_attr("units", "mm");
+        ra.write("test_recode_in.ra").unwrap();
+        let opts = WriteOptions::new()
+            .endianness(Endianness::Big)
+            .codec(codec::Rle);
+        recode_file("test_recode_in.ra", "test_recode_be.ra", &opts).unwrap();
+        let back = RawArray::<i32>::read("test_recode_be.ra").unwrap();
+        assert_eq!(back.data(), ra.data());
+        assert_eq!(back.get_attr("units"), ra.get_attr("units"));
+
+        let opts = WriteOptions::new().dtype(DType::of::<i16>());
+        recode_file("test_recode_be.ra", "test_recode_i16.ra", &opts).unwrap();
+        let narrow = RawArray::<i16>::read("test_recode_i16.ra").unwrap();
+        assert_eq!(narrow.data(), vec![7, 7, 7, -1, i16::MAX, i16::MAX]);
+
+        for f in [
+            "test_recode_in.ra",
+            "test_recode_be.ra",
+            "test_recode_i16.ra",
+        ]
+        .iter()
+        {
+            fs::remove_file(f).expect("unable to remove file");
+        }
+    }
+
+    #[test]
+    fn half_spectrum_2d() {
+        use super::*;
+        // naive 2D DFT of a real 5x3 array
+        let (n0, n1) = (5usize, 3usize);
+        let x: Vec<f64> = (0..n0 * n1).map(|i| (i * i % 7) as f64).collect();
+        let mut spec = Vec::new();
+        for k1 in 0..n1 {
+            for k0 in 0..n0 {
+                let mut acc = Complex::new(0.0, 0.0);
+                for j1 in 0..n1 {
+                    for j0 in 0..n0 {
+                        let phase = -2.0
+                            * std::f64::consts::PI
+                            * ((k0 * j0) as f64 / n0 as f64 + (k1 * j1) as f64 / n1 as f64);
+                        acc += Complex::from_polar(&x[j0 + n0 * j1], &phase);
+                    }
+                }
+                spec.push(acc);
+            }
+        }
+        let mut full: RawArray<Complex<f64>> = spec.into();
+        full.reshape(vec![n0 as u64, n1 as u64]);
+        let half = full.to_half_spectrum();
+        assert_eq!(half.dims(), vec![3, 3]);
+        let rebuilt = half.from_half_spectrum(n0 as u64);
+        for (a, b) in rebuilt.data().iter().zip(full.data().iter()) {
+            assert!((a - b).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn sos_combine_file_rejects_bad_dims() {
+        use super::*;
+        use std::fs;
+        let r: RawArray<Complex<f32>> = vec![Complex::new(3.0, 4.0); 4].into();
+        r.write("test_sos_dims.ra").unwrap();
+        let mut bytes = fs::read("test_sos_dims.ra").unwrap();
+        for dims in [[1u64 << 32, 1 << 32], [1, 2]] {
+            bytes.truncate(48);
+            bytes[40..48].copy_from_slice(&2u64.to_le_bytes());
+            for d in dims {
+                bytes.extend_from_slice(&d.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0; 32]);
+            fs::write("test_sos_dims.ra", &bytes).unwrap();
+            let err =
+                RawArray::<Complex<f32>>::sos_combine_file("test_sos_dims.ra", 1).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        fs::remove_file("test_sos_dims.ra").expect("unable to remove file");
+    }
+
+    #[test]
+    fn provenance_escapes_paths() {
+        use super::*;
+        use std::path::PathBuf;
+        let mut prov = Provenance::new("test", "1");
+        for path in ["plain.ra", "two\nlines.ra", "back\\slash.ra", "both\\n.ra"] {
+            prov.inputs.push(ProvenanceInput {
+                path: PathBuf::from(path),
+                sha256: "0".repeat(64),
+            });
+        }
+        let mut ra: RawArray<u8> = vec![1].into();
+        ra.set_provenance(&prov);
+        let listed = ra
+            .get_attr("ra.provenance.inputs")
+            .unwrap()
+            .as_str()
+            .unwrap();
+        assert_eq!(listed.lines().count(), 4);
+        assert!(listed.lines().nth(1).unwrap().starts_with('\\'));
+        assert_eq!(ra.provenance().unwrap().inputs, prov.inputs);
+    }
+
+    #[test]
+    fn half_spectrum_empty() {
+        use super::*;
+        let mut empty: RawArray<Complex<f32>> = Vec::new().into();
+        empty.reshape(vec![0, 3]);
+        let half = empty.to_half_spectrum();
+        assert_eq!(half.dims(), vec![0, 3]);
+        let full = half.from_half_spectrum(0);
+        assert_eq!(full.dims(), vec![0, 3]);
+        assert!(full.data().is_empty());
+    }
+
+    #[test]
+    fn complex_i16() {
+        use super::*;
+        use std::fs;
+        let ra: RawArray<Complex<i16>> =
+            vec![Complex::new(1, -1), Complex::new(i16::MAX, i16::MIN)].into();
+        ra.write("test_ci16.ra").unwrap();
+        let back = RawArray::<Complex<i16>>::read("test_ci16.ra").unwrap();
+        assert_eq!(back, ra);
+        assert_eq!((back.eltype(), back.elbyte()), (6, 4));
+
+        let opts = WriteOptions::new().dtype(DType::parse("c64").unwrap());
+        recode_file("test_ci16.ra", "test_c64.ra", &opts).unwrap();
+        let wide = RawArray::<Complex<f32>>::read("test_c64.ra").unwrap();
+        fs::remove_file("test_ci16.ra").expect("unable to remove file");
+        fs::remove_file("test_c64.ra").expect("unable to remove file");
+        assert_eq!(wide, ra.to_complex_f32());
+    }
+
+    #[test]
+    fn absurd_headers() {
+        use super::*;
+        use std::fs;
+        let ra: RawArray<u32> = vec![1, 2, 3, 4].into();
+        ra.write("test_absurd.ra").unwrap();
+        let good = fs::read("test_absurd.ra").unwrap();
+        let patch = |offset: usize, value: u64| {
+            let mut bytes = good.clone();
+            bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            fs::write("test_absurd.ra", &bytes).unwrap();
+            RawArray::<u32>::read("test_absurd.ra").unwrap_err()
+        };
+        let errors = [
+            patch(0, 1),        // magic
+            patch(40, 1 << 40), // ndims
+            patch(48, 1 << 62), // dims overflow
+            patch(32, 1 << 40), // size bigger than the file
+        ];
+        let mut bytes = good.clone();
+        bytes.truncate(good.len() - 4);
+        fs::write("test_absurd.ra", &bytes).unwrap();
+        let truncated = RawArray::<u32>::read("test_absurd.ra").unwrap_err();
+        let wrong_type = RawArray::<f32>::read("test_absurd.ra").unwrap_err();
+        fs::remove_file("test_absurd.ra").expect("unable to remove file");
+
+        for e in errors.iter().chain([&truncated, &wrong_type]) {
+            assert_eq!(e.kind(), ErrorKind::InvalidData, "{}", e);
+        }
+        assert!(wrong_type.to_string().contains("u32"));
+    }
+
+    #[test]
+    fn short_stream_claiming_huge_array() {
+        use super::*;
+        // 32 GiB of u8s by the header, under the default limit, behind a
+        // stream of a few bytes
+        let mut bytes = RawArray::<u8>::from(vec![1, 2, 3]).to_bytes();
+        bytes[32..40].copy_from_slice(&(1u64 << 35).to_le_bytes());
+        bytes[48..56].copy_from_slice(&(1u64 << 35).to_le_bytes());
+        let e = RawArray::<u8>::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn encoded_header_over_limit() {
+        use super::*;
+        // an encoded file needn't be as long as its declared size, so only
+        // the byte limit stands between a hostile header and the allocator
+        let ra: RawArray<u8> = vec![7; 16].into();
+        ra.write_with("test_limit.ra", &WriteOptions::new().codec(codec::Rle))
+            .unwrap();
+        let mut bytes = std::fs::read("test_limit.ra").unwrap();
+        std::fs::remove_file("test_limit.ra").unwrap();
+        bytes[32..40].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        bytes[48..56].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let err = RawArray::<u8>::read_from_bytes(&bytes).unwrap_err();
+        assert_eq!(
+            RawArrayError::from_io(&err),
+            Some(&RawArrayError::TooLarge {
+                bytes: 1 << 40,
+                limit: DEFAULT_MAX_BYTES
+            })
+        );
+    }
+
+    #[test]
+    fn encoded_header_beyond_payload() {
+        use super::*;
+        // under the byte limit, but far more than 16 bytes of runs can hold
+        let ra: RawArray<u8> = vec![7; 16].into();
+        ra.write_with("test_beyond.ra", &WriteOptions::new().codec(codec::Rle))
+            .unwrap();
+        let mut bytes = std::fs::read("test_beyond.ra").unwrap();
+        std::fs::remove_file("test_beyond.ra").unwrap();
+        bytes[32..40].copy_from_slice(&(1u64 << 35).to_le_bytes());
+        bytes[48..56].copy_from_slice(&(1u64 << 35).to_le_bytes());
+        let e = RawArray::<u8>::read_from(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn patch_refuses_corrupt_checksum() {
+        use super::*;
+        let mut ra: RawArray<u32> = vec![1, 2, 3].into();
+        ra.set_checksum(true);
+        ra.write("test_patch_crc.ra").unwrap();
+        let mut bytes = std::fs::read("test_patch_crc.ra").unwrap();
+        let first = RawArrayHeader::read("test_patch_crc.ra").unwrap().len();
+        bytes[first] ^= 0xff;
+        std::fs::write("test_patch_crc.ra", &bytes).unwrap();
+        let e = RawArrayFileMut::<u32>::open("test_patch_crc.ra")
+            .err()
+            .unwrap();
+        std::fs::remove_file("test_patch_crc.ra").unwrap();
+        assert!(matches!(
+            RawArrayError::from_io(&e),
+            Some(RawArrayError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn c_api() {
+        use super::*;
+        use crate::ffi::*;
+        use std::ffi::{CStr, CString};
+        use std::{fs, ptr, slice};
+        assert!(
+            include_str!("../include/rawarray.h")
+                == include_str!(concat!(env!("OUT_DIR"), "/rawarray.h")),
+            "include/rawarray.h is out of date; copy it from {}",
+            env!("OUT_DIR")
+        );
+
+        let path = CString::new("c_api.ra").unwrap();
+        let dims = [3u64, 2];
+        let data = [1.5f32, -2.0, 0.0, 4.0, 5.0, 6.0];
+        unsafe {
+            let status = ra_write(
+                path.as_ptr(),
+                f32::ra_type_code(),
+                4,
+                dims.as_ptr(),
+                2,
+                data.as_ptr().cast(),
+            );
+            assert_eq!(status, 0);
+            let ra = ra_read(path.as_ptr());
+            assert!(!ra.is_null());
+            assert_eq!(ra_eltype(ra), f32::ra_type_code());
+            assert_eq!((ra_elbyte(ra), ra_size(ra), ra_ndims(ra)), (4, 24, 2));
+            assert_eq!(slice::from_raw_parts(ra_dims(ra), 2), &dims);
+            assert_eq!(slice::from_raw_parts(ra_data(ra).cast::<f32>(), 6), &data);
+            ra_free(ra);
+
+            assert!(ra_read_limited(path.as_ptr(), 16).is_null());
+            let msg = CStr::from_ptr(ra_last_error()).to_str().unwrap();
+            assert!(msg.contains("limit"), "{}", msg);
+            assert!(ra_read(ptr::null()).is_null());
+            let status = ra_write(path.as_ptr(), 4, 0, dims.as_ptr(), 2, data.as_ptr().cast());
+            assert_eq!(status, -1);
+        }
+
+        // big-endian files come back in this machine's byte order
+        let opts = WriteOptions::new().endianness(Endianness::Big);
+        RawArray::from(vec![1u16, 300])
+            .write_with("c_api.ra", &opts)
+            .unwrap();
+        unsafe {
+            let ra = ra_read(path.as_ptr());
+            assert_eq!(
+                slice::from_raw_parts(ra_data(ra).cast::<u16>(), 2),
+                &[1, 300]
+            );
+            ra_free(ra);
+        }
+
+        // a header claiming far more data than the file has
+        let mut bytes = RawArray::from(vec![0u8; 8]).to_bytes();
+        bytes[32..40].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        bytes[48..56].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        fs::write("c_api.ra", bytes).unwrap();
+        unsafe {
+            assert!(ra_read(path.as_ptr()).is_null());
+            assert!(ra_read_limited(path.as_ptr(), u64::MAX).is_null());
+        }
+        fs::remove_file("c_api.ra").unwrap();
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn python_module() {
+        use super::*;
+        use crate::python::rawarray;
+        use pyo3::prelude::*;
+        use pyo3::types::PyDict;
+        use std::fs;
+        let mut ra = RawArray::from_iter_with_dims(0..6i32, &[2, 3]).unwrap();
+        ra.set_attr("units", "mm");
+        ra.set_attr("scale", 0.5);
+        ra.set_attr("n", -3i64);
+        ra.set_attr("raw", &[1u8, 2][..]);
+        ra.write("python.ra").unwrap();
+
+        pyo3::append_to_inittab!(rawarray);
+        pyo3::prepare_freethreaded_python();
+        let have_numpy = Python::with_gil(|py| {
+            let locals = PyDict::new_bound(py);
+            py.run_bound(
+                r#"
+import rawarray
+attrs = {"units": "mm", "scale": 0.5, "n": -3, "raw": b"\x01\x02"}
+assert rawarray.read_attrs("python.ra") == attrs
+try:
+    rawarray.read("python.ra", max_bytes=8)
+    raise AssertionError("read more than max_bytes")
+except OSError:
+    pass
+try:
+    import numpy as np
+except ImportError:
+    np = None
+if np is not None:
+    a = rawarray.read("python.ra")
+    assert a.shape == (2, 3) and a.dtype == np.int32
+    assert (a.ravel(order="F") == np.arange(6)).all()
+    rawarray.write("python_t.ra", a.T, attrs={"units": "mm", "big": 2**63})
+    assert rawarray.read_attrs("python_t.ra") == {"units": "mm", "big": 2**63}
+    try:
+        rawarray.write("python_t.ra", a, attrs={"bad": [1]})
+        raise AssertionError("wrote a list attribute")
+    except TypeError:
+        pass
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            !locals.get_item("np").unwrap().unwrap().is_none()
+        });
+        if have_numpy {
+            let t = RawArray::<i32>::read("python_t.ra").unwrap();
+            assert_eq!(t.dims(), vec![3, 2]);
+            assert_eq!(t.data(), vec![0, 2, 4, 1, 3, 5]);
+            assert_eq!(t.get_attr("big"), Some(&AttrValue::UInt(1 << 63)));
+            fs::remove_file("python_t.ra").unwrap();
+        } else {
+            eprintln!("numpy isn't installed, so arrays weren't read or written");
+        }
+        fs::remove_file("python.ra").unwrap();
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn hdf5_datasets() {
+        use super::*;
+        use hdf5_sys::{h5d, h5f, h5p, h5s, h5t};
+        use num_complex::Complex;
+        use std::fs;
+        // one test, so the raw HDF5 calls below can't race with the crate's
+        let ra = RawArray::from_iter_with_dims((0..12).map(|x| x as i16), &[4, 3]).unwrap();
+        ra.to_hdf5("test.h5", "/group/ints").unwrap();
+        let back = RawArray::<f32>::from_hdf5("test.h5", "/group/ints").unwrap();
+        assert_eq!(back.dims(), vec![4, 3]);
+        assert_eq!(back.data(), (0..12).map(|x| x as f32).collect::<Vec<_>>());
+        assert!(RawArray::<u8>::from_hdf5("test.h5", "/group/ints").is_err());
+        assert_eq!(
+            RawArray::<i16>::from_hdf5("test.h5", "/nothing")
+                .unwrap_err()
+                .kind(),
+            ErrorKind::NotFound
+        );
+
+        let z: RawArray<Complex<f64>> =
+            vec![Complex::new(1.0, -2.0), Complex::new(0.5, 3.0)].into();
+        z.to_hdf5("test.h5", "/z").unwrap();
+        assert_eq!(
+            RawArray::<Complex<f64>>::from_hdf5("test.h5", "/z").unwrap(),
+            z
+        );
+        // replacing a dataset leaves the others alone
+        z.to_hdf5("test.h5", "/z").unwrap();
+        assert_eq!(
+            RawArray::<i16>::from_hdf5("test.h5", "/group/ints").unwrap(),
+            ra
+        );
+
+        // a complex dataset with members named other than h5py's
+        unsafe {
+            let file = h5f::H5Fopen(
+                b"test.h5\0".as_ptr().cast(),
+                h5f::H5F_ACC_RDWR,
+                h5p::H5P_DEFAULT,
+            );
+            let ty = h5t::H5Tcreate(h5t::H5T_class_t::H5T_COMPOUND, 16);
+            h5t::H5Tinsert(ty, b"real\0".as_ptr().cast(), 0, *h5t::H5T_NATIVE_DOUBLE);
+            h5t::H5Tinsert(ty, b"imag\0".as_ptr().cast(), 8, *h5t::H5T_NATIVE_DOUBLE);
+            let shape = [2u64];
+            let space = h5s::H5Screate_simple(1, shape.as_ptr(), std::ptr::null());
+            let dset = h5d::H5Dcreate2(
+                file,
+                b"/named\0".as_ptr().cast(),
+                ty,
+                space,
+                h5p::H5P_DEFAULT,
+                h5p::H5P_DEFAULT,
+                h5p::H5P_DEFAULT,
+            );
+            let values = [1.0f64, -2.0, 0.5, 3.0];
+            let status = h5d::H5Dwrite(
+                dset,
+                ty,
+                h5s::H5S_ALL,
+                h5s::H5S_ALL,
+                h5p::H5P_DEFAULT,
+                values.as_ptr().cast(),
+            );
+            assert!(status >= 0);
+            h5d::H5Dclose(dset);
+            h5s::H5Sclose(space);
+            h5t::H5Tclose(ty);
+            h5f::H5Fclose(file);
+        }
+        let e = RawArray::<Complex<f64>>::from_hdf5("test.h5", "/named").unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        fs::remove_file("test.h5").unwrap();
+    }
+
+    #[test]
+    fn big_endian_append_patch() {
+        use super::*;
+        use std::fs;
+        let mut ra: RawArray<i32> = vec![1, 2, 3, 4].into();
+        ra.reshape(vec![2, 2]);
+        ra.set_checksum(true);
+        ra.to_big_endian().unwrap();
+        ra.write("test_be.ra").unwrap();
+        let bytes = fs::read("test_be.ra").unwrap();
+        assert_eq!(&bytes[64..68], &[0, 0, 0, 1]);
+
+        RawArray::append("test_be.ra", &[5i32, 6]).unwrap();
+        RawArray::patch_data_at("test_be.ra", 0, &[-1i32]).unwrap();
+        let back = RawArray::<i32>::read("test_be.ra").unwrap();
+        fs::remove_file("test_be.ra").expect("unable to remove file");
+        assert_eq!(back.data(), vec![-1, 2, 3, 4, 5, 6]);
+        assert_eq!(back.endianness(), Endianness::native());
+    }
+
+    #[test]
+    fn virtual_concat_first_axis() {
+        use super::*;
+        use std::fs;
+        let a = RawArray::from_iter_with_dims(0..6i32, &[2, 3]).unwrap();
+        let mut b = RawArray::from_iter_with_dims(10..19i32, &[3, 3]).unwrap();
+        b.to_big_endian().unwrap();
+        a.write("test_vc_a.ra").unwrap();
+        b.write("test_vc_b.ra").unwrap();
+        let mut vc = VirtualConcat::<i32>::open(&["test_vc_a.ra", "test_vc_b.ra"], 0).unwrap();
+        let all = vc.read_all().unwrap();
+        let middle = vc.read_slice(1, 2).unwrap();
+        assert!(vc.read_slice(4, 2).is_err());
+        fs::remove_file("test_vc_a.ra").expect("unable to remove file");
+        fs::remove_file("test_vc_b.ra").expect("unable to remove file");
+        assert_eq!(all.dims(), vec![5, 3]);
+        assert_eq!(
+            all.data(),
+            vec![0, 1, 10, 11, 12, 2, 3, 13, 14, 15, 4, 5, 16, 17, 18]
+        );
+        assert_eq!(middle.data(), vec![1, 10, 3, 13, 5, 16]);
+    }
+
+    /// Needs over 4 GB each of memory and disk, so it only runs with
+    /// `--features large-file-tests`.
+    #[cfg(feature = "large-file-tests")]
+    #[test]
+    fn larger_than_4_gb() {
+        use super::*;
+        use std::fs;
+        // just over 4 GB of u64s, so offsets past 2^32 bytes get exercised
+        let n: u64 = (1 << 29) + 1024;
+        let chunk: u64 = 1 << 20;
+        RawArray::from((0..chunk).collect::<Vec<u64>>())
+            .write("test_large.ra")
+            .unwrap();
+        let mut start = chunk;
+        while start < n {
+            let end = (start + chunk).min(n);
+            RawArray::append("test_large.ra", &(start..end).collect::<Vec<u64>>()).unwrap();
+            start = end;
+        }
+        let h = RawArrayHeader::read("test_large.ra").unwrap();
+        assert_eq!((h.size, h.dims.clone()), (8 * n, vec![n]));
+        assert_eq!(
+            fs::metadata("test_large.ra").unwrap().len(),
+            h.data_offset() + 8 * n
+        );
+
+        // in-place paths, without loading the data
+        assert_eq!(
+            RawArray::<u64>::search_sorted_file("test_large.ra", &(n - 5)).unwrap(),
+            (n - 5) as usize
+        );
+        RawArrayFileMut::<u64>::open("test_large.ra")
+            .unwrap()
+            .set(n - 1, 42)
+            .unwrap();
+        let mut vc = VirtualConcat::<u64>::open(&["test_large.ra"], 0).unwrap();
+        assert_eq!(vc.read_slice(n - 2, 2).unwrap().data(), vec![n - 2, 42]);
+        drop(vc);
+
+        let back = RawArray::<u64>::read("test_large.ra").unwrap();
+        fs::remove_file("test_large.ra").expect("unable to remove file");
+        assert_eq!(back.dims(), vec![n]);
+        assert_eq!(back[[(1 << 29) + 7]], (1 << 29) + 7);
+        assert_eq!(back.slice_axis(0, n - 3..n).data(), vec![n - 3, n - 2, 42]);
+    }
+
+    #[test]
+    fn elementwise_ops() {
+        use super::*;
+        let a = RawArray::from_iter_with_dims((0..6).map(|i| i as f32), &[2, 3]).unwrap();
+        let b = RawArray::from_iter_with_dims((0..6).map(|i| (i * i) as f32), &[2, 3]).unwrap();
+        let diff = &b - &a;
+        assert_eq!(diff.dims(), vec![2, 3]);
+        assert_eq!(diff.data(), vec![0.0, 0.0, 2.0, 6.0, 12.0, 20.0]);
+        let scaled = diff * 0.5;
+        assert_eq!(scaled.data(), vec![0.0, 0.0, 1.0, 3.0, 6.0, 10.0]);
+        let mut c = &a + 1.0;
+        c /= &(&a + 1.0);
+        assert!(c.data().iter().all(|&x| x == 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't combine arrays")]
+    fn elementwise_ops_check_shape() {
+        use super::*;
+        let a: RawArray<i32> = vec![1, 2, 3].into();
+        let b: RawArray<i32> = vec![1, 2].into();
+        let _ = a + b;
+    }
+
+    #[test]
+    fn checksum() {
+        use super::*;
+        use std::fs;
+        let mut ra: RawArray<f64> = vec![1.0, 2.0, 3.0, 4.0].into();
+        ra.set_checksum(true);
+        ra.write("test_crc.ra").unwrap();
+        assert_eq!(RawArray::<f64>::read("test_crc.ra").unwrap(), ra);
+
+        // flip a bit in the data section
+        let mut bytes = fs::read("test_crc.ra").unwrap();
+        bytes[60] ^= 0x10;
+        fs::write("test_crc.ra", &bytes).unwrap();
+        let err = RawArray::<f64>::read("test_crc.ra").unwrap_err();
+        fs::remove_file("test_crc.ra").expect("unable to remove file");
+
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        match RawArrayError::from_io(&err) {
+            Some(RawArrayError::ChecksumMismatch { .. }) => {}
+            other => panic!("expected checksum mismatch, got {:?}", other),
+        }
+    }
+    #[test]
+    fn narrow_rolls_back_on_bad_checksum() {
+        use super::*;
+        use std::fs;
+        // spans several chunks, some of which narrow exactly and some not
+        let data: Vec<f64> = (0..3_000_000)
+            .map(|i| {
+                if i / 1_000_000 == 1 {
+                    i as f64 + 0.1
+                } else {
+                    i as f64
+                }
+            })
+            .collect();
+        let mut ra: RawArray<f64> = data.into();
+        ra.set_checksum(true);
+        ra.set_attr("units", "s");
+        ra.write("test_narrow.ra").unwrap();
+        // corrupt the stored checksum, which is only found once all the
+        // data has been narrowed
+        let mut bytes = fs::read("test_narrow.ra").unwrap();
+        let crc_at = 56 + 8 * 3_000_000;
+        bytes[crc_at] ^= 1;
+        fs::write("test_narrow.ra", &bytes).unwrap();
+        let err = narrow_in_place("test_narrow.ra", DType::of::<f32>()).unwrap_err();
+        let after = fs::read("test_narrow.ra").unwrap();
+        let journal_left = Path::new("test_narrow.ra.journal").exists();
+        fs::remove_file("test_narrow.ra").expect("unable to remove file");
+
+        assert!(matches!(
+            RawArrayError::from_io(&err),
+            Some(RawArrayError::ChecksumMismatch { .. })
+        ));
+        assert!(after == bytes);
+        assert!(!journal_left);
+    }
+    #[cfg(feature = "mat")]
+    #[test]
+    fn mat_compressed_variable() {
+        use super::*;
+        use std::fs;
+        // as MATLAB saves small doubles: compressed, the name packed into a
+        // small element, and the values stored as uint8
+        fn push(out: &mut Vec<u8>, ty: u32, data: &[u8]) {
+            out.extend_from_slice(&ty.to_le_bytes());
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(data);
+            out.resize(out.len().div_ceil(8) * 8, 0);
+        }
+        let mut body = Vec::new();
+        push(&mut body, 6, &[6, 0, 0, 0, 0, 0, 0, 0]);
+        push(&mut body, 5, &[2, 0, 0, 0, 2, 0, 0, 0]);
+        body.extend_from_slice(&(1u32 | 3 << 16).to_le_bytes());
+        body.extend_from_slice(b"abc\0");
+        push(&mut body, 2, &[1, 2, 3, 250]);
+        let mut element = 14u32.to_le_bytes().to_vec();
+        element.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        element.extend_from_slice(&body);
+        let packed = miniz_oxide::deflate::compress_to_vec_zlib(&element, 6);
+
+        let mut file = vec![b' '; 116];
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&[0, 1]);
+        file.extend_from_slice(b"IM");
+        file.extend_from_slice(&15u32.to_le_bytes());
+        file.extend_from_slice(&(packed.len() as u32).to_le_bytes());
+        file.extend_from_slice(&packed);
+        fs::write("test_compressed.mat", &file).unwrap();
+        let ra = RawArray::<f64>::from_mat("test_compressed.mat", "abc");
+        fs::remove_file("test_compressed.mat").expect("unable to remove file");
+
+        let ra = ra.unwrap();
+        assert_eq!(ra.dims(), vec![2, 2]);
+        assert_eq!(ra.data(), vec![1.0, 2.0, 3.0, 250.0]);
+    }
     #[test]
     fn bf16() {
         use super::*;
@@ -605,4 +3182,50 @@ mod tests {
 
         assert_eq!(bvec, vec2);
     }
+    #[test]
+    fn zarr_store_from_elsewhere() {
+        use super::*;
+        use std::fs;
+        let dir = "test_zarr_v2_keys.zarr";
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            format!("{}/zarr.json", dir),
+            r#"{"zarr_format": 3, "node_type": "array", "shape": [2, 3],
+                "data_type": "int16",
+                "chunk_grid": {"name": "regular", "configuration": {"chunk_shape": [2, 2]}},
+                "chunk_key_encoding": {"name": "v2"},
+                "fill_value": 7,
+                "codecs": [{"name": "bytes", "configuration": {"endian": "big"}}]}"#,
+        )
+        .unwrap();
+        // only the first chunk, [[1, 2], [3, 4]]; the second is all fill
+        fs::write(format!("{}/0.0", dir), [0, 1, 0, 2, 0, 3, 0, 4]).unwrap();
+        let ra = RawArray::<i16>::from_zarr(dir);
+        fs::remove_dir_all(dir).unwrap();
+
+        let ra = ra.unwrap();
+        assert_eq!(ra.dims(), vec![3, 2]);
+        assert_eq!(ra.data(), vec![1, 2, 7, 3, 4, 7]);
+    }
+    #[test]
+    fn concat_into_an_input() {
+        use super::*;
+        use std::fs;
+        // too large to be held in a read buffer while the output is written
+        let a: RawArray<u32> = (0..25_000).collect();
+        let b: RawArray<u32> = (25_000..50_000).collect();
+        a.write("test_concat_a.ra").unwrap();
+        b.write("test_concat_b.ra").unwrap();
+        let result = concat_files(
+            &["test_concat_a.ra", "test_concat_b.ra"],
+            0,
+            "test_concat_a.ra",
+        );
+        let ab = RawArray::<u32>::read("test_concat_a.ra");
+        fs::remove_file("test_concat_a.ra").unwrap();
+        fs::remove_file("test_concat_b.ra").unwrap();
+
+        result.unwrap();
+        assert_eq!(ab.unwrap().data(), (0..50_000).collect::<Vec<u32>>());
+    }
 }
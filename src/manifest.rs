@@ -0,0 +1,335 @@
+//! Integrity manifests for directories of `.ra` files, and comparison of
+//! two directory trees.
+//!
+//! A manifest is a tab-separated text file in the dataset directory with
+//! one line per `.ra` file below it:
+//!
+//! ```text
+//! <sha256 of file>\t<dtype>\t<dims, comma separated>\t<path relative to dir>
+//! ```
+//!
+//! followed by a final `#sha256\t<digest>` line hashing all of the lines
+//! before it, so that edits to the manifest itself are caught too. Being
+//! plain text holding standard SHA-256 digests, it can still be checked by
+//! hand in the far future when this library is long gone, though its extra
+//! columns mean it can't be fed to `sha256sum -c` as it is.
+
+use crate::{dtype_name, RawArrayFile, RawArrayHeader};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+/// File name of the manifest written into a dataset directory
+pub const MANIFEST_NAME: &str = "MANIFEST.ra.tsv";
+
+/// Outcome of checking a directory against its manifest.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ManifestReport {
+    /// Files whose contents match the manifest
+    pub verified: Vec<PathBuf>,
+    /// Files whose contents have changed since the manifest was written
+    pub changed: Vec<PathBuf>,
+    /// Files listed in the manifest that no longer exist
+    pub missing: Vec<PathBuf>,
+    /// `.ra` files present that the manifest doesn't list
+    pub untracked: Vec<PathBuf>,
+}
+
+impl ManifestReport {
+    /// True if every listed file is present and unchanged. Untracked files
+    /// don't count against the dataset.
+    pub fn is_ok(&self) -> bool {
+        self.changed.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Recursively collect the `.ra` files below `dir`, sorted, as `ra ls`
+/// and the manifest functions do. Directories are followed into, files
+/// are matched by extension alone, and the paths returned start with
+/// `dir`.
+/// ```
+/// # use std::io;
+/// use rawarray::{find_ra_files, RawArray};
+/// use std::path::PathBuf;
+/// # fn main() -> io::Result<()> {
+/// std::fs::create_dir_all("find_doc/sub")?;
+/// let ra: RawArray<u8> = vec![1, 2, 3].into();
+/// ra.write("find_doc/sub/b.ra")?;
+/// ra.write("find_doc/a.ra")?;
+/// std::fs::write("find_doc/notes.txt", "not an array")?;
+/// assert_eq!(
+///     find_ra_files("find_doc")?,
+///     vec![PathBuf::from("find_doc/a.ra"), PathBuf::from("find_doc/sub/b.ra")]
+/// );
+/// # std::fs::remove_dir_all("find_doc")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn find_ra_files<P: AsRef<Path>>(dir: P) -> io::Result<Vec<PathBuf>> {
+    find_files(dir, &["ra"])
+}
+
+/// Recursively collect the files below `dir` whose extension is one of
+/// `extensions`, given without the dot, sorted. See [`find_ra_files`].
+/// ```
+/// # use std::io;
+/// use rawarray::{find_files, RawArray};
+/// use std::path::PathBuf;
+/// # fn main() -> io::Result<()> {
+/// std::fs::create_dir_all("find_files_doc")?;
+/// RawArray::<u8>::from(vec![1, 2]).write("find_files_doc/a.ra")?;
+/// std::fs::write("find_files_doc/b.npy", "")?;
+/// std::fs::write("find_files_doc/c.txt", "")?;
+/// assert_eq!(
+///     find_files("find_files_doc", &["ra", "npy"])?,
+///     vec![PathBuf::from("find_files_doc/a.ra"), PathBuf::from("find_files_doc/b.npy")]
+/// );
+/// # std::fs::remove_dir_all("find_files_doc")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn find_files<P: AsRef<Path>>(dir: P, extensions: &[&str]) -> io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.as_ref().to_path_buf()];
+    while let Some(d) = pending.pop() {
+        for entry in fs::read_dir(&d)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|e| extensions.iter().any(|x| e == *x))
+            {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+pub(crate) fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut r = BufReader::new(File::open(path)?);
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = r.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Path relative to `dir` with `/` separators, so manifests are portable.
+fn relative_name(dir: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(dir).unwrap_or(path);
+    let parts: Vec<_> = rel.iter().map(|p| p.to_string_lossy()).collect();
+    parts.join("/")
+}
+
+fn manifest_line(dir: &Path, path: &Path) -> io::Result<String> {
+    let mut r = RawArrayFile::valid_open(path)?;
+    let eltype = r.u64_at(16)?;
+    let elbyte = r.u64_at(24)?;
+    let ndims = r.u64_at(40)?;
+    let mut dims = Vec::new();
+    for i in 0..ndims {
+        dims.push(r.u64_at(48 + 8 * i)?.to_string());
+    }
+    Ok(format!(
+        "{}\t{}\t{}\t{}\n",
+        sha256_file(path)?,
+        dtype_name(eltype, elbyte),
+        dims.join(","),
+        relative_name(dir, path)
+    ))
+}
+
+/// Hash every `.ra` file below `dir` and record the results in
+/// `dir/MANIFEST.ra.tsv`, replacing any existing manifest.
+/// ```
+/// # use std::io;
+/// # fn main() -> io::Result<()> {
+/// use rawarray::RawArray;
+/// # std::fs::create_dir_all("manifest_doc")?;
+/// let ra: RawArray<f32> = vec![1.0, 2.0].into();
+/// ra.write("manifest_doc/a.ra")?;
+/// rawarray::write_manifest("manifest_doc")?;
+/// assert!(rawarray::verify_manifest("manifest_doc")?.is_ok());
+///
+/// RawArray::<f32>::from(vec![1.0, 2.5]).write("manifest_doc/a.ra")?;
+/// let report = rawarray::verify_manifest("manifest_doc")?;
+/// assert_eq!(report.changed.len(), 1);
+/// # std::fs::remove_dir_all("manifest_doc")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn write_manifest<P: AsRef<Path>>(dir: P) -> io::Result<()> {
+    let dir = dir.as_ref();
+    let mut body = String::new();
+    for path in find_ra_files(dir)? {
+        body.push_str(&manifest_line(dir, &path)?);
+    }
+    let digest = hex(&Sha256::digest(body.as_bytes()));
+    body.push_str(&format!("#sha256\t{}\n", digest));
+    fs::write(dir.join(MANIFEST_NAME), body)
+}
+
+/// Read the manifest in `dir`, checking its digest, and split each line
+/// into its hash, dtype, dims, and relative path.
+pub(crate) fn read_manifest(dir: &Path) -> io::Result<Vec<[String; 4]>> {
+    let text = fs::read_to_string(dir.join(MANIFEST_NAME))?;
+    let corrupt = |msg: &str| Error::new(ErrorKind::InvalidData, format!("manifest {}", msg));
+
+    let body_end = text
+        .rfind("#sha256\t")
+        .ok_or_else(|| corrupt("has no digest line"))?;
+    let (body, trailer) = text.split_at(body_end);
+    if trailer["#sha256\t".len()..].trim_end() != hex(&Sha256::digest(body.as_bytes())) {
+        return Err(corrupt("has been modified since it was written"));
+    }
+
+    let mut lines = Vec::new();
+    for line in body.lines() {
+        let fields: Vec<&str> = line.splitn(4, '\t').collect();
+        if fields.len() != 4 {
+            return Err(corrupt("has a malformed line"));
+        }
+        lines.push([
+            fields[0].to_string(),
+            fields[1].to_string(),
+            fields[2].to_string(),
+            fields[3].to_string(),
+        ]);
+    }
+    Ok(lines)
+}
+
+/// Check the `.ra` files below `dir` against the manifest written by
+/// `write_manifest`. Fails with `ErrorKind::InvalidData` if the manifest
+/// itself has been altered or is malformed.
+pub fn verify_manifest<P: AsRef<Path>>(dir: P) -> io::Result<ManifestReport> {
+    let dir = dir.as_ref();
+    let mut expected = BTreeMap::new();
+    for line in read_manifest(dir)? {
+        expected.insert(line[3].clone(), line.join("\t") + "\n");
+    }
+
+    let mut report = ManifestReport::default();
+    for path in find_ra_files(dir)? {
+        match expected.remove(&relative_name(dir, &path)) {
+            Some(line) if line == manifest_line(dir, &path)? => report.verified.push(path),
+            Some(_) => report.changed.push(path),
+            None => report.untracked.push(path),
+        }
+    }
+    report.missing = expected.keys().map(|name| dir.join(name)).collect();
+    Ok(report)
+}
+
+/// Outcome of comparing the `.ra` files in two directory trees. Paths are
+/// relative to the tree roots.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TreeComparison {
+    /// Files present in both trees with the same contents
+    pub identical: Vec<PathBuf>,
+    /// Files present in both trees whose headers or contents differ
+    pub differing: Vec<PathBuf>,
+    /// Files only in the first tree
+    pub only_in_a: Vec<PathBuf>,
+    /// Files only in the second tree
+    pub only_in_b: Vec<PathBuf>,
+    /// Files present in both trees that couldn't be read, with the error
+    pub unreadable: Vec<(PathBuf, String)>,
+}
+
+impl TreeComparison {
+    /// True if both trees hold the same files with the same contents.
+    pub fn is_same(&self) -> bool {
+        self.differing.is_empty()
+            && self.only_in_a.is_empty()
+            && self.only_in_b.is_empty()
+            && self.unreadable.is_empty()
+    }
+}
+
+/// Whether two files hold the same array. Headers are compared first, so
+/// files of different shape or type aren't hashed at all.
+fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    if RawArrayHeader::read(a)? != RawArrayHeader::read(b)? {
+        return Ok(false);
+    }
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    Ok(sha256_file(a)? == sha256_file(b)?)
+}
+
+/// Compare the `.ra` files below `dir_a` and `dir_b`, matching them by
+/// relative path, for checking that an archive survived a migration.
+/// A file that can't be read in either tree is listed in `unreadable`
+/// and the comparison carries on.
+/// ```
+/// # use std::io;
+/// # fn main() -> io::Result<()> {
+/// use rawarray::RawArray;
+/// use std::path::PathBuf;
+/// # std::fs::create_dir_all("tree_doc/a")?;
+/// # std::fs::create_dir_all("tree_doc/b")?;
+/// let ra: RawArray<u8> = vec![1, 2, 3].into();
+/// ra.write("tree_doc/a/x.ra")?;
+/// ra.write("tree_doc/b/x.ra")?;
+/// ra.write("tree_doc/a/y.ra")?;
+/// RawArray::<u8>::from(vec![1, 2]).write("tree_doc/b/y.ra")?;
+/// ra.write("tree_doc/b/z.ra")?;
+///
+/// let cmp = rawarray::compare_trees("tree_doc/a", "tree_doc/b")?;
+/// assert_eq!(cmp.identical, vec![PathBuf::from("x.ra")]);
+/// assert_eq!(cmp.differing, vec![PathBuf::from("y.ra")]);
+/// assert_eq!(cmp.only_in_b, vec![PathBuf::from("z.ra")]);
+/// assert!(!cmp.is_same());
+///
+/// std::fs::write("tree_doc/b/x.ra", b"not an array")?;
+/// let cmp = rawarray::compare_trees("tree_doc/a", "tree_doc/b")?;
+/// assert_eq!(cmp.unreadable[0].0, PathBuf::from("x.ra"));
+/// assert_eq!(cmp.differing, vec![PathBuf::from("y.ra")]);
+/// # std::fs::remove_dir_all("tree_doc")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn compare_trees<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir_a: P,
+    dir_b: Q,
+) -> io::Result<TreeComparison> {
+    let (dir_a, dir_b) = (dir_a.as_ref(), dir_b.as_ref());
+    let mut in_b: BTreeMap<String, PathBuf> = find_ra_files(dir_b)?
+        .into_iter()
+        .map(|p| (relative_name(dir_b, &p), p))
+        .collect();
+    let mut cmp = TreeComparison::default();
+    for a in find_ra_files(dir_a)? {
+        let name = relative_name(dir_a, &a);
+        let b = match in_b.remove(&name) {
+            Some(b) => b,
+            None => {
+                cmp.only_in_a.push(PathBuf::from(name));
+                continue;
+            }
+        };
+        match same_contents(&a, &b) {
+            Ok(true) => cmp.identical.push(PathBuf::from(name)),
+            Ok(false) => cmp.differing.push(PathBuf::from(name)),
+            Err(e) => cmp.unreadable.push((PathBuf::from(name), e.to_string())),
+        }
+    }
+    cmp.only_in_b = in_b.into_keys().map(PathBuf::from).collect();
+    Ok(cmp)
+}
@@ -0,0 +1,417 @@
+//! MATLAB's Level 5 MAT-file format, behind the `mat` feature, for moving
+//! numeric arrays between RawArray and MATLAB without MATLAB itself.
+//!
+//! Only numeric arrays, real or complex, are handled; cell arrays,
+//! structs, sparse matrices, and character arrays are not. Compressed
+//! variables, as MATLAB saves by default, are read. Files saved with
+//! `-v7.3` are HDF5 files and can't be read this way.
+//!
+//! MATLAB stores arrays column major, as RawArray does, so dimensions
+//! carry over unchanged.
+
+use crate::convert::{check_conversion, convert_bytes, elements_from_bytes_with_endianness};
+use crate::storage::{FsFile, OpenMode};
+use crate::{as_u8_slice, DType, Endianness, RawArray, RawArrayType};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+const MI_INT8: u32 = 1;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_MATRIX: u32 = 14;
+const MI_COMPRESSED: u32 = 15;
+
+/// Array flags bit marking a complex array
+const MX_COMPLEX: u32 = 0x0800;
+
+const HEADER_LEN: usize = 128;
+
+/// Longest variable name MATLAB allows
+const MAX_NAME_LEN: usize = 63;
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// Element type of MAT data type `mi`
+fn mi_dtype(mi: u32) -> Option<DType> {
+    let (eltype, elbyte) = match mi {
+        1 => (1, 1),
+        2 => (2, 1),
+        3 => (1, 2),
+        4 => (2, 2),
+        5 => (1, 4),
+        6 => (2, 4),
+        7 => (3, 4),
+        9 => (3, 8),
+        12 => (1, 8),
+        13 => (2, 8),
+        _ => return None,
+    };
+    Some(DType { eltype, elbyte })
+}
+
+/// MAT class and data type holding real elements of type `d`
+fn class_of(d: DType) -> Option<(u32, u32)> {
+    Some(match (d.eltype, d.elbyte) {
+        (3, 8) => (6, 9),
+        (3, 4) => (7, 7),
+        (1, 1) => (8, 1),
+        (2, 1) => (9, 2),
+        (1, 2) => (10, 3),
+        (2, 2) => (11, 4),
+        (1, 4) => (12, 5),
+        (2, 4) => (13, 6),
+        (1, 8) => (14, 12),
+        (2, 8) => (15, 13),
+        _ => return None,
+    })
+}
+
+/// Type of one part of a complex element, or `d` itself if it's real
+fn part_dtype(d: DType) -> DType {
+    match d.eltype {
+        4 => DType {
+            eltype: 3,
+            elbyte: d.elbyte / 2,
+        },
+        6 => DType {
+            eltype: 1,
+            elbyte: d.elbyte / 2,
+        },
+        _ => d,
+    }
+}
+
+/// Data elements of a MAT-file, in the file's byte order
+struct Elements<'a> {
+    buf: &'a [u8],
+    endianness: Endianness,
+}
+
+impl<'a> Elements<'a> {
+    fn u32_at(&self, at: usize) -> u32 {
+        let mut b = [0u8; 4];
+        b.copy_from_slice(&self.buf[at..at + 4]);
+        match self.endianness {
+            Endianness::Little => u32::from_le_bytes(b),
+            Endianness::Big => u32::from_be_bytes(b),
+        }
+    }
+
+    /// Type and contents of the next element, or `None` at the end
+    fn next(&mut self) -> io::Result<Option<(u32, &'a [u8])>> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        if self.buf.len() < 8 {
+            return Err(invalid("MAT-file ends inside a data element tag".into()));
+        }
+        let first = self.u32_at(0);
+        // small elements pack the length into the type word, with up to
+        // four bytes of data in what would be the length word
+        if first >> 16 != 0 {
+            let len = (first >> 16) as usize;
+            if len > 4 {
+                return Err(invalid(format!("small data element of {} bytes", len)));
+            }
+            let data = &self.buf[4..4 + len];
+            self.buf = &self.buf[8..];
+            return Ok(Some((first & 0xffff, data)));
+        }
+        let len = self.u32_at(4) as usize;
+        if self.buf.len() - 8 < len {
+            return Err(invalid("MAT-file ends inside a data element".into()));
+        }
+        let data = &self.buf[8..8 + len];
+        // everything but compressed data is padded to 8 bytes
+        let padded = if first == MI_COMPRESSED {
+            len
+        } else {
+            len.div_ceil(8) * 8
+        };
+        self.buf = &self.buf[(8 + padded).min(self.buf.len())..];
+        Ok(Some((first, data)))
+    }
+
+    /// The next element, which must be of type `mi`
+    fn expect(&mut self, mi: u32, what: &str) -> io::Result<&'a [u8]> {
+        match self.next()? {
+            Some((t, data)) if t == mi => Ok(data),
+            _ => Err(invalid(format!("matrix is missing its {}", what))),
+        }
+    }
+}
+
+/// A numeric MAT variable, with its parts still as stored
+struct Variable {
+    dims: Vec<u64>,
+    real: (DType, Vec<u8>),
+    imag: Option<(DType, Vec<u8>)>,
+}
+
+/// Parse the matrix `data` if it's named `name`.
+fn parse_matrix(data: &[u8], endianness: Endianness, name: &str) -> io::Result<Option<Variable>> {
+    let mut sub = Elements {
+        buf: data,
+        endianness,
+    };
+    let flags = sub.expect(MI_UINT32, "array flags")?;
+    if flags.len() < 8 {
+        return Err(invalid("array flags are too short".into()));
+    }
+    let dims = sub.expect(MI_INT32, "dimensions")?;
+    let this_name = sub.expect(MI_INT8, "name")?;
+    if this_name != name.as_bytes() {
+        return Ok(None);
+    }
+    let flags = Elements {
+        buf: flags,
+        endianness,
+    }
+    .u32_at(0);
+    let class = flags & 0xff;
+    if !(6..=15).contains(&class) {
+        return Err(invalid(format!(
+            "{} is of MATLAB class {}, not a numeric array",
+            name, class
+        )));
+    }
+    let ints = Elements {
+        buf: dims,
+        endianness,
+    };
+    let dims = (0..dims.len() / 4)
+        .map(|i| u64::try_from(ints.u32_at(4 * i) as i32).ok())
+        .collect::<Option<Vec<u64>>>()
+        .ok_or_else(|| invalid(format!("{} has a negative dimension", name)))?;
+    let nelem = dims
+        .iter()
+        .try_fold(1u64, |n, &d| n.checked_mul(d))
+        .ok_or_else(|| invalid(format!("{} has too many elements", name)))?;
+    let mut part = |what| -> io::Result<(DType, Vec<u8>)> {
+        match sub.next()? {
+            Some((mi, bytes)) => match mi_dtype(mi) {
+                Some(d) if Some(bytes.len() as u64) == d.elbyte.checked_mul(nelem) => {
+                    Ok((d, bytes.to_vec()))
+                }
+                Some(_) => Err(invalid(format!("{} part has the wrong length", what))),
+                None => Err(invalid(format!("{} part has unknown type {}", what, mi))),
+            },
+            None => Err(invalid(format!("matrix is missing its {} part", what))),
+        }
+    };
+    let real = part("real")?;
+    let imag = if flags & MX_COMPLEX != 0 {
+        Some(part("imaginary")?)
+    } else {
+        None
+    };
+    Ok(Some(Variable { dims, real, imag }))
+}
+
+/// Append a data element to `out`, padded to 8 bytes.
+fn push_element(out: &mut Vec<u8>, mi: u32, data: &[u8]) {
+    out.extend_from_slice(&mi.to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    out.resize(out.len().div_ceil(8) * 8, 0);
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Read the numeric variable `varname` from a MATLAB MAT-file (Level 5,
+    /// as written by `save` without `-v7.3`). Elements are converted to `T`
+    /// as by `convert`, so a `double` matrix can be read as `f32`, and a
+    /// complex one needs a complex `T`.
+    /// ```
+    /// # use std::io;
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let m = RawArray::from_iter_with_dims((0..6).map(f64::from), &[2, 3])?;
+    /// m.to_mat("m.mat", "m")?;
+    /// let back = RawArray::<f64>::from_mat("m.mat", "m")?;
+    /// assert_eq!(back, m);
+    /// assert!(RawArray::<f64>::from_mat("m.mat", "x").is_err());
+    /// // a negative dimension, as a corrupt file might hold
+    /// let mut bytes = std::fs::read("m.mat")?;
+    /// bytes[160..164].copy_from_slice(&(-1i32).to_le_bytes());
+    /// std::fs::write("m.mat", &bytes)?;
+    /// assert!(RawArray::<f64>::from_mat("m.mat", "m").is_err());
+    ///
+    /// let z: RawArray<Complex<f32>> = vec![Complex::new(1.0, -1.0)].into();
+    /// z.to_mat("m.mat", "z")?;
+    /// assert_eq!(RawArray::<Complex<f64>>::from_mat("m.mat", "z")?.data(), vec![Complex::new(1.0, -1.0)]);
+    /// assert_eq!(RawArray::<Complex<f64>>::from_mat("m.mat", "z")?.dims(), vec![1, 1]);
+    /// # std::fs::remove_file("m.mat")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_mat<P: AsRef<Path>>(path: P, varname: &str) -> io::Result<RawArray<T>> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < HEADER_LEN {
+            return Err(invalid("too short for a MAT-file".into()));
+        }
+        let endianness = match &bytes[126..128] {
+            b"IM" => Endianness::Little,
+            b"MI" => Endianness::Big,
+            _ => return Err(invalid("not a Level 5 MAT-file".into())),
+        };
+        let mut elements = Elements {
+            buf: &bytes[HEADER_LEN..],
+            endianness,
+        };
+        let mut found = None;
+        while let Some((mi, data)) = elements.next()? {
+            found = match mi {
+                MI_MATRIX => parse_matrix(data, endianness, varname)?,
+                MI_COMPRESSED => {
+                    let inflated = miniz_oxide::inflate::decompress_to_vec_zlib(data)
+                        .map_err(|e| invalid(format!("can't decompress variable: {}", e)))?;
+                    let mut inner = Elements {
+                        buf: &inflated,
+                        endianness,
+                    };
+                    match inner.next()? {
+                        Some((MI_MATRIX, data)) => parse_matrix(data, endianness, varname)?,
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            if found.is_some() {
+                break;
+            }
+        }
+        let var = found.ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("no variable named {}", varname),
+            )
+        })?;
+
+        let to = DType::of::<T>();
+        let part = part_dtype(to);
+        if var.imag.is_some() && to.components() < 2 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is complex, but {} is not", varname, to),
+            ));
+        }
+        let convert = |(from, bytes): &(DType, Vec<u8>)| -> io::Result<Vec<u8>> {
+            check_conversion(*from, part)?;
+            let mut out =
+                Vec::with_capacity(bytes.len() / from.elbyte as usize * part.elbyte as usize);
+            convert_bytes(bytes, *from, endianness, &mut out, part, Endianness::Little)?;
+            Ok(out)
+        };
+        let real = convert(&var.real)?;
+        let le = if to.components() < 2 {
+            real
+        } else {
+            let imag = match &var.imag {
+                Some(imag) => convert(imag)?,
+                None => vec![0u8; real.len()],
+            };
+            let w = part.elbyte as usize;
+            real.chunks(w)
+                .zip(imag.chunks(w))
+                .flat_map(|(re, im)| re.iter().chain(im))
+                .copied()
+                .collect()
+        };
+        let data = elements_from_bytes_with_endianness(&le, Endianness::Little)?;
+        Ok(RawArray::from_parts(var.dims, data))
+    }
+
+    /// Write the array to a MATLAB MAT-file (Level 5) as the only variable,
+    /// named `varname`, replacing any file at `path`. Arrays of real or
+    /// complex integers or of `f32` or `f64` can be written; one-dimensional
+    /// arrays become column vectors. Attributes aren't written.
+    pub fn to_mat<P: AsRef<Path>>(&self, path: P, varname: &str) -> io::Result<()> {
+        let bad_input = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+        let mut chars = varname.chars();
+        let valid_name = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+            && varname.len() <= MAX_NAME_LEN;
+        if !valid_name {
+            return Err(bad_input(format!(
+                "{:?} isn't a valid MATLAB variable name",
+                varname
+            )));
+        }
+        let dtype = DType::of::<T>();
+        let part = part_dtype(dtype);
+        let (class, mi) = class_of(part)
+            .ok_or_else(|| bad_input(format!("MAT-files can't hold elements of type {}", dtype)))?;
+        let mut le = Vec::with_capacity(self.data.len() * dtype.elbyte as usize);
+        convert_bytes(
+            as_u8_slice(&self.data),
+            dtype,
+            self.endianness(),
+            &mut le,
+            dtype,
+            Endianness::Little,
+        )?;
+
+        let mut dims = self.dims.clone();
+        if dims.len() == 1 {
+            dims.push(1);
+        }
+        let mut dim_bytes = Vec::with_capacity(4 * dims.len());
+        for &d in &dims {
+            if d > i32::MAX as u64 {
+                return Err(bad_input(format!(
+                    "dimension {} is too large for MATLAB",
+                    d
+                )));
+            }
+            dim_bytes.extend_from_slice(&(d as i32).to_le_bytes());
+        }
+        let complex = dtype.components() == 2;
+        let mut flags = [0u8; 8];
+        flags[..4].copy_from_slice(&(class | if complex { MX_COMPLEX } else { 0 }).to_le_bytes());
+
+        let mut body = Vec::new();
+        push_element(&mut body, MI_UINT32, &flags);
+        push_element(&mut body, MI_INT32, &dim_bytes);
+        push_element(&mut body, MI_INT8, varname.as_bytes());
+        if complex {
+            let w = part.elbyte as usize;
+            let (mut real, mut imag) = (Vec::new(), Vec::new());
+            for x in le.chunks(2 * w) {
+                real.extend_from_slice(&x[..w]);
+                imag.extend_from_slice(&x[w..]);
+            }
+            push_element(&mut body, mi, &real);
+            push_element(&mut body, mi, &imag);
+        } else {
+            push_element(&mut body, mi, &le);
+        }
+        if body.len() > u32::MAX as usize {
+            return Err(bad_input(
+                "array is too large for a Level 5 MAT-file".into(),
+            ));
+        }
+
+        let mut header = format!(
+            "MATLAB 5.0 MAT-file, Platform: rawarray, Created by: rawarray {}",
+            env!("CARGO_PKG_VERSION")
+        )
+        .into_bytes();
+        header.resize(116, b' ');
+        header.extend_from_slice(&[0u8; 8]);
+        header.extend_from_slice(&0x0100u16.to_le_bytes());
+        header.extend_from_slice(b"IM");
+
+        let mut w = BufWriter::new(FsFile::open_fs(path.as_ref(), OpenMode::Create)?);
+        w.write_all(&header)?;
+        w.write_all(&MI_MATRIX.to_le_bytes())?;
+        w.write_all(&(body.len() as u32).to_le_bytes())?;
+        w.write_all(&body)?;
+        w.flush()?;
+        w.get_mut().finish(false)
+    }
+}
@@ -0,0 +1,531 @@
+//! Narrowing the element type of a file in place, such as `f64` to `f32`,
+//! without room for a second copy of the data.
+//!
+//! The narrowed data is written over the start of the old data section a
+//! chunk at a time, followed by the checksum and attributes, and the file
+//! is then cut short. Before anything is overwritten, the bytes it replaces
+//! go into a journal next to the file (its path with `.journal` added), so
+//! an interrupted run can be undone with `recover_narrow`.
+//!
+//! Bytes whose elements survive the trip to the new type and back aren't
+//! journaled, since they can be rebuilt from the narrowed data. So when the
+//! values fit the new type exactly, the journal stays small; when they
+//! don't, it grows to at most the size of the narrowed data.
+//!
+//! The journal starts with the old element types, file length, and header,
+//! then has one record per overwritten region, each a tag followed by its
+//! fields, all little-endian `u64`s:
+//!
+//! | Tag | Fields                               | Meaning                    |
+//! |-----|--------------------------------------|----------------------------|
+//! | 1   | offset, length, then the saved bytes | region saved as is         |
+//! | 2   | offset, length                       | region rebuilt on rollback |
+//! | 3   | new file length                      | new header is in place     |
+//! | 4   |                                      | one region rolled back     |
+//!
+//! Offsets count from the start of the data section.
+
+use crate::codec::CODEC_FLAGS;
+use crate::convert::{check_conversion, convert_bytes, DType, Endianness};
+use crate::header::RawArrayHeader;
+use crate::{to_usize, RawArrayError, FLAG_BIG_ENDIAN, FLAG_BITS, FLAG_CRC};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Elements narrowed per step
+const CHUNK_ELEMS: u64 = 1 << 20;
+
+/// First word of a journal: "RANARROW" in ASCII
+const JOURNAL_MAGIC: u64 = 0x574f_5252_414e_4152;
+
+const REC_SAVED: u64 = 1;
+const REC_REBUILD: u64 = 2;
+const REC_COMMIT: u64 = 3;
+const REC_RESTORED: u64 = 4;
+
+fn journal_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".journal");
+    PathBuf::from(name)
+}
+
+fn read_at(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut buf = vec![0u8; to_usize(len)?];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_at(file: &mut File, offset: u64, bytes: &[u8]) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(bytes)
+}
+
+/// Journal being written, each record synced before the change it covers
+struct Journal {
+    file: File,
+}
+
+impl Journal {
+    fn append(&mut self, words: &[u64], bytes: &[u8]) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(8 * words.len() + bytes.len());
+        for w in words {
+            buf.extend_from_slice(&w.to_le_bytes());
+        }
+        buf.extend_from_slice(bytes);
+        self.file.write_all(&buf)?;
+        self.file.sync_data()
+    }
+}
+
+/// What narrowing does to a file, and what undoing it needs
+struct Narrowing {
+    from: DType,
+    to: DType,
+    endianness: Endianness,
+    /// Offset of the data section
+    base: u64,
+}
+
+impl Narrowing {
+    /// Old bytes of elements `a..b`, rebuilt from their narrowed values
+    fn rebuild(&self, file: &mut File, a: u64, b: u64) -> io::Result<Vec<u8>> {
+        let narrowed = read_at(
+            file,
+            self.base + a * self.to.elbyte,
+            (b - a) * self.to.elbyte,
+        )?;
+        let mut old = Vec::with_capacity(narrowed.len());
+        convert_bytes(
+            &narrowed,
+            self.to,
+            self.endianness,
+            &mut old,
+            self.from,
+            self.endianness,
+        )?;
+        Ok(old)
+    }
+
+    /// Journal the `len` bytes at `offset`, of which the first `in_data`
+    /// are old data, before they're overwritten. They're only saved if
+    /// rebuilding them later wouldn't give them back exactly or would read
+    /// the region itself.
+    fn save(
+        &self,
+        file: &mut File,
+        journal: &mut Journal,
+        offset: u64,
+        len: u64,
+        in_data: u64,
+    ) -> io::Result<()> {
+        let old = read_at(file, self.base + offset, len)?;
+        let fe = self.from.elbyte;
+        let b = (offset + len) / fe;
+        let rebuildable = len == in_data
+            && offset % fe == 0
+            && len % fe == 0
+            && b * self.to.elbyte <= offset
+            && {
+                let mut narrowed = Vec::with_capacity(old.len());
+                convert_bytes(
+                    &old,
+                    self.from,
+                    self.endianness,
+                    &mut narrowed,
+                    self.to,
+                    self.endianness,
+                )?;
+                let mut back = Vec::with_capacity(old.len());
+                convert_bytes(
+                    &narrowed,
+                    self.to,
+                    self.endianness,
+                    &mut back,
+                    self.from,
+                    self.endianness,
+                )?;
+                back == old
+            };
+        if rebuildable {
+            journal.append(&[REC_REBUILD, offset, len], &[])
+        } else {
+            journal.append(&[REC_SAVED, offset, len], &old)
+        }
+    }
+}
+
+/// Convert the array in `path` to the narrower element type `to`, such as
+/// `f64` to `f32` or `i32` to `i16`, rewriting the file in place. Elements
+/// are converted as by `convert`, and the byte order, checksum setting,
+/// and attributes are kept.
+///
+/// Unlike `recode_file`, this doesn't need room for a second copy of the
+/// file, only for its journal; see the module docs. If it fails, including
+/// on a checksum mismatch in the old data, the file is put back as it was.
+/// If the process dies part way, `recover_narrow` does the same.
+///
+/// Encoded and single-bit arrays can't be narrowed in place.
+/// ```
+/// # use std::io;
+/// use rawarray::{DType, RawArray};
+/// # fn main() -> io::Result<()> {
+/// let mut ra: RawArray<f64> = vec![1.5, -2.0, 1e10].into();
+/// ra.set_attr("units", "mm");
+/// ra.set_checksum(true);
+/// ra.write("wide.ra")?;
+/// let before = std::fs::metadata("wide.ra")?.len();
+/// rawarray::narrow_in_place("wide.ra", DType::of::<f32>())?;
+/// let narrow = RawArray::<f32>::read("wide.ra")?;
+/// assert_eq!(narrow.data(), vec![1.5, -2.0, 1e10]);
+/// assert_eq!(narrow.get_attr("units").and_then(|v| v.as_str()), Some("mm"));
+/// assert_eq!(std::fs::metadata("wide.ra")?.len(), before - 12);
+/// assert!(!std::path::Path::new("wide.ra.journal").exists());
+/// # std::fs::remove_file("wide.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn narrow_in_place<P: AsRef<Path>>(path: P, to: DType) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let h = RawArrayHeader::read_from(&mut BufReader::new(&file))?;
+    let from = DType {
+        eltype: h.eltype,
+        elbyte: h.elbyte,
+    };
+    if h.flags & (CODEC_FLAGS | FLAG_BITS) != 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "encoded and single-bit arrays can't be narrowed in place",
+        ));
+    }
+    check_conversion(from, to)?;
+    if to.elbyte >= from.elbyte {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is no narrower than {}", to, from),
+        ));
+    }
+    let old_len = file.metadata()?.len();
+    let mut header = vec![0u8; h.len()];
+    h.write_to_buf(&mut header);
+
+    let jpath = journal_path(path);
+    let jfile = OpenOptions::new()
+        .append(true)
+        .create_new(true)
+        .open(&jpath)
+        .map_err(|e| {
+            if e.kind() == ErrorKind::AlreadyExists {
+                Error::new(
+                    e.kind(),
+                    format!(
+                        "{} exists; run recover_narrow to undo the interrupted run",
+                        jpath.display()
+                    ),
+                )
+            } else {
+                e
+            }
+        })?;
+    let mut journal = Journal { file: jfile };
+    let words = [
+        JOURNAL_MAGIC,
+        from.eltype,
+        from.elbyte,
+        to.eltype,
+        to.elbyte,
+        old_len,
+        header.len() as u64,
+    ];
+    if let Err(e) = journal.append(&words, &header) {
+        drop(journal);
+        fs::remove_file(&jpath).ok();
+        return Err(e);
+    }
+    let result = narrow(&mut file, &mut journal, &h, to, old_len);
+    drop(journal);
+    drop(file);
+    match result {
+        Ok(()) => fs::remove_file(&jpath),
+        Err(e) => {
+            // leave the journal for recover_narrow if this fails too
+            recover_narrow(path).ok();
+            Err(e)
+        }
+    }
+}
+
+fn narrow(
+    file: &mut File,
+    journal: &mut Journal,
+    h: &RawArrayHeader,
+    to: DType,
+    old_len: u64,
+) -> io::Result<()> {
+    let endianness = if h.flags & FLAG_BIG_ENDIAN != 0 {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    };
+    let n = Narrowing {
+        from: DType {
+            eltype: h.eltype,
+            elbyte: h.elbyte,
+        },
+        to,
+        endianness,
+        base: h.data_offset(),
+    };
+    let (fe, te) = (n.from.elbyte, to.elbyte);
+    let nelem = h.size / fe;
+    let old_end = n.base + h.size;
+    // the checksum and attributes follow the data to its new end
+    let mut trailer = read_at(file, old_end, old_len.saturating_sub(old_end))?;
+
+    let mut check = crc32fast::Hasher::new();
+    let mut hasher = crc32fast::Hasher::new();
+    let mut narrowed = Vec::new();
+    let mut start = 0;
+    while start < nelem {
+        let count = CHUNK_ELEMS.min(nelem - start);
+        let chunk = read_at(file, n.base + start * fe, count * fe)?;
+        check.update(&chunk);
+        narrowed.clear();
+        convert_bytes(&chunk, n.from, endianness, &mut narrowed, to, endianness)?;
+        hasher.update(&narrowed);
+        n.save(file, journal, start * te, count * te, count * te)?;
+        write_at(file, n.base + start * te, &narrowed)?;
+        file.sync_data()?;
+        start += count;
+    }
+
+    if h.flags & FLAG_CRC != 0 {
+        if trailer.len() < 8 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "checksum missing"));
+        }
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&trailer[..8]);
+        let stored = u64::from_le_bytes(word);
+        let computed = u64::from(check.finalize());
+        if stored != computed {
+            return Err(RawArrayError::ChecksumMismatch { stored, computed }.into());
+        }
+        trailer[..8].copy_from_slice(&u64::from(hasher.finalize()).to_le_bytes());
+    }
+    let new_size = nelem * te;
+    let tlen = trailer.len() as u64;
+    let in_data = tlen.min(h.size - new_size);
+    n.save(file, journal, new_size, tlen, in_data)?;
+    write_at(file, n.base + new_size, &trailer)?;
+    let new_header = RawArrayHeader {
+        eltype: to.eltype,
+        elbyte: to.elbyte,
+        size: new_size,
+        ..h.clone()
+    };
+    let mut header = vec![0u8; new_header.len()];
+    new_header.write_to_buf(&mut header);
+    write_at(file, 0, &header)?;
+    file.sync_data()?;
+
+    let new_len = n.base + new_size + tlen;
+    journal.append(&[REC_COMMIT, new_len], &[])?;
+    file.set_len(new_len)?;
+    file.sync_all()
+}
+
+/// A region overwritten during narrowing
+struct Region {
+    offset: u64,
+    len: u64,
+    /// Where its old bytes are in the journal, if they were saved
+    saved_at: Option<u64>,
+}
+
+/// Everything a journal says, up to its last complete record
+struct Recovery {
+    narrowing: Narrowing,
+    old_len: u64,
+    header: Vec<u8>,
+    regions: Vec<Region>,
+    restored: usize,
+    committed: Option<u64>,
+    /// Length of the journal up to its last complete record
+    valid_len: u64,
+}
+
+/// Read the next word, or `None` at the end of the journal, even in the
+/// middle of a word
+fn next_word<R: Read>(r: &mut R, pos: &mut u64) -> io::Result<Option<u64>> {
+    let mut buf = [0u8; 8];
+    match r.read_exact(&mut buf) {
+        Ok(()) => {
+            *pos += 8;
+            Ok(Some(u64::from_le_bytes(buf)))
+        }
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Parse a journal, or return `None` if it ends before the file was
+/// touched.
+fn parse_journal(file: &File) -> io::Result<Option<Recovery>> {
+    let journal_len = file.metadata()?.len();
+    let mut r = BufReader::new(file);
+    let mut pos = 0;
+    let mut words = [0u64; 7];
+    for w in words.iter_mut() {
+        match next_word(&mut r, &mut pos)? {
+            Some(x) => *w = x,
+            None => return Ok(None),
+        }
+    }
+    let [magic, from_eltype, from_elbyte, to_eltype, to_elbyte, old_len, header_len] = words;
+    if magic != JOURNAL_MAGIC {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "not a narrow_in_place journal",
+        ));
+    }
+    if pos + header_len > journal_len {
+        return Ok(None);
+    }
+    let mut header = vec![0u8; to_usize(header_len)?];
+    r.read_exact(&mut header)?;
+    pos += header_len;
+    let h = RawArrayHeader::parse_from_buf(&header)?;
+    let endianness = if h.flags & FLAG_BIG_ENDIAN != 0 {
+        Endianness::Big
+    } else {
+        Endianness::Little
+    };
+
+    let mut rec = Recovery {
+        narrowing: Narrowing {
+            from: DType {
+                eltype: from_eltype,
+                elbyte: from_elbyte,
+            },
+            to: DType {
+                eltype: to_eltype,
+                elbyte: to_elbyte,
+            },
+            endianness,
+            base: header_len,
+        },
+        old_len,
+        header,
+        regions: Vec::new(),
+        restored: 0,
+        committed: None,
+        valid_len: pos,
+    };
+    while let Some(tag) = next_word(&mut r, &mut pos)? {
+        match tag {
+            REC_SAVED | REC_REBUILD => {
+                let (offset, len) =
+                    match (next_word(&mut r, &mut pos)?, next_word(&mut r, &mut pos)?) {
+                        (Some(o), Some(l)) => (o, l),
+                        _ => break,
+                    };
+                let mut saved_at = None;
+                if tag == REC_SAVED {
+                    if pos + len > journal_len {
+                        break;
+                    }
+                    saved_at = Some(pos);
+                    r.seek_relative(len as i64)?;
+                    pos += len;
+                }
+                rec.regions.push(Region {
+                    offset,
+                    len,
+                    saved_at,
+                });
+            }
+            REC_COMMIT => match next_word(&mut r, &mut pos)? {
+                Some(len) => rec.committed = Some(len),
+                None => break,
+            },
+            REC_RESTORED => rec.restored += 1,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown journal record {}", tag),
+                ))
+            }
+        }
+        rec.valid_len = pos;
+    }
+    Ok(Some(rec))
+}
+
+/// Finish with the journal left by an interrupted `narrow_in_place` on
+/// `path`. If the new header wasn't in place yet, the file is rolled back
+/// to exactly what it was before; otherwise the narrowing is finished.
+/// Either way the journal is removed. Returns `false` if there was no
+/// journal.
+///
+/// Running this again after it's interrupted itself is safe.
+/// ```
+/// # use std::io;
+/// # fn main() -> io::Result<()> {
+/// assert!(!rawarray::recover_narrow("never_narrowed.ra")?);
+/// # Ok(())
+/// # }
+/// ```
+pub fn recover_narrow<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let path = path.as_ref();
+    let jpath = journal_path(path);
+    let jfile = match OpenOptions::new().read(true).append(true).open(&jpath) {
+        Ok(f) => f,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    let rec = match parse_journal(&jfile)? {
+        Some(rec) => rec,
+        None => {
+            // the journal never got its header, so the file wasn't touched
+            drop(jfile);
+            fs::remove_file(&jpath)?;
+            return Ok(true);
+        }
+    };
+    // drop any half-written record, so new ones follow the last good one
+    jfile.set_len(rec.valid_len)?;
+    let mut journal = Journal { file: jfile };
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+    if let Some(new_len) = rec.committed {
+        file.set_len(new_len)?;
+        file.sync_all()?;
+    } else {
+        let n = &rec.narrowing;
+        let mut jr = File::open(&jpath)?;
+        let (fe, te) = (n.from.elbyte, n.to.elbyte);
+        // newest first, since older regions hold what newer ones rebuild from
+        for region in rec.regions.iter().rev().skip(rec.restored) {
+            let old = match region.saved_at {
+                Some(at) => read_at(&mut jr, at, region.len)?,
+                None => {
+                    let (a, b) = (region.offset / fe, (region.offset + region.len) / fe);
+                    debug_assert!(b * te <= region.offset);
+                    n.rebuild(&mut file, a, b)?
+                }
+            };
+            write_at(&mut file, n.base + region.offset, &old)?;
+            file.sync_data()?;
+            journal.append(&[REC_RESTORED], &[])?;
+        }
+        write_at(&mut file, 0, &rec.header)?;
+        file.set_len(rec.old_len)?;
+        file.sync_all()?;
+    }
+    drop(journal);
+    fs::remove_file(&jpath)?;
+    Ok(true)
+}
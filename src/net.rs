@@ -0,0 +1,76 @@
+//! Streaming arrays between processes over TCP or Unix sockets, in the
+//! same format as on disk, so a scanner can send each frame it acquires
+//! straight to a reconstruction service.
+//!
+//! A header says how long its record is, so there is no framing beyond
+//! the records themselves: the sender writes arrays back to back, and the
+//! receiver reads them until the sender closes the connection. See
+//! `examples/array_server.rs` and `examples/array_client.rs`.
+//! ```
+//! # use std::io;
+//! use rawarray::{net, RawArray};
+//! use std::net::{TcpListener, TcpStream};
+//! use std::thread;
+//! # fn main() -> io::Result<()> {
+//! let listener = TcpListener::bind("127.0.0.1:0")?;
+//! let addr = listener.local_addr()?;
+//! let scanner = thread::spawn(move || -> io::Result<()> {
+//!     let mut socket = TcpStream::connect(addr)?;
+//!     for frame in 0..3u16 {
+//!         net::send_array(&mut socket, &RawArray::from(vec![frame; 4]))?;
+//!     }
+//!     Ok(())
+//! });
+//! let (mut socket, _) = listener.accept()?;
+//! let mut frames = Vec::new();
+//! while let Some(frame) = net::recv_array::<u16, _>(&mut socket)? {
+//!     frames.push(frame.data()[0]);
+//! }
+//! scanner.join().unwrap()?;
+//! assert_eq!(frames, vec![0, 1, 2]);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{RawArray, RawArrayStream, RawArrayType, ReadOptions};
+use std::io::{self, BufWriter, Read, Write};
+
+/// Send `ra` over `w`, header, data, and all, and flush it, so the
+/// receiver gets the whole array without waiting for the next one.
+pub fn send_array<T: RawArrayType, W: Write>(w: &mut W, ra: &RawArray<T>) -> io::Result<()> {
+    // a socket would otherwise see one small write per header field
+    let mut w = BufWriter::new(w);
+    ra.write_to(&mut w)
+}
+
+/// Receive the next array sent with `send_array`, or `None` if the sender
+/// closed the connection between arrays. An array of some other element
+/// type is an error, and so is one larger than `DEFAULT_MAX_BYTES`; use
+/// `recv_array_with` to set a tighter limit for peers that can't be
+/// trusted.
+pub fn recv_array<T: RawArrayType, R: Read>(r: &mut R) -> io::Result<Option<RawArray<T>>> {
+    recv_array_with(r, &ReadOptions::new())
+}
+
+/// Receive the next array as `recv_array` does, refusing any larger than
+/// the limit in `opts` as soon as its header arrives. Memory for the data
+/// is allocated as it's received, so a peer can't make the receiver
+/// allocate much more than it actually sends.
+/// ```
+/// # use std::io;
+/// use rawarray::{net, RawArray, ReadOptions};
+/// # fn main() -> io::Result<()> {
+/// let mut wire = Vec::new();
+/// net::send_array(&mut wire, &RawArray::from(vec![0u16; 1 << 20]))?;
+/// let opts = ReadOptions::new().max_bytes(1 << 16);
+/// assert!(net::recv_array_with::<u16, _>(&mut wire.as_slice(), &opts).is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub fn recv_array_with<T: RawArrayType, R: Read>(
+    r: &mut R,
+    opts: &ReadOptions,
+) -> io::Result<Option<RawArray<T>>> {
+    // the stream reads no further than the end of the array
+    RawArrayStream::with_options(r, opts).next()
+}
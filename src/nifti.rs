@@ -0,0 +1,429 @@
+//! NIfTI-1 (`.nii` and `.nii.gz`) images, behind the `nifti` feature, for
+//! neuroimaging data.
+//!
+//! NIfTI stores voxels with the first dimension varying fastest, as
+//! RawArray does, so dimensions carry over unchanged. The 348-byte NIfTI
+//! header is kept in the `nifti.header` attribute, so voxel sizes,
+//! orientation, and the rest survive a trip through a RawArray file and
+//! back. Scaling by `scl_slope` and `scl_inter` isn't applied; the stored
+//! values are read as they are.
+//!
+//! Only single-file images (`n+1`) are handled, not `.hdr`/`.img` pairs,
+//! and header extensions are dropped.
+
+use crate::attrs::{write_attrs, AttrValue};
+use crate::convert::{check_conversion, convert_bytes, elements_from_bytes_with_endianness};
+use crate::header::RawArrayHeader;
+use crate::recode::{open_source, write_or_remove};
+use crate::{as_u8_slice, to_usize, DType, Endianness, RawArray, RawArrayType};
+use crate::{FLAG_ATTRS, FLAG_BIG_ENDIAN};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+/// Attribute holding the NIfTI header
+const HEADER_ATTR: &str = "nifti.header";
+
+const HEADER_LEN: usize = 348;
+/// Header, then four bytes saying there are no extensions
+const VOX_OFFSET: usize = HEADER_LEN + 4;
+const MAX_DIMS: usize = 7;
+
+// offsets of the header fields that are read or changed
+const DIM: usize = 40;
+const DATATYPE: usize = 70;
+const BITPIX: usize = 72;
+const PIXDIM: usize = 76;
+const VOX_OFFSET_FIELD: usize = 108;
+const MAGIC: usize = 344;
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// NIfTI datatype codes and the RawArray element types, as type code and
+/// width, of the types both have
+const DATATYPES: &[(i16, u64, u64)] = &[
+    (2, 2, 1),
+    (4, 1, 2),
+    (8, 1, 4),
+    (16, 3, 4),
+    (32, 4, 8),
+    (64, 3, 8),
+    (256, 1, 1),
+    (512, 2, 2),
+    (768, 2, 4),
+    (1024, 1, 8),
+    (1280, 2, 8),
+    (1792, 4, 16),
+];
+
+fn dtype_of_code(code: i16) -> Option<DType> {
+    DATATYPES
+        .iter()
+        .find(|t| t.0 == code)
+        .map(|&(_, eltype, elbyte)| DType { eltype, elbyte })
+}
+
+fn code_of_dtype(d: DType) -> Option<i16> {
+    DATATYPES
+        .iter()
+        .find(|t| (t.1, t.2) == (d.eltype, d.elbyte))
+        .map(|t| t.0)
+}
+
+fn get_i16(b: &[u8], at: usize, e: Endianness) -> i16 {
+    let v = [b[at], b[at + 1]];
+    match e {
+        Endianness::Little => i16::from_le_bytes(v),
+        Endianness::Big => i16::from_be_bytes(v),
+    }
+}
+
+fn get_f32(b: &[u8], at: usize, e: Endianness) -> f32 {
+    let v = [b[at], b[at + 1], b[at + 2], b[at + 3]];
+    match e {
+        Endianness::Little => f32::from_le_bytes(v),
+        Endianness::Big => f32::from_be_bytes(v),
+    }
+}
+
+fn put(b: &mut [u8], at: usize, bytes: &[u8], e: Endianness) {
+    let field = &mut b[at..at + bytes.len()];
+    field.copy_from_slice(bytes);
+    if e == Endianness::Big {
+        field.reverse();
+    }
+}
+
+/// A parsed NIfTI file
+struct Nifti {
+    header: Vec<u8>,
+    endianness: Endianness,
+    dtype: DType,
+    dims: Vec<u64>,
+    data: Vec<u8>,
+}
+
+/// The file contents, decompressed if they're gzipped
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(bytes);
+    }
+    let bad = || invalid(format!("{} is a damaged gzip file", path.display()));
+    let flags = *bytes.get(3).ok_or_else(bad)?;
+    let mut at = 10;
+    if flags & 4 != 0 {
+        let len = bytes.get(at..at + 2).ok_or_else(bad)?;
+        at += 2 + usize::from(u16::from_le_bytes([len[0], len[1]]));
+    }
+    // file name and comment, each ending in a zero byte
+    for flag in [8, 16] {
+        if flags & flag != 0 {
+            let rest = bytes.get(at..).ok_or_else(bad)?;
+            at += rest.iter().position(|&b| b == 0).ok_or_else(bad)? + 1;
+        }
+    }
+    if flags & 2 != 0 {
+        at += 2;
+    }
+    miniz_oxide::inflate::decompress_to_vec(bytes.get(at..).ok_or_else(bad)?).map_err(|_| bad())
+}
+
+/// Write `bytes` to `path`, gzipped if its name ends in `.gz`.
+fn write_file(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    write_or_remove(path, |path| {
+        let mut w = BufWriter::new(File::create(path)?);
+        if path.extension().is_some_and(|e| e == "gz") {
+            w.write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff])?;
+            w.write_all(&miniz_oxide::deflate::compress_to_vec(bytes, 6))?;
+            w.write_all(&crc32fast::hash(bytes).to_le_bytes())?;
+            w.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        } else {
+            w.write_all(bytes)?;
+        }
+        w.flush()
+    })
+}
+
+fn parse(path: &Path) -> io::Result<Nifti> {
+    let mut bytes = read_file(path)?;
+    if bytes.len() < HEADER_LEN {
+        return Err(invalid(format!(
+            "{} is too short for NIfTI",
+            path.display()
+        )));
+    }
+    let endianness = match bytes[..4] {
+        [0x5c, 1, 0, 0] => Endianness::Little,
+        [0, 0, 1, 0x5c] => Endianness::Big,
+        _ => return Err(invalid(format!("{} isn't a NIfTI-1 file", path.display()))),
+    };
+    if &bytes[MAGIC..MAGIC + 4] != b"n+1\0" {
+        return Err(invalid(format!(
+            "{} isn't a single-file NIfTI image",
+            path.display()
+        )));
+    }
+    let ndims = get_i16(&bytes, DIM, endianness);
+    if !(1..=MAX_DIMS as i16).contains(&ndims) {
+        return Err(invalid(format!(
+            "{} has {} dimensions",
+            path.display(),
+            ndims
+        )));
+    }
+    let dims = (1..=ndims as usize)
+        .map(|i| match get_i16(&bytes, DIM + 2 * i, endianness) {
+            d if d > 0 => Ok(d as u64),
+            d => Err(invalid(format!("dimension {} is {}", i, d))),
+        })
+        .collect::<io::Result<Vec<u64>>>()?;
+    let code = get_i16(&bytes, DATATYPE, endianness);
+    let dtype = dtype_of_code(code)
+        .ok_or_else(|| invalid(format!("NIfTI datatype {} isn't supported", code)))?;
+    let offset = get_f32(&bytes, VOX_OFFSET_FIELD, endianness);
+    if offset.is_nan() || offset < VOX_OFFSET as f32 {
+        return Err(invalid(format!(
+            "voxel offset {} is inside the header",
+            offset
+        )));
+    }
+    let start = offset as usize;
+    let len = dims
+        .iter()
+        .try_fold(dtype.elbyte, |n, &d| n.checked_mul(d))
+        .ok_or_else(|| invalid(format!("{} has dims {:?} too large", path.display(), dims)))?;
+    let end = start
+        .checked_add(to_usize(len)?)
+        .filter(|&end| end <= bytes.len())
+        .ok_or_else(|| invalid(format!("{} is truncated", path.display())))?;
+    bytes.truncate(end);
+    let data = bytes.split_off(start);
+    bytes.truncate(HEADER_LEN);
+    Ok(Nifti {
+        header: bytes,
+        endianness,
+        dtype,
+        dims,
+        data,
+    })
+}
+
+/// A NIfTI file holding `data`, elements of `dtype` in byte order
+/// `endianness`, with dimensions `dims`. The header starts from `header`
+/// if it's given and in byte order `endianness`.
+fn build(
+    header: Option<&[u8]>,
+    endianness: Endianness,
+    dtype: DType,
+    dims: &[u64],
+    data: &[u8],
+) -> io::Result<Vec<u8>> {
+    let bad_input = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+    let code = code_of_dtype(dtype)
+        .ok_or_else(|| bad_input(format!("NIfTI can't hold elements of type {}", dtype)))?;
+    if dims.is_empty() || dims.len() > MAX_DIMS {
+        return Err(bad_input(format!(
+            "NIfTI can't hold {}-dimensional arrays",
+            dims.len()
+        )));
+    }
+    if let Some(&d) = dims.iter().find(|&&d| d == 0 || d > i16::MAX as u64) {
+        return Err(bad_input(format!("NIfTI can't hold a dimension of {}", d)));
+    }
+
+    let mut out = vec![0u8; VOX_OFFSET];
+    match header {
+        Some(h) => out[..HEADER_LEN].copy_from_slice(h),
+        None => {
+            put(&mut out, 0, &(HEADER_LEN as i32).to_le_bytes(), endianness);
+            for i in 0..8 {
+                put(&mut out, PIXDIM + 4 * i, &1f32.to_le_bytes(), endianness);
+            }
+        }
+    }
+    put(
+        &mut out,
+        DIM,
+        &(dims.len() as i16).to_le_bytes(),
+        endianness,
+    );
+    for i in 1..=MAX_DIMS {
+        let d = dims.get(i - 1).map_or(1, |&d| d as i16);
+        put(&mut out, DIM + 2 * i, &d.to_le_bytes(), endianness);
+    }
+    put(&mut out, DATATYPE, &code.to_le_bytes(), endianness);
+    let bitpix = 8 * dtype.elbyte as i16;
+    put(&mut out, BITPIX, &bitpix.to_le_bytes(), endianness);
+    let offset = VOX_OFFSET as f32;
+    put(
+        &mut out,
+        VOX_OFFSET_FIELD,
+        &offset.to_le_bytes(),
+        endianness,
+    );
+    out[MAGIC..MAGIC + 4].copy_from_slice(b"n+1\0");
+    out.extend_from_slice(data);
+    Ok(out)
+}
+
+/// The NIfTI header kept in `attrs` and its byte order, if there is a
+/// sound one
+fn kept_header(attrs: &BTreeMap<String, AttrValue>) -> Option<(&[u8], Endianness)> {
+    let h = attrs.get(HEADER_ATTR)?.as_bytes()?;
+    if h.len() != HEADER_LEN {
+        return None;
+    }
+    match h[..4] {
+        [0x5c, 1, 0, 0] => Some((h, Endianness::Little)),
+        [0, 0, 1, 0x5c] => Some((h, Endianness::Big)),
+        _ => None,
+    }
+}
+
+/// Convert a NIfTI file to a RawArray file with the same element type,
+/// byte order, and dimensions, keeping the NIfTI header as an attribute.
+/// ```
+/// # use std::io;
+/// use rawarray::{nifti, RawArray};
+/// # fn main() -> io::Result<()> {
+/// let brain = RawArray::from_iter_with_dims((0..24).map(|x| x as i16), &[2, 3, 4])?;
+/// brain.to_nifti("brain.nii.gz")?;
+/// nifti::nifti_to_ra("brain.nii.gz", "brain.ra")?;
+/// nifti::ra_to_nifti("brain.ra", "brain.nii")?;
+/// let back = RawArray::<i16>::from_nifti("brain.nii")?;
+/// assert_eq!(back.dims(), vec![2, 3, 4]);
+/// assert_eq!(back.data(), brain.data());
+/// assert!(back.get_attr("nifti.header").is_some());
+/// # for f in &["brain.nii.gz", "brain.ra", "brain.nii"] {
+/// #     std::fs::remove_file(f)?;
+/// # }
+/// # Ok(())
+/// # }
+/// ```
+pub fn nifti_to_ra<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> io::Result<()> {
+    let Nifti {
+        header,
+        endianness,
+        dtype,
+        dims,
+        data,
+    } = parse(input.as_ref())?;
+    let mut attrs = BTreeMap::new();
+    attrs.insert(HEADER_ATTR.to_string(), AttrValue::Bytes(header));
+    let mut flags = FLAG_ATTRS;
+    if endianness == Endianness::Big {
+        flags |= FLAG_BIG_ENDIAN;
+    }
+    write_or_remove(output.as_ref(), |out| {
+        let mut w = BufWriter::new(File::create(out)?);
+        RawArrayHeader {
+            flags,
+            eltype: dtype.eltype,
+            elbyte: dtype.elbyte,
+            size: data.len() as u64,
+            dims: dims.clone(),
+        }
+        .write_to(&mut w)?;
+        w.write_all(&data)?;
+        write_attrs(&mut w, &attrs)?;
+        w.flush()
+    })
+}
+
+/// Convert a RawArray file to a NIfTI file, gzipped if `output` ends in
+/// `.gz`, with the same element type and dimensions. A NIfTI header kept
+/// by `nifti_to_ra` or `from_nifti` is used as the starting point.
+pub fn ra_to_nifti<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> io::Result<()> {
+    let mut src = open_source(input.as_ref())?;
+    let dtype = src.dtype();
+    let dims = src.header.dims.clone();
+    let mut data = Vec::new();
+    // the byte order isn't known until the attributes are read, so read
+    // little-endian and swap afterwards if needed
+    src.convert(dtype, Endianness::Little, |bytes| {
+        data.extend_from_slice(bytes);
+        Ok(())
+    })?;
+    let attrs = src.finish()?;
+    let (header, endianness) = match kept_header(&attrs) {
+        Some((h, e)) => (Some(h), e),
+        None => (None, Endianness::Little),
+    };
+    if endianness == Endianness::Big {
+        let mut swapped = Vec::with_capacity(data.len());
+        convert_bytes(
+            &data,
+            dtype,
+            Endianness::Little,
+            &mut swapped,
+            dtype,
+            endianness,
+        )?;
+        data = swapped;
+    }
+    let file = build(header, endianness, dtype, &dims, &data)?;
+    write_file(output.as_ref(), &file)
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Read a NIfTI-1 image from a `.nii` or `.nii.gz` file, converting its
+    /// voxels to `T` as by `convert`. The NIfTI header is kept in the
+    /// `nifti.header` attribute, where `to_nifti` finds it.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let ra: RawArray<u8> = vec![1, 2, 3].into();
+    /// ra.to_nifti("small.nii")?;
+    /// assert_eq!(RawArray::<u8>::from_nifti("small.nii")?.data(), vec![1, 2, 3]);
+    ///
+    /// // a voxel offset far past the end of the file
+    /// let mut bytes = std::fs::read("small.nii")?;
+    /// bytes[108..112].copy_from_slice(&1e30f32.to_le_bytes());
+    /// std::fs::write("small.nii", &bytes)?;
+    /// assert!(RawArray::<u8>::from_nifti("small.nii").is_err());
+    /// # std::fs::remove_file("small.nii")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_nifti<P: AsRef<Path>>(path: P) -> io::Result<RawArray<T>> {
+        let n = parse(path.as_ref())?;
+        let to = DType::of::<T>();
+        check_conversion(n.dtype, to)?;
+        let mut bytes =
+            Vec::with_capacity(n.data.len() / n.dtype.elbyte as usize * to.elbyte as usize);
+        let native = Endianness::native();
+        convert_bytes(&n.data, n.dtype, n.endianness, &mut bytes, to, native)?;
+        let data = elements_from_bytes_with_endianness(&bytes, native)?;
+        let mut ra = RawArray::from_parts(n.dims, data);
+        ra.set_attr(HEADER_ATTR, AttrValue::Bytes(n.header));
+        Ok(ra)
+    }
+
+    /// Write the array as a NIfTI-1 image, gzipped if `path` ends in `.gz`.
+    /// The header kept by `from_nifti`, if any, is used as the starting
+    /// point, so voxel sizes and orientation carry over; otherwise voxels
+    /// are 1 by 1 by 1 with no orientation.
+    pub fn to_nifti<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let dtype = DType::of::<T>();
+        let (header, endianness) = match kept_header(&self.attrs) {
+            Some((h, e)) => (Some(h), e),
+            None => (None, Endianness::Little),
+        };
+        let mut data = Vec::with_capacity(self.data.len() * dtype.elbyte as usize);
+        convert_bytes(
+            as_u8_slice(&self.data),
+            dtype,
+            self.endianness(),
+            &mut data,
+            dtype,
+            endianness,
+        )?;
+        let file = build(header, endianness, dtype, &self.dims, &data)?;
+        write_file(path.as_ref(), &file)
+    }
+}
@@ -0,0 +1,102 @@
+//! Finding and replacing NaNs and infinities, such as those left in the
+//! output of a reconstruction that diverged.
+
+use crate::{Endianness, RawArray, RawArrayType};
+use half::prelude::*;
+use num_complex::Complex;
+
+/// Element types that can hold NaNs and infinities: the floating point
+/// types and complex numbers made of them.
+pub trait FloatElement: RawArrayType {
+    /// Whether the value, or both parts of a complex value, is neither NaN
+    /// nor infinite
+    fn is_finite_element(&self) -> bool;
+}
+
+macro_rules! float_element {
+    ($($t:ty),*) => {
+        $(
+            impl FloatElement for $t {
+                fn is_finite_element(&self) -> bool {
+                    self.is_finite()
+                }
+            }
+
+            impl FloatElement for Complex<$t> where Complex<$t>: RawArrayType {
+                fn is_finite_element(&self) -> bool {
+                    self.re.is_finite() && self.im.is_finite()
+                }
+            }
+        )*
+    };
+}
+
+float_element!(f32, f64);
+
+impl FloatElement for f16 {
+    fn is_finite_element(&self) -> bool {
+        self.is_finite()
+    }
+}
+
+impl FloatElement for bf16 {
+    fn is_finite_element(&self) -> bool {
+        self.is_finite()
+    }
+}
+
+impl<T: FloatElement> RawArray<T> {
+    /// Flat indices of the elements that are NaN or infinite. Use `dims()`
+    /// to turn them into coordinates.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut ra: RawArray<f32> = vec![1.0, f32::NAN, 2.0, f32::NEG_INFINITY].into();
+    /// assert_eq!(ra.find_nonfinite(), vec![1, 3]);
+    /// assert_eq!(ra.replace_nonfinite(0.0), 2);
+    /// assert_eq!(ra.data(), vec![1.0, 0.0, 2.0, 0.0]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data isn't in native byte order, as does
+    /// `replace_nonfinite`.
+    pub fn find_nonfinite(&self) -> Vec<usize> {
+        self.check_native_floats();
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| !x.is_finite_element())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Replace every element that is NaN or infinite with `value`, and
+    /// return how many were replaced. A complex element is replaced whole
+    /// if either part isn't finite.
+    /// ```
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// let mut ra: RawArray<Complex<f64>> =
+    ///     vec![Complex::new(1.0, f64::INFINITY), Complex::new(2.0, 3.0)].into();
+    /// ra.replace_nonfinite(Complex::new(0.0, 0.0));
+    /// assert_eq!(ra.data(), vec![Complex::new(0.0, 0.0), Complex::new(2.0, 3.0)]);
+    /// ```
+    pub fn replace_nonfinite(&mut self, value: T) -> usize {
+        self.check_native_floats();
+        let mut n = 0;
+        for x in self.data.iter_mut() {
+            if !x.is_finite_element() {
+                *x = value;
+                n += 1;
+            }
+        }
+        n
+    }
+
+    fn check_native_floats(&self) {
+        assert!(
+            self.endianness() == Endianness::native(),
+            "checking for NaNs needs data in native byte order"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Conversion between RawArray files and NumPy's `.npy` format.
+//!
+//! Both formats are a header followed by the raw elements, so conversion
+//! streams the data through without reordering it. RawArrays are column
+//! major, so they are written as Fortran-ordered `.npy` files with the same
+//! shape. C-ordered `.npy` files become RawArrays with their shape
+//! reversed, the same convention the Python RawArray reader uses.
+
+use crate::convert::{DType, Endianness};
+use crate::header::RawArrayHeader;
+use crate::options::WriteOptions;
+use crate::recode::{open_source, write_or_remove, write_source, Source};
+use crate::{DEFAULT_MAX_BYTES, FLAG_BIG_ENDIAN};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::Path;
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(msg: E) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// NumPy type string, like `<f4`, for elements of `dtype`
+pub(crate) fn descr(dtype: DType, endianness: Endianness) -> io::Result<String> {
+    let kind = match (dtype.eltype, dtype.elbyte) {
+        (1, 1) | (1, 2) | (1, 4) | (1, 8) => 'i',
+        (2, 1) | (2, 2) | (2, 4) | (2, 8) => 'u',
+        (3, 2) | (3, 4) | (3, 8) => 'f',
+        (4, 8) | (4, 16) => 'c',
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("NumPy has no equivalent of {}", dtype),
+            ))
+        }
+    };
+    let order = match (dtype.elbyte, endianness) {
+        (1, _) => '|',
+        (_, Endianness::Little) => '<',
+        (_, Endianness::Big) => '>',
+    };
+    Ok(format!("{}{}{}", order, kind, dtype.elbyte))
+}
+
+/// Parse a NumPy type string into an element type and byte order
+pub(crate) fn parse_descr(s: &str) -> io::Result<(DType, Endianness)> {
+    let unsupported = || invalid(format!("unsupported NumPy dtype {:?}", s));
+    let mut chars = s.chars();
+    let endianness = match chars.next() {
+        Some('<') | Some('|') | Some('=') => Endianness::Little,
+        Some('>') => Endianness::Big,
+        _ => return Err(unsupported()),
+    };
+    let eltype = match chars.next() {
+        Some('i') => 1,
+        Some('u') | Some('b') => 2,
+        Some('f') => 3,
+        Some('c') => 4,
+        _ => return Err(unsupported()),
+    };
+    let elbyte = chars.as_str().parse().map_err(|_| unsupported())?;
+    let dtype = DType { eltype, elbyte };
+    descr(dtype, endianness).map_err(|_| unsupported())?;
+    Ok((dtype, endianness))
+}
+
+/// The value following `'key':` in a NumPy header dictionary
+fn dict_value<'a>(header: &'a str, key: &str) -> io::Result<&'a str> {
+    let pattern = format!("'{}':", key);
+    let start = header
+        .find(&pattern)
+        .ok_or_else(|| invalid(format!("npy header has no {:?}", key)))?;
+    Ok(header[start + pattern.len()..].trim_start())
+}
+
+/// Parse the header of a `.npy` file into a RawArray header, leaving the
+/// reader at the data.
+fn read_npy_header<R: Read>(r: &mut R) -> io::Result<RawArrayHeader> {
+    let mut preamble = [0u8; 8];
+    r.read_exact(&mut preamble)?;
+    if &preamble[..6] != NPY_MAGIC {
+        return Err(invalid("not a .npy file"));
+    }
+    let len = if preamble[6] == 1 {
+        let mut len = [0u8; 2];
+        r.read_exact(&mut len)?;
+        u16::from_le_bytes(len) as usize
+    } else {
+        let mut len = [0u8; 4];
+        r.read_exact(&mut len)?;
+        u32::from_le_bytes(len) as usize
+    };
+    let mut header = vec![0u8; len];
+    r.read_exact(&mut header)?;
+    let header = String::from_utf8(header).map_err(|_| invalid("npy header isn't UTF-8"))?;
+
+    let descr = dict_value(&header, "descr")?;
+    let descr = descr
+        .strip_prefix('\'')
+        .and_then(|d| d.split('\'').next())
+        .ok_or_else(|| invalid("npy header has a structured dtype"))?;
+    let (dtype, endianness) = parse_descr(descr)?;
+    let fortran = dict_value(&header, "fortran_order")?.starts_with("True");
+    let shape = dict_value(&header, "shape")?;
+    let shape = shape
+        .strip_prefix('(')
+        .and_then(|s| s.split(')').next())
+        .ok_or_else(|| invalid("npy header has a malformed shape"))?;
+    let mut dims = shape
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| d.parse::<u64>())
+        .collect::<Result<Vec<u64>, _>>()
+        .map_err(|_| invalid("npy header has a malformed shape"))?;
+    if !fortran {
+        dims.reverse();
+    }
+    if dims.is_empty() {
+        dims.push(1);
+    }
+    let size = dims
+        .iter()
+        .try_fold(dtype.elbyte, |acc, &d| acc.checked_mul(d))
+        .ok_or_else(|| invalid("npy shape overflows"))?;
+    Ok(RawArrayHeader {
+        flags: if endianness == Endianness::Big {
+            FLAG_BIG_ENDIAN
+        } else {
+            0
+        },
+        eltype: dtype.eltype,
+        elbyte: dtype.elbyte,
+        size,
+        dims,
+    })
+}
+
+/// Write a version 1.0 `.npy` header for a Fortran-ordered array
+fn write_npy_header<W: Write>(w: &mut W, descr: &str, dims: &[u64]) -> io::Result<()> {
+    let shape = match dims.len() {
+        1 => format!("({},)", dims[0]),
+        _ => {
+            let dims: Vec<String> = dims.iter().map(|d| d.to_string()).collect();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': True, 'shape': {}, }}",
+        descr, shape
+    );
+    // pad so the data starts on a 64 byte boundary
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+    w.write_all(NPY_MAGIC)?;
+    w.write_all(&[1, 0])?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())
+}
+
+/// Convert a `.npy` file to a RawArray file, applying `opts` on the way.
+/// ```
+/// # use std::io;
+/// use rawarray::{npy, RawArray, WriteOptions};
+/// # fn main() -> io::Result<()> {
+/// let mut ra: RawArray<f32> = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0].into();
+/// ra.reshape(vec![2, 3]);
+/// ra.write("npy_in.ra")?;
+/// npy::ra_to_npy("npy_in.ra", "npy_mid.npy", &WriteOptions::new())?;
+/// npy::npy_to_ra("npy_mid.npy", "npy_out.ra", &WriteOptions::new())?;
+/// assert_eq!(RawArray::<f32>::read("npy_out.ra")?, ra);
+/// # for f in &["npy_in.ra", "npy_mid.npy", "npy_out.ra"] {
+/// #     std::fs::remove_file(f)?;
+/// # }
+/// # Ok(())
+/// # }
+/// ```
+pub fn npy_to_ra<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    opts: &WriteOptions,
+) -> io::Result<()> {
+    let mut r = BufReader::new(File::open(input)?);
+    let header = read_npy_header(&mut r)?;
+    let src = Source::new(header, r, DEFAULT_MAX_BYTES)?;
+    write_or_remove(output.as_ref(), |out| write_source(src, out, opts))
+}
+
+/// Convert a RawArray file to a Fortran-ordered `.npy` file, changing the
+/// element type and byte order as given by `opts`. Attributes and the
+/// checksum setting are dropped, since `.npy` has nowhere to put them, and
+/// `.npy` files can't be encoded.
+pub fn ra_to_npy<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    opts: &WriteOptions,
+) -> io::Result<()> {
+    if opts.codec.is_some() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            ".npy files can't be encoded",
+        ));
+    }
+    let mut src = open_source(input.as_ref())?;
+    let to = opts.dtype.unwrap_or_else(|| src.dtype());
+    let descr = descr(to, opts.endianness)?;
+    write_or_remove(output.as_ref(), |out| {
+        let mut w = BufWriter::new(File::create(out)?);
+        write_npy_header(&mut w, &descr, &src.header.dims)?;
+        src.convert(to, opts.endianness, |bytes| w.write_all(bytes))?;
+        src.finish()?;
+        w.flush()
+    })
+}
@@ -0,0 +1,103 @@
+//! Elementwise arithmetic between arrays of the same shape, and between
+//! an array and a scalar.
+//!
+//! Like ndarray's operators, these panic if the shapes differ. The result
+//! keeps the flags and attributes of the left-hand array.
+
+use crate::{Endianness, RawArray, RawArrayType};
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign};
+
+fn check_operands<T: RawArrayType>(a: &RawArray<T>, b: &RawArray<T>) {
+    assert_eq!(
+        a.dims, b.dims,
+        "can't combine arrays of dims {:?} and {:?}",
+        a.dims, b.dims
+    );
+    check_native(a);
+    check_native(b);
+}
+
+fn check_native<T: RawArrayType>(a: &RawArray<T>) {
+    assert!(
+        a.endianness() == Endianness::native(),
+        "arithmetic needs data in native byte order"
+    );
+}
+
+macro_rules! elementwise_op {
+    ($op:ident, $method:ident, $assign:ident, $assign_method:ident) => {
+        impl<T: RawArrayType + $op<Output = T>> $assign<&RawArray<T>> for RawArray<T> {
+            fn $assign_method(&mut self, rhs: &RawArray<T>) {
+                check_operands(self, rhs);
+                for (a, &b) in self.data.iter_mut().zip(&rhs.data) {
+                    *a = $op::$method(*a, b);
+                }
+            }
+        }
+
+        impl<T: RawArrayType + $op<Output = T>> $assign<T> for RawArray<T> {
+            fn $assign_method(&mut self, rhs: T) {
+                check_native(self);
+                for a in self.data.iter_mut() {
+                    *a = $op::$method(*a, rhs);
+                }
+            }
+        }
+
+        impl<T: RawArrayType + $op<Output = T>> $op<&RawArray<T>> for RawArray<T> {
+            type Output = RawArray<T>;
+
+            fn $method(mut self, rhs: &RawArray<T>) -> RawArray<T> {
+                $assign::$assign_method(&mut self, rhs);
+                self
+            }
+        }
+
+        impl<T: RawArrayType + $op<Output = T>> $op<RawArray<T>> for RawArray<T> {
+            type Output = RawArray<T>;
+
+            fn $method(self, rhs: RawArray<T>) -> RawArray<T> {
+                $op::$method(self, &rhs)
+            }
+        }
+
+        impl<T: RawArrayType + $op<Output = T>> $op<&RawArray<T>> for &RawArray<T> {
+            type Output = RawArray<T>;
+
+            fn $method(self, rhs: &RawArray<T>) -> RawArray<T> {
+                check_operands(self, rhs);
+                let data = self
+                    .data
+                    .iter()
+                    .zip(&rhs.data)
+                    .map(|(&a, &b)| $op::$method(a, b))
+                    .collect();
+                self.with_data(data)
+            }
+        }
+
+        impl<T: RawArrayType + $op<Output = T>> $op<T> for RawArray<T> {
+            type Output = RawArray<T>;
+
+            fn $method(mut self, rhs: T) -> RawArray<T> {
+                $assign::$assign_method(&mut self, rhs);
+                self
+            }
+        }
+
+        impl<T: RawArrayType + $op<Output = T>> $op<T> for &RawArray<T> {
+            type Output = RawArray<T>;
+
+            fn $method(self, rhs: T) -> RawArray<T> {
+                check_native(self);
+                let data = self.data.iter().map(|&a| $op::$method(a, rhs)).collect();
+                self.with_data(data)
+            }
+        }
+    };
+}
+
+elementwise_op!(Add, add, AddAssign, add_assign);
+elementwise_op!(Sub, sub, SubAssign, sub_assign);
+elementwise_op!(Mul, mul, MulAssign, mul_assign);
+elementwise_op!(Div, div, DivAssign, div_assign);
@@ -0,0 +1,272 @@
+//! Options controlling how arrays are read and written.
+
+use crate::codec::Codec;
+use crate::convert::{DType, Endianness};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How to lay out the data section when writing or recoding a file.
+///
+/// Every setting defaults to "keep what the source has".
+/// ```
+/// use rawarray::codec::Rle;
+/// use rawarray::{DType, Endianness, WriteOptions};
+/// let opts = WriteOptions::new()
+///     .endianness(Endianness::Big)
+///     .dtype(DType::parse("f32").unwrap())
+///     .codec(Rle)
+///     .checksum(true);
+/// ```
+#[derive(Clone, Default)]
+pub struct WriteOptions {
+    pub(crate) endianness: Endianness,
+    pub(crate) codec: Option<Arc<dyn Codec>>,
+    pub(crate) dtype: Option<DType>,
+    pub(crate) checksum: Option<bool>,
+    pub(crate) magnitude: bool,
+    pub(crate) sync: bool,
+}
+
+impl WriteOptions {
+    /// Little endian, uncompressed, same element type and checksum
+    /// setting as the source.
+    pub fn new() -> WriteOptions {
+        WriteOptions::default()
+    }
+
+    /// Byte order of the elements written
+    pub fn endianness(mut self, endianness: Endianness) -> WriteOptions {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Encode the data section with `codec`
+    pub fn codec<C: Codec + 'static>(mut self, codec: C) -> WriteOptions {
+        self.codec = Some(Arc::new(codec));
+        self
+    }
+
+    /// Convert elements to `dtype`
+    pub fn dtype(mut self, dtype: DType) -> WriteOptions {
+        self.dtype = Some(dtype);
+        self
+    }
+
+    /// Whether to store a checksum of the data section
+    pub fn checksum(mut self, enabled: bool) -> WriteOptions {
+        self.checksum = Some(enabled);
+        self
+    }
+
+    /// Whether to write the magnitudes of complex elements, so they can be
+    /// converted to a real `dtype`. Real elements are left as they are.
+    /// Without a `dtype`, magnitudes are `f64` for `c128` and `ci64`
+    /// elements and `f32` for the rest.
+    /// ```
+    /// # use std::io;
+    /// use num_complex::Complex;
+    /// use rawarray::{RawArray, WriteOptions};
+    /// # fn main() -> io::Result<()> {
+    /// let ra: RawArray<Complex<f32>> = vec![Complex::new(3.0, 4.0)].into();
+    /// ra.write_with("magnitude.ra", &WriteOptions::new().magnitude(true))?;
+    /// assert_eq!(RawArray::<f32>::read("magnitude.ra")?.data(), vec![5.0]);
+    /// # std::fs::remove_file("magnitude.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn magnitude(mut self, enabled: bool) -> WriteOptions {
+        self.magnitude = enabled;
+        self
+    }
+
+    /// Whether to wait for the file to reach the disk before returning,
+    /// so that it survives a crash or power failure. Off by default, since
+    /// it makes writing many small files much slower.
+    pub fn sync(mut self, enabled: bool) -> WriteOptions {
+        self.sync = enabled;
+        self
+    }
+}
+
+impl fmt::Debug for WriteOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteOptions")
+            .field("endianness", &self.endianness)
+            .field("codec", &self.codec.as_ref().map(|c| c.name().to_string()))
+            .field("dtype", &self.dtype)
+            .field("checksum", &self.checksum)
+            .field("magnitude", &self.magnitude)
+            .field("sync", &self.sync)
+            .finish()
+    }
+}
+
+/// What to do when a header's `size` disagrees with its dimensions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SizePolicy {
+    /// Refuse to read the file
+    #[default]
+    Strict,
+    /// Believe the dimensions and recompute `size` from them
+    TrustDims,
+    /// Believe `size` and treat the dimensions as suspect
+    TrustSize,
+}
+
+/// Most bytes of data a read allocates unless told otherwise: 64 GiB.
+pub const DEFAULT_MAX_BYTES: u64 = 64 << 30;
+
+/// How to read files that don't quite follow the format, or that can't
+/// be trusted.
+#[derive(Clone, Debug)]
+pub struct ReadOptions {
+    pub(crate) size_policy: SizePolicy,
+    pub(crate) allow_width_promotion: bool,
+    pub(crate) collect_stats: bool,
+    pub(crate) max_bytes: u64,
+}
+
+impl Default for ReadOptions {
+    fn default() -> ReadOptions {
+        ReadOptions {
+            size_policy: SizePolicy::default(),
+            allow_width_promotion: false,
+            collect_stats: false,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+impl ReadOptions {
+    /// Strict reading, the same as `RawArray::read`
+    pub fn new() -> ReadOptions {
+        ReadOptions::default()
+    }
+
+    /// How to reconcile a `size` that disagrees with the dimensions
+    pub fn size_policy(mut self, policy: SizePolicy) -> ReadOptions {
+        self.size_policy = policy;
+        self
+    }
+
+    /// Whether to read files of a narrower element type than asked for,
+    /// converting each element, such as an `i32` file into a
+    /// `RawArray<i64>`. Only conversions that can't change a value are
+    /// made: to the same kind of element at least as wide, from unsigned
+    /// to wider signed integers, from integers to floats whose mantissa
+    /// holds them, from `bf16` to wider floats, and from real to complex.
+    /// Other mismatches are still refused. The report gives the type the
+    /// file held.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{DType, RawArray, ReadOptions};
+    /// # fn main() -> io::Result<()> {
+    /// RawArray::from(vec![-1i32, 2, i32::MAX]).write("narrow.ra")?;
+    /// assert!(RawArray::<i64>::read("narrow.ra").is_err());
+    ///
+    /// let opts = ReadOptions::new().allow_width_promotion(true);
+    /// let (ra, report) = RawArray::<i64>::read_with("narrow.ra", &opts)?;
+    /// assert_eq!(ra.data(), vec![-1, 2, i32::MAX as i64]);
+    /// assert_eq!(report.widened_from, DType::parse("i32"));
+    /// let (ra, _) = RawArray::<f64>::read_with("narrow.ra", &opts)?;
+    /// assert_eq!(ra.data()[2], 2147483647.0);
+    /// assert!(RawArray::<i16>::read_with("narrow.ra", &opts).is_err());
+    /// assert!(RawArray::<f32>::read_with("narrow.ra", &opts).is_err());
+    /// # std::fs::remove_file("narrow.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn allow_width_promotion(mut self, enabled: bool) -> ReadOptions {
+        self.allow_width_promotion = enabled;
+        self
+    }
+
+    /// Whether to time the read and count the bytes it took from the
+    /// file, giving them in the report's `stats`.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{RawArray, ReadOptions};
+    /// # fn main() -> io::Result<()> {
+    /// RawArray::from(vec![0f64; 1000]).write("timed.ra")?;
+    /// let opts = ReadOptions::new().collect_stats(true);
+    /// let (_, report) = RawArray::<f64>::read_with("timed.ra", &opts)?;
+    /// let stats = report.stats.unwrap();
+    /// assert_eq!(stats.bytes, std::fs::metadata("timed.ra")?.len());
+    /// assert!(stats.throughput > 0.0);
+    /// # std::fs::remove_file("timed.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn collect_stats(mut self, enabled: bool) -> ReadOptions {
+        self.collect_stats = enabled;
+        self
+    }
+
+    /// Most bytes of memory the array may take, checked against the
+    /// header before anything is allocated, so that a hostile or corrupt
+    /// header can't exhaust memory. Within the limit, memory for plain
+    /// data is allocated as the data arrives, so a short stream behind a
+    /// header claiming a huge array fails before using much of it. Larger arrays fail with
+    /// `RawArrayError::TooLarge`. Reads without options are limited to
+    /// `DEFAULT_MAX_BYTES`.
+    /// ```
+    /// use rawarray::{RawArray, RawArrayError, ReadOptions};
+    /// let bytes = RawArray::from(vec![0u32; 1000]).to_bytes();
+    /// let opts = ReadOptions::new().max_bytes(1024);
+    /// let err = RawArray::<u32>::read_from_with(&mut bytes.as_slice(), &opts).unwrap_err();
+    /// assert_eq!(
+    ///     RawArrayError::from_io(&err),
+    ///     Some(&RawArrayError::TooLarge { bytes: 4000, limit: 1024 })
+    /// );
+    /// let opts = ReadOptions::new().max_bytes(4000);
+    /// assert!(RawArray::<u32>::read_from_with(&mut bytes.as_slice(), &opts).is_ok());
+    /// ```
+    pub fn max_bytes(mut self, limit: u64) -> ReadOptions {
+        self.max_bytes = limit;
+        self
+    }
+}
+
+/// Problems found, and worked around, while reading a file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReadReport {
+    /// Set if the header's `size` disagreed with its dimensions
+    pub size_mismatch: Option<SizeMismatch>,
+    /// Element type of the file, if it was widened to the one asked for
+    pub widened_from: Option<DType>,
+    /// How long the read took, if asked for with `collect_stats`
+    pub stats: Option<ReadStats>,
+}
+
+/// Bytes read and time taken by one read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReadStats {
+    /// Bytes read from the file: header, data, checksum, and attributes
+    pub bytes: u64,
+    /// Time from opening the file to having the array in memory
+    pub wall_time: Duration,
+    /// `bytes` per second of `wall_time`
+    pub throughput: f64,
+}
+
+impl ReadStats {
+    pub(crate) fn new(bytes: u64, wall_time: Duration) -> ReadStats {
+        ReadStats {
+            bytes,
+            wall_time,
+            throughput: bytes as f64 / wall_time.as_secs_f64().max(f64::MIN_POSITIVE),
+        }
+    }
+}
+
+/// A header whose `size` disagreed with its dimensions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SizeMismatch {
+    /// How the disagreement was resolved
+    pub policy: SizePolicy,
+    /// `size` as recorded in the header
+    pub header_size: u64,
+    /// Dimensions as recorded in the header
+    pub header_dims: Vec<u64>,
+}
@@ -0,0 +1,82 @@
+//! Moving data between the column-major order of the format, which Julia,
+//! MATLAB, and Fortran share, and the row-major order of C, NumPy's
+//! default, and `ndarray`'s default.
+//!
+//! Files are always column major, so there is no flag to say otherwise:
+//! a file written from row-major data would be misread by every other
+//! implementation. Instead, data is put into column-major order on its
+//! way in and taken out of it on its way out, keeping each element at the
+//! same coordinates.
+
+use crate::{RawArray, RawArrayType};
+use ndarray::{ArrayBase, ArrayD, Data, Dimension, IxDyn, ShapeBuilder};
+use std::io;
+
+impl<T: RawArrayType> RawArray<T> {
+    /// The elements in row-major order, with the last coordinate varying
+    /// fastest, for code written for C or NumPy. Dimensions are unchanged:
+    /// element `[i, j]` is at `i * dims[1] + j`.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims(0..6u8, &[2, 3]).unwrap();
+    /// assert_eq!(ra.to_row_major(), vec![0, 2, 4, 1, 3, 5]);
+    /// assert_eq!(ra.to_row_major()[1 * 3 + 2], ra[[1, 2]]);
+    /// ```
+    pub fn to_row_major(&self) -> Vec<T> {
+        self.transpose().into()
+    }
+
+    /// An array of dimensions `dims` from `data` in row-major order, as a
+    /// C or NumPy program lays it out, stored in column-major order with
+    /// each element at the same coordinates. Fails if `data` isn't as long
+    /// as `dims` calls for.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// // the 2×3 matrix [[0, 1, 2], [3, 4, 5]]
+    /// let ra = RawArray::from_row_major(vec![0u8, 1, 2, 3, 4, 5], &[2, 3]).unwrap();
+    /// assert_eq!(ra[[0, 2]], 2);
+    /// assert_eq!(ra[[1, 0]], 3);
+    /// assert_eq!(ra.data(), vec![0, 3, 1, 4, 2, 5]);
+    /// assert_eq!(ra.to_row_major(), vec![0, 1, 2, 3, 4, 5]);
+    /// ```
+    pub fn from_row_major(data: Vec<T>, dims: &[u64]) -> io::Result<RawArray<T>> {
+        let reversed: Vec<u64> = dims.iter().rev().copied().collect();
+        Ok(RawArray::from_iter_with_dims(data, &reversed)?.transpose())
+    }
+
+    /// Copy an `ndarray` array or view, in any memory layout, keeping each
+    /// element at the same coordinates.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let a = ndarray::arr2(&[[0u8, 1, 2], [3, 4, 5]]);
+    /// let ra = RawArray::from_ndarray(&a);
+    /// assert_eq!(ra.dims(), vec![2, 3]);
+    /// assert_eq!(ra[[1, 0]], 3);
+    /// assert_eq!(RawArray::from_ndarray(&a.t()).dims(), vec![3, 2]);
+    /// ```
+    pub fn from_ndarray<S, D>(a: &ArrayBase<S, D>) -> RawArray<T>
+    where
+        S: Data<Elem = T>,
+        D: Dimension,
+    {
+        let dims: Vec<u64> = a.shape().iter().map(|&d| d as u64).collect();
+        // iterating the transpose in logical order is column-major order
+        RawArray::from_iter_with_dims(a.t().iter().copied(), &dims).expect("shape matches the data")
+    }
+
+    /// Convert to an `ndarray` array of the same shape, without copying:
+    /// the result is in Fortran (column-major) layout, which `ndarray`
+    /// indexes just like any other.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims(0..6u8, &[2, 3]).unwrap();
+    /// let a = ra.clone().into_ndarray();
+    /// assert_eq!(a.shape(), &[2, 3]);
+    /// assert_eq!(a[[1, 2]], ra[[1, 2]]);
+    /// assert_eq!(RawArray::from_ndarray(&a), ra);
+    /// ```
+    pub fn into_ndarray(self) -> ArrayD<T> {
+        let shape: Vec<usize> = self.dims.iter().map(|&d| d as usize).collect();
+        ArrayD::from_shape_vec(IxDyn(&shape).f(), self.data).expect("dims match the data length")
+    }
+}
@@ -0,0 +1,63 @@
+//! Reading large files with several threads, behind the `rayon` feature.
+
+use crate::options::{SizePolicy, DEFAULT_MAX_BYTES};
+use crate::{as_u8_slice_mut, codec, zeroed_vec, FsStorage, RawArray, RawArrayType};
+use rayon::prelude::*;
+use std::io::{self, Seek, SeekFrom};
+use std::path::Path;
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Read a `RawArray<T>` from a file using `n_threads` threads, each
+    /// reading its own range of the data section. This helps on storage
+    /// that is faster than one thread can copy, like NVMe drives. Checksums
+    /// and attributes are handled as by `read()`, and encoded files are
+    /// read with a single thread, since codecs decode a whole data section.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let ra: RawArray<u16> = (0..10_000).collect::<Vec<u16>>().into();
+    /// ra.write("parallel.ra")?;
+    /// assert_eq!(RawArray::<u16>::read_parallel("parallel.ra", 4)?, ra);
+    /// # std::fs::remove_file("parallel.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_parallel<P: AsRef<Path>>(path: P, n_threads: usize) -> io::Result<RawArray<T>> {
+        let (mut ra, _, mut r) = RawArray::open_checked_in(
+            &FsStorage,
+            path.as_ref(),
+            SizePolicy::Strict,
+            DEFAULT_MAX_BYTES,
+        )?;
+        if codec::codec_for_flags(ra.flags).is_some() {
+            ra.read_data(&mut r)?;
+            return Ok(ra);
+        }
+        let n_threads = n_threads.max(1);
+        let offset = ra.data_offset();
+        let elbyte = ra.elbyte as usize;
+        let mut data: Vec<T> = zeroed_vec(ra.nelem_in_file()?);
+        let file = r.get_ref();
+        {
+            let bytes = as_u8_slice_mut(&mut data);
+            // whole elements per range, so no element straddles two threads
+            let per_thread = (bytes.len() / elbyte).div_ceil(n_threads).max(1) * elbyte;
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(n_threads)
+                .build()
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            pool.install(|| {
+                bytes
+                    .par_chunks_mut(per_thread)
+                    .enumerate()
+                    .try_for_each(|(i, chunk)| {
+                        file.read_exact_at(chunk, offset + (i * per_thread) as u64)
+                    })
+            })?;
+        }
+        r.seek(SeekFrom::Start(offset + ra.size))?;
+        ra.finish_data(data, &mut r)?;
+        Ok(ra)
+    }
+}
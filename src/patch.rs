@@ -0,0 +1,248 @@
+//! Overwriting elements of a file in place, for fixing a few bad values in
+//! a file too big to read and write back whole.
+
+use crate::storage::StorageFile;
+use crate::{
+    bytes_in_order, codec, dtype_name, elements_from_bytes_with_endianness, Endianness, FsStorage,
+    OpenMode, RaStorage, RawArrayError, RawArrayHeader, RawArrayType, FLAG_BIG_ENDIAN, FLAG_BITS,
+    FLAG_CRC,
+};
+use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
+use std::path::Path;
+
+/// Bytes of data hashed at a time when updating a checksum
+const CRC_CHUNK: u64 = 1 << 20;
+
+/// A file opened for overwriting its elements where they lie, without
+/// rewriting the rest. Its dimensions and element type can't change, and
+/// its data must be stored plainly, not encoded.
+///
+/// A file with a checksum has it verified on opening and recomputed by
+/// `sync`, each of which reads the whole data section once, so a file
+/// that was already corrupt isn't given a checksum that hides it.
+/// Dropping the handle syncs too, ignoring errors, so call `sync` to see
+/// them. Files in other storage are opened with `open_in_storage`.
+/// ```
+/// # use std::io;
+/// use rawarray::{RawArray, RawArrayFileMut};
+/// # fn main() -> io::Result<()> {
+/// RawArray::from_iter_with_dims(0..12i16, &[4, 3])?.write("channels.ra")?;
+/// let mut f = RawArrayFileMut::<i16>::open("channels.ra")?;
+/// f.set(1, -1)?;
+/// // zero the third column
+/// f.set_slice(8..12, &[0; 4])?;
+/// assert_eq!(f.get(1)?, -1);
+/// assert!(f.set(12, 0).is_err());
+/// assert!(f.get(u64::MAX).is_err());
+/// f.sync()?;
+/// let ra = RawArray::<i16>::read("channels.ra")?;
+/// assert_eq!(ra.data(), vec![0, -1, 2, 3, 4, 5, 6, 7, 0, 0, 0, 0]);
+/// # std::fs::remove_file("channels.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RawArrayFileMut<'a, T: RawArrayType, S: RaStorage = FsStorage> {
+    file: StorageFile<'a, S>,
+    header: RawArrayHeader,
+    dirty: bool,
+    _elements: PhantomData<T>,
+}
+
+impl<T: RawArrayType> RawArrayFileMut<'static, T> {
+    /// Open the file at `path` for reading and writing, checking that it
+    /// holds plainly stored `T`s.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<RawArrayFileMut<'static, T>> {
+        RawArrayFileMut::open_in_storage(&FsStorage, path)
+    }
+}
+
+impl<'a, T: RawArrayType, S: RaStorage> RawArrayFileMut<'a, T, S> {
+    /// Open a file in `storage` as `open()` does. See `RaStorage`.
+    pub fn open_in_storage<P: AsRef<Path>>(
+        storage: &'a S,
+        path: P,
+    ) -> io::Result<RawArrayFileMut<'a, T, S>> {
+        let path = path.as_ref();
+        let invalid = |msg: String| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), msg),
+            )
+        };
+        let mut file = StorageFile::open(storage, path, OpenMode::ReadWrite)?;
+        let header = RawArrayHeader::read_from(&mut file)?;
+        let want = (T::ra_type_code(), mem::size_of::<T>() as u64);
+        if (header.eltype, header.elbyte) != want {
+            return Err(invalid(format!(
+                "file holds {} elements, not {}",
+                dtype_name(header.eltype, header.elbyte),
+                dtype_name(want.0, want.1)
+            )));
+        }
+        if header.flags & (codec::CODEC_FLAGS | FLAG_BITS) != 0 {
+            return Err(invalid(
+                "data is encoded, so elements can't be written in place".to_string(),
+            ));
+        }
+        if header.nelem().and_then(|n| n.checked_mul(header.elbyte)) != Some(header.size) {
+            return Err(invalid(format!(
+                "header size {} doesn't match dims {:?}",
+                header.size, header.dims
+            )));
+        }
+        let crc_len = if header.flags & FLAG_CRC != 0 { 8 } else { 0 };
+        let end = header
+            .data_offset()
+            .checked_add(header.size)
+            .and_then(|n| n.checked_add(crc_len));
+        let len = file.len()?;
+        if !matches!(end, Some(end) if end <= len) {
+            return Err(invalid("data section is truncated".to_string()));
+        }
+        let mut f = RawArrayFileMut {
+            file,
+            header,
+            dirty: false,
+            _elements: PhantomData,
+        };
+        if f.header.flags & FLAG_CRC != 0 {
+            let end = f.header.data_offset() + f.header.size;
+            let mut stored = [0u8; 8];
+            f.read_at(end, &mut stored)?;
+            let stored = u64::from_le_bytes(stored);
+            let computed = f.data_crc()?;
+            if stored != computed {
+                return Err(RawArrayError::ChecksumMismatch { stored, computed }.into());
+            }
+        }
+        Ok(f)
+    }
+
+    /// Dimensions of the array
+    pub fn dims(&self) -> &[u64] {
+        &self.header.dims
+    }
+
+    /// Number of elements in the array
+    pub fn nelem(&self) -> u64 {
+        self.header.size / self.header.elbyte
+    }
+
+    fn endianness(&self) -> Endianness {
+        if self.header.flags & FLAG_BIG_ENDIAN != 0 {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        }
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf)
+    }
+
+    fn write_at(&mut self, offset: u64, bytes: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(bytes)
+    }
+
+    /// Checksum of the data section as it is in the file
+    fn data_crc(&mut self) -> io::Result<u64> {
+        let start = self.header.data_offset();
+        let end = start + self.header.size;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0u8; CRC_CHUNK.min(self.header.size) as usize];
+        for at in (start..end).step_by(CRC_CHUNK as usize) {
+            let chunk = &mut buf[..(end.min(at + CRC_CHUNK) - at) as usize];
+            self.file.seek(SeekFrom::Start(at))?;
+            self.file.read_exact(chunk)?;
+            hasher.update(chunk);
+        }
+        Ok(u64::from(hasher.finalize()))
+    }
+
+    /// Byte offset of element `i`, checking that `range` is in bounds
+    fn offset_of(&self, range: &Range<u64>) -> io::Result<u64> {
+        if range.start > range.end || range.end > self.nelem() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "elements {:?} out of range for {} elements",
+                    range,
+                    self.nelem()
+                ),
+            ));
+        }
+        Ok(self.header.data_offset() + range.start * mem::size_of::<T>() as u64)
+    }
+
+    /// The range holding just element `i`
+    fn one(&self, i: u64) -> io::Result<Range<u64>> {
+        let end = i.checked_add(1).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("element {} out of range for {} elements", i, self.nelem()),
+            )
+        })?;
+        Ok(i..end)
+    }
+
+    /// Read the element at flat index `i`.
+    pub fn get(&mut self, i: u64) -> io::Result<T> {
+        let offset = self.offset_of(&self.one(i)?)?;
+        let mut bytes = vec![0u8; mem::size_of::<T>()];
+        self.read_at(offset, &mut bytes)?;
+        Ok(elements_from_bytes_with_endianness(&bytes, self.endianness())?[0])
+    }
+
+    /// Overwrite the element at flat index `i`.
+    pub fn set(&mut self, i: u64, value: T) -> io::Result<()> {
+        self.set_slice(self.one(i)?, &[value])
+    }
+
+    /// Overwrite the elements at flat indices `range` with `values`, which
+    /// must be as long as the range.
+    pub fn set_slice(&mut self, range: Range<u64>, values: &[T]) -> io::Result<()> {
+        let offset = self.offset_of(&range)?;
+        if values.len() as u64 != range.end - range.start {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} values given for the {} elements {:?}",
+                    values.len(),
+                    range.end - range.start,
+                    range
+                ),
+            ));
+        }
+        let bytes = bytes_in_order(values, self.endianness())?;
+        self.write_at(offset, &bytes)?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Bring the checksum, if any, up to date and make everything written
+    /// so far durable.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        if self.header.flags & FLAG_CRC != 0 {
+            let crc = self.data_crc()?;
+            let end = self.header.data_offset() + self.header.size;
+            self.write_at(end, &crc.to_le_bytes())?;
+        }
+        self.file.finish(true)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl<T: RawArrayType, S: RaStorage> Drop for RawArrayFileMut<'_, T, S> {
+    fn drop(&mut self) {
+        let _ = self.sync();
+    }
+}
@@ -0,0 +1,13 @@
+//! The types most programs need, in one import.
+//!
+//! ```
+//! use rawarray::prelude::*;
+//! let ra: RawArray<f32> = vec![1.0, 2.0].into();
+//! let opts = WriteOptions::new().endianness(Endianness::Little);
+//! # let _ = (ra, opts);
+//! ```
+
+pub use crate::codec::Codec;
+pub use crate::{AttrValue, DType, Endianness, RawArray, RawArrayBuilder, RawArrayError};
+pub use crate::{RawArrayHeader, RawArrayType, ReadOptions, ReadReport, SizePolicy};
+pub use crate::{UserElement, ValueFormatter, WriteOptions};
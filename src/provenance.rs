@@ -0,0 +1,284 @@
+//! Where an array came from: the program that made it, when, and from
+//! which files, so derived arrays in a long pipeline can be traced back
+//! to their inputs.
+//!
+//! Provenance is kept in attributes, so it's written with the array and
+//! read back with it, and older readers just see extra attributes:
+//!
+//! | Attribute               | Type | Value                                  |
+//! |-------------------------|------|----------------------------------------|
+//! | `ra.provenance.program` | Str  | name of the program                    |
+//! | `ra.provenance.version` | Str  | its version                            |
+//! | `ra.provenance.time`    | UInt | nanoseconds since the Unix epoch       |
+//! | `ra.provenance.inputs`  | Str  | a `<sha256>  <path>` line per input    |
+//!
+//! The inputs are listed as `sha256sum` prints them, so they can be
+//! checked with `sha256sum -c`. As there, a path holding a backslash or a
+//! line break is written with those escaped and a backslash before the
+//! line.
+//!
+//! Provenance is recorded by hand with [`RawArrayBuilder::provenance`] or
+//! [`RawArray::set_provenance`], or automatically for every array a
+//! program writes with [`Provenance::set_default`].
+//!
+//! [`RawArrayBuilder::provenance`]: crate::RawArrayBuilder::provenance
+
+use crate::attrs::{read_file_attrs, AttrValue};
+use crate::manifest::sha256_file;
+use crate::{RawArray, RawArrayType};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const PROGRAM_KEY: &str = "ra.provenance.program";
+const VERSION_KEY: &str = "ra.provenance.version";
+const TIME_KEY: &str = "ra.provenance.time";
+const INPUTS_KEY: &str = "ra.provenance.inputs";
+
+/// Provenance stamped on every array written, set by
+/// `Provenance::set_default`
+fn default_provenance() -> &'static RwLock<Option<Provenance>> {
+    static DEFAULT: OnceLock<RwLock<Option<Provenance>>> = OnceLock::new();
+    DEFAULT.get_or_init(|| RwLock::new(None))
+}
+
+/// The attributes to write for an array with attributes `attrs`: those,
+/// plus the default provenance, stamped now, if there is one and the
+/// array has no provenance of its own.
+pub(crate) fn attrs_to_write(
+    attrs: &BTreeMap<String, AttrValue>,
+) -> Cow<'_, BTreeMap<String, AttrValue>> {
+    if attrs.contains_key(PROGRAM_KEY) {
+        return Cow::Borrowed(attrs);
+    }
+    let default = default_provenance()
+        .read()
+        .unwrap_or_else(|e| e.into_inner());
+    match &*default {
+        Some(p) => {
+            let mut attrs = attrs.clone();
+            Provenance {
+                time: SystemTime::now(),
+                ..p.clone()
+            }
+            .to_attrs(&mut attrs);
+            Cow::Owned(attrs)
+        }
+        None => Cow::Borrowed(attrs),
+    }
+}
+
+/// `path` as `sha256sum` lists it: a backslash and line break each
+/// escaped with a backslash, with a flag saying whether any were
+fn escape_path(path: &str) -> (bool, String) {
+    let mut escaped = false;
+    let mut out = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => {
+                out.push(c);
+                continue;
+            }
+        }
+        escaped = true;
+    }
+    (escaped, out)
+}
+
+fn unescape_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// A file an array was made from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProvenanceInput {
+    /// Path of the file, as given when it was recorded
+    pub path: PathBuf,
+    /// SHA-256 of the file's contents, in lowercase hex
+    pub sha256: String,
+}
+
+/// The program that made an array, when, and from which files.
+/// ```
+/// # use std::io;
+/// use rawarray::{Provenance, RawArray, RawArrayBuilder};
+/// # fn main() -> io::Result<()> {
+/// let raw: RawArray<f32> = vec![1.0, 2.0, 3.0].into();
+/// raw.write("raw.ra")?;
+///
+/// let prov = Provenance::new("recon", "2.1.0").input("raw.ra")?;
+/// let image = RawArrayBuilder::new()
+///     .data(vec![0.5f32; 3])
+///     .provenance(&prov)
+///     .build()?;
+/// image.write("image.ra")?;
+///
+/// let found = Provenance::read("image.ra")?.unwrap();
+/// assert_eq!(found.program, "recon");
+/// assert_eq!(found.inputs[0].path.to_str(), Some("raw.ra"));
+/// assert_eq!(found.inputs[0].sha256.len(), 64);
+/// assert_eq!(RawArray::<f32>::read("image.ra")?.provenance(), Some(found));
+/// # std::fs::remove_file("raw.ra")?;
+/// # std::fs::remove_file("image.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Provenance {
+    /// Name of the program
+    pub program: String,
+    /// Version of the program
+    pub version: String,
+    /// When the array was made, to the nanosecond
+    pub time: SystemTime,
+    /// Files the array was made from
+    pub inputs: Vec<ProvenanceInput>,
+}
+
+impl Provenance {
+    /// Provenance for an array made now by `program` at `version`, with
+    /// no inputs yet. Passing `env!("CARGO_PKG_NAME")` and
+    /// `env!("CARGO_PKG_VERSION")` records the calling crate.
+    pub fn new(program: &str, version: &str) -> Provenance {
+        Provenance {
+            program: program.to_string(),
+            version: version.to_string(),
+            time: SystemTime::now(),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Add the file at `path` as an input, hashing its contents.
+    pub fn input<P: AsRef<Path>>(mut self, path: P) -> io::Result<Provenance> {
+        let path = path.as_ref();
+        self.inputs.push(ProvenanceInput {
+            path: path.to_path_buf(),
+            sha256: sha256_file(path)?,
+        });
+        Ok(self)
+    }
+
+    /// Stamp every `RawArray` this process writes from now on, by `write`
+    /// and its variants, with `provenance`, or stop with `None`. The time
+    /// is set to when each array is written, and arrays that already
+    /// carry provenance keep their own. Operations from file to file,
+    /// like `concat_files` or `recode_file`, copy the attributes of their
+    /// input instead.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{Provenance, RawArray};
+    /// # fn main() -> io::Result<()> {
+    /// RawArray::<u8>::from(vec![1, 2]).write("stamp_in.ra")?;
+    /// let prov = Provenance::new(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    ///     .input("stamp_in.ra")?;
+    /// Provenance::set_default(Some(prov));
+    /// RawArray::<u8>::from(vec![3, 4]).write("stamp_out.ra")?;
+    /// Provenance::set_default(None);
+    ///
+    /// let found = Provenance::read("stamp_out.ra")?.unwrap();
+    /// assert_eq!(found.program, env!("CARGO_PKG_NAME"));
+    /// assert_eq!(found.inputs[0].path.to_str(), Some("stamp_in.ra"));
+    /// # std::fs::remove_file("stamp_in.ra")?;
+    /// # std::fs::remove_file("stamp_out.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_default(provenance: Option<Provenance>) {
+        *default_provenance()
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = provenance;
+    }
+
+    /// Provenance recorded in the file at `path`, without reading its
+    /// data, or `None` if there is none.
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Option<Provenance>> {
+        Ok(Provenance::from_attrs(&read_file_attrs(path.as_ref())?))
+    }
+
+    pub(crate) fn to_attrs(&self, attrs: &mut BTreeMap<String, AttrValue>) {
+        let nanos = self
+            .time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX));
+        let inputs: String = self
+            .inputs
+            .iter()
+            .map(|i| match escape_path(&i.path.to_string_lossy()) {
+                (true, path) => format!("\\{}  {}\n", i.sha256, path),
+                (false, path) => format!("{}  {}\n", i.sha256, path),
+            })
+            .collect();
+        attrs.insert(PROGRAM_KEY.to_string(), self.program.as_str().into());
+        attrs.insert(VERSION_KEY.to_string(), self.version.as_str().into());
+        attrs.insert(TIME_KEY.to_string(), AttrValue::UInt(nanos));
+        attrs.insert(INPUTS_KEY.to_string(), inputs.into());
+    }
+
+    fn from_attrs(attrs: &BTreeMap<String, AttrValue>) -> Option<Provenance> {
+        let text = |key| attrs.get(key).and_then(AttrValue::as_str);
+        let nanos = match attrs.get(TIME_KEY) {
+            Some(&AttrValue::UInt(n)) => n,
+            _ => 0,
+        };
+        let inputs = text(INPUTS_KEY)
+            .unwrap_or("")
+            .lines()
+            .filter_map(|line| {
+                let (escaped, line) = match line.strip_prefix('\\') {
+                    Some(rest) => (true, rest),
+                    None => (false, line),
+                };
+                let (sha256, path) = line.split_once("  ")?;
+                let path = if escaped {
+                    unescape_path(path)
+                } else {
+                    path.to_string()
+                };
+                Some(ProvenanceInput {
+                    path: PathBuf::from(path),
+                    sha256: sha256.to_string(),
+                })
+            })
+            .collect();
+        Some(Provenance {
+            program: text(PROGRAM_KEY)?.to_string(),
+            version: text(VERSION_KEY).unwrap_or("").to_string(),
+            time: UNIX_EPOCH + Duration::from_nanos(nanos),
+            inputs,
+        })
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Record `provenance` in the array's attributes, replacing any
+    /// already there, so it's saved with the array by `write()`.
+    pub fn set_provenance(&mut self, provenance: &Provenance) {
+        provenance.to_attrs(&mut self.attrs);
+    }
+
+    /// The provenance recorded with the array, if any.
+    pub fn provenance(&self) -> Option<Provenance> {
+        Provenance::from_attrs(&self.attrs)
+    }
+}
@@ -0,0 +1,229 @@
+//! The Python `rawarray` module, behind the `python` feature, which reads
+//! RawArray files into NumPy arrays and writes NumPy arrays to them with
+//! this crate, so Python code sees exactly the files Rust code does.
+//!
+//! Build it with maturin, which picks up the settings in `pyproject.toml`:
+//! ```text
+//! maturin develop --release
+//! ```
+//! and then
+//! ```text
+//! >>> import numpy as np, rawarray
+//! >>> rawarray.write("x.ra", np.arange(6, dtype=np.float32).reshape(2, 3))
+//! >>> rawarray.read("x.ra").shape
+//! (2, 3)
+//! ```
+//! Arrays keep their shape, element type, and byte order. Elements are
+//! stored column major, so `read` gives Fortran-ordered arrays, and `write`
+//! only copies arrays that aren't Fortran-ordered already. The elements
+//! of an array from `read` are the buffer they were decoded into, shared
+//! with NumPy through the buffer protocol rather than copied.
+//!
+//! NumPy arrays have nowhere to keep attributes, so they're passed
+//! separately, as a dict of `int`, `float`, `str`, and `bytes` values:
+//! ```text
+//! >>> rawarray.write("x.ra", np.zeros(4), attrs={"units": "mm"})
+//! >>> rawarray.read_attrs("x.ra")
+//! {'units': 'mm'}
+//! ```
+//! `read` refuses files holding more than `max_bytes` of data, 64 GiB
+//! unless given.
+
+// the code pyo3's macros generate for `PyResult` trips this lint
+#![allow(clippy::useless_conversion)]
+
+use crate::attrs::{read_file_attrs, write_attrs, AttrValue};
+use crate::convert::{DType, Endianness};
+use crate::header::RawArrayHeader;
+use crate::npy::{descr, parse_descr};
+use crate::recode::{open_source_with, write_or_remove};
+use crate::{DEFAULT_MAX_BYTES, FLAG_ATTRS, FLAG_BIG_ENDIAN};
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use pyo3::types::{IntoPyDict, PyBytes, PyDict, PyFloat};
+use std::cell::UnsafeCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::os::raw::c_int;
+use std::path::PathBuf;
+
+fn value_error(e: io::Error) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+/// Elements read from a file, lent to the NumPy array made from them
+#[pyclass]
+struct Elements {
+    // NumPy writes through the pointer it is lent, never through `Elements`
+    bytes: UnsafeCell<Vec<u8>>,
+}
+
+#[pymethods]
+impl Elements {
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        let (ptr, len) = {
+            let bytes = &mut *slf.borrow().bytes.get();
+            (bytes.as_mut_ptr(), bytes.len())
+        };
+        // holds a reference to `slf` until NumPy releases the buffer
+        if ffi::PyBuffer_FillInfo(view, slf.as_ptr(), ptr.cast(), len as isize, 0, flags) < 0 {
+            return Err(PyErr::fetch(slf.py()));
+        }
+        Ok(())
+    }
+
+    unsafe fn __releasebuffer__(&self, _view: *mut ffi::Py_buffer) {}
+}
+
+/// An attribute value from Python
+fn attr_from_py(key: &str, value: &Bound<'_, PyAny>) -> PyResult<AttrValue> {
+    if let Ok(b) = value.downcast::<PyBytes>() {
+        Ok(AttrValue::Bytes(b.as_bytes().to_vec()))
+    } else if let Ok(s) = value.extract::<String>() {
+        Ok(AttrValue::Str(s))
+    } else if value.is_instance_of::<PyFloat>() {
+        Ok(AttrValue::Float(value.extract()?))
+    } else if let Ok(i) = value.extract::<i64>() {
+        Ok(AttrValue::Int(i))
+    } else if let Ok(u) = value.extract::<u64>() {
+        Ok(AttrValue::UInt(u))
+    } else if let Ok(f) = value.extract::<f64>() {
+        Ok(AttrValue::Float(f))
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "attribute {} isn't an int, float, str, or bytes",
+            key
+        )))
+    }
+}
+
+fn attr_to_py(py: Python<'_>, value: &AttrValue) -> PyObject {
+    match value {
+        AttrValue::Int(i) => i.into_py(py),
+        AttrValue::UInt(u) => u.into_py(py),
+        AttrValue::Float(f) => f.into_py(py),
+        AttrValue::Str(s) => s.into_py(py),
+        AttrValue::Bytes(b) => PyBytes::new_bound(py, b).into_py(py),
+    }
+}
+
+/// Read the RawArray file at `path` into a NumPy array, refusing files
+/// with more than `max_bytes` of data.
+#[pyfunction]
+#[pyo3(signature = (path, max_bytes = DEFAULT_MAX_BYTES))]
+fn read(py: Python<'_>, path: PathBuf, max_bytes: u64) -> PyResult<PyObject> {
+    let (dtype, endianness, dims, bytes) = py.allow_threads(|| -> io::Result<_> {
+        let mut src = open_source_with(&path, max_bytes)?;
+        let dtype = src.dtype();
+        let endianness = src.endianness();
+        let dims = src.header.dims.clone();
+        // grows as the elements arrive, not by what the header claims
+        let mut bytes = Vec::new();
+        src.convert(dtype, endianness, |b| {
+            bytes.extend_from_slice(b);
+            Ok(())
+        })?;
+        src.finish()?;
+        Ok((dtype, endianness, dims, bytes))
+    })?;
+    let descr = descr(dtype, endianness).map_err(value_error)?;
+    let elements = Bound::new(
+        py,
+        Elements {
+            bytes: UnsafeCell::new(bytes),
+        },
+    )?;
+    let flat = py
+        .import_bound("numpy")?
+        .call_method1("frombuffer", (elements, descr))?;
+    let order = [("order", "F")].into_py_dict_bound(py);
+    Ok(flat.call_method("reshape", (dims,), Some(&order))?.unbind())
+}
+
+/// Read the attributes of the RawArray file at `path` into a dict.
+#[pyfunction]
+fn read_attrs(py: Python<'_>, path: PathBuf) -> PyResult<Py<PyDict>> {
+    let attrs = py.allow_threads(|| read_file_attrs(&path))?;
+    let dict = PyDict::new_bound(py);
+    for (key, value) in &attrs {
+        dict.set_item(key, attr_to_py(py, value))?;
+    }
+    Ok(dict.unbind())
+}
+
+/// Write the NumPy array `array` to a RawArray file at `path`, with the
+/// attributes in the dict `attrs`, if given.
+#[pyfunction]
+#[pyo3(signature = (path, array, attrs = None))]
+fn write(
+    py: Python<'_>,
+    path: PathBuf,
+    array: &Bound<'_, PyAny>,
+    attrs: Option<&Bound<'_, PyDict>>,
+) -> PyResult<()> {
+    let mut attr_values = BTreeMap::new();
+    for (key, value) in attrs.into_iter().flatten() {
+        let key: String = key.extract()?;
+        let value = attr_from_py(&key, &value)?;
+        attr_values.insert(key, value);
+    }
+    let array = py
+        .import_bound("numpy")?
+        .call_method1("asfortranarray", (array,))?;
+    let descr: String = array.getattr("dtype")?.getattr("str")?.extract()?;
+    let (dtype, endianness): (DType, Endianness) = parse_descr(&descr).map_err(value_error)?;
+    let mut dims: Vec<u64> = array.getattr("shape")?.extract()?;
+    if dims.is_empty() {
+        dims.push(1);
+    }
+    // the elements as bytes in column-major order, still in the array
+    let order = [("order", "F")].into_py_dict_bound(py);
+    let flat = array
+        .call_method("reshape", (-1,), Some(&order))?
+        .call_method1("view", ("u1",))?;
+    let buffer = PyBuffer::<u8>::get_bound(&flat)?;
+    let data =
+        unsafe { std::slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes()) };
+    py.allow_threads(|| {
+        write_or_remove(&path, |out| {
+            let mut w = BufWriter::new(File::create(out)?);
+            let mut flags = if endianness == Endianness::Big {
+                FLAG_BIG_ENDIAN
+            } else {
+                0
+            };
+            if !attr_values.is_empty() {
+                flags |= FLAG_ATTRS;
+            }
+            RawArrayHeader {
+                flags,
+                eltype: dtype.eltype,
+                elbyte: dtype.elbyte,
+                size: data.len() as u64,
+                dims,
+            }
+            .write_to(&mut w)?;
+            w.write_all(data)?;
+            if !attr_values.is_empty() {
+                write_attrs(&mut w, &attr_values)?;
+            }
+            w.flush()
+        })
+    })?;
+    Ok(())
+}
+
+#[pymodule]
+pub(crate) fn rawarray(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(read, m)?)?;
+    m.add_function(wrap_pyfunction!(read_attrs, m)?)?;
+    m.add_function(wrap_pyfunction!(write, m)?)?;
+    Ok(())
+}
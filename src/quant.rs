@@ -0,0 +1,186 @@
+//! Affine quantization: storing `f32` data as small integers plus a scale
+//! and offset, for files a quarter or half the size.
+//!
+//! A quantized array holds integer codes `q` and two attributes,
+//! `ra.quant.scale` and `ra.quant.offset`, and stands for the values
+//! `q * scale + offset`. Any reader that understands attributes can
+//! dequantize it; this crate does so with `to_f32` or `read_dequantized`.
+
+use crate::{AttrValue, DType, Endianness, RawArray, RawArrayHeader, RawArrayType};
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+const SCALE_KEY: &str = "ra.quant.scale";
+const OFFSET_KEY: &str = "ra.quant.offset";
+
+/// Integer element types that quantized data can be stored as.
+pub trait QuantizedElement: RawArrayType {
+    /// Smallest code
+    const MIN: f64;
+    /// Largest code
+    const MAX: f64;
+    /// The code nearest `x`, saturating at `MIN` and `MAX`
+    fn from_code(x: f64) -> Self;
+    /// The code as a float
+    fn to_code(self) -> f64;
+}
+
+macro_rules! quantized_element {
+    ($($t:ty),*) => {
+        $(
+            impl QuantizedElement for $t {
+                const MIN: f64 = <$t>::MIN as f64;
+                const MAX: f64 = <$t>::MAX as f64;
+
+                fn from_code(x: f64) -> $t {
+                    x.round() as $t
+                }
+
+                fn to_code(self) -> f64 {
+                    f64::from(self)
+                }
+            }
+        )*
+    };
+}
+
+quantized_element!(u8, i8, u16, i16);
+
+fn check_native<T: RawArrayType>(ra: &RawArray<T>) {
+    assert!(
+        ra.endianness() == Endianness::native(),
+        "quantizing needs data in native byte order"
+    );
+}
+
+impl RawArray<f32> {
+    /// Quantize to codes of type `Q`, spreading the range of the finite
+    /// values over every code. NaNs become the code for the offset, and
+    /// infinities saturate. Other attributes are kept.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let ra = RawArray::from_iter_with_dims((0..6).map(|i| i as f32 * 0.5 - 1.0), &[3, 2])?;
+    /// let q = ra.quantize::<u8>();
+    /// assert_eq!(q.dims(), vec![3, 2]);
+    /// assert_eq!(q.data(), vec![0, 51, 102, 153, 204, 255]);
+    /// q.write("quantized.ra")?;
+    ///
+    /// let back = RawArray::<f32>::read_dequantized("quantized.ra")?;
+    /// for (a, b) in back.data().iter().zip(ra.data()) {
+    ///     assert!((a - b).abs() <= 0.5 * 2.5 / 255.0);
+    /// }
+    /// assert!(back.attrs().is_empty());
+    /// # std::fs::remove_file("quantized.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data isn't in native byte order.
+    pub fn quantize<Q: QuantizedElement>(&self) -> RawArray<Q> {
+        check_native(self);
+        let (lo, hi) = self
+            .as_slice()
+            .iter()
+            .filter(|x| x.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &x| {
+                (lo.min(f64::from(x)), hi.max(f64::from(x)))
+            });
+        let (scale, offset) = if lo > hi {
+            // nothing finite to fit
+            (1.0, 0.0)
+        } else if lo == hi {
+            (1.0, lo - Q::MIN)
+        } else {
+            let scale = (hi - lo) / (Q::MAX - Q::MIN);
+            (scale, lo - Q::MIN * scale)
+        };
+        let data = self
+            .as_slice()
+            .iter()
+            .map(|&x| {
+                let x = f64::from(x);
+                Q::from_code(if x.is_nan() {
+                    0.0
+                } else {
+                    (x - offset) / scale
+                })
+            })
+            .collect();
+        let mut q = self.clone_attrs_to(RawArray::from_parts(self.dims(), data));
+        q.set_attr(SCALE_KEY, scale);
+        q.set_attr(OFFSET_KEY, offset);
+        q
+    }
+
+    /// Read a quantized file of any `QuantizedElement` type, returning the
+    /// values its codes stand for.
+    pub fn read_dequantized<P: AsRef<Path>>(path: P) -> io::Result<RawArray<f32>> {
+        let h = RawArrayHeader::read(path.as_ref())?;
+        let dtype = DType {
+            eltype: h.eltype,
+            elbyte: h.elbyte,
+        };
+        if dtype == DType::of::<u8>() {
+            RawArray::<u8>::read(path)?.to_f32()
+        } else if dtype == DType::of::<i8>() {
+            RawArray::<i8>::read(path)?.to_f32()
+        } else if dtype == DType::of::<u16>() {
+            RawArray::<u16>::read(path)?.to_f32()
+        } else if dtype == DType::of::<i16>() {
+            RawArray::<i16>::read(path)?.to_f32()
+        } else {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} elements can't be quantized codes", dtype),
+            ))
+        }
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Copy this array's attributes to `other`.
+    fn clone_attrs_to<U: RawArrayType>(&self, mut other: RawArray<U>) -> RawArray<U> {
+        for (k, v) in self.attrs() {
+            other.set_attr(k, v.clone());
+        }
+        other
+    }
+}
+
+impl<Q: QuantizedElement> RawArray<Q> {
+    /// The scale and offset of a quantized array, if it is one
+    pub fn quantization(&self) -> Option<(f64, f64)> {
+        let scale = self.get_attr(SCALE_KEY).and_then(AttrValue::as_f64)?;
+        let offset = self.get_attr(OFFSET_KEY).and_then(AttrValue::as_f64)?;
+        Some((scale, offset))
+    }
+
+    /// Dequantize: the values the codes stand for, with the other
+    /// attributes kept. Fails if the array has no scale and offset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data isn't in native byte order.
+    pub fn to_f32(&self) -> io::Result<RawArray<f32>> {
+        check_native(self);
+        let (scale, offset) = self.quantization().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                "array has no quantization scale and offset",
+            )
+        })?;
+        let data = self
+            .as_slice()
+            .iter()
+            .map(|&q| (q.to_code() * scale + offset) as f32)
+            .collect();
+        let mut ra = self.clone_attrs_to(RawArray::from_parts(self.dims(), data));
+        ra.remove_attr(SCALE_KEY);
+        ra.remove_attr(OFFSET_KEY);
+        Ok(ra)
+    }
+}
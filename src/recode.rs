@@ -0,0 +1,327 @@
+//! Rewriting a file with a different byte order, codec, or element type,
+//! without loading it all into memory.
+
+use crate::attrs::{read_attrs, write_attrs, AttrValue};
+use crate::codec::{check_decoded_len, codec_for_flags, flag_for, lookup, CODEC_FLAGS};
+use crate::convert::{
+    check_conversion, check_magnitude, convert_bytes, magnitude_bytes, DType, Endianness,
+};
+use crate::header::RawArrayHeader;
+use crate::options::WriteOptions;
+use crate::{check_max_bytes, to_usize, DEFAULT_MAX_BYTES};
+use crate::{FLAG_ATTRS, FLAG_BIG_ENDIAN, FLAG_CRC, FLAG_ENCODED};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Cursor, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Elements converted per step when streaming
+const CHUNK_ELEMS: u64 = 1 << 20;
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// A data section being read element by element, decoded if it was
+/// encoded, with its checksum computed along the way.
+pub(crate) struct Source<R> {
+    pub(crate) header: RawArrayHeader,
+    reader: R,
+    decoded: Option<Cursor<Vec<u8>>>,
+    hasher: crc32fast::Hasher,
+}
+
+impl<R: Read> Source<R> {
+    /// Start reading the data section of a RawArray. `reader` must be just
+    /// past the header. Encoded data is decoded whole, so it's refused if
+    /// it would decode to more than `max_bytes`, or to more than its
+    /// payload can hold.
+    pub(crate) fn new(
+        header: RawArrayHeader,
+        mut reader: R,
+        max_bytes: u64,
+    ) -> io::Result<Source<R>> {
+        if header.elbyte == 0 {
+            return Err(Error::new(ErrorKind::InvalidData, "elbyte is zero"));
+        }
+        let mut decoded = None;
+        if let Some((id, id_stored)) = codec_for_flags(header.flags) {
+            let id = if id_stored {
+                read_u64(&mut reader)?
+            } else {
+                id
+            };
+            check_max_bytes(Some(header.size), max_bytes)?;
+            let codec = lookup(id)?;
+            let len = read_u64(&mut reader)?;
+            let mut payload = Vec::new();
+            (&mut reader).take(len).read_to_end(&mut payload)?;
+            if payload.len() as u64 != len {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "encoded data section is truncated",
+                ));
+            }
+            let elbyte = header.elbyte as usize;
+            check_decoded_len(codec.as_ref(), &payload, header.size, elbyte)?;
+            let mut bytes = vec![0u8; to_usize(header.size)?];
+            codec.decode(&payload, &mut bytes, elbyte)?;
+            decoded = Some(Cursor::new(bytes));
+        }
+        Ok(Source {
+            header,
+            reader,
+            decoded,
+            hasher: crc32fast::Hasher::new(),
+        })
+    }
+
+    pub(crate) fn dtype(&self) -> DType {
+        DType {
+            eltype: self.header.eltype,
+            elbyte: self.header.elbyte,
+        }
+    }
+
+    pub(crate) fn endianness(&self) -> Endianness {
+        if self.header.flags & FLAG_BIG_ENDIAN != 0 {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        }
+    }
+
+    /// Convert every element to `to` in byte order `to_endian`, passing the
+    /// converted bytes to `sink` a chunk at a time.
+    pub(crate) fn convert(
+        &mut self,
+        to: DType,
+        to_endian: Endianness,
+        sink: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let nelem = self.header.size / self.header.elbyte;
+        self.convert_next(nelem, to, to_endian, sink)
+    }
+
+    /// Convert the next `nelem` elements as `convert` does, leaving the
+    /// rest to later calls.
+    pub(crate) fn convert_next(
+        &mut self,
+        nelem: u64,
+        to: DType,
+        to_endian: Endianness,
+        mut sink: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let from = self.dtype();
+        let from_endian = self.endianness();
+        check_conversion(from, to)?;
+        let mut src = vec![0u8; (CHUNK_ELEMS.min(nelem) * from.elbyte) as usize];
+        let mut dst = Vec::new();
+        let mut remaining = nelem;
+        while remaining > 0 {
+            let n = CHUNK_ELEMS.min(remaining);
+            let chunk = &mut src[..(n * from.elbyte) as usize];
+            match self.decoded.as_mut() {
+                Some(c) => c.read_exact(chunk)?,
+                None => self.reader.read_exact(chunk)?,
+            }
+            self.hasher.update(chunk);
+            dst.clear();
+            convert_bytes(chunk, from, from_endian, &mut dst, to, to_endian)?;
+            sink(&dst)?;
+            remaining -= n;
+        }
+        Ok(())
+    }
+
+    /// Once every element has been converted, verify the checksum if there
+    /// is one and return the attributes that follow the data.
+    pub(crate) fn finish(mut self) -> io::Result<BTreeMap<String, AttrValue>> {
+        if self.header.flags & FLAG_CRC != 0 {
+            let stored = read_u64(&mut self.reader)?;
+            let computed = u64::from(self.hasher.finalize());
+            if stored != computed {
+                return Err(crate::RawArrayError::ChecksumMismatch { stored, computed }.into());
+            }
+        }
+        if self.header.flags & FLAG_ATTRS != 0 {
+            read_attrs(&mut self.reader)
+        } else {
+            Ok(BTreeMap::new())
+        }
+    }
+}
+
+/// Open a RawArray file for streaming conversion.
+pub(crate) fn open_source(path: &Path) -> io::Result<Source<BufReader<File>>> {
+    let mut r = BufReader::new(File::open(path)?);
+    let h = RawArrayHeader::read_from(&mut r)?;
+    Source::new(h, r, DEFAULT_MAX_BYTES)
+}
+
+/// Open a RawArray file to be read whole into memory, refusing it if its
+/// data is more than `max_bytes`.
+#[cfg(any(feature = "ffi", feature = "python"))]
+pub(crate) fn open_source_with(path: &Path, max_bytes: u64) -> io::Result<Source<BufReader<File>>> {
+    let mut r = BufReader::new(File::open(path)?);
+    let h = RawArrayHeader::read_from(&mut r)?;
+    check_max_bytes(Some(h.size), max_bytes)?;
+    Source::new(h, r, max_bytes)
+}
+
+/// A path for a temporary file next to `output`, keeping its name, and so
+/// its extension, at the end
+fn temp_path(output: &Path) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    let n = COUNT.fetch_add(1, Ordering::Relaxed);
+    output.with_file_name(format!(".tmp-{}-{}-{}", process::id(), n, name))
+}
+
+/// Run `f` to write `output`, by way of a temporary file in the same
+/// directory that is renamed over `output` only once `f` succeeds, and
+/// removed if it fails. `output` is never truncated, so it can also be one
+/// of the files `f` reads, and a failure leaves it as it was.
+pub(crate) fn write_or_remove(
+    output: &Path,
+    f: impl FnOnce(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+    let tmp = temp_path(output);
+    let result = f(&tmp).and_then(|()| fs::rename(&tmp, output));
+    if result.is_err() {
+        fs::remove_file(&tmp).ok();
+    }
+    result
+}
+
+/// Copy the array in `input` to `output`, changing its byte order, codec,
+/// element type, and checksum as given by `opts`, or taking the magnitudes
+/// of complex elements. Attributes are carried
+/// over. See `convert` for how element types are converted.
+///
+/// Plain data is streamed a chunk at a time, so memory use stays small no
+/// matter how large the file. Codecs work on whole data sections, so an
+/// encoded input or output is held in memory once.
+///
+/// The output is written to a temporary file that replaces `output` only
+/// once it's complete, so `output` may be `input` for converting in place.
+/// If anything goes wrong, including a checksum mismatch in the input,
+/// the partial output is removed and `output` is left as it was.
+/// ```
+/// # use std::io;
+/// use rawarray::{DType, Endianness, RawArray, WriteOptions};
+/// # fn main() -> io::Result<()> {
+/// let ra: RawArray<i16> = vec![1, -2, 300].into();
+/// ra.write("in16.ra")?;
+/// let opts = WriteOptions::new()
+///     .dtype(DType::of::<f64>())
+///     .endianness(Endianness::Big);
+/// rawarray::recode_file("in16.ra", "out64.ra", &opts)?;
+/// assert_eq!(RawArray::<f64>::read("out64.ra")?.data(), vec![1.0, -2.0, 300.0]);
+/// # std::fs::remove_file("in16.ra")?;
+/// # std::fs::remove_file("out64.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn recode_file<P: AsRef<Path>, Q: AsRef<Path>>(
+    input: P,
+    output: Q,
+    opts: &WriteOptions,
+) -> io::Result<()> {
+    let src = open_source(input.as_ref())?;
+    write_or_remove(output.as_ref(), |out| write_source(src, out, opts))
+}
+
+/// Write everything left in `src` to a new RawArray file at `output`.
+pub(crate) fn write_source<R: Read>(
+    mut src: Source<R>,
+    output: &Path,
+    opts: &WriteOptions,
+) -> io::Result<()> {
+    let h = &src.header;
+    let from = src.dtype();
+    let from_endian = src.endianness();
+    let magnitude = opts.magnitude && from.components() == 2;
+    let to = match opts.dtype {
+        Some(to) => to,
+        None if magnitude => from.magnitude(),
+        None => from,
+    };
+    if magnitude {
+        check_magnitude(from, to)?;
+    } else {
+        check_conversion(from, to)?;
+    }
+    let crc_out = opts.checksum.unwrap_or(h.flags & FLAG_CRC != 0);
+    let nelem = h.size / h.elbyte;
+
+    let mut flags = h.flags & !(CODEC_FLAGS | FLAG_BIG_ENDIAN | FLAG_CRC);
+    if opts.endianness == Endianness::Big {
+        flags |= FLAG_BIG_ENDIAN;
+    }
+    if crc_out {
+        flags |= FLAG_CRC;
+    }
+    if let Some(codec) = &opts.codec {
+        flags |= flag_for(codec.as_ref());
+    }
+    let mut w = BufWriter::new(File::create(output)?);
+    RawArrayHeader {
+        flags,
+        eltype: to.eltype,
+        elbyte: to.elbyte,
+        size: nelem * to.elbyte,
+        dims: h.dims.clone(),
+    }
+    .write_to(&mut w)?;
+
+    let mut hasher = crc32fast::Hasher::new();
+    let mut whole = Vec::new();
+    let mut mags = Vec::new();
+    // magnitudes are taken of the elements as stored
+    let (step, step_endian) = if magnitude {
+        (from, from_endian)
+    } else {
+        (to, opts.endianness)
+    };
+    src.convert(step, step_endian, |bytes| {
+        let bytes = if magnitude {
+            mags.clear();
+            magnitude_bytes(bytes, from, from_endian, &mut mags, to, opts.endianness)?;
+            &mags
+        } else {
+            bytes
+        };
+        hasher.update(bytes);
+        if opts.codec.is_some() {
+            whole.extend_from_slice(bytes);
+            Ok(())
+        } else {
+            w.write_all(bytes)
+        }
+    })?;
+    if let Some(codec) = &opts.codec {
+        let payload = codec.encode(&whole, to.elbyte as usize)?;
+        if flag_for(codec.as_ref()) == FLAG_ENCODED {
+            w.write_all(&codec.id().to_le_bytes())?;
+        }
+        w.write_all(&(payload.len() as u64).to_le_bytes())?;
+        w.write_all(&payload)?;
+    }
+    if crc_out {
+        w.write_all(&u64::from(hasher.finalize()).to_le_bytes())?;
+    }
+    let attrs = src.finish()?;
+    if !attrs.is_empty() {
+        write_attrs(&mut w, &attrs)?;
+    }
+    w.flush()?;
+    if opts.sync {
+        w.get_ref().sync_data()?;
+    }
+    Ok(())
+}
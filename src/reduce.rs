@@ -0,0 +1,162 @@
+//! Reductions over a whole array or along one axis.
+
+use crate::axis::axis_strides;
+use crate::{Endianness, RawArray, RawArrayType};
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
+use std::ops::Add;
+
+impl<T: RawArrayType> RawArray<T> {
+    fn assert_native(&self, what: &str) {
+        assert!(
+            self.endianness() == Endianness::native(),
+            "{} needs data in native byte order",
+            what
+        );
+    }
+}
+
+impl<T: RawArrayType + Zero + Add<Output = T>> RawArray<T> {
+    /// Sum along `axis`, which is removed from the result, as numpy's
+    /// `sum(axis=...)` does.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims(0..6i32, &[2, 3]).unwrap();
+    /// assert_eq!(ra.sum_axis(0).data(), vec![1, 5, 9]);
+    /// assert_eq!(ra.sum_axis(1).data(), vec![6, 9]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range, or if the data isn't in native
+    /// byte order.
+    pub fn sum_axis(&self, axis: usize) -> RawArray<T> {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        self.assert_native("sum_axis");
+        let (inner, outer) = axis_strides(&self.dims, axis);
+        let n = self.dims[axis] as usize;
+        let mut out = vec![T::zero(); inner * outer];
+        for o in 0..outer {
+            let acc = &mut out[inner * o..inner * (o + 1)];
+            for j in 0..n {
+                let begin = inner * (j + n * o);
+                for (a, &x) in acc.iter_mut().zip(&self.data[begin..begin + inner]) {
+                    *a = *a + x;
+                }
+            }
+        }
+        let mut dims = self.dims.clone();
+        dims.remove(axis);
+        RawArray::from_parts(dims, out)
+    }
+
+    /// Sum of all elements
+    ///
+    /// # Panics
+    ///
+    /// Panics if the data isn't in native byte order.
+    pub fn sum(&self) -> T {
+        self.assert_native("sum");
+        self.data.iter().fold(T::zero(), |acc, &x| acc + x)
+    }
+}
+
+impl<T: RawArrayType + ToPrimitive + FromPrimitive> RawArray<T> {
+    /// Mean along `axis`, which is removed from the result, such as the
+    /// temporal mean of a 4-D series with `axis` 3. Sums are accumulated as
+    /// `f64`, so they can't overflow the element type, and integer means
+    /// round toward zero.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims((0..8).map(|i| i as f32), &[2, 2, 2]).unwrap();
+    /// let m = ra.mean_axis(2);
+    /// assert_eq!(m.dims(), vec![2, 2]);
+    /// assert_eq!(m.data(), vec![2.0, 3.0, 4.0, 5.0]);
+    ///
+    /// let bytes = RawArray::from_iter_with_dims(vec![200u8; 600], &[2, 300]).unwrap();
+    /// assert_eq!(bytes.mean_axis(1).data(), vec![200, 200]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range or has length zero, or if the data
+    /// isn't in native byte order.
+    pub fn mean_axis(&self, axis: usize) -> RawArray<T> {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        assert!(self.dims[axis] > 0, "can't average along an empty axis");
+        self.assert_native("mean_axis");
+        let (inner, outer) = axis_strides(&self.dims, axis);
+        let n = self.dims[axis] as usize;
+        let mut sums = vec![0f64; inner * outer];
+        for o in 0..outer {
+            let acc = &mut sums[inner * o..inner * (o + 1)];
+            for j in 0..n {
+                let begin = inner * (j + n * o);
+                for (a, x) in acc.iter_mut().zip(&self.data[begin..begin + inner]) {
+                    *a += x.to_f64().expect("element must convert to f64");
+                }
+            }
+        }
+        let out = sums
+            .into_iter()
+            .map(|s| T::from_f64(s / n as f64).expect("a mean lies within the element type"))
+            .collect();
+        let mut dims = self.dims.clone();
+        dims.remove(axis);
+        RawArray::from_parts(dims, out)
+    }
+}
+
+/// Whether `x` can be ordered at all; false only for NaNs
+fn comparable<T: PartialOrd>(x: &T) -> bool {
+    x.partial_cmp(x).is_some()
+}
+
+impl<T: RawArrayType + PartialOrd> RawArray<T> {
+    /// Index and value of the element that `better` prefers over all the
+    /// others, keeping the first of equals and skipping NaNs
+    fn extreme(&self, better: impl Fn(&T, &T) -> bool) -> Option<(usize, T)> {
+        self.assert_native("min, max, argmin, and argmax");
+        let mut best: Option<(usize, T)> = None;
+        for (i, &x) in self.data.iter().enumerate() {
+            if !comparable(&x) {
+                continue;
+            }
+            match best {
+                Some((_, b)) if !better(&x, &b) => {}
+                _ => best = Some((i, x)),
+            }
+        }
+        best
+    }
+
+    /// Smallest element, ignoring NaNs, or `None` if there are none. This
+    /// and the other extremes panic if the data isn't in native byte order.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra: RawArray<f64> = vec![2.0, f64::NAN, -1.0, 7.5].into();
+    /// assert_eq!(ra.min(), Some(-1.0));
+    /// assert_eq!(ra.max(), Some(7.5));
+    /// assert_eq!(ra.argmax(), Some(3));
+    /// assert_eq!(ra.argmin(), Some(2));
+    /// ```
+    pub fn min(&self) -> Option<T> {
+        self.extreme(|x, best| x < best).map(|(_, x)| x)
+    }
+
+    /// Largest element, ignoring NaNs, or `None` if there are none.
+    pub fn max(&self) -> Option<T> {
+        self.extreme(|x, best| x > best).map(|(_, x)| x)
+    }
+
+    /// Flat index of the first smallest element, ignoring NaNs. Use
+    /// `dims()` to turn it into coordinates.
+    pub fn argmin(&self) -> Option<usize> {
+        self.extreme(|x, best| x < best).map(|(i, _)| i)
+    }
+
+    /// Flat index of the first largest element, ignoring NaNs. Use
+    /// `dims()` to turn it into coordinates.
+    pub fn argmax(&self) -> Option<usize> {
+        self.extreme(|x, best| x > best).map(|(i, _)| i)
+    }
+}
@@ -0,0 +1,77 @@
+//! Reproducible shuffling and sampling along an axis, for building
+//! randomized training subsets out of stored datasets.
+//!
+//! The generator is ChaCha8 seeded directly from the caller's seed, so the
+//! same seed gives the same permutation on every platform and release.
+
+use crate::axis::take_axis_indices;
+use crate::{RawArray, RawArrayType};
+use rand::seq::{index, SliceRandom};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Return a copy with the hyperslices along `axis` in a random order
+    /// determined by `seed`. The copy keeps the flags and attributes.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<u8> = vec![0, 1, 2, 3, 4, 5, 6, 7].into();
+    /// r.reshape(vec![2, 4]);
+    /// let s = r.shuffle_axis(1, 42);
+    /// assert_eq!(s.dims(), vec![2, 4]);
+    /// assert_eq!(s, r.shuffle_axis(1, 42));
+    /// let mut sorted = s.data();
+    /// sorted.sort();
+    /// assert_eq!(sorted, r.data());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range.
+    pub fn shuffle_axis(&self, axis: usize, seed: u64) -> RawArray<T> {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut indices: Vec<usize> = (0..self.dims[axis] as usize).collect();
+        indices.shuffle(&mut rng);
+        let data = take_axis_indices(&self.data, &self.dims, axis, &indices);
+        let mut ra = RawArray::from_parts(self.dims.clone(), data);
+        ra.flags = self.flags;
+        ra.attrs = self.attrs.clone();
+        ra
+    }
+
+    /// Return `n` hyperslices along `axis` chosen at random without
+    /// replacement, using `seed`. The chosen slices keep their original
+    /// relative order, and the result keeps the flags and attributes.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let mut r: RawArray<u8> = vec![0, 1, 2, 3, 4, 5, 6, 7].into();
+    /// r.reshape(vec![2, 4]);
+    /// let s = r.sample_axis(1, 2, 7);
+    /// assert_eq!(s.dims(), vec![2, 2]);
+    /// assert_eq!(s, r.sample_axis(1, 2, 7));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range or `n` exceeds the length of `axis`.
+    pub fn sample_axis(&self, axis: usize, n: u64, seed: u64) -> RawArray<T> {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        assert!(
+            n <= self.dims[axis],
+            "cannot sample {} of {} elements",
+            n,
+            self.dims[axis]
+        );
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut indices = index::sample(&mut rng, self.dims[axis] as usize, n as usize).into_vec();
+        indices.sort_unstable();
+        let data = take_axis_indices(&self.data, &self.dims, axis, &indices);
+        let mut dims = self.dims.clone();
+        dims[axis] = n;
+        let mut ra = RawArray::from_parts(dims, data);
+        ra.flags = self.flags;
+        ra.attrs = self.attrs.clone();
+        ra
+    }
+}
@@ -0,0 +1,193 @@
+//! Searching sorted one-dimensional arrays, like timestamps, in place on
+//! disk.
+//!
+//! A sorted array can carry a sparse index of every `stride`-th element in
+//! its attributes, so a lookup in the file costs two small reads instead
+//! of a binary search over the whole data section. Without the index the
+//! search falls back to one read per bisection step.
+
+use crate::attrs::AttrValue;
+use crate::convert::{elements_from_bytes_with_endianness, elements_to_le_bytes};
+use crate::{
+    attrs, codec, to_usize, Endianness, FsStorage, RaStorage, RawArray, RawArrayType, SizePolicy,
+    FLAG_ATTRS, FLAG_BITS, FLAG_CRC,
+};
+use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::mem;
+use std::path::Path;
+
+/// Attribute holding the distance between indexed elements
+const STRIDE_KEY: &str = "ra.search_stride";
+/// Attribute holding the indexed elements as little-endian bytes
+const INDEX_KEY: &str = "ra.search_index";
+/// Attribute holding the length of the array when it was indexed
+const LEN_KEY: &str = "ra.search_len";
+
+impl<T: RawArrayType + PartialOrd> RawArray<T> {
+    /// Index of the first element that is not less than `value`, or the
+    /// length if there is none, like numpy's `searchsorted`. The elements
+    /// must be sorted in ascending order.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra: RawArray<u32> = vec![10, 20, 20, 30].into();
+    /// assert_eq!(ra.search_sorted(&20), 1);
+    /// assert_eq!(ra.search_sorted(&25), 3);
+    /// assert_eq!(ra.search_sorted(&99), 4);
+    /// ```
+    pub fn search_sorted(&self, value: &T) -> usize {
+        self.data.partition_point(|x| x < value)
+    }
+
+    /// Record every `stride`-th element in the attributes, so that
+    /// `search_sorted_file` can find values with a couple of reads once
+    /// the array is written. Fails with `ErrorKind::InvalidInput` if the
+    /// array isn't one-dimensional, sorted, and in native byte order.
+    ///
+    /// The index records the array's length, and is ignored once that no
+    /// longer matches, e.g. after `append`, until it's built again.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let times: RawArray<i64> = (0..100_000).map(|i| 3 * i).collect();
+    /// let mut indexed = times.clone();
+    /// indexed.build_search_index(1000)?;
+    /// indexed.write("times.ra")?;
+    /// assert_eq!(RawArray::<i64>::search_sorted_file("times.ra", &3001)?, 1001);
+    /// assert_eq!(RawArray::<i64>::search_sorted_file("times.ra", &-5)?, 0);
+    /// assert_eq!(RawArray::<i64>::search_sorted_file("times.ra", &(1 << 40))?, 100_000);
+    ///
+    /// // appending leaves the index behind, so it's no longer used
+    /// RawArray::append("times.ra", &[300_000i64, 300_003])?;
+    /// assert_eq!(RawArray::<i64>::search_sorted_file("times.ra", &300_001)?, 100_001);
+    ///
+    /// // without an index, the file is bisected instead
+    /// times.write("times.ra")?;
+    /// assert_eq!(RawArray::<i64>::search_sorted_file("times.ra", &3001)?, 1001);
+    /// # std::fs::remove_file("times.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn build_search_index(&mut self, stride: u64) -> io::Result<()> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidInput, msg.to_string());
+        if stride == 0 {
+            return Err(invalid("search index stride must be nonzero"));
+        }
+        if self.dims.len() != 1 {
+            return Err(invalid("only one-dimensional arrays can be searched"));
+        }
+        if self.endianness() != Endianness::native() {
+            return Err(invalid("array must be in native byte order to index it"));
+        }
+        if self.data.windows(2).any(|w| w[1] < w[0]) {
+            return Err(invalid("array is not sorted"));
+        }
+        let samples: Vec<T> = self.data.iter().step_by(stride as usize).copied().collect();
+        self.set_attr(STRIDE_KEY, stride);
+        self.set_attr(INDEX_KEY, elements_to_le_bytes(&samples));
+        self.set_attr(LEN_KEY, self.data.len() as u64);
+        Ok(())
+    }
+
+    /// Find where `value` belongs in the sorted one-dimensional array in
+    /// the file at `path`, as `search_sorted` would, without loading the
+    /// data. The data section must not be compressed or encoded.
+    pub fn search_sorted_file<P: AsRef<Path>>(path: P, value: &T) -> io::Result<usize> {
+        RawArray::search_sorted_in_storage(&FsStorage, path, value)
+    }
+
+    /// Search a file in `storage` as `search_sorted_file()` does. See
+    /// `RaStorage`.
+    pub fn search_sorted_in_storage<S: RaStorage, P: AsRef<Path>>(
+        storage: &S,
+        path: P,
+        value: &T,
+    ) -> io::Result<usize> {
+        let (ra, _, mut r) =
+            RawArray::<T>::open_checked_in(storage, path.as_ref(), SizePolicy::Strict, u64::MAX)?;
+        if ra.flags & (codec::CODEC_FLAGS | FLAG_BITS) != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "can't search encoded data in place",
+            ));
+        }
+        if ra.dims.len() != 1 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "only one-dimensional arrays can be searched",
+            ));
+        }
+        let n = to_usize(ra.dims[0])?;
+        let mut lo = 0;
+        let mut hi = n;
+        if let Some((stride, samples)) = ra.read_search_index(&mut r)? {
+            // samples[j] is element j * stride, so the answer lies after
+            // the last sample below `value` and at or before the next one
+            let p = samples.partition_point(|x| x < value);
+            if p == 0 {
+                return Ok(0);
+            }
+            lo = (p - 1) * stride + 1;
+            hi = (p * stride).min(n);
+        }
+        if lo >= hi {
+            return Ok(lo);
+        }
+        // a block small enough to read whole; otherwise bisect on disk
+        if hi - lo <= 4096 {
+            let block = ra.read_elements(&mut r, lo, hi - lo)?;
+            return Ok(lo + block.partition_point(|x| x < value));
+        }
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if ra.read_elements(&mut r, mid, 1)?[0] < *value {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        Ok(lo)
+    }
+
+    /// Read `count` elements starting at element `start` of the data
+    /// section, converting them to native byte order.
+    fn read_elements<R: Read + Seek>(
+        &self,
+        r: &mut R,
+        start: usize,
+        count: usize,
+    ) -> io::Result<Vec<T>> {
+        let width = mem::size_of::<T>();
+        r.seek(SeekFrom::Start(self.data_offset() + (start * width) as u64))?;
+        let mut bytes = vec![0u8; count * width];
+        r.read_exact(&mut bytes)?;
+        elements_from_bytes_with_endianness(&bytes, self.endianness())
+    }
+
+    /// The stride and samples stored by `build_search_index`, if any.
+    fn read_search_index<R: Read + Seek>(&self, r: &mut R) -> io::Result<Option<(usize, Vec<T>)>> {
+        if self.flags & FLAG_ATTRS == 0 {
+            return Ok(None);
+        }
+        let mut offset = self.data_offset() + self.size;
+        if self.flags & FLAG_CRC != 0 {
+            offset += 8;
+        }
+        r.seek(SeekFrom::Start(offset))?;
+        let attrs = attrs::read_attrs(r)?;
+        let len = attrs.get(LEN_KEY);
+        match (attrs.get(STRIDE_KEY), attrs.get(INDEX_KEY)) {
+            (Some(&AttrValue::UInt(stride)), Some(AttrValue::Bytes(bytes)))
+                if stride > 0 && len == Some(&AttrValue::UInt(self.dims[0])) =>
+            {
+                let samples = elements_from_bytes_with_endianness(bytes, Endianness::Little)?;
+                if samples.len() as u64 != self.dims[0].div_ceil(stride) {
+                    return Ok(None);
+                }
+                Ok(Some((stride as usize, samples)))
+            }
+            // missing, or left over from before the array changed length
+            _ => Ok(None),
+        }
+    }
+}
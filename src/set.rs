@@ -0,0 +1,250 @@
+//! Several arrays stored back to back in one file.
+//!
+//! A set file is just complete RawArray records written one after another,
+//! followed by an index:
+//!
+//! | object  | type           | meaning
+//! |---------|----------------|--------
+//! | offsets | Vector{UInt64} | byte offset of each record
+//! | count   | UInt64         | number of records
+//! | magic   | UInt64         | `SET_MAGIC`, marks the index
+//!
+//! Because the first record starts at offset 0 and everything after it
+//! counts as volatile metadata, a set file is also a valid single-array
+//! file holding its first array. Conversely, files without an index,
+//! including plain single-array files, are opened by walking the records.
+
+use crate::codec::codec_for_flags;
+use crate::{RawArray, RawArrayType, FLAG_ATTRS, FLAG_CRC, MAGIC_NUMBER};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Marks the index at the end of a set file ("rasetidx")
+const SET_MAGIC: u64 = 0x78_64_69_74_65_73_61_72;
+
+fn read_u64_at<R: Read + Seek>(r: &mut R, offset: u64) -> io::Result<u64> {
+    r.seek(SeekFrom::Start(offset))?;
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn corrupt_record() -> Error {
+    Error::new(ErrorKind::InvalidData, "corrupt record header in set")
+}
+
+/// Length in bytes of the record starting at `offset`, worked out from
+/// its header alone.
+fn record_len<R: Read + Seek>(r: &mut R, offset: u64) -> io::Result<u64> {
+    let at = |n: u64| offset.checked_add(n).ok_or_else(corrupt_record);
+    let flags = read_u64_at(r, at(8)?)?;
+    let size = read_u64_at(r, at(32)?)?;
+    let ndims = read_u64_at(r, at(40)?)?;
+    let data_offset = ndims
+        .checked_mul(8)
+        .and_then(|n| n.checked_add(48))
+        .ok_or_else(corrupt_record)?;
+    let (fixed, payload) = match codec_for_flags(flags) {
+        Some((_, true)) => (16, read_u64_at(r, at(data_offset.saturating_add(8))?)?),
+        Some((_, false)) => (8, read_u64_at(r, at(data_offset)?)?),
+        None => (0, size),
+    };
+    let crc = if flags & FLAG_CRC != 0 { 8 } else { 0 };
+    let mut len = data_offset
+        .checked_add(fixed + crc)
+        .and_then(|n| n.checked_add(payload))
+        .ok_or_else(corrupt_record)?;
+    if flags & FLAG_ATTRS != 0 {
+        let attrs_len = read_u64_at(r, at(len)?)?;
+        len = len
+            .checked_add(8)
+            .and_then(|n| n.checked_add(attrs_len))
+            .ok_or_else(corrupt_record)?;
+    }
+    Ok(len)
+}
+
+/// Read access to a file holding several arrays, possibly of different
+/// element types.
+/// ```
+/// # use std::io;
+/// use rawarray::{RawArray, RawArraySet, RawArraySetWriter};
+/// # fn main() -> io::Result<()> {
+/// let image: RawArray<f32> = vec![0.5, 1.5, 2.5].into();
+/// let mask: RawArray<u8> = vec![0, 1, 1].into();
+/// let mut w = RawArraySetWriter::create("scan.ra")?;
+/// w.push(&image)?;
+/// w.push(&mask)?;
+/// w.finish()?;
+///
+/// let mut set = RawArraySet::open("scan.ra")?;
+/// assert_eq!(set.len(), 2);
+/// assert_eq!(set.read_nth::<u8>(1)?, mask);
+/// // the first array is still readable the usual way
+/// assert_eq!(RawArray::<f32>::read("scan.ra")?, image);
+/// # std::fs::remove_file("scan.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RawArraySet {
+    file: BufReader<File>,
+    offsets: Vec<u64>,
+}
+
+/// Whether the file `r` of length `file_len` ends in a set index
+fn ends_in_index<R: Read + Seek>(r: &mut R, file_len: u64) -> io::Result<bool> {
+    Ok(file_len >= 16 && read_u64_at(r, file_len - 8)? == SET_MAGIC)
+}
+
+impl RawArraySet {
+    /// Whether the file at `path` ends in a set index, so that the bytes
+    /// after its first array are more arrays rather than disposable
+    /// metadata.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{RawArray, RawArraySet, RawArraySetWriter};
+    /// # fn main() -> io::Result<()> {
+    /// let ra: RawArray<u8> = vec![1, 2].into();
+    /// ra.write("single.ra")?;
+    /// let mut w = RawArraySetWriter::create("pair.ra")?;
+    /// w.push(&ra)?;
+    /// w.push(&ra)?;
+    /// w.finish()?;
+    /// assert!(!RawArraySet::has_index("single.ra")?);
+    /// assert!(RawArraySet::has_index("pair.ra")?);
+    /// # std::fs::remove_file("single.ra")?;
+    /// # std::fs::remove_file("pair.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn has_index<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+        let mut file = File::open(path)?;
+        let file_len = file.seek(SeekFrom::End(0))?;
+        ends_in_index(&mut file, file_len)
+    }
+
+    /// Open a set file, or any single-array file as a set of one.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<RawArraySet> {
+        let mut file = BufReader::new(File::open(path)?);
+        let file_len = file.seek(SeekFrom::End(0))?;
+        let mut offsets = Vec::new();
+        if ends_in_index(&mut file, file_len)? {
+            let count = read_u64_at(&mut file, file_len - 16)?;
+            let index_start = count
+                .checked_mul(8)
+                .and_then(|n| (file_len - 16).checked_sub(n))
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "corrupt set index"))?;
+            file.seek(SeekFrom::Start(index_start))?;
+            for _ in 0..count {
+                let mut buf = [0u8; 8];
+                file.read_exact(&mut buf)?;
+                offsets.push(u64::from_le_bytes(buf));
+            }
+        } else {
+            // anything after the last record is volatile metadata
+            let mut offset = 0u64;
+            while offset.checked_add(8).is_some_and(|end| end <= file_len)
+                && read_u64_at(&mut file, offset)? == MAGIC_NUMBER
+            {
+                offsets.push(offset);
+                offset = offset
+                    .checked_add(record_len(&mut file, offset)?)
+                    .ok_or_else(corrupt_record)?;
+            }
+            if offsets.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Invalid magic, likely not a RawArray file.",
+                ));
+            }
+        }
+        Ok(RawArraySet { file, offsets })
+    }
+
+    /// Number of arrays in the set
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Whether the set has no arrays at all
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// Read the array at position `i`.
+    pub fn read_nth<T: RawArrayType>(&mut self, i: usize) -> io::Result<RawArray<T>> {
+        let offset = *self.offsets.get(i).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("set has {} arrays, no index {}", self.offsets.len(), i),
+            )
+        })?;
+        self.file.seek(SeekFrom::Start(offset))?;
+        RawArray::read_from(&mut self.file)
+    }
+
+    /// Iterate over the arrays of a set whose arrays share element type `T`.
+    pub fn iter<T: RawArrayType>(&mut self) -> RawArraySetIter<'_, T> {
+        RawArraySetIter {
+            set: self,
+            next: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Iterator over the arrays of a `RawArraySet`, created by
+/// [`RawArraySet::iter`].
+pub struct RawArraySetIter<'a, T: RawArrayType> {
+    set: &'a mut RawArraySet,
+    next: usize,
+    marker: PhantomData<T>,
+}
+
+impl<'a, T: RawArrayType> Iterator for RawArraySetIter<'a, T> {
+    type Item = io::Result<RawArray<T>>;
+
+    fn next(&mut self) -> Option<io::Result<RawArray<T>>> {
+        if self.next >= self.set.len() {
+            return None;
+        }
+        self.next += 1;
+        Some(self.set.read_nth(self.next - 1))
+    }
+}
+
+/// Writes several arrays into one file. See [`RawArraySet`].
+pub struct RawArraySetWriter {
+    w: BufWriter<File>,
+    offsets: Vec<u64>,
+}
+
+impl RawArraySetWriter {
+    /// Create a new set file, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<RawArraySetWriter> {
+        Ok(RawArraySetWriter {
+            w: BufWriter::new(File::create(path)?),
+            offsets: Vec::new(),
+        })
+    }
+
+    /// Append an array to the set.
+    pub fn push<T: RawArrayType>(&mut self, ra: &RawArray<T>) -> io::Result<()> {
+        self.offsets.push(self.w.stream_position()?);
+        ra.write_to(&mut self.w)
+    }
+
+    /// Write the index and close the file. Without this the file can still
+    /// be opened, but only by walking every record.
+    pub fn finish(mut self) -> io::Result<()> {
+        for &offset in &self.offsets {
+            self.w.write_all(&offset.to_le_bytes())?;
+        }
+        self.w
+            .write_all(&(self.offsets.len() as u64).to_le_bytes())?;
+        self.w.write_all(&SET_MAGIC.to_le_bytes())?;
+        self.w.flush()
+    }
+}
@@ -0,0 +1,119 @@
+//! Conversion between full complex spectra and the half-spectrum layout
+//! produced by real-input FFTs.
+//!
+//! The FFT of real data is Hermitian, `X[k] = conj(X[-k])`, so real-input
+//! FFTs only return the first `n / 2 + 1` elements along one axis. FFTW
+//! halves the last axis of a row-major array, which is the first axis of
+//! the same array stored column major, as RawArrays are. rustfft's real
+//! transforms halve the axis they run along, which is also the first axis
+//! for contiguous column-major data. So here axis 0 is the halved one.
+
+use crate::axis::{axis_strides, take_axis_range};
+use crate::{RawArray, RawArrayType};
+use num_complex::Complex;
+use num_traits::Float;
+
+/// Length of the half spectrum of `n` elements; an empty axis stays empty.
+fn half_len(n: u64) -> u64 {
+    if n == 0 {
+        0
+    } else {
+        n / 2 + 1
+    }
+}
+
+/// Dimensions of the half spectrum of an array with dimensions `dims`.
+/// ```
+/// assert_eq!(rawarray::half_spectrum_dims(&[8, 3]), vec![5, 3]);
+/// assert_eq!(rawarray::half_spectrum_dims(&[7]), vec![4]);
+/// assert_eq!(rawarray::half_spectrum_dims(&[0, 3]), vec![0, 3]);
+/// ```
+pub fn half_spectrum_dims(dims: &[u64]) -> Vec<u64> {
+    let mut half = dims.to_vec();
+    if let Some(d) = half.first_mut() {
+        *d = half_len(*d);
+    }
+    half
+}
+
+impl<F> RawArray<Complex<F>>
+where
+    F: Float,
+    Complex<F>: RawArrayType,
+{
+    /// Keep only the non-redundant half of a Hermitian spectrum: the first
+    /// `n / 2 + 1` elements along axis 0, or none if axis 0 is empty. The
+    /// other half is assumed to be the conjugate mirror image and is
+    /// dropped without being checked.
+    /// ```
+    /// use num_complex::Complex;
+    /// use rawarray::RawArray;
+    /// // the spectrum of the real signal [1, 2, 3, 4]
+    /// let full: RawArray<Complex<f32>> = vec![
+    ///     Complex::new(10.0, 0.0),
+    ///     Complex::new(-2.0, 2.0),
+    ///     Complex::new(-2.0, 0.0),
+    ///     Complex::new(-2.0, -2.0),
+    /// ]
+    /// .into();
+    /// let half = full.to_half_spectrum();
+    /// assert_eq!(half.dims(), vec![3]);
+    /// assert_eq!(half.from_half_spectrum(4), full);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the array has no dimensions.
+    pub fn to_half_spectrum(&self) -> RawArray<Complex<F>> {
+        assert!(!self.dims.is_empty(), "a spectrum needs at least one axis");
+        let dims = half_spectrum_dims(&self.dims);
+        let data = take_axis_range(&self.data, &self.dims, 0, 0, dims[0] as usize);
+        RawArray::from_parts(dims, data)
+    }
+
+    /// Rebuild the full spectrum from a half spectrum, given the full
+    /// length `n` of axis 0, which can't be recovered from the half
+    /// spectrum alone. Missing elements are filled in by Hermitian
+    /// symmetry, negating the index along every axis.
+    ///
+    /// # Panics
+    ///
+    /// Panics if axis 0 doesn't have `n / 2 + 1` elements, or none when
+    /// `n` is 0.
+    pub fn from_half_spectrum(&self, n: u64) -> RawArray<Complex<F>> {
+        assert!(
+            !self.dims.is_empty() && self.dims[0] == half_len(n),
+            "a half spectrum of length {} needs {} elements along axis 0",
+            n,
+            half_len(n)
+        );
+        let half = self.dims[0] as usize;
+        let n = n as usize;
+        let (_, outer) = axis_strides(&self.dims, 0);
+        let rest: Vec<usize> = self.dims[1..].iter().map(|&d| d as usize).collect();
+
+        // the block with every index after axis 0 negated
+        let mirror: Vec<usize> = (0..outer)
+            .map(|o| {
+                let (mut o, mut m, mut stride) = (o, 0, 1);
+                for &d in &rest {
+                    m += (d - o % d) % d * stride;
+                    o /= d;
+                    stride *= d;
+                }
+                m
+            })
+            .collect();
+
+        let mut data = Vec::with_capacity(n * outer);
+        for (o, &m) in mirror.iter().enumerate() {
+            data.extend_from_slice(&self.data[o * half..(o + 1) * half]);
+            for k in half..n {
+                data.push(self.data[(n - k) + m * half].conj());
+            }
+        }
+        let mut dims = self.dims.clone();
+        dims[0] = n as u64;
+        RawArray::from_parts(dims, data)
+    }
+}
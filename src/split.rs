@@ -0,0 +1,148 @@
+//! Splitting an array along an axis into several smaller ones, such as a
+//! time series into one array per frame.
+
+use crate::attrs::write_attrs;
+use crate::axis::take_axis_range;
+use crate::header::RawArrayHeader;
+use crate::recode::{open_source, write_or_remove};
+use crate::{RawArray, RawArrayType, FLAG_ATTRS, FLAG_BIG_ENDIAN, FLAG_BITS, FLAG_CRC};
+use std::fs::File;
+use std::io::{self, BufWriter, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Start and length of each of `n` pieces of `len` slices. Like numpy's
+/// `array_split`, the first `len % n` pieces are one slice longer.
+fn pieces(len: u64, n: u64) -> Vec<(u64, u64)> {
+    let (base, extra) = (len / n, len % n);
+    let mut start = 0;
+    (0..n)
+        .map(|i| {
+            let size = base + u64::from(i < extra);
+            let piece = (start, size);
+            start += size;
+            piece
+        })
+        .collect()
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Split the array into `n` pieces along `axis`. When `n` doesn't
+    /// divide the length of the axis, the first pieces get one extra slice.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let ra = RawArray::from_iter_with_dims(0..10u8, &[2, 5]).unwrap();
+    /// let parts = ra.split(1, 2);
+    /// assert_eq!(parts[0].dims(), vec![2, 3]);
+    /// assert_eq!(parts[1].data(), vec![6, 7, 8, 9]);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is out of range, or `n` is zero or more than the
+    /// length of the axis.
+    pub fn split(&self, axis: usize, n: usize) -> Vec<RawArray<T>> {
+        assert!(axis < self.dims.len(), "axis {} out of range", axis);
+        let len = self.dims[axis];
+        assert!(
+            n > 0 && n as u64 <= len,
+            "can't split {} slices into {} pieces",
+            len,
+            n
+        );
+        pieces(len, n as u64)
+            .into_iter()
+            .map(|(start, size)| self.slice_axis(axis, start..start + size))
+            .collect()
+    }
+}
+
+/// Split the array in `input` into `pieces` files along `axis`, naming
+/// piece `i` by calling `name(i)`, and return the paths written. The
+/// pieces keep the byte order, checksum setting, and attributes of the
+/// input; encoded input is written out plain. The input's element type
+/// doesn't need to be known.
+/// ```
+/// # use std::io;
+/// use rawarray::RawArray;
+/// use std::path::PathBuf;
+/// # fn main() -> io::Result<()> {
+/// let mut series = RawArray::from_iter_with_dims(0..12i16, &[2, 2, 3])?;
+/// series.set_attr("tr_ms", 500);
+/// series.write("series.ra")?;
+/// let files = rawarray::split_file("series.ra", 2, 3, |i| PathBuf::from(format!("frame{}.ra", i)))?;
+/// let frame = RawArray::<i16>::read(&files[1])?;
+/// assert_eq!(frame.dims(), vec![2, 2, 1]);
+/// assert_eq!(frame.data(), vec![4, 5, 6, 7]);
+/// assert_eq!(frame.get_attr("tr_ms").and_then(|v| v.as_i64()), Some(500));
+/// # for f in files { std::fs::remove_file(f)?; }
+/// # std::fs::remove_file("series.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn split_file<P, F>(input: P, axis: usize, pieces: usize, name: F) -> io::Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+    F: Fn(usize) -> PathBuf,
+{
+    let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+    let mut src = open_source(input.as_ref())?;
+    let h = src.header.clone();
+    if h.flags & FLAG_BITS != 0 {
+        return Err(invalid("can't split arrays of single bits".to_string()));
+    }
+    if axis >= h.dims.len() {
+        return Err(invalid(format!("axis {} out of range", axis)));
+    }
+    let len = h.dims[axis];
+    if pieces == 0 || pieces as u64 > len {
+        return Err(invalid(format!(
+            "can't split {} slices into {} pieces",
+            len, pieces
+        )));
+    }
+
+    let mut bytes = Vec::with_capacity(crate::to_usize(h.size)?);
+    let (dtype, endianness) = (src.dtype(), src.endianness());
+    src.convert(dtype, endianness, |chunk| {
+        bytes.extend_from_slice(chunk);
+        Ok(())
+    })?;
+    let attrs = src.finish()?;
+
+    // treat the bytes of each element as one more, fastest varying axis
+    let mut byte_dims = vec![h.elbyte];
+    byte_dims.extend_from_slice(&h.dims);
+    let mut flags = h.flags & (FLAG_BIG_ENDIAN | FLAG_CRC);
+    if !attrs.is_empty() {
+        flags |= FLAG_ATTRS;
+    }
+
+    let mut written = Vec::with_capacity(pieces);
+    for (i, (start, size)) in self::pieces(len, pieces as u64).into_iter().enumerate() {
+        let data = take_axis_range(&bytes, &byte_dims, axis + 1, start as usize, size as usize);
+        let mut dims = h.dims.clone();
+        dims[axis] = size;
+        let header = RawArrayHeader {
+            flags,
+            eltype: h.eltype,
+            elbyte: h.elbyte,
+            size: data.len() as u64,
+            dims,
+        };
+        let path = name(i);
+        write_or_remove(&path, |out| {
+            let mut w = BufWriter::new(File::create(out)?);
+            header.write_to(&mut w)?;
+            w.write_all(&data)?;
+            if flags & FLAG_CRC != 0 {
+                w.write_all(&u64::from(crc32fast::hash(&data)).to_le_bytes())?;
+            }
+            if !attrs.is_empty() {
+                write_attrs(&mut w, &attrs)?;
+            }
+            w.flush()
+        })?;
+        written.push(path);
+    }
+    Ok(written)
+}
@@ -0,0 +1,299 @@
+//! Where RawArray files are kept, so that reading and writing can go
+//! through backends other than the local filesystem, such as a FUSE
+//! mount with odd semantics, a database, or a custom RPC service.
+//!
+//! `read()`, `write()`, and the other path-based methods use `FsStorage`;
+//! the `_storage` variants take any `RaStorage`.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// How to open a file in a storage backend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Read an existing file
+    Read,
+    /// Read and overwrite parts of an existing file
+    ReadWrite,
+    /// Create a new, empty file, replacing any that exists
+    Create,
+}
+
+/// A backend that RawArray files are read from and written to, addressed
+/// by path. Reads and writes name their byte offset, so a backend doesn't
+/// need to keep a file position.
+/// ```
+/// # use std::io;
+/// use rawarray::{OpenMode, RaStorage, RawArray, RawArrayFileMut, ReadOptions};
+/// use std::collections::HashMap;
+/// use std::path::{Path, PathBuf};
+/// use std::sync::Mutex;
+///
+/// /// Files kept in memory
+/// #[derive(Default)]
+/// struct MemStorage(Mutex<HashMap<PathBuf, Vec<u8>>>);
+///
+/// impl RaStorage for MemStorage {
+///     type Handle = (PathBuf, Vec<u8>);
+///
+///     fn open(&self, path: &Path, mode: OpenMode) -> io::Result<Self::Handle> {
+///         let files = self.0.lock().unwrap();
+///         let bytes = match mode {
+///             OpenMode::Create => Vec::new(),
+///             _ => files.get(path).cloned().ok_or(io::ErrorKind::NotFound)?,
+///         };
+///         Ok((path.to_path_buf(), bytes))
+///     }
+///
+///     fn read_at(&self, h: &Self::Handle, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+///         let rest = h.1.get(offset as usize..).unwrap_or(&[]);
+///         let n = rest.len().min(buf.len());
+///         buf[..n].copy_from_slice(&rest[..n]);
+///         Ok(n)
+///     }
+///
+///     fn write_at(&self, h: &mut Self::Handle, offset: u64, buf: &[u8]) -> io::Result<usize> {
+///         let end = offset as usize + buf.len();
+///         if h.1.len() < end {
+///             h.1.resize(end, 0);
+///         }
+///         h.1[offset as usize..end].copy_from_slice(buf);
+///         Ok(buf.len())
+///     }
+///
+///     fn len(&self, h: &Self::Handle) -> io::Result<u64> {
+///         Ok(h.1.len() as u64)
+///     }
+///
+///     fn set_len(&self, h: &mut Self::Handle, len: u64) -> io::Result<()> {
+///         h.1.resize(len as usize, 0);
+///         Ok(())
+///     }
+///
+///     fn flush(&self, h: &mut Self::Handle) -> io::Result<()> {
+///         self.0.lock().unwrap().insert(h.0.clone(), h.1.clone());
+///         Ok(())
+///     }
+/// }
+///
+/// # fn main() -> io::Result<()> {
+/// let mem = MemStorage::default();
+/// let ra: RawArray<f32> = vec![1.0, 2.0].into();
+/// ra.write_to_storage(&mem, "a.ra")?;
+/// RawArray::append_to_storage(&mem, "a.ra", &[3.0f32])?;
+/// assert_eq!(RawArray::<f32>::read_from_storage(&mem, "a.ra")?.data(), vec![1.0, 2.0, 3.0]);
+/// assert!(!Path::new("a.ra").exists());
+///
+/// let mut f = RawArrayFileMut::<f32, _>::open_in_storage(&mem, "a.ra")?;
+/// f.set(0, 0.5)?;
+/// f.sync()?;
+/// drop(f);
+/// assert_eq!(RawArray::<f32>::search_sorted_in_storage(&mem, "a.ra", &2.5)?, 2);
+/// let opts = ReadOptions::new().allow_width_promotion(true);
+/// let (wide, _) = RawArray::<f64>::read_from_storage_with(&mem, "a.ra", &opts)?;
+/// assert_eq!(wide.data(), vec![0.5, 2.0, 3.0]);
+/// # Ok(())
+/// # }
+/// ```
+pub trait RaStorage {
+    /// An open file
+    type Handle;
+
+    /// Open the file at `path`.
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<Self::Handle>;
+
+    /// Read up to `buf.len()` bytes starting at `offset`, returning how
+    /// many were read, which is zero only at the end of the file.
+    fn read_at(&self, handle: &Self::Handle, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Write up to `buf.len()` bytes starting at `offset`, growing the file
+    /// if needed, and return how many were written.
+    fn write_at(&self, handle: &mut Self::Handle, offset: u64, buf: &[u8]) -> io::Result<usize>;
+
+    /// Length of the file in bytes
+    #[allow(clippy::len_without_is_empty)]
+    fn len(&self, handle: &Self::Handle) -> io::Result<u64>;
+
+    /// Cut the file short, or grow it with zeros, to `len` bytes.
+    fn set_len(&self, handle: &mut Self::Handle, len: u64) -> io::Result<()>;
+
+    /// Make everything written so far visible to other readers of the
+    /// backend. Every write ends with this.
+    fn flush(&self, handle: &mut Self::Handle) -> io::Result<()>;
+
+    /// Make everything written so far durable. Writes end with this
+    /// instead of `flush` only when asked to, with `WriteOptions::sync` or
+    /// an explicit `sync()`. Backends without a notion of durability can
+    /// keep the default, which flushes.
+    fn sync(&self, handle: &mut Self::Handle) -> io::Result<()> {
+        self.flush(handle)
+    }
+}
+
+/// The local filesystem, which `read()`, `write()`, and the other
+/// path-based methods use. Writes go straight to the operating system, so
+/// flushing does nothing more, while syncing waits for file data to reach
+/// the disk.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FsStorage;
+
+impl RaStorage for FsStorage {
+    type Handle = File;
+
+    fn open(&self, path: &Path, mode: OpenMode) -> io::Result<File> {
+        match mode {
+            OpenMode::Read => File::open(path),
+            OpenMode::ReadWrite => OpenOptions::new().read(true).write(true).open(path),
+            OpenMode::Create => File::create(path),
+        }
+    }
+
+    #[cfg(unix)]
+    fn read_at(&self, file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::read_at(file, buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+    }
+
+    #[cfg(not(any(unix, windows)))]
+    fn read_at(&self, file: &File, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut f = file.try_clone()?;
+        f.seek(SeekFrom::Start(offset))?;
+        f.read(buf)
+    }
+
+    fn write_at(&self, file: &mut File, offset: u64, buf: &[u8]) -> io::Result<usize> {
+        file.seek(SeekFrom::Start(offset))?;
+        file.write(buf)
+    }
+
+    fn len(&self, file: &File) -> io::Result<u64> {
+        Ok(file.metadata()?.len())
+    }
+
+    fn set_len(&self, file: &mut File, len: u64) -> io::Result<()> {
+        file.set_len(len)
+    }
+
+    fn flush(&self, _file: &mut File) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn sync(&self, file: &mut File) -> io::Result<()> {
+        file.sync_data()
+    }
+}
+
+/// An open file of a storage backend with a position, so it can be used
+/// as a reader or writer.
+pub(crate) struct StorageFile<'a, S: RaStorage> {
+    storage: &'a S,
+    handle: S::Handle,
+    pos: u64,
+}
+
+/// A file on the local filesystem
+pub(crate) type FsFile = StorageFile<'static, FsStorage>;
+
+impl<'a, S: RaStorage> StorageFile<'a, S> {
+    pub(crate) fn open(storage: &'a S, path: &Path, mode: OpenMode) -> io::Result<Self> {
+        Ok(StorageFile {
+            storage,
+            handle: storage.open(path, mode)?,
+            pos: 0,
+        })
+    }
+
+    #[allow(clippy::len_without_is_empty)]
+    pub(crate) fn len(&self) -> io::Result<u64> {
+        self.storage.len(&self.handle)
+    }
+
+    pub(crate) fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.storage.set_len(&mut self.handle, len)
+    }
+
+    /// End a write, making it durable if `sync` is set and otherwise
+    /// just visible to other readers.
+    pub(crate) fn finish(&mut self, sync: bool) -> io::Result<()> {
+        if sync {
+            self.storage.sync(&mut self.handle)
+        } else {
+            self.storage.flush(&mut self.handle)
+        }
+    }
+
+    /// Fill `buf` starting at byte `offset`, without moving the position,
+    /// so several threads can read at once.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.storage.read_at(&self.handle, offset, buf) {
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    ))
+                }
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FsFile {
+    pub(crate) fn open_fs(path: &Path, mode: OpenMode) -> io::Result<FsFile> {
+        StorageFile::open(&FsStorage, path, mode)
+    }
+}
+
+impl<S: RaStorage> Read for StorageFile<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.storage.read_at(&self.handle, self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<S: RaStorage> Write for StorageFile<'_, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.storage.write_at(&mut self.handle, self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    /// Nothing is buffered here; `finish` flushes the backend.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S: RaStorage> Seek for StorageFile<'_, S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::Current(d) => (self.pos, d),
+            SeekFrom::End(d) => (self.len()?, d),
+        };
+        self.pos = base.checked_add_signed(delta).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
@@ -0,0 +1,191 @@
+//! Several arrays sent back to back over one stream, like a socket, pipe,
+//! or tape.
+//!
+//! No extra framing is needed: every record (header, data section, and
+//! optional checksum and attributes) says how long it is, so a stream is
+//! just records written one after another with `write_to`, and it ends
+//! where a record would start.
+
+use crate::codec::codec_for_flags;
+use crate::{RawArray, RawArrayHeader, RawArrayType, ReadOptions, FLAG_ATTRS, FLAG_CRC, MAX_NDIMS};
+use std::io::{self, Error, ErrorKind, Read};
+
+/// Reader for arrays written back to back on one stream.
+/// ```
+/// # use std::io;
+/// use rawarray::{RawArray, RawArrayStream};
+/// # fn main() -> io::Result<()> {
+/// let mut wire = Vec::new();
+/// RawArray::<f32>::from(vec![1.0, 2.0]).write_to(&mut wire)?;
+/// RawArray::<u8>::from(vec![7, 8, 9]).write_to(&mut wire)?;
+/// RawArray::<f32>::from(vec![3.0]).write_to(&mut wire)?;
+///
+/// let mut stream = RawArrayStream::new(wire.as_slice());
+/// assert_eq!(stream.next::<f32>()?.unwrap().data(), vec![1.0, 2.0]);
+/// // look before reading, and skip what we don't want
+/// assert_eq!(stream.peek_header()?.unwrap().eltype, 2);
+/// stream.skip()?;
+/// assert_eq!(stream.next::<f32>()?.unwrap().data(), vec![3.0]);
+/// assert!(stream.next::<f32>()?.is_none());
+/// # Ok(())
+/// # }
+/// ```
+pub struct RawArrayStream<R: Read> {
+    reader: R,
+    /// Header of the next record, if it has been read already
+    pending: Option<RawArrayHeader>,
+    opts: ReadOptions,
+}
+
+impl<R: Read> RawArrayStream<R> {
+    /// Read arrays from `reader`, which should be at the start of a record.
+    pub fn new(reader: R) -> RawArrayStream<R> {
+        RawArrayStream::with_options(reader, &ReadOptions::new())
+    }
+
+    /// Read arrays from `reader` with the size policy and byte limit of
+    /// `opts`, which is the way to read from a peer that can't be trusted.
+    /// Memory for plain data is allocated as it arrives, so a header
+    /// claiming more than the stream holds costs little before it fails.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::{RawArray, RawArrayError, RawArrayStream, ReadOptions};
+    /// # fn main() -> io::Result<()> {
+    /// let mut wire = Vec::new();
+    /// RawArray::<u8>::from(vec![0; 100]).write_to(&mut wire)?;
+    /// let mut stream = RawArrayStream::with_options(wire.as_slice(), &ReadOptions::new().max_bytes(64));
+    /// let e = stream.next::<u8>().unwrap_err();
+    /// assert!(matches!(RawArrayError::from_io(&e), Some(RawArrayError::TooLarge { .. })));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_options(reader: R, opts: &ReadOptions) -> RawArrayStream<R> {
+        RawArrayStream {
+            reader,
+            pending: None,
+            opts: opts.clone(),
+        }
+    }
+
+    /// The underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Header of the next array without consuming it, or `None` at the
+    /// end of the stream.
+    pub fn peek_header(&mut self) -> io::Result<Option<&RawArrayHeader>> {
+        if self.pending.is_none() {
+            self.pending = self.read_header()?;
+        }
+        Ok(self.pending.as_ref())
+    }
+
+    /// Read the next array, or return `None` if the stream ended cleanly
+    /// between records. If the next array isn't a `RawArray<T>`, or is
+    /// larger than the stream's limit, this fails and leaves it in place,
+    /// to be read with another type or skipped.
+    // generic over the element type, so it can't be `Iterator::next`
+    #[allow(clippy::should_implement_trait)]
+    pub fn next<T: RawArrayType>(&mut self) -> io::Result<Option<RawArray<T>>> {
+        let header = match self.pending.take() {
+            Some(h) => h,
+            None => match self.read_header()? {
+                Some(h) => h,
+                None => return Ok(None),
+            },
+        };
+        let mut buf = vec![0u8; header.len()];
+        header.write_to_buf(&mut buf);
+        let mut ra = RawArray::default();
+        let (policy, max_bytes) = (self.opts.size_policy, self.opts.max_bytes);
+        if let Err(e) = ra.read_header_with(&mut buf.as_slice(), policy, max_bytes) {
+            self.pending = Some(header);
+            return Err(e);
+        }
+        ra.read_data(&mut self.reader)?;
+        Ok(Some(ra))
+    }
+
+    /// Skip the next array without decoding it. Returns `false` at the end
+    /// of the stream.
+    pub fn skip(&mut self) -> io::Result<bool> {
+        let h = match self.pending.take() {
+            Some(h) => h,
+            None => match self.read_header()? {
+                Some(h) => h,
+                None => return Ok(false),
+            },
+        };
+        let mut len = h.size;
+        if let Some((_, id_stored)) = codec_for_flags(h.flags) {
+            if id_stored {
+                self.read_u64()?;
+            }
+            len = self.read_u64()?;
+        }
+        self.discard(len)?;
+        if h.flags & FLAG_CRC != 0 {
+            self.discard(8)?;
+        }
+        if h.flags & FLAG_ATTRS != 0 {
+            let len = self.read_u64()?;
+            self.discard(len)?;
+        }
+        Ok(true)
+    }
+
+    /// Read a header, or `None` if the stream ends before its first byte.
+    fn read_header(&mut self) -> io::Result<Option<RawArrayHeader>> {
+        let mut first = [0u8; 8];
+        let mut got = 0;
+        while got < first.len() {
+            match self.reader.read(&mut first[got..]) {
+                Ok(0) if got == 0 => return Ok(None),
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "stream ended inside a header",
+                    ))
+                }
+                Ok(n) => got += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let mut fixed = [0u8; 40];
+        self.reader.read_exact(&mut fixed)?;
+        let ndims = u64::from_le_bytes([
+            fixed[32], fixed[33], fixed[34], fixed[35], fixed[36], fixed[37], fixed[38], fixed[39],
+        ]);
+        if ndims > MAX_NDIMS {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("header claims {} dimensions", ndims),
+            ));
+        }
+        let mut dims = vec![0u8; 8 * ndims as usize];
+        self.reader.read_exact(&mut dims)?;
+        let mut whole = first.to_vec();
+        whole.extend_from_slice(&fixed);
+        whole.extend_from_slice(&dims);
+        RawArrayHeader::parse_from_buf(&whole).map(Some)
+    }
+
+    fn read_u64(&mut self) -> io::Result<u64> {
+        let mut buf = [0u8; 8];
+        self.reader.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn discard(&mut self, len: u64) -> io::Result<()> {
+        let n = io::copy(&mut (&mut self.reader).take(len), &mut io::sink())?;
+        if n != len {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "stream ended inside a record",
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+//! Rate-limited I/O, so bulk jobs like archive migrations can share
+//! storage with production pipelines without starving them.
+
+use crate::{RawArray, RawArrayType};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Wraps a reader or writer and sleeps as needed to keep its average
+/// throughput under a fixed number of megabytes (10^6 bytes) per second.
+///
+/// Large requests are split so no single call moves more than a tenth of
+/// a second's worth of data, which keeps the traffic smooth rather than
+/// bursty.
+/// ```
+/// # use std::io::{self, Read};
+/// use rawarray::RateLimited;
+/// let mut r = RateLimited::new(io::repeat(0).take(1000), 1.0);
+/// let mut buf = Vec::new();
+/// r.read_to_end(&mut buf).unwrap();
+/// assert_eq!(buf.len(), 1000);
+/// ```
+pub struct RateLimited<I> {
+    inner: I,
+    bytes_per_sec: f64,
+    start: Instant,
+    total: u64,
+}
+
+impl<I> RateLimited<I> {
+    /// Limit `inner` to `mb_per_sec` megabytes per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mb_per_sec` is not a positive number.
+    pub fn new(inner: I, mb_per_sec: f64) -> RateLimited<I> {
+        assert!(mb_per_sec > 0.0, "rate limit must be positive");
+        RateLimited {
+            inner,
+            bytes_per_sec: mb_per_sec * 1e6,
+            start: Instant::now(),
+            total: 0,
+        }
+    }
+
+    /// Unwrap the inner reader or writer.
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+
+    /// Largest request to pass through in one call
+    fn chunk(&self, len: usize) -> usize {
+        len.min((self.bytes_per_sec / 10.0).max(1.0) as usize)
+    }
+
+    /// Account for `n` bytes moved and sleep off any excess speed.
+    fn throttle(&mut self, n: usize) {
+        self.total += n as u64;
+        let due = Duration::from_secs_f64(self.total as f64 / self.bytes_per_sec);
+        let elapsed = self.start.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+    }
+}
+
+impl<R: Read> Read for RateLimited<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.chunk(buf.len());
+        let n = self.inner.read(&mut buf[..len])?;
+        self.throttle(n);
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for RateLimited<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.chunk(buf.len());
+        let n = self.inner.write(&buf[..len])?;
+        self.throttle(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: Seek> Seek for RateLimited<S> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Read a `RawArray<T>` from a file, using at most `mb_per_sec`
+    /// megabytes per second of I/O bandwidth.
+    pub fn read_rate_limited<P: AsRef<Path>>(path: P, mb_per_sec: f64) -> io::Result<RawArray<T>> {
+        let f = RateLimited::new(File::open(path)?, mb_per_sec);
+        RawArray::read_from(&mut BufReader::new(f))
+    }
+
+    /// Write a `RawArray<T>` to file, using at most `mb_per_sec`
+    /// megabytes per second of I/O bandwidth.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()>{
+    /// let ra: RawArray<f32> = vec![1.0, 2.0, 3.0, 4.0].into();
+    /// ra.write_rate_limited("slow.ra", 50.0)?;
+    /// assert_eq!(RawArray::<f32>::read_rate_limited("slow.ra", 50.0)?, ra);
+    /// # std::fs::remove_file("slow.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_rate_limited<P: AsRef<Path>>(&self, path: P, mb_per_sec: f64) -> io::Result<()> {
+        let f = RateLimited::new(File::create(path)?, mb_per_sec);
+        self.write_to(&mut BufWriter::new(f))
+    }
+}
@@ -0,0 +1,159 @@
+//! Arrays that grow over time, with a timestamp for every appended chunk,
+//! for monitoring daemons that log a frame every so often.
+//!
+//! The log is an ordinary RawArray file growing along its last dimension,
+//! plus a sidecar file next to it (the log's path with `.times` added)
+//! holding a `RawArray<u64>` of dims `[2, chunks]`: for each chunk, the
+//! index along the last dimension where it starts and its time in
+//! nanoseconds since the Unix epoch. Both are plain RawArray files, so
+//! other tools can read them without knowing about the log.
+
+use crate::vconcat::VirtualConcat;
+use crate::{RawArray, RawArrayHeader, RawArrayType};
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::io::{self, Error, ErrorKind};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// An append-only array with a timestamp for each appended chunk.
+/// ```
+/// # use std::io;
+/// use rawarray::TimeLog;
+/// use std::time::{Duration, UNIX_EPOCH};
+/// # fn main() -> io::Result<()> {
+/// let t = |s| UNIX_EPOCH + Duration::from_secs(s);
+/// let mut log = TimeLog::<f32>::create("cpu.ra", &[2])?;
+/// log.append_at(t(100), &[0.5, 0.1])?;
+/// log.append_at(t(160), &[0.7, 0.2, 0.6, 0.3])?;
+/// log.append_at(t(220), &[0.9, 0.4])?;
+///
+/// let mut log = TimeLog::<f32>::open("cpu.ra")?;
+/// let slab = log.read_range_by_time(t(150), t(200))?;
+/// assert_eq!(slab.dims(), vec![2, 2]);
+/// assert_eq!(slab.data(), vec![0.7, 0.2, 0.6, 0.3]);
+/// assert_eq!(log.times()?, vec![t(100), t(160), t(220)]);
+/// assert!(log.append_at(t(200), &[0.0, 0.0]).is_err());
+/// # std::fs::remove_file("cpu.ra")?;
+/// # std::fs::remove_file("cpu.ra.times")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct TimeLog<T: RawArrayType> {
+    path: PathBuf,
+    times_path: PathBuf,
+    /// Time of the last chunk, so appending needn't read the sidecar
+    last: Option<u64>,
+    _elem: PhantomData<T>,
+}
+
+fn sidecar(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".times");
+    PathBuf::from(name)
+}
+
+fn to_nanos(t: SystemTime) -> io::Result<u64> {
+    let d = t.duration_since(UNIX_EPOCH).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "times before the Unix epoch can't be logged",
+        )
+    })?;
+    u64::try_from(d.as_nanos()).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "times after the year 2554 can't be logged",
+        )
+    })
+}
+
+impl<T: RawArrayType> TimeLog<T> {
+    /// Start a new, empty log at `path` whose chunks are made of slices of
+    /// dims `slice_dims`, replacing any log already there.
+    pub fn create<P: AsRef<Path>>(path: P, slice_dims: &[u64]) -> io::Result<TimeLog<T>> {
+        let path = path.as_ref().to_path_buf();
+        let mut dims = slice_dims.to_vec();
+        dims.push(0);
+        RawArray::<T>::from_iter_with_dims(std::iter::empty(), &dims)?.write(&path)?;
+        let times_path = sidecar(&path);
+        RawArray::<u64>::from_iter_with_dims(std::iter::empty(), &[2, 0])?.write(&times_path)?;
+        Ok(TimeLog {
+            path,
+            times_path,
+            last: None,
+            _elem: PhantomData,
+        })
+    }
+
+    /// Open an existing log.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<TimeLog<T>> {
+        let path = path.as_ref().to_path_buf();
+        let times_path = sidecar(&path);
+        RawArrayHeader::read(&path)?;
+        let times = RawArray::<u64>::read(&times_path)?;
+        Ok(TimeLog {
+            path,
+            times_path,
+            last: times.as_slice().last().copied(),
+            _elem: PhantomData,
+        })
+    }
+
+    /// Append a chunk of whole slices, stamped with the current time.
+    pub fn append(&mut self, data: &[T]) -> io::Result<()> {
+        self.append_at(SystemTime::now(), data)
+    }
+
+    /// Append a chunk of whole slices stamped with `time`, which can't be
+    /// earlier than the last chunk's. The data goes in first, so if the
+    /// process dies in between, the extra slices count as part of the
+    /// previous chunk.
+    pub fn append_at(&mut self, time: SystemTime, data: &[T]) -> io::Result<()> {
+        let nanos = to_nanos(time)?;
+        if self.last.is_some_and(|last| nanos < last) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "chunks must be appended in time order",
+            ));
+        }
+        let h = RawArrayHeader::read(&self.path)?;
+        let start = *h.dims.last().unwrap_or(&0);
+        RawArray::append(&self.path, data)?;
+        RawArray::append(&self.times_path, &[start, nanos])?;
+        self.last = Some(nanos);
+        Ok(())
+    }
+
+    /// Time of every chunk, oldest first.
+    pub fn times(&self) -> io::Result<Vec<SystemTime>> {
+        let times = RawArray::<u64>::read(&self.times_path)?;
+        Ok(times
+            .as_slice()
+            .chunks_exact(2)
+            .map(|c| UNIX_EPOCH + Duration::from_nanos(c[1]))
+            .collect())
+    }
+
+    /// Read the chunks stamped at or after `t0` and before `t1`, as one
+    /// array along the last dimension. Only those slices are read from
+    /// the file.
+    pub fn read_range_by_time(&self, t0: SystemTime, t1: SystemTime) -> io::Result<RawArray<T>> {
+        let (t0, t1) = (to_nanos(t0)?, to_nanos(t1)?);
+        let times = RawArray::<u64>::read(&self.times_path)?;
+        let chunks: Vec<&[u64]> = times.as_slice().chunks_exact(2).collect();
+        let first = chunks.partition_point(|c| c[1] < t0);
+        let last = chunks.partition_point(|c| c[1] < t1);
+        let axis = RawArrayHeader::read(&self.path)?
+            .dims
+            .len()
+            .saturating_sub(1);
+        let mut data = VirtualConcat::<T>::open(&[&self.path], axis)?;
+        let total = data.dims()[axis];
+        let start = chunks.get(first).map_or(total, |c| c[0]);
+        let end = chunks.get(last).map_or(total, |c| c[0]);
+        data.read_slice(start, end.saturating_sub(start))
+    }
+}
@@ -0,0 +1,270 @@
+//! Checking a file against the format, reporting everything wrong with it
+//! rather than stopping at the first problem as reading does.
+
+use crate::attrs::read_attrs;
+use crate::codec::{codec_for_flags, FLAG_LZ4, FLAG_ZSTD};
+use crate::header::RawArrayHeader;
+use crate::recode::Source;
+use crate::{ALL_KNOWN_FLAGS, DEFAULT_MAX_BYTES, FLAG_ATTRS, FLAG_CRC, FLAG_ENCODED, MAX_NDIMS};
+use rawarray_core::{header_len, Header};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Something wrong with a file, as found by `validate_file`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    /// The file doesn't start with the RawArray magic number
+    NotRawArray,
+    /// The header claims more dimensions than the format allows
+    TooManyDims(u64),
+    /// Flags this version of the library doesn't know
+    UnknownFlags(u64),
+    /// More than one codec flag is set
+    ConflictingCodecs(u64),
+    /// Elements are zero bytes wide
+    ZeroElbyte,
+    /// The header's `size` disagrees with its dimensions
+    SizeMismatch {
+        /// `size` as recorded in the header
+        size: u64,
+        /// Length of the data the dimensions call for
+        expected: u64,
+    },
+    /// The file ends before the array does
+    Truncated {
+        /// Length of the file
+        len: u64,
+        /// Length the array needs
+        expected: u64,
+    },
+    /// The file goes on past the end of the array. The format allows this,
+    /// calling the bytes volatile metadata, and set files keep their other
+    /// arrays there, so this is only informational; see `is_informational`.
+    TrailingBytes {
+        /// Length of the file
+        len: u64,
+        /// Where the array ends
+        expected: u64,
+    },
+    /// The stored checksum doesn't match the data
+    ChecksumMismatch {
+        /// Checksum recorded in the file
+        stored: u64,
+        /// Checksum computed from the data
+        computed: u64,
+    },
+    /// The encoded data section can't be decoded
+    BadData(String),
+    /// The attribute block can't be parsed
+    BadAttrs(String),
+}
+
+impl Problem {
+    /// True for findings that don't make a file invalid, which is only
+    /// `TrailingBytes`.
+    pub fn is_informational(&self) -> bool {
+        matches!(self, Problem::TrailingBytes { .. })
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::NotRawArray => write!(f, "no magic number, likely not a RawArray file"),
+            Problem::TooManyDims(n) => write!(
+                f,
+                "header claims {} dimensions, more than the limit of {}",
+                n, MAX_NDIMS
+            ),
+            Problem::UnknownFlags(flags) => write!(f, "unknown flags {:#x}", flags),
+            Problem::ConflictingCodecs(flags) => {
+                write!(f, "more than one codec flag set in {:#x}", flags)
+            }
+            Problem::ZeroElbyte => write!(f, "elbyte is zero"),
+            Problem::SizeMismatch { size, expected } => write!(
+                f,
+                "header size is {} bytes, but the dims call for {}",
+                size, expected
+            ),
+            Problem::Truncated { len, expected } => write!(
+                f,
+                "file is {} bytes, but the array needs {}; it's truncated",
+                len, expected
+            ),
+            Problem::TrailingBytes { len, expected } => write!(
+                f,
+                "file is {} bytes, but the array ends at byte {}; {} bytes of volatile metadata follow it",
+                len,
+                expected,
+                len - expected
+            ),
+            Problem::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "data checksum mismatch (stored {:08x}, computed {:08x})",
+                stored, computed
+            ),
+            Problem::BadData(msg) => write!(f, "data section can't be decoded: {}", msg),
+            Problem::BadAttrs(msg) => write!(f, "attributes can't be read: {}", msg),
+        }
+    }
+}
+
+fn read_u64_at<R: Read + Seek>(r: &mut R, offset: u64) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.seek(SeekFrom::Start(offset))?;
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Check the file at `path` from its magic number to its last byte: the
+/// header fields, that `size` agrees with the dimensions, that the file is
+/// long enough to hold the array, and that the checksum, encoded data, and
+/// attributes are all readable. Returns every problem found, or nothing if
+/// the file is sound; only failing to read the file at all is an error.
+/// Bytes after the array are reported too, but they're allowed, so a file
+/// is sound if every problem `is_informational`.
+///
+/// When `size` and the dimensions disagree, the length of the file is
+/// checked against the dimensions.
+/// ```
+/// # use std::io;
+/// use rawarray::{validate_file, Problem, RawArray};
+/// # fn main() -> io::Result<()> {
+/// RawArray::from(vec![1u16, 2, 3]).write("checked.ra")?;
+/// assert_eq!(validate_file("checked.ra")?, vec![]);
+///
+/// let mut bytes = std::fs::read("checked.ra")?;
+/// bytes.extend_from_slice(b"junk");
+/// std::fs::write("checked.ra", &bytes)?;
+/// assert_eq!(
+///     validate_file("checked.ra")?,
+///     vec![Problem::TrailingBytes { len: 66, expected: 62 }]
+/// );
+/// assert!(validate_file("checked.ra")?.iter().all(Problem::is_informational));
+/// # std::fs::remove_file("checked.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn validate_file<P: AsRef<Path>>(path: P) -> io::Result<Vec<Problem>> {
+    let mut r = BufReader::new(File::open(path.as_ref())?);
+    let len = r.get_ref().metadata()?.len();
+    let mut buf = Vec::new();
+    (&mut r)
+        .take(header_len(MAX_NDIMS as usize) as u64)
+        .read_to_end(&mut buf)?;
+    let h = match Header::parse(&buf) {
+        Ok(h) => h,
+        Err(rawarray_core::Error::BadMagic) => return Ok(vec![Problem::NotRawArray]),
+        Err(rawarray_core::Error::TooManyDims(n)) => return Ok(vec![Problem::TooManyDims(n)]),
+        Err(rawarray_core::Error::Truncated { needed, .. }) => {
+            let expected = needed as u64;
+            return Ok(vec![Problem::Truncated { len, expected }]);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let h = RawArrayHeader {
+        flags: h.flags,
+        eltype: h.eltype,
+        elbyte: h.elbyte,
+        size: h.size,
+        dims: h.dims,
+    };
+
+    let mut problems = Vec::new();
+    if h.flags & !ALL_KNOWN_FLAGS != 0 {
+        problems.push(Problem::UnknownFlags(h.flags & !ALL_KNOWN_FLAGS));
+    }
+    let codecs = h.flags & (FLAG_ENCODED | FLAG_ZSTD | FLAG_LZ4);
+    if codecs.count_ones() > 1 {
+        problems.push(Problem::ConflictingCodecs(codecs));
+    }
+    if h.elbyte == 0 {
+        problems.push(Problem::ZeroElbyte);
+        return Ok(problems);
+    }
+    let from_dims = h
+        .dims
+        .iter()
+        .try_fold(h.elbyte, |acc, &d| acc.checked_mul(d));
+    if from_dims != Some(h.size) {
+        problems.push(Problem::SizeMismatch {
+            size: h.size,
+            expected: from_dims.unwrap_or(u64::MAX),
+        });
+    }
+
+    // walk the sections after the header, stopping at the first that
+    // runs past the end of the file
+    let truncated = |expected: u64| Problem::Truncated { len, expected };
+    let data_start = h.data_offset();
+    let mut end = match codec_for_flags(h.flags) {
+        Some((_, id_stored)) => {
+            let len_at = data_start + if id_stored { 8 } else { 0 };
+            if len_at + 8 > len {
+                problems.push(truncated(len_at + 8));
+                return Ok(problems);
+            }
+            (len_at + 8).saturating_add(read_u64_at(&mut r, len_at)?)
+        }
+        None => data_start.saturating_add(from_dims.unwrap_or(h.size)),
+    };
+    if h.flags & FLAG_CRC != 0 {
+        end = end.saturating_add(8);
+    }
+    if h.flags & FLAG_ATTRS != 0 {
+        // the block starts with its own length
+        end = match end.checked_add(8) {
+            Some(block_at) if block_at <= len => block_at.saturating_add(read_u64_at(&mut r, end)?),
+            _ => end.saturating_add(8),
+        };
+    }
+    if end > len {
+        problems.push(truncated(end));
+        return Ok(problems);
+    }
+    if end < len {
+        problems.push(Problem::TrailingBytes { len, expected: end });
+    }
+    if problems.iter().any(|p| !p.is_informational()) {
+        return Ok(problems);
+    }
+
+    // the layout is sound, so read the sections themselves
+    r.seek(SeekFrom::Start(data_start))?;
+    let crc = h.flags & FLAG_CRC != 0;
+    let attrs = h.flags & FLAG_ATTRS != 0;
+    let mut hasher = crc32fast::Hasher::new();
+    let decoded = Source::new(h, &mut r, DEFAULT_MAX_BYTES).and_then(|mut src| {
+        let (dtype, endianness) = (src.dtype(), src.endianness());
+        src.convert(dtype, endianness, |bytes| {
+            hasher.update(bytes);
+            Ok(())
+        })
+    });
+    if let Err(e) = decoded {
+        if e.kind() != ErrorKind::InvalidData && e.kind() != ErrorKind::Unsupported {
+            return Err(e);
+        }
+        problems.push(Problem::BadData(e.to_string()));
+        return Ok(problems);
+    }
+    if crc {
+        let stored = {
+            let mut b = [0u8; 8];
+            r.read_exact(&mut b)?;
+            u64::from_le_bytes(b)
+        };
+        let computed = u64::from(hasher.finalize());
+        if stored != computed {
+            problems.push(Problem::ChecksumMismatch { stored, computed });
+        }
+    }
+    if attrs {
+        if let Err(e) = read_attrs(&mut r) {
+            problems.push(Problem::BadAttrs(e.to_string()));
+        }
+    }
+    Ok(problems)
+}
@@ -0,0 +1,235 @@
+//! Several files read as one array, for datasets sharded across files by
+//! acquisition session or similar.
+
+use crate::axis::axis_strides;
+use crate::convert::elements_from_bytes_with_endianness;
+use crate::storage::StorageFile;
+use crate::{codec, to_usize, FsStorage, RaStorage, RawArray, RawArrayType, SizePolicy, FLAG_BITS};
+use std::io::{self, BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::mem;
+use std::path::Path;
+
+/// One file of a `VirtualConcat`, with its header and an open reader
+struct Shard<T: RawArrayType, S: RaStorage + 'static> {
+    header: RawArray<T>,
+    reader: BufReader<StorageFile<'static, S>>,
+    /// Index along the concatenation axis of the shard's first slice
+    start: u64,
+}
+
+/// Files presented as one logical array concatenated along an axis,
+/// without copying them. Data is read from the files on demand, so only
+/// the slices asked for are ever in memory.
+///
+/// The files must have the same element type and the same dimensions
+/// except along the concatenation axis, and their data must not be
+/// compressed or encoded. Checksums and attributes in the files are not
+/// read. Files in storage other than the local filesystem are opened with
+/// `open_in_storage`.
+/// ```
+/// # use std::io;
+/// use rawarray::{RawArray, VirtualConcat};
+/// # fn main() -> io::Result<()> {
+/// let a = RawArray::from_iter_with_dims(0..6u16, &[2, 3])?;
+/// let b = RawArray::from_iter_with_dims(6..10u16, &[2, 2])?;
+/// a.write("session1.ra")?;
+/// b.write("session2.ra")?;
+///
+/// let mut all = VirtualConcat::<u16>::open(&["session1.ra", "session2.ra"], 1)?;
+/// assert_eq!(all.dims(), &[2, 5]);
+/// let middle = all.read_slice(2, 2)?;
+/// assert_eq!(middle.data(), vec![4, 5, 6, 7]);
+///
+/// let chunks: Vec<RawArray<u16>> = all.chunks(2).collect::<io::Result<_>>()?;
+/// assert_eq!(chunks.len(), 3);
+/// assert_eq!(chunks[2].dims(), vec![2, 1]);
+/// # std::fs::remove_file("session1.ra")?;
+/// # std::fs::remove_file("session2.ra")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct VirtualConcat<T: RawArrayType, S: RaStorage + 'static = FsStorage> {
+    axis: usize,
+    dims: Vec<u64>,
+    shards: Vec<Shard<T, S>>,
+}
+
+impl<T: RawArrayType> VirtualConcat<T> {
+    /// Open `paths`, in order, as one array concatenated along `axis`.
+    pub fn open<P: AsRef<Path>>(paths: &[P], axis: usize) -> io::Result<VirtualConcat<T>> {
+        VirtualConcat::open_in_storage(&FsStorage, paths, axis)
+    }
+}
+
+impl<T: RawArrayType, S: RaStorage + 'static> VirtualConcat<T, S> {
+    /// Open files in `storage` as `open()` does. See `RaStorage`. The
+    /// files stay open as long as the `VirtualConcat`, so the storage must
+    /// live for the rest of the program, as unit structs like
+    /// `FsStorage` do.
+    pub fn open_in_storage<P: AsRef<Path>>(
+        storage: &'static S,
+        paths: &[P],
+        axis: usize,
+    ) -> io::Result<VirtualConcat<T, S>> {
+        let invalid = |path: &Path, msg: String| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{}: {}", path.display(), msg),
+            )
+        };
+        let mut shards = Vec::with_capacity(paths.len());
+        let mut dims: Vec<u64> = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let (header, _, reader) =
+                RawArray::<T>::open_checked_in(storage, path, SizePolicy::Strict, u64::MAX)?;
+            if header.flags & (codec::CODEC_FLAGS | FLAG_BITS) != 0 {
+                return Err(invalid(path, "encoded data can't be read in place".into()));
+            }
+            if axis >= header.dims.len() {
+                return Err(invalid(path, format!("axis {} out of range", axis)));
+            }
+            let start = if shards.is_empty() {
+                dims = header.dims.clone();
+                0
+            } else {
+                let mismatch = header.dims.len() != dims.len()
+                    || (0..dims.len()).any(|i| i != axis && header.dims[i] != dims[i]);
+                if mismatch {
+                    return Err(invalid(
+                        path,
+                        format!(
+                            "dims {:?} don't fit with {:?} along axis {}",
+                            header.dims, dims, axis
+                        ),
+                    ));
+                }
+                let start = dims[axis];
+                dims[axis] += header.dims[axis];
+                start
+            };
+            shards.push(Shard {
+                header,
+                reader,
+                start,
+            });
+        }
+        if shards.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "no files to concatenate",
+            ));
+        }
+        Ok(VirtualConcat { axis, dims, shards })
+    }
+
+    /// Dimensions of the combined array
+    pub fn dims(&self) -> &[u64] {
+        &self.dims
+    }
+
+    /// Axis the files are concatenated along
+    pub fn axis(&self) -> usize {
+        self.axis
+    }
+
+    /// Number of files
+    pub fn num_files(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Read `len` slices starting at index `start` along the concatenation
+    /// axis, from however many files they span.
+    pub fn read_slice(&mut self, start: u64, len: u64) -> io::Result<RawArray<T>> {
+        let end = start
+            .checked_add(len)
+            .filter(|&end| end <= self.dims[self.axis])
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "slices {}..{} out of range for {} along axis {}",
+                        start,
+                        start.saturating_add(len),
+                        self.dims[self.axis],
+                        self.axis
+                    ),
+                )
+            })?;
+        let mut dims = self.dims.clone();
+        dims[self.axis] = len;
+        let (inner, outer) = axis_strides(&dims, self.axis);
+        let mut data = Vec::with_capacity(to_usize(dims.iter().product())?);
+        for o in 0..outer {
+            for shard in self.shards.iter_mut() {
+                let n = shard.header.dims[self.axis];
+                let lo = start.max(shard.start);
+                let hi = end.min(shard.start + n);
+                if lo >= hi {
+                    continue;
+                }
+                let first = inner as u64 * (lo - shard.start + n * o as u64);
+                data.extend(shard.read_elements(first, inner * (hi - lo) as usize)?);
+            }
+        }
+        Ok(RawArray::from_parts(dims, data))
+    }
+
+    /// Read the whole combined array.
+    pub fn read_all(&mut self) -> io::Result<RawArray<T>> {
+        let n = self.dims[self.axis];
+        self.read_slice(0, n)
+    }
+
+    /// Iterate over the combined array in chunks of `len` slices along the
+    /// concatenation axis. The last chunk may be shorter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is zero.
+    pub fn chunks(&mut self, len: u64) -> Chunks<'_, T, S> {
+        assert!(len > 0, "chunk length must be nonzero");
+        Chunks {
+            concat: self,
+            len,
+            next: 0,
+        }
+    }
+}
+
+impl<T: RawArrayType, S: RaStorage> Shard<T, S> {
+    /// Read `count` elements starting at element `first` of the data
+    /// section, in native byte order.
+    fn read_elements(&mut self, first: u64, count: usize) -> io::Result<Vec<T>> {
+        let width = mem::size_of::<T>();
+        let offset = self.header.data_offset() + first * width as u64;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut bytes = vec![0u8; count * width];
+        self.reader.read_exact(&mut bytes)?;
+        elements_from_bytes_with_endianness(&bytes, self.header.endianness())
+    }
+}
+
+/// Iterator over chunks of a `VirtualConcat`.
+///
+/// Created by [`VirtualConcat::chunks`].
+pub struct Chunks<'a, T: RawArrayType, S: RaStorage + 'static = FsStorage> {
+    concat: &'a mut VirtualConcat<T, S>,
+    len: u64,
+    next: u64,
+}
+
+impl<'a, T: RawArrayType, S: RaStorage> Iterator for Chunks<'a, T, S> {
+    type Item = io::Result<RawArray<T>>;
+
+    fn next(&mut self) -> Option<io::Result<RawArray<T>>> {
+        let total = self.concat.dims[self.concat.axis];
+        if self.next >= total {
+            return None;
+        }
+        let len = self.len.min(total - self.next);
+        let chunk = self.concat.read_slice(self.next, len);
+        self.next += len;
+        Some(chunk)
+    }
+}
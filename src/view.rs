@@ -0,0 +1,94 @@
+//! Borrowed arrays, for writing or inspecting data that lives elsewhere
+//! without copying it into a `RawArray`.
+
+use crate::{as_u8_slice, write_header_fields, RawArray, RawArrayType};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::Path;
+
+/// A shaped, read-only view of borrowed data, laid out like the data of a
+/// `RawArray<T>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawArrayView<'a, T: RawArrayType> {
+    dims: Vec<u64>,
+    data: &'a [T],
+}
+
+impl<'a, T: RawArrayType> RawArrayView<'a, T> {
+    /// Dimensions of the view
+    pub fn dims(&self) -> &[u64] {
+        &self.dims
+    }
+
+    /// Number of dimensions of the view
+    pub fn ndims(&self) -> u64 {
+        self.dims.len() as u64
+    }
+
+    /// Total size of the data in bytes
+    pub fn size(&self) -> u64 {
+        mem::size_of_val(self.data) as u64
+    }
+
+    /// The borrowed data
+    pub fn as_slice(&self) -> &'a [T] {
+        self.data
+    }
+
+    /// Copy the view into an owned `RawArray<T>`.
+    pub fn to_owned(&self) -> RawArray<T> {
+        RawArray::from_parts(self.dims.clone(), self.data.to_vec())
+    }
+
+    /// Write the view to a file, exactly as the equivalent `RawArray<T>`
+    /// would be written.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let buffer = vec![1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0];
+    /// RawArray::from_slice(&buffer, &[3, 2]).write("view.ra")?;
+    /// let ra = RawArray::<f64>::read("view.ra")?;
+    /// assert_eq!(ra.dims(), vec![3, 2]);
+    /// assert_eq!(ra.as_slice(), &buffer[..]);
+    /// # std::fs::remove_file("view.ra")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        write_header_fields::<T, _>(&mut w, 0, self.size(), &self.dims)?;
+        w.write_all(as_u8_slice(self.data))?;
+        w.flush()
+    }
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Borrow `data` as an array with dimensions `dims`, without copying.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the product of `dims` isn't the length of `data`.
+    pub fn from_slice<'a>(data: &'a [T], dims: &[u64]) -> RawArrayView<'a, T> {
+        let nelem: u64 = dims.iter().product();
+        assert_eq!(nelem, data.len() as u64, "dims don't match data length");
+        RawArrayView {
+            dims: dims.to_vec(),
+            data,
+        }
+    }
+
+    /// Borrow this array as a view.
+    /// ```
+    /// # use rawarray::RawArray;
+    /// let r: RawArray<u8> = vec![1, 2, 3].into();
+    /// assert_eq!(r.view().to_owned(), r);
+    /// ```
+    pub fn view(&self) -> RawArrayView<'_, T> {
+        RawArrayView {
+            dims: self.dims.clone(),
+            data: &self.data,
+        }
+    }
+}
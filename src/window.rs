@@ -0,0 +1,129 @@
+//! Intensity windowing, the usual last step before showing or exporting
+//! medical images: a range of values is spread over the gray levels of a
+//! `u8` or `u16` image, and everything outside it is clipped to black or
+//! white.
+
+use crate::{Endianness, RawArray, RawArrayType};
+use num_traits::AsPrimitive;
+
+/// Element types that hold gray levels, from black at zero to white at
+/// `MAX`.
+pub trait GrayLevel: RawArrayType {
+    /// White
+    const MAX: f64;
+    /// The level nearest `x`, which is in `0.0..=MAX`
+    fn from_level(x: f64) -> Self;
+}
+
+macro_rules! gray_level {
+    ($($t:ty),*) => {
+        $(
+            impl GrayLevel for $t {
+                const MAX: f64 = <$t>::MAX as f64;
+
+                fn from_level(x: f64) -> $t {
+                    x.round() as $t
+                }
+            }
+        )*
+    };
+}
+
+gray_level!(u8, u16);
+
+impl<T: RawArrayType + AsPrimitive<f64>> RawArray<T> {
+    /// Gray levels for the values from `low` to `high`, black at `low` and
+    /// below and white at `high` and above. NaNs are black. If `low` and
+    /// `high` are equal, values above them are white and the rest black.
+    fn levels<Q: GrayLevel>(&self, low: f64, high: f64) -> RawArray<Q> {
+        assert!(
+            self.endianness() == Endianness::native(),
+            "windowing needs data in native byte order"
+        );
+        let span = high - low;
+        let data = self
+            .as_slice()
+            .iter()
+            .map(|x| {
+                let v: f64 = x.as_();
+                let t = if span > 0.0 {
+                    (v - low) / span
+                } else if v > low {
+                    1.0
+                } else {
+                    0.0
+                };
+                // NaN clamps to NaN, which becomes zero
+                let t = t.clamp(0.0, 1.0);
+                Q::from_level(if t.is_nan() { 0.0 } else { t * Q::MAX })
+            })
+            .collect();
+        RawArray::from_parts(self.dims(), data)
+    }
+
+    /// Window the values to gray levels, as a DICOM viewer does: the
+    /// values from `center - width / 2` to `center + width / 2` are spread
+    /// evenly from black to white. Dimensions are kept; attributes aren't.
+    /// ```
+    /// use rawarray::RawArray;
+    /// // CT values in Hounsfield units, shown with a soft tissue window
+    /// let ct: RawArray<i16> = vec![-1000, -160, 40, 240, 1500].into();
+    /// let image = ct.window::<u8>(40.0, 400.0);
+    /// assert_eq!(image.data(), vec![0, 0, 128, 255, 255]);
+    /// assert_eq!(ct.window::<u16>(40.0, 400.0).data()[2], 32768);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is negative or NaN, or if the data isn't in native
+    /// byte order.
+    pub fn window<Q: GrayLevel>(&self, center: f64, width: f64) -> RawArray<Q> {
+        assert!(width >= 0.0, "window width must not be negative");
+        self.levels(center - width / 2.0, center + width / 2.0)
+    }
+
+    /// Window the values between two percentiles of the finite values,
+    /// such as 1 and 99, so that a few extreme values don't leave the rest
+    /// of the image washed out. Percentiles are interpolated linearly
+    /// between the sorted values, as NumPy's `percentile` does.
+    /// ```
+    /// use rawarray::RawArray;
+    /// let mut ra: RawArray<f32> = (0..=100).map(|x| x as f32).collect();
+    /// ra.as_mut_slice()[100] = 1e9;
+    /// let image = ra.normalize_percentiles::<u8>(0.0, 50.0);
+    /// assert_eq!(image.data()[0], 0);
+    /// assert_eq!(image.data()[25], 128);
+    /// assert_eq!(image.data()[50], 255);
+    /// assert_eq!(image.data()[100], 255);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `0 <= low <= high <= 100`, or if the data isn't in
+    /// native byte order.
+    pub fn normalize_percentiles<Q: GrayLevel>(&self, low: f64, high: f64) -> RawArray<Q> {
+        assert!(
+            (0.0..=100.0).contains(&low) && (low..=100.0).contains(&high),
+            "percentiles must satisfy 0 <= low <= high <= 100"
+        );
+        let mut values: Vec<f64> = self
+            .as_slice()
+            .iter()
+            .map(|x| x.as_())
+            .filter(|v: &f64| v.is_finite())
+            .collect();
+        values.sort_unstable_by(f64::total_cmp);
+        let percentile = |p: f64| {
+            if values.is_empty() {
+                return 0.0;
+            }
+            let rank = p / 100.0 * (values.len() - 1) as f64;
+            let (i, frac) = (rank.floor() as usize, rank.fract());
+            match values.get(i + 1) {
+                Some(next) => values[i] + frac * (next - values[i]),
+                None => values[i],
+            }
+        };
+        self.levels(percentile(low), percentile(high))
+    }
+}
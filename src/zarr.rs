@@ -0,0 +1,478 @@
+//! Zarr v3 stores, for handing arrays to cloud-native analysis tools that
+//! read chunked data, like xarray and zarr-python.
+//!
+//! A store is a directory holding a `zarr.json` metadata document and one
+//! file per chunk, `c/<i>/<j>/...`. Chunks are written uncompressed with
+//! the `bytes` codec in little-endian order, and only stores using that
+//! codec can be read back; compressed stores fail with
+//! `ErrorKind::Unsupported`.
+//!
+//! Zarr arrays are row major, so dimensions are reversed on the way in and
+//! out, as for HDF5 and `.npy`: an array of dimensions `[4, 3]` here is a
+//! Zarr array of shape `(3, 4)`, with the same elements in the same order.
+//! Attributes aren't copied in either direction.
+
+use crate::convert::{check_conversion, convert_bytes, elements_from_bytes_with_endianness};
+use crate::format::parse_component;
+use crate::json::{json_string, parse, scalar_text, Json};
+use crate::options::DEFAULT_MAX_BYTES;
+use crate::{
+    as_u8_slice, check_max_bytes, invalid, to_usize, DType, Endianness, RawArray, RawArrayType,
+};
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+const METADATA_NAME: &str = "zarr.json";
+
+/// Zarr data type names and the element types they match
+const DATA_TYPES: &[(&str, &str)] = &[
+    ("int8", "i8"),
+    ("int16", "i16"),
+    ("int32", "i32"),
+    ("int64", "i64"),
+    ("uint8", "u8"),
+    ("uint16", "u16"),
+    ("uint32", "u32"),
+    ("uint64", "u64"),
+    ("float16", "f16"),
+    ("float32", "f32"),
+    ("float64", "f64"),
+    ("complex64", "c64"),
+    ("complex128", "c128"),
+];
+
+fn zarr_type(dtype: DType) -> io::Result<&'static str> {
+    DATA_TYPES
+        .iter()
+        .find(|(_, name)| DType::parse(name) == Some(dtype))
+        .map(|(zarr, _)| *zarr)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Zarr has no data type for elements of type {}", dtype),
+            )
+        })
+}
+
+fn unsupported(msg: String) -> Error {
+    Error::new(ErrorKind::Unsupported, msg)
+}
+
+/// Comma-separated list of `dims` in Zarr's order
+fn shape_json(dims: &[u64]) -> String {
+    let shape: Vec<String> = dims.iter().rev().map(u64::to_string).collect();
+    format!("[{}]", shape.join(", "))
+}
+
+/// Bytes in one chunk of dimensions `chunk`, if that fits in a `u64`
+fn chunk_bytes(chunk: &[u64], elbyte: u64) -> Option<u64> {
+    chunk.iter().try_fold(elbyte, |n, &c| n.checked_mul(c))
+}
+
+/// Visit every chunk of an array of dimensions `dims` cut into chunks of
+/// `chunk`, passing the chunk's grid coordinates, first dimension first.
+fn for_each_chunk<F>(dims: &[u64], chunk: &[u64], mut f: F) -> io::Result<()>
+where
+    F: FnMut(&[u64]) -> io::Result<()>,
+{
+    let counts: Vec<u64> = dims
+        .iter()
+        .zip(chunk)
+        .map(|(d, c)| d.div_ceil(*c))
+        .collect();
+    if counts.contains(&0) {
+        return Ok(());
+    }
+    let mut grid = vec![0; dims.len()];
+    loop {
+        f(&grid)?;
+        let mut i = 0;
+        loop {
+            if i == grid.len() {
+                return Ok(());
+            }
+            grid[i] += 1;
+            if grid[i] < counts[i] {
+                break;
+            }
+            grid[i] = 0;
+            i += 1;
+        }
+    }
+}
+
+/// Call `f(array_offset, chunk_offset, len)`, in elements, for each run of
+/// the chunk at `grid` that lies inside the array. Runs go along the first
+/// dimension, which is contiguous both in the array and in the chunk.
+fn for_each_run<F: FnMut(usize, usize, usize)>(
+    dims: &[u64],
+    chunk: &[u64],
+    grid: &[u64],
+    mut f: F,
+) {
+    let n = dims.len();
+    let origin: Vec<u64> = grid.iter().zip(chunk).map(|(g, c)| g * c).collect();
+    let extent: Vec<u64> = (0..n).map(|i| chunk[i].min(dims[i] - origin[i])).collect();
+    let mut local = vec![0u64; n];
+    loop {
+        let (mut a, mut c) = (0, 0);
+        let (mut a_stride, mut c_stride) = (1, 1);
+        for i in 0..n {
+            a += (origin[i] + local[i]) * a_stride;
+            c += local[i] * c_stride;
+            a_stride *= dims[i];
+            c_stride *= chunk[i];
+        }
+        f(a as usize, c as usize, extent[0] as usize);
+        let mut i = 1;
+        loop {
+            if i >= n {
+                return;
+            }
+            local[i] += 1;
+            if local[i] < extent[i] {
+                break;
+            }
+            local[i] = 0;
+            i += 1;
+        }
+    }
+}
+
+/// Metadata of a store, as far as reading it needs
+struct Metadata {
+    dims: Vec<u64>,
+    chunk: Vec<u64>,
+    dtype: DType,
+    endianness: Endianness,
+    /// One element of the fill value, little-endian
+    fill: Vec<u8>,
+    /// Directory of the store
+    dir: PathBuf,
+    /// Start of every chunk key, `c` in the default encoding
+    prefix: Option<String>,
+    /// Separator between the parts of a chunk key
+    separator: String,
+}
+
+impl Metadata {
+    /// Path of the chunk at grid coordinates `grid`, first dimension first
+    fn chunk_path(&self, grid: &[u64]) -> PathBuf {
+        let mut parts: Vec<String> = self.prefix.iter().cloned().collect();
+        parts.extend(grid.iter().rev().map(u64::to_string));
+        self.dir.join(parts.join(&self.separator))
+    }
+}
+
+fn member<'a>(members: &'a [(String, Json)], key: &str) -> Option<&'a Json> {
+    members.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+}
+
+fn object<'a>(v: Option<&'a Json>, what: &str) -> io::Result<&'a [(String, Json)]> {
+    match v {
+        Some(Json::Object(members)) => Ok(members),
+        _ => Err(invalid(format!("Zarr metadata has no {} object", what))),
+    }
+}
+
+fn string<'a>(v: Option<&'a Json>, what: &str) -> io::Result<&'a str> {
+    match v {
+        Some(Json::Str(s)) => Ok(s),
+        _ => Err(invalid(format!("Zarr metadata has no {} string", what))),
+    }
+}
+
+/// A list of integers, reversed into this crate's order
+fn dims(v: Option<&Json>, what: &str) -> io::Result<Vec<u64>> {
+    match v {
+        Some(Json::Array(items)) => items
+            .iter()
+            .rev()
+            .map(|d| match d {
+                Json::Number(s) => s.parse::<u64>().ok(),
+                _ => None,
+            })
+            .collect::<Option<Vec<u64>>>()
+            .ok_or_else(|| invalid(format!("Zarr {} must be non-negative integers", what))),
+        _ => Err(invalid(format!("Zarr metadata has no {} list", what))),
+    }
+}
+
+fn read_metadata(dir: &Path) -> io::Result<Metadata> {
+    let text = fs::read_to_string(dir.join(METADATA_NAME))?;
+    let members = match parse(&text)? {
+        Json::Object(members) => members,
+        other => {
+            return Err(invalid(format!(
+                "Zarr metadata is {}, not an object",
+                other.describe()
+            )))
+        }
+    };
+    match member(&members, "zarr_format") {
+        Some(Json::Number(v)) if v == "3" => {}
+        _ => return Err(unsupported("only Zarr version 3 stores can be read".into())),
+    }
+    let node_type = string(member(&members, "node_type"), "node_type")?;
+    if node_type != "array" {
+        return Err(invalid(format!(
+            "Zarr store is a {}, not an array",
+            node_type
+        )));
+    }
+
+    let dims = dims(member(&members, "shape"), "shape")?;
+    if dims.is_empty() {
+        return Err(unsupported(
+            "zero-dimensional Zarr arrays can't be read".into(),
+        ));
+    }
+    let name = string(member(&members, "data_type"), "data_type")?;
+    let dtype = DATA_TYPES
+        .iter()
+        .find(|(zarr, _)| *zarr == name)
+        .and_then(|(_, name)| DType::parse(name))
+        .ok_or_else(|| unsupported(format!("Zarr data type {} can't be read", name)))?;
+
+    let grid = object(member(&members, "chunk_grid"), "chunk_grid")?;
+    if string(member(grid, "name"), "chunk grid name")? != "regular" {
+        return Err(unsupported(
+            "only regular Zarr chunk grids can be read".into(),
+        ));
+    }
+    let config = object(member(grid, "configuration"), "chunk grid configuration")?;
+    let chunk = self::dims(member(config, "chunk_shape"), "chunk_shape")?;
+    if chunk.len() != dims.len() || chunk.contains(&0) {
+        return Err(invalid("Zarr chunk_shape doesn't fit the shape"));
+    }
+
+    let encoding = object(member(&members, "chunk_key_encoding"), "chunk_key_encoding")?;
+    let encoding_name = string(member(encoding, "name"), "chunk key encoding name")?;
+    let separator = match member(encoding, "configuration") {
+        Some(Json::Object(config)) => member(config, "separator"),
+        _ => None,
+    };
+    let separator = match (encoding_name, separator) {
+        (_, Some(Json::Str(s))) if s == "/" || s == "." => s.clone(),
+        ("default", None) => "/".to_string(),
+        ("v2", None) => ".".to_string(),
+        _ => return Err(invalid("Zarr chunk key encoding is malformed")),
+    };
+    let prefix = match encoding_name {
+        "default" => Some("c".to_string()),
+        "v2" => None,
+        other => {
+            return Err(unsupported(format!(
+                "Zarr chunk key encoding {} can't be read",
+                other
+            )))
+        }
+    };
+
+    let mut endianness = Endianness::Little;
+    let codecs = match member(&members, "codecs") {
+        Some(Json::Array(codecs)) => codecs,
+        _ => return Err(invalid("Zarr metadata has no codecs list")),
+    };
+    for codec in codecs {
+        let codec = object(Some(codec), "codec")?;
+        let name = string(member(codec, "name"), "codec name")?;
+        if name != "bytes" {
+            return Err(unsupported(format!("Zarr codec {} can't be read", name)));
+        }
+        if let Some(Json::Object(config)) = member(codec, "configuration") {
+            if let Some(Json::Str(endian)) = member(config, "endian") {
+                endianness = match endian.as_str() {
+                    "little" => Endianness::Little,
+                    "big" => Endianness::Big,
+                    _ => return Err(invalid("Zarr bytes codec has a malformed endian")),
+                };
+            }
+        }
+    }
+
+    let (code, width) = dtype.component();
+    let scalars = match member(&members, "fill_value") {
+        Some(Json::Array(parts)) if dtype.components() == 2 && parts.len() == 2 => &parts[..],
+        Some(v) if dtype.components() == 1 => std::slice::from_ref(v),
+        _ => return Err(invalid("Zarr metadata has no fill_value")),
+    };
+    let mut fill = Vec::with_capacity(dtype.elbyte as usize);
+    for v in scalars {
+        scalar_text(v)
+            .and_then(|s| parse_component(code, width, s, &mut fill))
+            .ok_or_else(|| unsupported(format!("Zarr fill_value isn't a {}", dtype)))?;
+    }
+
+    Ok(Metadata {
+        dims,
+        chunk,
+        dtype,
+        endianness,
+        fill,
+        dir: dir.to_path_buf(),
+        prefix,
+        separator,
+    })
+}
+
+impl<T: RawArrayType> RawArray<T> {
+    /// Write the array as a Zarr v3 store in the directory `dir`, cut into
+    /// chunks of dimensions `chunk`, given in this crate's order. Chunks
+    /// at the far edges are padded with zeros, as Zarr requires, so a chunk
+    /// may be no longer than the array along any dimension. The
+    /// directory is created if needed; chunks of a store already there
+    /// are overwritten.
+    /// ```
+    /// # use std::io;
+    /// use rawarray::RawArray;
+    /// # fn main() -> io::Result<()> {
+    /// let ra = RawArray::from_iter_with_dims((0..30).map(|i| i as f32), &[5, 6])?;
+    /// ra.to_zarr("array.zarr", &[2, 4])?;
+    /// let meta = std::fs::read_to_string("array.zarr/zarr.json")?;
+    /// assert!(meta.contains(r#""shape": [6, 5]"#));
+    /// // three chunks down the first dimension by two across the second
+    /// assert!(std::path::Path::new("array.zarr/c/1/2").exists());
+    ///
+    /// assert_eq!(RawArray::<f32>::from_zarr("array.zarr")?, ra);
+    /// assert_eq!(RawArray::<f64>::from_zarr("array.zarr")?.data()[29], 29.0);
+    /// assert!(ra.to_zarr("array.zarr", &[2, 1 << 40]).is_err());
+    ///
+    /// // chunks claiming to be enormous are refused before allocating
+    /// let huge = meta.replace("[4, 2]", "[4294967296, 4294967296]");
+    /// std::fs::write("array.zarr/zarr.json", huge)?;
+    /// assert!(RawArray::<f32>::from_zarr("array.zarr").is_err());
+    /// # std::fs::remove_dir_all("array.zarr")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_zarr<P: AsRef<Path>>(&self, dir: P, chunk: &[u64]) -> io::Result<()> {
+        let dir = dir.as_ref();
+        let too_long = chunk.iter().zip(&self.dims).any(|(&c, &d)| c > d.max(1));
+        if chunk.len() != self.dims.len() || chunk.contains(&0) || too_long {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "chunk dimensions {:?} don't fit array dimensions {:?}",
+                    chunk, self.dims
+                ),
+            ));
+        }
+        let dtype = DType::of::<T>();
+        let data_type = zarr_type(dtype)?;
+        let elbyte = dtype.elbyte as usize;
+        let chunk_len = chunk_bytes(chunk, dtype.elbyte);
+        check_max_bytes(chunk_len, DEFAULT_MAX_BYTES)?;
+        let fill = match (dtype.components(), dtype.component().0) {
+            (2, _) => "[0.0, 0.0]",
+            (_, 3) => "0.0",
+            _ => "0",
+        };
+        let metadata = format!(
+            concat!(
+                "{{\n",
+                "  \"zarr_format\": 3,\n",
+                "  \"node_type\": \"array\",\n",
+                "  \"shape\": {},\n",
+                "  \"data_type\": {},\n",
+                "  \"chunk_grid\": {{\"name\": \"regular\", \"configuration\": {{\"chunk_shape\": {}}}}},\n",
+                "  \"chunk_key_encoding\": {{\"name\": \"default\", \"configuration\": {{\"separator\": \"/\"}}}},\n",
+                "  \"fill_value\": {},\n",
+                "  \"codecs\": [{{\"name\": \"bytes\", \"configuration\": {{\"endian\": \"little\"}}}}],\n",
+                "  \"attributes\": {{}}\n",
+                "}}\n"
+            ),
+            shape_json(&self.dims),
+            json_string(data_type),
+            shape_json(chunk),
+            fill
+        );
+
+        let mut le = Vec::with_capacity(self.data.len() * elbyte);
+        convert_bytes(
+            as_u8_slice(&self.data),
+            dtype,
+            self.endianness(),
+            &mut le,
+            dtype,
+            Endianness::Little,
+        )?;
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join(METADATA_NAME), metadata)?;
+        let mut buf = vec![0u8; to_usize(chunk_len.unwrap_or(u64::MAX))?];
+        for_each_chunk(&self.dims, chunk, |grid| {
+            buf.iter_mut().for_each(|b| *b = 0);
+            for_each_run(&self.dims, chunk, grid, |a, c, len| {
+                buf[c * elbyte..(c + len) * elbyte]
+                    .copy_from_slice(&le[a * elbyte..(a + len) * elbyte]);
+            });
+            let mut path = dir.join("c");
+            for g in grid.iter().rev() {
+                path.push(g.to_string());
+            }
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, &buf)
+        })
+    }
+
+    /// Read a Zarr v3 array store of uncompressed chunks, converting its
+    /// elements to `T` as `read_as` does. Chunks missing from the store
+    /// hold the fill value.
+    pub fn from_zarr<P: AsRef<Path>>(dir: P) -> io::Result<RawArray<T>> {
+        let meta = read_metadata(dir.as_ref())?;
+        let to = DType::of::<T>();
+        check_conversion(meta.dtype, to)?;
+        let elbyte = meta.dtype.elbyte as usize;
+        let nelem = meta.dims.iter().try_fold(1u64, |n, &d| n.checked_mul(d));
+        check_max_bytes(
+            nelem.and_then(|n| n.checked_mul(meta.dtype.elbyte)),
+            DEFAULT_MAX_BYTES,
+        )?;
+        let nelem = to_usize(nelem.unwrap_or(u64::MAX))?;
+        let chunk_len = chunk_bytes(&meta.chunk, meta.dtype.elbyte);
+        check_max_bytes(chunk_len, DEFAULT_MAX_BYTES)?;
+        let chunk_len = to_usize(chunk_len.unwrap_or(u64::MAX))?;
+
+        let mut le: Vec<u8> = meta.fill.repeat(nelem);
+        for_each_chunk(&meta.dims, &meta.chunk, |grid| {
+            let path = meta.chunk_path(grid);
+            let mut bytes = match fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            if bytes.len() != chunk_len {
+                return Err(invalid(format!(
+                    "Zarr chunk {} has {} bytes, not {}",
+                    path.display(),
+                    bytes.len(),
+                    chunk_len
+                )));
+            }
+            if meta.endianness != Endianness::Little {
+                let mut swapped = Vec::with_capacity(chunk_len);
+                convert_bytes(
+                    &bytes,
+                    meta.dtype,
+                    meta.endianness,
+                    &mut swapped,
+                    meta.dtype,
+                    Endianness::Little,
+                )?;
+                bytes = swapped;
+            }
+            for_each_run(&meta.dims, &meta.chunk, grid, |a, c, len| {
+                le[a * elbyte..(a + len) * elbyte]
+                    .copy_from_slice(&bytes[c * elbyte..(c + len) * elbyte]);
+            });
+            Ok(())
+        })?;
+
+        let mut out = Vec::with_capacity(nelem * to.elbyte as usize);
+        let native = Endianness::native();
+        convert_bytes(&le, meta.dtype, Endianness::Little, &mut out, to, native)?;
+        let data = elements_from_bytes_with_endianness(&out, native)?;
+        Ok(RawArray::from_parts(meta.dims, data))
+    }
+}
@@ -0,0 +1,84 @@
+//! Reading arrays from a small HTTP server on localhost.
+#![cfg(feature = "http")]
+
+use rawarray::http::RemoteRawArray;
+use rawarray::RawArray;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+
+/// Serve `body` on a local port, answering range requests with
+/// `206 Partial Content` if `ranges` is set and with the whole body
+/// otherwise, and give the URL of the file.
+fn serve(body: Vec<u8>, ranges: bool) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/array.ra", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut range = None;
+            for line in BufReader::new(&stream).lines() {
+                let line = line.unwrap();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(r) = line.strip_prefix("Range: bytes=") {
+                    let (first, last) = r.split_once('-').unwrap();
+                    range = Some((
+                        first.parse::<usize>().unwrap(),
+                        last.parse::<usize>().unwrap(),
+                    ));
+                }
+            }
+            let response = match range {
+                Some((first, last)) if ranges => {
+                    let last = last.min(body.len() - 1);
+                    let mut r = format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        first,
+                        last,
+                        body.len(),
+                        last + 1 - first
+                    )
+                    .into_bytes();
+                    r.extend_from_slice(&body[first..=last]);
+                    r
+                }
+                _ => {
+                    let mut r = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    )
+                    .into_bytes();
+                    r.extend_from_slice(&body);
+                    r
+                }
+            };
+            stream.write_all(&response).ok();
+        }
+    });
+    url
+}
+
+#[test]
+fn reads_whole_arrays_and_slices() {
+    let ra = RawArray::from_iter_with_dims(0..60u16, &[3, 4, 5]).unwrap();
+    let url = serve(ra.to_bytes(), true);
+    assert_eq!(RawArray::<u16>::read_url(&url).unwrap(), ra);
+
+    let mut remote = RemoteRawArray::<u16>::open(&url).unwrap();
+    assert_eq!(remote.dims(), &[3, 4, 5]);
+    let slab = remote.read_slice(2, 2).unwrap();
+    assert_eq!(slab.dims(), vec![3, 4, 2]);
+    assert_eq!(slab.data(), (24..48).collect::<Vec<u16>>());
+    assert!(remote.read_slice(4, 2).is_err());
+    assert!(remote.read_slice(u64::MAX, 2).is_err());
+    assert!(RemoteRawArray::<u8>::open(&url).is_err());
+}
+
+#[test]
+fn needs_range_requests() {
+    let ra: RawArray<u8> = vec![1, 2, 3].into();
+    let url = serve(ra.to_bytes(), false);
+    assert!(RemoteRawArray::<u8>::open(&url).is_err());
+}
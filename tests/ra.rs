@@ -0,0 +1,171 @@
+//! Runs of the `ra` binary on small files.
+
+use num_complex::Complex;
+use rawarray::{RawArray, RawArraySetWriter};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rawarray_cli_{}.ra", name))
+}
+
+fn ra(args: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_ra"))
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .success()
+}
+
+#[test]
+fn cast_in_place() {
+    let path = temp_path("cast");
+    // larger than any read buffer, so the input can't survive truncation
+    let ra_in: RawArray<i32> = (-50_000..50_000).collect();
+    ra_in.write(&path).unwrap();
+    let p = path.to_str().unwrap();
+    assert!(ra(&["cast", p, p, "--to", "f64"]));
+    let out = RawArray::<f64>::read(&path).unwrap();
+    assert_eq!(
+        out.data(),
+        (-50_000..50_000).map(f64::from).collect::<Vec<_>>()
+    );
+    let q = temp_path("cast_complex");
+    let q = q.to_str().unwrap();
+    assert!(ra(&["cast", p, q, "--to", "c128"]));
+    assert!(!ra(&["cast", q, q, "--to", "f64"]));
+    // the failed cast leaves the file as it was
+    let z = RawArray::<Complex<f64>>::read(q).unwrap();
+    assert_eq!(z.data()[0], Complex::new(-50_000.0, 0.0));
+    fs::remove_file(q).unwrap();
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn fix_keeps_volatile_metadata_and_sets() {
+    let path = temp_path("fix_trailing");
+    let p = path.to_str().unwrap();
+    let ra_in: RawArray<u16> = vec![1, 2, 3].into();
+    ra_in.write(&path).unwrap();
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.extend_from_slice(b"notes");
+    fs::write(&path, &bytes).unwrap();
+    assert!(ra(&["validate", p]));
+    assert!(ra(&["fix", "--yes", p]));
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+
+    let mut w = RawArraySetWriter::create(&path).unwrap();
+    w.push(&ra_in).unwrap();
+    w.push(&ra_in).unwrap();
+    w.finish().unwrap();
+    let bytes = fs::read(&path).unwrap();
+    assert!(!ra(&["fix", "--yes", p]));
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn diff_decodes_byte_order_and_codecs() {
+    use rawarray::codec::Rle;
+    use rawarray::{Endianness, WriteOptions};
+    let a = temp_path("diff_a");
+    let b = temp_path("diff_b");
+    let (pa, pb) = (a.to_str().unwrap(), b.to_str().unwrap());
+    let diff = || {
+        let out = Command::new(env!("CARGO_BIN_EXE_ra"))
+            .args(["diff", pa, pb])
+            .output()
+            .unwrap();
+        String::from_utf8(out.stdout).unwrap()
+    };
+    let ra_in: RawArray<i32> = vec![1, -2, 3, 3, 3, 70_000].into();
+    ra_in.write(&a).unwrap();
+    let big = WriteOptions::new().endianness(Endianness::Big);
+    ra_in.write_with(&b, &big).unwrap();
+    let out = diff();
+    assert!(out.contains("flags") && !out.contains("differ"), "{}", out);
+    ra_in
+        .write_with(&b, &WriteOptions::new().codec(Rle))
+        .unwrap();
+    let out = diff();
+    assert!(out.contains("flags") && !out.contains("differ"), "{}", out);
+
+    let changed: RawArray<i32> = vec![1, -2, 3, 4, 3, 70_000].into();
+    changed.write_with(&b, &big.codec(Rle)).unwrap();
+    let out = diff();
+    assert!(out.contains("[3]: 3 != 4"), "{}", out);
+    assert!(out.contains("1 of 6 elements differ"), "{}", out);
+    fs::remove_file(&a).unwrap();
+    fs::remove_file(&b).unwrap();
+}
+
+#[test]
+fn diff_compares_integers_exactly() {
+    let a = temp_path("diff_exact_a");
+    let b = temp_path("diff_exact_b");
+    let (pa, pb) = (a.to_str().unwrap(), b.to_str().unwrap());
+    // equal once converted to f64
+    RawArray::from(vec![1u64 << 53]).write(&a).unwrap();
+    RawArray::from(vec![(1u64 << 53) + 1]).write(&b).unwrap();
+    assert!(!ra(&["diff", pa, pb]));
+    assert!(ra(&["diff", pa, pb, "--atol", "1"]));
+    RawArray::from(vec![i64::MIN]).write(&a).unwrap();
+    RawArray::from(vec![i64::MIN + 1]).write(&b).unwrap();
+    assert!(!ra(&["diff", pa, pb]));
+    fs::remove_file(&a).unwrap();
+    fs::remove_file(&b).unwrap();
+}
+
+#[test]
+fn peek_trusts_size_over_dims() {
+    let path = temp_path("peek");
+    let p = path.to_str().unwrap();
+    RawArray::from(vec![1u16, 2, 3]).write(&path).unwrap();
+    let mut bytes = fs::read(&path).unwrap();
+    bytes[48..56].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    fs::write(&path, &bytes).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ra"))
+        .args(["peek", p, "--count", "100"])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8(out.stdout).unwrap().lines().count(), 3);
+    assert!(!ra(&["peek", p, "--offset", "3"]));
+    assert!(!ra(&["peek", p, "--at", "1099511627775"]));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn reshape_plans_and_rewrites() {
+    let path = temp_path("reshape");
+    let p = path.to_str().unwrap();
+    let mut ra_in = RawArray::from(vec![1u16, 2, 3, 4, 5, 6]);
+    ra_in.reshape(vec![2, 3]);
+    ra_in.write(&path).unwrap();
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.extend_from_slice(b"notes");
+    fs::write(&path, &bytes).unwrap();
+
+    assert!(ra(&["reshape", "--dry-run", p, "6"]));
+    assert!(!ra(&["reshape", p, "6"]));
+    assert!(!ra(&[
+        "reshape",
+        "--yes",
+        p,
+        "4",
+        "4294967296",
+        "4294967296"
+    ]));
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+
+    assert!(ra(&["reshape", p, "3", "2"]));
+    assert_eq!(RawArray::<u16>::read(&path).unwrap().dims(), vec![3, 2]);
+    assert!(ra(&["reshape", "--yes", p, "6"]));
+    let out = RawArray::<u16>::read(&path).unwrap();
+    assert_eq!(out.dims(), vec![6]);
+    assert_eq!(out.data(), ra_in.data());
+    assert!(fs::read(&path).unwrap().ends_with(b"notes"));
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,54 @@
+//! Runs of the `ra-convert` binary on small directory trees.
+
+use rawarray::{RawArray, RawArrayHeader};
+use rawarray_core::FLAG_BIG_ENDIAN;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rawarray_convert_{}", name));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    dir
+}
+
+fn ra_convert(args: &[&str]) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_ra-convert"))
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .success()
+}
+
+#[test]
+fn converts_tree_in_place() {
+    let dir = temp_dir("in_place");
+    // larger than any read buffer, so the input can't survive truncation
+    let ra: RawArray<u32> = (0..100_000).collect();
+    let path = dir.join("sub").join("big.ra");
+    ra.write(&path).unwrap();
+    let d = dir.to_str().unwrap();
+    assert!(ra_convert(&[d, d, "--endian", "big", "--dtype", "u64"]));
+    assert_ne!(RawArrayHeader::read(&path).unwrap().flags & FLAG_BIG_ENDIAN, 0);
+    let out = RawArray::<u64>::read(&path).unwrap();
+    assert_eq!(out.data(), (0..100_000).collect::<Vec<u64>>());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mirrors_tree_and_rejects_bad_options() {
+    let dir = temp_dir("mirror");
+    let ra: RawArray<i16> = vec![1, -2, 3].into();
+    ra.write(dir.join("sub").join("a.ra")).unwrap();
+    let (src, dst) = (dir.to_str().unwrap(), dir.join("out"));
+    let dst = dst.to_str().unwrap();
+    assert!(!ra_convert(&[src, dst, "--checksum", "yes"]));
+    assert!(!dir.join("out").exists());
+    assert!(ra_convert(&[src, dst, "--checksum", "on"]));
+    let out = RawArray::<i16>::read(dir.join("out").join("sub").join("a.ra")).unwrap();
+    assert!(out.checksum());
+    assert_eq!(out.data(), ra.data());
+    fs::remove_dir_all(&dir).unwrap();
+}
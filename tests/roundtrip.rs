@@ -0,0 +1,231 @@
+//! Property tests: arrays of any shape, element type, and layout read back
+//! exactly as they were written, and corrupted files fail with an error
+//! rather than a panic.
+
+use num_complex::Complex;
+use proptest::prelude::*;
+use rawarray::codec::{Delta, Rle};
+use rawarray::{RawArray, RawArrayHeader, RawArrayType, ReadOptions, SizePolicy, WriteOptions};
+use std::fmt::Debug;
+use std::path::PathBuf;
+
+/// Arrays of up to four dimensions of up to five elements each
+fn arrays<T, S>(elements: S) -> impl Strategy<Value = RawArray<T>>
+where
+    T: RawArrayType + Debug,
+    S: Strategy<Value = T> + Clone,
+{
+    prop::collection::vec(1u64..=5, 1..=4).prop_flat_map(move |dims| {
+        let n = dims.iter().product::<u64>() as usize;
+        prop::collection::vec(elements.clone(), n)
+            .prop_map(move |data| RawArray::from_iter_with_dims(data, &dims).unwrap())
+    })
+}
+
+/// Floats that compare equal to themselves
+fn comparable_f32() -> impl Strategy<Value = f32> + Clone {
+    use prop::num::f32::*;
+    NORMAL | SUBNORMAL | ZERO | INFINITE
+}
+
+fn comparable_f64() -> impl Strategy<Value = f64> + Clone {
+    use prop::num::f64::*;
+    NORMAL | SUBNORMAL | ZERO | INFINITE
+}
+
+fn attrs() -> impl Strategy<Value = Vec<(String, i64)>> {
+    prop::collection::vec(("[a-z.]{1,8}", any::<i64>()), 0..3)
+}
+
+/// Write `ra` in memory with the given flags, and read it back.
+fn in_memory<T: RawArrayType + Debug + PartialEq>(
+    mut ra: RawArray<T>,
+    big_endian: bool,
+    checksum: bool,
+    attrs: Vec<(String, i64)>,
+) -> Result<(), TestCaseError> {
+    for (key, value) in attrs {
+        ra.set_attr(&key, value);
+    }
+    ra.set_checksum(checksum);
+    let want = ra.clone();
+    if big_endian {
+        ra.to_big_endian().unwrap();
+    }
+    let back = RawArray::<T>::read_from_bytes(&ra.to_bytes()).unwrap();
+    prop_assert_eq!(back.dims(), want.dims());
+    prop_assert_eq!(back.as_slice(), want.as_slice());
+    prop_assert_eq!(back.attrs(), want.attrs());
+    prop_assert_eq!(back.checksum(), checksum);
+    Ok(())
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rawarray_roundtrip_{}.ra", name))
+}
+
+/// Every codec that handles elements of any width up to 8 bytes
+fn codecs() -> Vec<Option<WriteOptions>> {
+    #[allow(unused_mut)]
+    let mut codecs = vec![
+        None,
+        Some(WriteOptions::new().codec(Rle)),
+        Some(WriteOptions::new().codec(Delta)),
+    ];
+    #[cfg(feature = "lz4")]
+    codecs.push(Some(WriteOptions::new().codec(rawarray::codec::Lz4)));
+    #[cfg(feature = "zstd")]
+    codecs.push(Some(WriteOptions::new().codec(rawarray::codec::Zstd(3))));
+    codecs
+}
+
+/// Write `ra` to a file with a codec, byte order, and checksum, and read
+/// it back from the file's bytes.
+fn encoded<T: RawArrayType + Debug + PartialEq>(
+    ra: RawArray<T>,
+    name: &str,
+    codec: usize,
+    big_endian: bool,
+    checksum: bool,
+) -> Result<(), TestCaseError> {
+    let codecs = codecs();
+    let opts = codecs[codec % codecs.len()]
+        .clone()
+        .unwrap_or_default()
+        .endianness(if big_endian {
+            rawarray::Endianness::Big
+        } else {
+            rawarray::Endianness::Little
+        })
+        .checksum(checksum);
+    let path = temp_path(name);
+    ra.write_with(&path, &opts).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let back = RawArray::<T>::read_from_bytes(&bytes).unwrap();
+    prop_assert_eq!(back.dims(), ra.dims());
+    prop_assert_eq!(back.as_slice(), ra.as_slice());
+    Ok(())
+}
+
+macro_rules! round_trips {
+    ($($name:ident: $t:ty = $elements:expr;)*) => {
+        mod in_memory {
+            use super::*;
+            proptest! {
+                $(
+                    #[test]
+                    fn $name(
+                        ra in arrays::<$t, _>($elements),
+                        big_endian: bool,
+                        checksum: bool,
+                        attrs in attrs(),
+                    ) {
+                        in_memory(ra, big_endian, checksum, attrs)?;
+                    }
+                )*
+            }
+        }
+
+        mod encoded {
+            use super::*;
+            proptest! {
+                $(
+                    #[test]
+                    fn $name(
+                        ra in arrays::<$t, _>($elements),
+                        codec: usize,
+                        big_endian: bool,
+                        checksum: bool,
+                    ) {
+                        encoded(ra, stringify!($name), codec, big_endian, checksum)?;
+                    }
+                )*
+            }
+        }
+    };
+}
+
+round_trips! {
+    u8s: u8 = any::<u8>();
+    i8s: i8 = any::<i8>();
+    u16s: u16 = any::<u16>();
+    i32s: i32 = any::<i32>();
+    u64s: u64 = any::<u64>();
+    i64s: i64 = any::<i64>();
+    f32s: f32 = comparable_f32();
+    f64s: f64 = comparable_f64();
+    c64s: Complex<f32> = (comparable_f32(), comparable_f32()).prop_map(|(re, im)| Complex::new(re, im));
+    ci16s: Complex<i16> = any::<(i16, i16)>().prop_map(|(re, im)| Complex::new(re, im));
+}
+
+/// A valid file to corrupt: encoded or not, with or without a checksum
+/// and attributes
+fn files() -> impl Strategy<Value = Vec<u8>> {
+    (
+        arrays::<f32, _>(comparable_f32()),
+        any::<bool>(),
+        any::<bool>(),
+        any::<bool>(),
+    )
+        .prop_map(|(mut ra, encoded, checksum, attrs)| {
+            ra.set_checksum(checksum);
+            if attrs {
+                ra.set_attr("ra.note", "corrupt me");
+            }
+            if !encoded {
+                return ra.to_bytes();
+            }
+            let path = temp_path(&format!("mutated_{:?}", std::thread::current().id()));
+            ra.write_with(&path, &WriteOptions::new().codec(Rle))
+                .unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            bytes
+        })
+}
+
+/// Read untrusted bytes every way there is, with a small byte limit so a
+/// corrupt size can't make the test itself run out of memory.
+fn read_everything(bytes: &[u8]) {
+    let _ = RawArrayHeader::read_from(&mut &bytes[..]);
+    let _ = RawArray::<f32>::read_from_bytes(bytes);
+    for policy in &[
+        SizePolicy::Strict,
+        SizePolicy::TrustDims,
+        SizePolicy::TrustSize,
+    ] {
+        let opts = ReadOptions::new().size_policy(*policy).max_bytes(1 << 20);
+        let _ = RawArray::<f32>::read_from_with(&mut &bytes[..], &opts);
+        let _ = RawArray::<u32>::read_from_with(&mut &bytes[..], &opts);
+    }
+}
+
+proptest! {
+    #[test]
+    fn corrupt_header_bytes(
+        mut bytes in files(),
+        edits in prop::collection::vec((0usize..120, any::<u8>()), 1..4),
+    ) {
+        for (at, value) in edits {
+            if let Some(b) = bytes.get_mut(at) {
+                *b = value;
+            }
+        }
+        read_everything(&bytes);
+    }
+
+    #[test]
+    fn corrupt_any_byte(mut bytes in files(), at: prop::sample::Index, bit in 0u8..8) {
+        let at = at.index(bytes.len());
+        bytes[at] ^= 1 << bit;
+        read_everything(&bytes);
+    }
+
+    #[test]
+    fn truncated(bytes in files(), at: prop::sample::Index) {
+        let at = at.index(bytes.len());
+        read_everything(&bytes[..at]);
+        prop_assert!(RawArray::<f32>::read_from_bytes(&bytes[..at]).is_err());
+    }
+}